@@ -2,20 +2,20 @@
 use std::path::{Path, PathBuf};
 
 use crate::{
-    assembler::Sizer,
     error::{
         to_user_error, ErrorCollector, ErrorCollectorTrait, GResult, GazmErrorKind,
-        NewErrorCollector, UserError,
+        NewErrorCollector, UserError, UserWarning, WarningKind,
     },
     frontend::{
-        tokenize_async, tokenize_no_async, AstNodeKind, CpuSpecific, FrontEndError,
-        FrontEndErrorKind, Node, TokenStore, TokenizeResult,
+        tokenize_async, tokenize_no_async, AstNodeKind, FrontEndError, FrontEndErrorKind,
+        LabelDefinition, Node, TokenStore, TokenizeResult,
     },
-    gazmsymbols::SymbolTree,
+    gazmsymbols::{SymbolBanks, SymbolTree},
     lookup::LabelUsageAndDefintions,
     messages::status,
     opts::{BinReference, Opts},
-    semantic::{Ast, AstCtx, AstNodeId, AstNodeRef},
+    sections::Sections,
+    semantic::{iter_refs_recursive, Ast, AstCtx, AstNodeId, AstNodeRef},
     status_err,
     vars::{Vars, VarsErrorKind},
 };
@@ -71,6 +71,12 @@ pub struct AsmOut {
     pub ast: Option<Ast>,
     /// Used for mapping labesl to source position
     pub lookup: Option<LabelUsageAndDefintions>,
+    /// Listing, only collected if a listing file is asked for
+    pub lst_file: LstFile,
+    /// Memory sections from config
+    pub sections: Sections,
+    /// Banks of labels defined in banked sections
+    pub symbol_banks: SymbolBanks,
 }
 
 impl AsmOut {
@@ -96,17 +102,37 @@ impl AsmOut {
     }
 }
 
+/// An item in the listing file
+#[derive(Debug, Clone)]
+pub enum LstItem {
+    /// A compiled source line and the bytes it wrote
+    Source {
+        pos: Position,
+        addr: usize,
+        bytes: Vec<u8>,
+        cycles: Option<usize>,
+        in_macro: bool,
+        /// Anything the assembler changed about the line
+        note: Option<String>,
+    },
+    /// An included file starts
+    FileStart(PathBuf),
+    /// Back to the file that included it
+    FileEnd(PathBuf),
+}
+
+/// Listing items in the order they were compiled
 #[derive(Default, Debug, Clone)]
 pub struct LstFile {
-    pub lines: Vec<String>,
+    pub items: Vec<LstItem>,
 }
 
 impl LstFile {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn add(&mut self, line: &str) {
-        self.lines.push(line.to_string())
+    pub fn add(&mut self, item: LstItem) {
+        self.items.push(item)
     }
 }
 
@@ -119,33 +145,6 @@ impl Assembler {
         panic!()
     }
 
-    pub fn compile_node(
-        &mut self,
-        node: AstNodeRef,
-        node_kind: CpuSpecific,
-        current_scope_id: u64,
-    ) -> GResult<()> {
-        use CpuSpecific::*;
-        match node_kind {
-            Cpu6800(node_kind) => self.compile_node_6800(node_kind, node, current_scope_id),
-            Cpu6809(node_kind) => self.compile_node_6809(node_kind, node, current_scope_id),
-        }
-    }
-
-    fn size_node(
-        &mut self,
-        sizer: &mut Sizer,
-        id: AstNodeId,
-        node_kind: CpuSpecific,
-        current_scope_id: u64,
-    ) -> GResult<()> {
-        use CpuSpecific::*;
-        match node_kind {
-            Cpu6800(node_kind) => self.size_node_6800(sizer,id,node_kind,current_scope_id),
-            Cpu6809(node_kind) => self.size_node_6809(sizer,id,node_kind,current_scope_id),
-        }
-    }
-
     pub fn get_untokenized_files(&self, files: &[(Position, PathBuf)]) -> Vec<(Position, PathBuf)> {
         files
             .iter()
@@ -264,8 +263,9 @@ impl Assembler {
         self.get_source_file_loader().sources.get_source_info(pos)
     }
 
-    pub fn binary_error(&self, _node: AstNodeRef, _e: BinaryError) -> GazmErrorKind {
-        panic!()
+    pub fn binary_error(&self, node: AstNodeRef, e: BinaryError) -> GazmErrorKind {
+        let msg = e.to_string();
+        self.make_user_error(msg, node, true).into()
     }
 
     pub fn binary_error_map<T>(
@@ -293,14 +293,30 @@ impl Assembler {
         Ok(())
     }
 
+    /// Write a byte, a value that doesn't fit is truncated with a warning
     pub fn write_byte_check_size(&mut self, val: i64, node: AstNodeRef) -> GResult<()> {
-        let ret = self.get_binary_mut().write_byte_check_size(val);
+        let ret = match self.get_binary_mut().write_byte_check_size(val) {
+            Err(BinaryError::DoesNotFit { .. }) => {
+                let msg = format!("{val} truncated to ${:02X}", val & 0xff);
+                self.add_warning(WarningKind::Truncated, msg, node);
+                self.get_binary_mut().write_byte(val as u8)
+            }
+            ret => ret,
+        };
         self.binary_error_map(node, ret)?;
         Ok(())
     }
 
+    /// Write a word, a value that doesn't fit is truncated with a warning
     pub fn write_word_check_size(&mut self, val: i64, node: AstNodeRef) -> GResult<()> {
-        let ret = self.get_binary_mut().write_word_check_size(val);
+        let ret = match self.get_binary_mut().write_word_check_size(val) {
+            Err(BinaryError::DoesNotFit { .. }) => {
+                let msg = format!("{val} truncated to ${:04X}", val & 0xffff);
+                self.add_warning(WarningKind::Truncated, msg, node);
+                self.get_binary_mut().write_word(val as u16)
+            }
+            ret => ret,
+        };
         self.binary_error_map(node, ret)?;
         Ok(())
     }
@@ -353,6 +369,7 @@ impl TryFrom<Opts> for AsmOut {
 
     fn try_from(opts: Opts) -> Result<AsmOut, String> {
         let mut binary = Binary::new(opts.mem_size, AccessType::ReadWrite);
+        binary.set_endian(opts.cpu.endian());
 
         for br in &opts.bin_references {
             let x = crate::utils::get_file_as_byte_vec(&br.file);
@@ -442,9 +459,10 @@ impl Assembler {
             status("Lexing async", |_| tokenize_async(self))
         }
         .map_err(|errors| {
-            let mut err_col = NewErrorCollector::new(1000);
+            let max_errors = self.opts.max_errors;
+            let mut err_col = NewErrorCollector::new(max_errors);
 
-            for fe_err in errors.to_vec() {
+            for fe_err in errors.to_vec().into_iter().take(max_errors) {
                 let ue = self.to_user_error(fe_err);
                 err_col.add(ue);
             }
@@ -453,7 +471,23 @@ impl Assembler {
         })
     }
 
+    /// Load the memory sections, read only sections are write protected
+    fn load_sections(&mut self) -> GResult<()> {
+        let sections = Sections::from_opts(&self.opts)?;
+
+        for s in sections.iter() {
+            if s.access_type == AccessType::ReadOnly {
+                let r = s.physical_range.clone();
+                self.asm_out.binary.add_protected_range(r);
+            }
+        }
+
+        self.asm_out.sections = sections;
+        Ok(())
+    }
+
     fn assemble_project(&mut self) -> GResult<()> {
+        self.load_sections()?;
         self.tokenize_project()?;
         let file = self.get_project_file();
         let tokes = self.get_tokens_from_full_path(&file).unwrap().clone();
@@ -495,10 +529,47 @@ impl Assembler {
         })?;
 
         let lookup = LabelUsageAndDefintions::new(&ast_tree, &self.asm_out.symbols, docs);
+        self.warn_unused_labels(&ast_tree, &lookup);
         self.asm_out.ast = Some(ast_tree);
         self.asm_out.lookup = Some(lookup);
+
+        if self.opts.warnings_as_errors && self.asm_out.errors.has_warnings() {
+            let mut errors = NewErrorCollector::new(self.opts.max_errors);
+
+            for w in &self.asm_out.errors.warnings {
+                errors.add(w.to_error());
+            }
+
+            return Err(GazmErrorKind::UserErrors(errors));
+        }
+
         Ok(())
     }
+
+    /// Warn about labels set from the PC that nothing refers to
+    /// Labels inside macro definitions are skipped
+    fn warn_unused_labels(&mut self, tree: &Ast, lookup: &LabelUsageAndDefintions) {
+        use AstNodeKind::*;
+
+        if !self.opts.is_warning_enabled(WarningKind::UnusedLabel) {
+            return;
+        }
+
+        for n in iter_refs_recursive(tree.as_ref().root()) {
+            if let AssignmentFromPc(LabelDefinition::Scoped(id)) = &n.value().item {
+                let in_macro = n.ancestors().any(|a| matches!(a.value().item, MacroDef(..)));
+
+                if in_macro || !lookup.find_references(*id).is_empty() {
+                    continue;
+                }
+
+                if let Ok(sym) = self.get_symbols().get_symbol_info_from_id(*id) {
+                    let msg = format!("Label {} is never used", sym.name());
+                    self.add_warning(WarningKind::UnusedLabel, msg, n);
+                }
+            }
+        }
+    }
 }
 
 // Symbol
@@ -639,4 +710,113 @@ impl Assembler {
         let info = self.get_source_info(&node.value().pos).unwrap();
         UserError::from_text(err, &info, is_failure)
     }
+
+    /// Add a warning for this node if that kind of warning is enabled
+    pub fn add_warning<S: Into<String>>(&mut self, kind: WarningKind, msg: S, node: AstNodeRef) {
+        if !self.opts.is_warning_enabled(kind) {
+            return;
+        }
+
+        if let Ok(info) = self.get_source_info(&node.value().pos) {
+            let warning = UserWarning::from_text(kind, msg, &info);
+            self.asm_out.errors.add_warning(warning);
+        }
+    }
+
+    /// Error for a relative offset that does not fit into `bits`
+    pub fn relative_error(&self, node: AstNodeRef, val: i64, bits: usize) -> GazmErrorKind {
+        let p = 1 << (bits - 1);
+
+        let message = if val < 0 {
+            format!("Branch out of range by {} bytes ({val})", (p + val).abs())
+        } else {
+            format!("Branch out of range by {} bytes ({val})", val - (p - 1))
+        };
+
+        self.make_user_error(message, node, true).into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembler::testing::{assemble_err, assemble_ok, bytes, warnings},
+        opts::Opts,
+    };
+
+    #[test]
+    fn test_truncated_values() {
+        let text = "
+    org $1000
+    lda #$1ff
+    ldx #$12345
+    fcb 1,-200
+    lda <$1234
+";
+        let asm = assemble_ok(text, Opts::default());
+
+        let expected = [0x86, 0xff, 0x8e, 0x23, 0x45, 0x01, 0x38, 0x96, 0x34];
+        assert_eq!(bytes(&asm, 0x1000, 9), expected);
+        assert_eq!(
+            warnings(&asm),
+            [
+                // Sizing warnings come first
+                "truncated: $1234 truncated to $34 for direct page",
+                "truncated: 511 truncated to $FF",
+                "truncated: 74565 truncated to $2345",
+                "truncated: -200 truncated to $38",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_as_error() {
+        let opts = Opts {
+            warnings_as_errors: true,
+            ..Opts::default()
+        };
+        let errs = assemble_err("    lda #$1ff\n", opts);
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("511 truncated to $FF"), "{errs:?}");
+    }
+
+    const OUT_OF_RANGE: &str = "
+    org $1000
+    beq far
+    bne far
+    bra far
+    zmb 200
+far rts
+";
+
+    const BAD_OFFSETS: &str = "
+    lda <200,x
+    ldb <300,y
+    ldx <400,u
+";
+
+    #[test]
+    fn test_collects_errors() {
+        // Sizing and compiling both carry on past a line in error
+        for text in [OUT_OF_RANGE, BAD_OFFSETS] {
+            let errs = assemble_err(text, Opts::default());
+            assert_eq!(errs.len(), 3, "{errs:?}");
+        }
+
+        let errs = assemble_err(BAD_OFFSETS, Opts::default());
+        assert!(errs[1].contains("Offset 300 does not fit in 8 bits"), "{errs:?}");
+    }
+
+    #[test]
+    fn test_max_errors() {
+        let opts = || Opts {
+            max_errors: 2,
+            ..Opts::default()
+        };
+
+        for text in [OUT_OF_RANGE, BAD_OFFSETS] {
+            let errs = assemble_err(text, opts());
+            assert_eq!(errs.len(), 2, "{errs:?}");
+        }
+    }
 }
@@ -11,6 +11,14 @@ pub enum AccessType {
     ReadOnly,
 }
 
+/// Byte order used when writing words
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Endian {
+    #[default]
+    Big,
+    Little,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct BinRef {
     pub file: PathBuf,
@@ -50,6 +58,13 @@ pub struct Binary {
     bin_refs: Vec<BinRefChunk>,
     unchecked_writes: Vec<MemoryLocation>,
     mismatches: Vec<ReferenceMismatch>,
+    endian: Endian,
+    /// Physical addresses written to so far
+    written: Vec<bool>,
+    /// Writes to memory that had already been written
+    overlaps: Vec<MemoryLocation>,
+    /// Physical ranges that can't be written to
+    protected: Vec<std::ops::Range<usize>>,
 }
 
 impl Default for Binary {
@@ -248,9 +263,26 @@ impl Binary {
             bin_refs: vec![],
             unchecked_writes: vec![],
             mismatches: Default::default(),
+            endian: Default::default(),
+            written: vec![false; size],
+            overlaps: vec![],
+            protected: vec![],
         }
     }
 
+    /// Make writes to this physical range an error
+    pub fn add_protected_range(&mut self, r: std::ops::Range<usize>) {
+        self.protected.push(r)
+    }
+
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian
+    }
+
+    pub fn get_endian(&self) -> Endian {
+        self.endian
+    }
+
     pub fn bump_write_address(&mut self, n: usize) {
         self.write_address += n;
     }
@@ -372,7 +404,9 @@ impl Binary {
 
         let physical = loc.physical;
 
-        if self.access_type == AccessType::ReadOnly {
+        let protected = self.protected.iter().any(|r| r.contains(&physical));
+
+        if self.access_type == AccessType::ReadOnly || protected {
             return Err(BinaryError::IllegalWrite(loc));
         }
 
@@ -401,11 +435,45 @@ impl Binary {
             }
         }
 
+        if self.written[physical] {
+            self.overlaps.push(loc);
+        }
+
+        self.written[physical] = true;
         self.data[physical] = val;
         self.write_address += 1;
         Ok(physical)
     }
 
+    /// Has this physical address been written to
+    pub fn is_written(&self, physical: usize) -> bool {
+        self.written.get(physical).copied().unwrap_or(false)
+    }
+
+    /// Physical ranges of memory that have been written to, in address order
+    pub fn written_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        let mut ret = vec![];
+        let mut start = None;
+
+        for (addr, written) in self.written.iter().chain([&false]).enumerate() {
+            match (start, *written) {
+                (None, true) => start = Some(addr),
+                (Some(s), false) => {
+                    ret.push(s..addr);
+                    start = None
+                }
+                _ => (),
+            }
+        }
+
+        ret
+    }
+
+    /// Take the overlapping writes made since the last call
+    pub fn take_overlaps(&mut self) -> Vec<MemoryLocation> {
+        std::mem::take(&mut self.overlaps)
+    }
+
     pub fn write_byte(&mut self, val: u8) -> Result<WriteStatus, BinaryError> {
         let physical = self.write_byte_internal(val)?;
         self.check_byte(physical, val)?;
@@ -473,13 +541,17 @@ impl Binary {
     }
 
     pub fn write_word(&mut self, val: u16) -> Result<WriteStatus, BinaryError> {
-        // TODO needs to write in correct order for dest processor
-        // rather than hard coded to big endian
         let hi = ( val >> 8 ) as u8;
         let lo = ( val & 0xff ) as u8;
-        let p1 = self.write_byte_internal(hi )?;
-        let p2 = self.write_byte_internal(lo )?;
-        self.check_byte(p1, hi)?;
-        self.check_byte(p2,lo)
+
+        let (b1, b2) = match self.endian {
+            Endian::Big => (hi, lo),
+            Endian::Little => (lo, hi),
+        };
+
+        let p1 = self.write_byte_internal(b1)?;
+        let p2 = self.write_byte_internal(b2)?;
+        self.check_byte(p1, b1)?;
+        self.check_byte(p2, b2)
     }
 }
@@ -1,13 +1,13 @@
 #![forbid(unused_imports)]
 use std::path::Path;
 
-use super::{binary::BinaryError, scopetracker::ScopeTracker, Assembler, };
+use super::{binary::BinaryError, scopetracker::ScopeTracker, Assembler, LstItem};
 
-use crate::cpukind::CpuAssmbler;
+use crate::cpukind::{CpuAssmbler, CpuKind};
 use crate::frontend::AstNodeKind;
 use crate::{
     debug_mess,
-    error::{GResult, GazmErrorKind, UserError},
+    error::{GResult, GazmErrorKind, UserError, WarningKind},
     info_mess,
     semantic::{Ast, AstNodeId, AstNodeRef},
 };
@@ -19,27 +19,46 @@ pub struct Compiler<'a>
 {
     tree: &'a Ast,
     pub scopes: ScopeTracker,
-    pub cpu_asm : CpuAssmbler
+    pub cpu_asm : CpuAssmbler,
+    /// How many macro expansions deep we are
+    macro_depth: usize,
+    /// Section code is being placed in
+    section: Option<usize>,
 }
 
 pub fn compile(asm: &mut Assembler, tree: &Ast) -> GResult<()>
 {
     let root_id = asm.get_symbols().get_root_scope_id();
-    let mut compiler = Compiler::new(tree, root_id)?;
+    let mut compiler = Compiler::new(tree, root_id, asm.opts.cpu)?;
     compiler.compile_root(asm)?;
-    Ok(())
+    asm.asm_out.errors.raise_errors()
 }
 
 impl<'a> Compiler<'a>
 {
-    pub fn new(tree: &'a Ast, current_scope_id: u64) -> GResult<Self> {
+    pub fn new(tree: &'a Ast, current_scope_id: u64, cpu: CpuKind) -> GResult<Self> {
         Ok(Self {
             tree,
             scopes: ScopeTracker::new(current_scope_id),
-            cpu_asm: CpuAssmbler::new(),
+            cpu_asm: CpuAssmbler::new(cpu),
+            macro_depth: 0,
+            section: None,
         })
     }
 
+    /// Offset from logical to physical addresses for the current section
+    fn section_offset(&self, asm: &Assembler) -> isize {
+        self.section
+            .map(|s| asm.asm_out.sections.get(s).write_offset())
+            .unwrap_or(0)
+    }
+
+    /// Switch cpu, words are written in the new cpu's byte order
+    fn set_cpu(&mut self, asm: &mut Assembler, cpu: CpuKind) {
+        self.cpu_asm.set_cpu(cpu);
+        asm.get_binary_mut().set_endian(cpu.endian());
+    }
+
     pub fn compile_root(&mut self, asm: &mut Assembler) -> GResult<()> {
         let scope_id = asm.get_symbols().get_root_scope_id();
         self.scopes.set_scope(scope_id);
@@ -279,11 +298,31 @@ impl<'a> Compiler<'a>
         let node = self.get_node(id);
         let kids: Vec<_> = node.children().map(|n| n.id()).collect();
         for c in kids {
-            self.compile_node_error(asm, c)?;
+            self.compile_line(asm, c)?;
         }
         Ok(())
     }
 
+    /// Compile a line of source, user errors are collected and compiling
+    /// carries on from where the sizer said the line ends
+    fn compile_line(&mut self, asm: &mut Assembler, id: AstNodeId) -> GResult<()> {
+        let scope = self.scopes.scope();
+
+        match self.compile_node_error(asm, id) {
+            Err(e @ GazmErrorKind::UserError(..)) => {
+                asm.asm_out.errors.add_error(e, false)?;
+
+                if let Some(pc) = asm.fixer_upper.get_end_pc(scope, id) {
+                    let offset = asm.get_binary().get_write_offset();
+                    asm.get_binary_mut().set_write_address(pc, offset);
+                }
+
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
     fn add_source_mapping(&self, asm: &mut Assembler, id: AstNodeId, addr: usize) {
         let node = self.get_node(id);
         let _i = node.value().item.clone();
@@ -291,6 +330,76 @@ impl<'a> Compiler<'a>
         let kind: ItemType = ItemType::OpCode;
 
         asm.add_source_mapping(&node.value().pos, addr, kind);
+        self.add_lst_item(asm, id, addr);
+
+        let overlaps = asm.get_binary_mut().take_overlaps();
+
+        if let Some(first) = overlaps.first() {
+            let msg = format!("Overwrites {} bytes already written from {first}", overlaps.len());
+            asm.add_warning(WarningKind::OverlappingWrite, msg, node);
+        }
+    }
+
+    /// Add this node and the memory written since `addr` to the listing
+    fn add_lst_item(&self, asm: &mut Assembler, id: AstNodeId, addr: usize) {
+        use AstNodeKind::*;
+
+        if asm.opts.lst_file.is_none() {
+            return;
+        }
+
+        let node = self.get_node(id);
+        let item = &node.value().item;
+
+        // Files are marked by their start and end instead
+        if matches!(item, TokenizedFile(..)) {
+            return;
+        }
+
+        let show_bytes = matches!(
+            item,
+            TargetSpecific(..)
+                | Fdb(..)
+                | Fcb(..)
+                | Fcc(..)
+                | Zmb
+                | Zmd
+                | Fill
+                | GrabMem
+                | IncBinResolved { .. }
+        );
+
+        // Sizing may have swapped the opcode, a relaxed branch say
+        let fixed = asm.get_fixup_or_default(id, item, self.scopes.scope());
+
+        let (cycles, note) = match (item, &fixed) {
+            (TargetSpecific(orig), TargetSpecific(cs)) => {
+                let text = cs.get_opcode_text();
+                let note = text
+                    .filter(|text| orig.get_opcode_text() != Some(*text))
+                    .map(|text| format!("relaxed to {text}"));
+                (cs.get_cycles(), note)
+            }
+            _ => (None, None),
+        };
+
+        // Taken now, later lines can write over them
+        let bytes = if show_bytes {
+            let (_, phys_range) = asm.get_binary().range_to_write_address(addr);
+            let bytes = asm.get_binary().get_bytes_range(phys_range);
+            bytes.map(|b| b.to_vec()).unwrap_or_default()
+        } else {
+            vec![]
+        };
+
+        asm.asm_out.lst_file.add(LstItem::Source {
+            pos: node.value().pos,
+            addr,
+            bytes,
+            cycles,
+            in_macro: self.macro_depth > 0,
+            note,
+        });
     }
 
     fn compile_node_error(&mut self, asm: &mut Assembler, id: AstNodeId) -> GResult<()> {
@@ -325,12 +434,22 @@ impl<'a> Compiler<'a>
             }
 
             SetPc(new_pc) => {
-                asm.get_binary_mut().set_write_address(new_pc, 0);
+                let offset = self.section_offset(asm);
+                asm.get_binary_mut().set_write_address(new_pc, offset);
 
                 pc = new_pc;
                 debug_mess!("Set PC to {:02X}", pc);
             }
 
+            SectionResolved { section, pc: new_pc } => {
+                self.section = Some(section);
+                let offset = self.section_offset(asm);
+                asm.get_binary_mut().set_write_address(new_pc, offset);
+
+                pc = new_pc;
+                debug_mess!("Section {section} at {:02X}", pc);
+            }
+
             SetPutOffset(offset) => {
                 debug_mess!("Set put offset to {}", offset);
                 asm.get_binary_mut().set_write_offset(offset);
@@ -355,30 +474,47 @@ impl<'a> Compiler<'a>
                 }
 
                 self.scopes.push(scope_id);
+                self.add_lst_item(asm, node_id, pc);
+                self.macro_depth += 1;
 
                 {
                     let m_node = self.get_node(macro_id);
                     let kids: Vec<_> = m_node.children().map(|n| n.id()).collect();
 
                     for c_node in kids {
-                        self.compile_node_error(asm, c_node)?;
+                        self.compile_line(asm, c_node)?;
                     }
                 }
 
+                self.macro_depth -= 1;
                 self.scopes.pop();
             }
 
-            TokenizedFile(..) => {
-                self.compile_children(asm, id)?;
+            TokenizedFile(file, _) => {
+                // Every file starts off targetting the project's cpu
+                let cpu = self.cpu_asm.get_cpu();
+                let project_cpu = asm.opts.cpu;
+                self.set_cpu(asm, project_cpu);
+
+                if asm.opts.lst_file.is_some() {
+                    asm.asm_out.lst_file.add(LstItem::FileStart(file.clone()));
+                    self.compile_children(asm, id)?;
+                    asm.asm_out.lst_file.add(LstItem::FileEnd(file));
+                } else {
+                    self.compile_children(asm, id)?;
+                }
+
+                self.set_cpu(asm, cpu);
             }
 
+            Cpu(cpu) => self.set_cpu(asm, cpu),
+
             Fdb(..) => {
                 let node = self.get_node(node_id);
 
                 for n in node.children() {
                     let x = asm.eval_node(n, current_scope_id)?;
-                    let e = asm.get_binary_mut().write_word_check_size(x);
-                    self.binary_error_map(asm, id, e)?;
+                    asm.write_word_check_size(x, n)?;
                 }
 
                 let (phys_range, range) = asm.get_binary().range_to_write_address(pc);
@@ -389,8 +525,7 @@ impl<'a> Compiler<'a>
                 let node = self.get_node(node_id);
                 for n in node.children() {
                     let x = asm.eval_node(n, current_scope_id)?;
-                    let e = asm.get_binary_mut().write_byte_check_size(x);
-                    self.binary_error_map(asm, id, e)?;
+                    asm.write_byte_check_size(x, n)?;
                 }
                 let (phys_range, range) = asm.get_binary().range_to_write_address(pc);
                 self.add_mapping(asm, phys_range, range, id, ItemType::Command);
@@ -450,12 +585,10 @@ impl<'a> Compiler<'a>
             IncBin(..) | Org | AssignmentFromPc(..) | Assignment(..) | Comment(..) | Rmb
             | StructDef(..) | MacroDef(..) | MacroCall(..) | Import => (),
 
-            TargetSpecific(_node_kind) => {
+            TargetSpecific(node_kind) => {
                 let node = self.get_node(id);
-
-                asm.compile_node(node, _node_kind, current_scope_id)?;
-                todo!()
-                // C::compile_node(self, asm, id, node_kind)?;
+                let cpu_asm = self.cpu_asm.asm_for(&node_kind);
+                cpu_asm.compile_node(asm, node, node_kind, current_scope_id)?;
             }
 
             _ => {
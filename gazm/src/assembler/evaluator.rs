@@ -4,7 +4,7 @@ use crate::{
     error::{GResult, UserError},
     frontend::AstNodeKind::*,
     gazmsymbols::SymbolInfo,
-    semantic::{eval, Ast, AstNodeId, AstNodeRef, EvalErrorEnum},
+    semantic::{eval_with_banks, Ast, AstNodeId, AstNodeRef, EvalErrorEnum},
 };
 
 use super::Assembler;
@@ -76,8 +76,9 @@ impl Assembler {
     pub fn eval_node(&self, node: AstNodeRef, current_scope_id: u64) -> GResult<i64> {
         let info = self.get_source_info(&node.value().pos).unwrap();
         let reader = self.asm_out.symbols.get_reader(current_scope_id);
+        let banks = &self.asm_out.symbol_banks;
 
-        eval(&reader, node).map_err(|err| {
+        eval_with_banks(&reader, banks, node).map_err(|err| {
             let e = match &err.source {
                 EvalErrorEnum::SymbolNotFoud(name) => {
                     let scope = self.get_symbols().get_fqn_from_id(current_scope_id);
@@ -103,6 +104,20 @@ impl Assembler {
         Ok((v, c))
     }
 
+    pub fn eval_nth_arg_n<'a>(
+        &self,
+        node: AstNodeRef<'a>,
+        n: usize,
+        current_scope_id: u64,
+    ) -> GResult<(i64, AstNodeRef<'a>)> {
+        let c = node
+            .children()
+            .nth(n)
+            .ok_or_else(|| self.make_user_error("Missing argument", node, true))?;
+        let v = self.eval_node(c, current_scope_id)?;
+        Ok((v, c))
+    }
+
     pub fn eval_first_arg(
         &self,
         node: AstNodeRef,
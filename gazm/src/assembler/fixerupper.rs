@@ -14,6 +14,8 @@ pub struct FixKey {
 pub struct FixerUpper
 {
     pub fixups: HashMap<FixKey, AstNodeKind>,
+    /// PC after each sized node, used to carry on compiling past a node in error
+    pub end_pcs: HashMap<FixKey, usize>,
 }
 
 impl FixerUpper
@@ -30,6 +32,14 @@ impl FixerUpper
         self.fixups.get(&FixKey { scope, id })
     }
 
+    pub fn set_end_pc(&mut self, scope: u64, id: AstNodeId, pc: usize) {
+        self.end_pcs.insert(FixKey { scope, id }, pc);
+    }
+
+    pub fn get_end_pc(&self, scope: u64, id: AstNodeId) -> Option<usize> {
+        self.end_pcs.get(&FixKey { scope, id }).copied()
+    }
+
     pub fn get_fixup_or_default(
         &self,
         scope: u64,
@@ -0,0 +1,250 @@
+#![forbid(unused_imports)]
+//! Intel HEX and Motorola S-record images of the written parts of a binary
+use super::Binary;
+use crate::error::{GResult, GazmErrorKind};
+use serde::Deserialize;
+
+/// Data bytes per record
+const BYTES_PER_RECORD: usize = 16;
+
+/// S-record flavour, named by the width of its addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SRecKind {
+    /// 16 bit addresses, S1 / S9 records
+    S19,
+    /// 24 bit addresses, S2 / S8 records
+    S28,
+    /// 32 bit addresses, S3 / S7 records
+    S37,
+}
+
+impl SRecKind {
+    /// The narrowest kind that can hold this address
+    pub fn for_addr(addr: usize) -> Self {
+        match addr {
+            0..=0xffff => Self::S19,
+            0x1_0000..=0xff_ffff => Self::S28,
+            _ => Self::S37,
+        }
+    }
+
+    fn addr_bytes(&self) -> usize {
+        match self {
+            Self::S19 => 2,
+            Self::S28 => 3,
+            Self::S37 => 4,
+        }
+    }
+
+    fn max_addr(&self) -> usize {
+        (1 << (self.addr_bytes() * 8)) - 1
+    }
+
+    /// Record types for data and the start address
+    fn record_types(&self) -> (u8, u8) {
+        match self {
+            Self::S19 => (1, 9),
+            Self::S28 => (2, 8),
+            Self::S37 => (3, 7),
+        }
+    }
+}
+
+/// Chunks of written memory no bigger than a record, with their addresses
+fn records(binary: &Binary) -> Vec<(usize, &[u8])> {
+    let mut ret = vec![];
+
+    for r in binary.written_ranges() {
+        let data = &binary.data[r.clone()];
+        let chunks = data.chunks(BYTES_PER_RECORD).enumerate();
+        ret.extend(chunks.map(|(i, chunk)| (r.start + i * BYTES_PER_RECORD, chunk)));
+    }
+
+    ret
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+fn intel_record(addr: u16, kind: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(addr.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+
+    let sum = bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+    bytes.push(sum.wrapping_neg());
+
+    format!(":{}", to_hex(&bytes))
+}
+
+/// An Intel HEX image
+/// Memory above 64K gets extended linear address records,
+/// the exec address is a start linear address record
+pub fn to_intel_hex(binary: &Binary, exec_addr: Option<usize>) -> String {
+    let mut ret = vec![];
+    let mut upper = 0;
+
+    for (addr, data) in records(binary) {
+        // Records can't cross a 64K boundary
+        let split = (0x1_0000 - (addr & 0xffff)).min(data.len());
+
+        for (addr, data) in [(addr, &data[..split]), (addr + split, &data[split..])] {
+            if data.is_empty() {
+                continue;
+            }
+
+            if addr >> 16 != upper {
+                upper = addr >> 16;
+                ret.push(intel_record(0, 4, &(upper as u16).to_be_bytes()));
+            }
+
+            ret.push(intel_record(addr as u16, 0, data));
+        }
+    }
+
+    if let Some(exec) = exec_addr {
+        ret.push(intel_record(0, 5, &(exec as u32).to_be_bytes()));
+    }
+
+    ret.push(intel_record(0, 1, &[]));
+
+    let mut text = ret.join("\n");
+    text.push('\n');
+    text
+}
+
+fn srec_record(kind: u8, addr: usize, addr_bytes: usize, data: &[u8]) -> String {
+    let addr = &(addr as u32).to_be_bytes()[4 - addr_bytes..];
+
+    let mut bytes = vec![(addr_bytes + data.len() + 1) as u8];
+    bytes.extend(addr);
+    bytes.extend(data);
+
+    let sum = bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+    bytes.push(!sum);
+
+    format!("S{kind}{}", to_hex(&bytes))
+}
+
+/// A Motorola S-record image
+/// `kind` defaults to the narrowest that fits the highest address written,
+/// the exec address, or zero, goes in the termination record
+/// An address too big for an explicit `kind` is an error
+pub fn to_srec(
+    binary: &Binary,
+    kind: Option<SRecKind>,
+    exec_addr: Option<usize>,
+) -> GResult<String> {
+    let records = records(binary);
+    let top = records.last().map(|(a, d)| a + d.len() - 1).unwrap_or(0);
+    let top = top.max(exec_addr.unwrap_or(0));
+
+    let kind = kind.unwrap_or_else(|| SRecKind::for_addr(top));
+
+    if top > kind.max_addr() {
+        let msg = format!("${top:X} is too big for {kind:?} S-records");
+        return Err(GazmErrorKind::Misc(msg));
+    }
+
+    let addr_bytes = kind.addr_bytes();
+    let (data_type, start_type) = kind.record_types();
+
+    let mut ret = vec![srec_record(0, 0, 2, b"gazm")];
+
+    for (addr, data) in &records {
+        ret.push(srec_record(data_type, *addr, addr_bytes, data));
+    }
+
+    // Record count, S6 if it needs 24 bits, left out if it needs more
+    match records.len() {
+        count @ 0..=0xffff => ret.push(srec_record(5, count, 2, &[])),
+        count @ 0x1_0000..=0xff_ffff => ret.push(srec_record(6, count, 3, &[])),
+        _ => (),
+    }
+
+    ret.push(srec_record(start_type, exec_addr.unwrap_or(0), addr_bytes, &[]));
+
+    let mut text = ret.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::AccessType;
+
+    #[test]
+    fn test_hex_formats() {
+        let mut binary = Binary::new(0x10000, AccessType::ReadWrite);
+        binary.set_write_address(0x1000, 0);
+        binary.write_bytes(&[0x86, 0x01, 0x39]).unwrap();
+
+        let hex = to_intel_hex(&binary, Some(0x1000));
+        let desired = ":031000008601392D\n:0400000500001000E7\n:00000001FF\n";
+        assert_eq!(hex, desired);
+
+        let srec = to_srec(&binary, None, Some(0x1000)).unwrap();
+        let desired = "S007000067617A6D49\nS106100086013929\nS5030001FB\nS9031000EC\n";
+        assert_eq!(srec, desired);
+    }
+
+    #[test]
+    fn test_multiple_regions() {
+        let mut binary = Binary::new(0x10000, AccessType::ReadWrite);
+        binary.set_write_address(0x1000, 0);
+        binary.write_bytes(&[0x01, 0x02]).unwrap();
+        binary.set_write_address(0x2000, 0);
+        binary.write_bytes(&[0x03]).unwrap();
+
+        let hex = to_intel_hex(&binary, None);
+        let desired = ":021000000102EB\n:0120000003DC\n:00000001FF\n";
+        assert_eq!(hex, desired);
+
+        let srec = to_srec(&binary, None, None).unwrap();
+        let desired = "S007000067617A6D49\nS10510000102E7\nS104200003D8\nS5030002FA\nS9030000FC\n";
+        assert_eq!(srec, desired);
+    }
+
+    #[test]
+    fn test_intel_extended_address() {
+        let mut binary = Binary::new(0x20000, AccessType::ReadWrite);
+        binary.set_write_address(0xfffe, 0);
+        binary.write_bytes(&[0x01, 0x02, 0x03, 0x04]).unwrap();
+
+        // The record is split at 64K and the upper address set before the second half
+        let hex = to_intel_hex(&binary, None);
+        let desired = ":02FFFE000102FE\n:020000040001F9\n:020000000304F7\n:00000001FF\n";
+        assert_eq!(hex, desired);
+    }
+
+    #[test]
+    fn test_srec_address_too_big() {
+        let mut binary = Binary::new(0x20000, AccessType::ReadWrite);
+        binary.set_write_address(0x10000, 0);
+        binary.write_bytes(&[0x01]).unwrap();
+
+        assert!(to_srec(&binary, Some(SRecKind::S19), None).is_err());
+        let srec = to_srec(&binary, None, None).unwrap();
+        assert!(srec.lines().nth(1).unwrap().starts_with("S205010000"));
+
+        let binary = Binary::new(0x20000, AccessType::ReadWrite);
+        assert!(to_srec(&binary, Some(SRecKind::S19), Some(0x12345)).is_err());
+        assert!(to_srec(&binary, Some(SRecKind::S28), Some(0x12345)).is_ok());
+    }
+
+    #[test]
+    fn test_srec_count_over_64k() {
+        let size = 0x1_0001 * BYTES_PER_RECORD;
+        let mut binary = Binary::new(size, AccessType::ReadWrite);
+        binary.set_write_address(0, 0);
+        binary.write_bytes(&vec![0; size]).unwrap();
+
+        let srec = to_srec(&binary, None, None).unwrap();
+        assert_eq!(srec.lines().rev().nth(1), Some("S604010001F9"));
+    }
+}
+
@@ -6,6 +6,8 @@ mod sizer;
 mod compile;
 mod bytesizes;
 mod binary;
+mod hexformats;
+mod romimage;
 mod scopes;
 mod scopetracker;
 mod asm;
@@ -13,6 +15,9 @@ mod traits;
 
 pub  mod fixerupper;
 
+#[cfg(test)]
+pub mod testing;
+
 pub use asm::*;
 pub use edit::*;
 pub use writers::*;
@@ -21,6 +26,8 @@ pub use sizer::*;
 pub use compile::*;
 pub use bytesizes::*;
 pub use binary::*;
+pub use hexformats::*;
+pub use romimage::*;
 pub use scopes::*;
 pub use scopetracker::*;
 pub use traits::*;
@@ -0,0 +1,107 @@
+#![forbid(unused_imports)]
+//! ROM images described by `[[output]]` tables in `gazm.toml`
+use super::Binary;
+use crate::{
+    error::{GResult, GazmErrorKind},
+    opts::{Interleave, OutputImage},
+};
+
+/// Build the image for an `[[output]]` table
+/// Unwritten memory is the fill byte, then bytes are swapped,
+/// interleaved and padded in that order
+/// Only an even sized image can be byte swapped
+pub fn make_rom_image(binary: &Binary, out: &OutputImage) -> GResult<Vec<u8>> {
+    let file = out.file.to_string_lossy();
+    let r = out.addr..out.addr + out.size;
+
+    if r.end > binary.data.len() {
+        let msg = format!("{file}: ${:X}-${:X} is outside of memory", r.start, r.end - 1);
+        return Err(GazmErrorKind::Misc(msg));
+    }
+
+    let mut image: Vec<u8> = r
+        .map(|a| if binary.is_written(a) { binary.data[a] } else { out.fill })
+        .collect();
+
+    if out.byte_swap {
+        if image.len() % 2 != 0 {
+            let msg = format!("{file}: can't byte swap an odd size of ${:X}", image.len());
+            return Err(GazmErrorKind::Misc(msg));
+        }
+
+        for word in image.chunks_exact_mut(2) {
+            word.swap(0, 1)
+        }
+    }
+
+    if let Some(lane) = out.interleave {
+        let skip = if lane == Interleave::Even { 0 } else { 1 };
+        image = image.into_iter().skip(skip).step_by(2).collect();
+    }
+
+    if let Some(pad_to) = out.pad_to {
+        if image.len() > pad_to {
+            let msg = format!("{file}: image is ${:X} bytes, bigger than ${pad_to:X}", image.len());
+            return Err(GazmErrorKind::Misc(msg));
+        }
+        image.resize(pad_to, out.fill);
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::AccessType;
+
+    #[test]
+    fn test_rom_image() {
+        let mut binary = Binary::new(0x100, AccessType::ReadWrite);
+        binary.set_write_address(0x10, 0);
+        binary.write_bytes(&[1, 2, 3, 4]).unwrap();
+
+        let mut out = OutputImage {
+            file: "rom.bin".into(),
+            addr: 0x10,
+            size: 6,
+            fill: 0xff,
+            pad_to: None,
+            byte_swap: false,
+            interleave: None,
+        };
+
+        assert_eq!(make_rom_image(&binary, &out).unwrap(), [1, 2, 3, 4, 0xff, 0xff]);
+
+        out.byte_swap = true;
+        out.interleave = Some(Interleave::Odd);
+        out.pad_to = Some(4);
+        assert_eq!(make_rom_image(&binary, &out).unwrap(), [1, 3, 0xff, 0xff]);
+
+        out.pad_to = Some(2);
+        assert!(make_rom_image(&binary, &out).is_err());
+    }
+
+    #[test]
+    fn test_byte_swap() {
+        let mut binary = Binary::new(0x100, AccessType::ReadWrite);
+        binary.set_write_address(0x10, 0);
+        binary.write_bytes(&[1, 2, 3, 4]).unwrap();
+
+        let mut out = OutputImage {
+            file: "rom.bin".into(),
+            addr: 0x10,
+            size: 4,
+            fill: 0xff,
+            pad_to: None,
+            byte_swap: true,
+            interleave: None,
+        };
+
+        assert_eq!(make_rom_image(&binary, &out).unwrap(), [2, 1, 4, 3]);
+
+        // The last byte would be left where it is
+        out.size = 3;
+        assert!(make_rom_image(&binary, &out).is_err());
+    }
+}
@@ -1,13 +1,18 @@
 #![forbid(unused_imports)]
 
-use super::{scopetracker::ScopeTracker, Assembler};
+use std::collections::HashSet;
+
+use super::{fixerupper::FixerUpper, scopetracker::ScopeTracker, Assembler};
 
 /// Take the AST and work out the sizes of everything
 /// Resolve labels where we can
 use crate::{
+    cpukind::CpuAssmbler,
     debug_mess,
-    error::GResult,
+    error::{GResult, GazmErrorKind, WarningKind},
     frontend::{AstNodeKind, LabelDefinition},
+    gazmsymbols::SymbolScopeId,
+    sections::SectionError,
     semantic::{Ast, AstNodeId, AstNodeRef},
 };
 
@@ -21,35 +26,185 @@ pub struct Sizer<'a> {
     pub tree: &'a Ast,
     pub scopes: ScopeTracker,
     pub pc: usize,
+    pub cpu_asm: CpuAssmbler,
+    /// Label values assigned from the PC this pass, in tree order
+    pub label_values: Vec<(AstNodeId, i64)>,
+    /// Number of args that couldn't be evaluated this pass
+    pub unresolved: usize,
+    /// Evaluation failures are errors on the final pass
+    pub final_pass: bool,
+    /// Relax short branches that can't reach, from the options until a `relax`
+    pub relax_branches: bool,
+    /// Short branches that have been relaxed to long ones
+    /// Kept between passes, a branch never goes back to short
+    pub long_branches: HashSet<(u64, AstNodeId)>,
+    /// Warnings from this pass, only the last pass's are kept
+    pub warnings: Vec<(WarningKind, String, AstNodeId)>,
+    /// Errors in lines sized this pass, sizing carries on past them
+    pub errors: Vec<GazmErrorKind>,
+    /// Section code is being placed in
+    pub section: Option<usize>,
+    /// Where each section's PC had got to
+    pub section_pcs: Vec<usize>,
+    /// Sections reported as overflowing this pass
+    overflowed: HashSet<usize>,
 }
 
+/// Most sizing passes made before giving up on label values settling
+const MAX_PASSES: usize = 16;
+
+/// Size of the address space the PC can move around in
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// Size the tree until label values stop changing
+/// Forward references can't be evaluated on the first pass so get
+/// the long form, later passes use the label values from the pass
+/// before and shrink instructions where they can
 pub fn size(asm: &mut Assembler, ast_tree: &Ast) -> GResult<()> {
-    let _ = Sizer::try_new(ast_tree, asm)?;
+    let mut sizer = Sizer::new(ast_tree, asm);
+    let mut last = None;
+
+    for _ in 0..MAX_PASSES {
+        sizer.size_pass(asm)?;
+
+        if last.as_ref() == Some(&sizer.label_values) {
+            if sizer.unresolved > 0 {
+                // Nothing is going to change now so anything
+                // still unresolved is an error
+                sizer.final_pass = true;
+                sizer.size_pass(asm)?;
+            }
+
+            for (kind, msg, id) in std::mem::take(&mut sizer.warnings) {
+                asm.add_warning(kind, msg, sizer.get_node(id));
+            }
+
+            return sizer.raise_errors(asm);
+        }
+
+        last = Some(sizer.label_values.clone());
+    }
+
+    // One more pass to find a label that is still moving
+    sizer.size_pass(asm)?;
+
+    // Labels after a line in error may never settle, report the line instead
+    if !sizer.errors.is_empty() {
+        return sizer.raise_errors(asm);
+    }
+
+    Err(not_settled_error(asm, &sizer, last.unwrap_or_default()))
+}
+
+/// Error for a directive arg that can't be negative
+fn check_positive(asm: &Assembler, v: i64, msg: &str, node: AstNodeRef) -> GResult<()> {
+    if v < 0 {
+        return Err(asm.make_user_error(msg, node, true).into());
+    }
+
     Ok(())
 }
 
+/// Error for label values that are still moving after `MAX_PASSES`
+fn not_settled_error(asm: &Assembler, sizer: &Sizer, last: Vec<(AstNodeId, i64)>) -> GazmErrorKind {
+    let msg = format!("Label values did not settle after {MAX_PASSES} sizing passes");
+
+    let moved = last
+        .into_iter()
+        .zip(sizer.label_values.iter())
+        .find(|(a, b)| a != *b);
+
+    match moved {
+        Some(((id, a), (_, b))) => {
+            let msg = format!("{msg}, oscillating between ${a:04X} and ${b:04X}");
+            asm.make_user_error(msg, sizer.get_node(id), true).into()
+        }
+        None => GazmErrorKind::Misc(msg),
+    }
+}
+
 impl<'a> Sizer<'a> {
-    pub fn try_new(tree: &'a Ast, asm: &mut Assembler) -> GResult<Sizer<'a>> {
+    pub fn new(tree: &'a Ast, asm: &Assembler) -> Sizer<'a> {
+        let root_id = asm.get_symbols().get_root_scope_id();
+
+        Self {
+            tree,
+            scopes: ScopeTracker::new(root_id),
+            pc: 0,
+            cpu_asm: CpuAssmbler::new(asm.opts.cpu),
+            label_values: vec![],
+            unresolved: 0,
+            final_pass: false,
+            relax_branches: asm.opts.relax_branches,
+            long_branches: Default::default(),
+            warnings: vec![],
+            errors: vec![],
+            section: None,
+            section_pcs: vec![],
+            overflowed: Default::default(),
+        }
+    }
+
+    /// Hand any errors from the last pass to the assembler
+    /// Nothing can be compiled if there are any
+    fn raise_errors(&mut self, asm: &mut Assembler) -> GResult<()> {
+        let errors = &mut asm.asm_out.errors;
+
+        for e in std::mem::take(&mut self.errors) {
+            errors.add_error(e, false)?;
+        }
+
+        errors.raise_errors()
+    }
+
+    /// Size the whole tree
+    /// Decisions made by any earlier pass, apart from relaxed branches, are thrown away
+    pub fn size_pass(&mut self, asm: &mut Assembler) -> GResult<()> {
         let pc = 0;
 
         asm.set_pc_symbol(pc).expect("Can't set PC symbol");
+        asm.fixer_upper = FixerUpper::new();
+        asm.asm_out.reset_dp();
+        self.relax_branches = asm.opts.relax_branches;
 
         let root_id = asm.get_symbols().get_root_scope_id();
 
-        let mut ret = Self {
-            tree,
-            scopes: ScopeTracker::new(root_id),
-            pc,
-        };
+        self.scopes = ScopeTracker::new(root_id);
+        self.pc = pc;
+        self.cpu_asm = CpuAssmbler::new(asm.opts.cpu);
+        self.label_values.clear();
+        self.unresolved = 0;
+        self.warnings.clear();
+        self.errors.clear();
+        self.section = None;
+        self.section_pcs = asm.asm_out.sections.iter().map(|s| s.logical_range.start).collect();
+        self.overflowed.clear();
+
+        let id = self.tree.as_ref().root().id();
+        self.size_node(asm, id)
+    }
+
+    /// Evaluate the first arg of a node
+    /// Before the final pass a failure, most likely a forward reference,
+    /// is counted as unresolved and returns None
+    pub fn try_eval_first_arg(&mut self, asm: &Assembler, id: AstNodeId) -> GResult<Option<i64>> {
+        let node = self.get_node(id);
 
-        let id = ret.tree.as_ref().root().id();
-        ret.size_node(asm, id)?;
+        match asm.eval_first_arg(node, self.scopes.scope()) {
+            Ok((value, _)) => Ok(Some(value)),
+            Err(e) if self.final_pass => Err(e),
+            Err(_) => {
+                self.unresolved += 1;
+                Ok(None)
+            }
+        }
+    }
 
-        Ok(ret)
+    pub fn add_warning<S: Into<String>>(&mut self, kind: WarningKind, msg: S, id: AstNodeId) {
+        self.warnings.push((kind, msg.into(), id))
     }
 
     pub fn advance_pc(&mut self, val: usize) {
-        assert!(self.pc < 65536);
         self.pc += val;
     }
 
@@ -59,7 +214,18 @@ impl<'a> Sizer<'a> {
 
     pub fn set_pc(&mut self, val: usize) {
         self.pc = val;
-        assert!(self.pc < 65536);
+    }
+
+    /// Size a line of source, user errors are kept and sizing carries on
+    /// with the PC where it had got to
+    fn size_line(&mut self, asm: &mut Assembler, id: AstNodeId) -> GResult<()> {
+        match self.size_node(asm, id) {
+            Err(e @ GazmErrorKind::UserError(..)) => {
+                self.errors.push(e);
+                Ok(())
+            }
+            res => res,
+        }
     }
 
     fn size_node(&mut self, asm: &mut Assembler, id: AstNodeId) -> GResult<()> {
@@ -68,6 +234,7 @@ impl<'a> Sizer<'a> {
         let node = self.get_node(id);
         let i = &node.value().item.clone();
         let current_scope_id = self.scopes.scope();
+        let start_pc = self.get_pc();
 
         asm.set_pc_symbol(self.get_pc())
             .expect("Can't set PC symbol value");
@@ -83,7 +250,7 @@ impl<'a> Sizer<'a> {
                 let m_node = self.get_node(*macro_id);
                 let kids: Vec<_> = m_node.children().map(|n| n.id()).collect();
                 for c in kids {
-                    self.size_node(asm, c)?;
+                    self.size_line(asm, c)?;
                 }
 
                 self.scopes.pop();
@@ -99,10 +266,29 @@ impl<'a> Sizer<'a> {
 
             Org => {
                 let pc = asm.eval_first_arg(node, current_scope_id)?.0 as usize;
+                self.check_org(asm, id, pc)?;
                 asm.add_fixup(id, AstNodeKind::SetPc(pc), current_scope_id);
                 self.set_pc(pc);
             }
 
+            Section(name) => {
+                let section = asm
+                    .asm_out
+                    .sections
+                    .get_section_id(name)
+                    .map_err(|e| asm.make_user_error(e.to_string(), node, true))?;
+
+                if let Some(current) = self.section {
+                    self.section_pcs[current] = self.get_pc();
+                }
+
+                let pc = self.section_pcs[section];
+                let item = AstNodeKind::SectionResolved { section, pc };
+                asm.add_fixup(id, item, current_scope_id);
+                self.section = Some(section);
+                self.set_pc(pc);
+            }
+
             SetPc(val) => {
                 self.set_pc(*val);
             }
@@ -115,20 +301,17 @@ impl<'a> Sizer<'a> {
 
             Rmb => {
                 let (bytes, _) = asm.eval_first_arg(node, current_scope_id)?;
-
-                if bytes < 0 {
-                    return Err(asm
-                        .make_user_error("Argument for RMB must be positive", node, true)
-                        .into());
-                };
-
+                check_positive(asm, bytes, "Argument for RMB must be positive", node)?;
                 asm.add_fixup(id, AstNodeKind::Skip(bytes as usize), current_scope_id);
                 self.advance_pc(bytes as usize);
             }
 
-            TargetSpecific(_i) => {
-                // C::size_node(self, asm, id, i.clone())?;
-                todo!()
+            Cpu(cpu) => self.cpu_asm.set_cpu(*cpu),
+
+            TargetSpecific(node_kind) => {
+                let cpu_asm = self.cpu_asm.asm_for(node_kind);
+                cpu_asm.size_node(self, asm, id, node_kind.clone(), current_scope_id)?;
+                self.check_cross_bank(asm, id);
             }
 
             AssignmentFromPc(LabelDefinition::Scoped(symbol_id)) => {
@@ -147,12 +330,23 @@ impl<'a> Sizer<'a> {
                 debug_mess!("Assigning {} = ${:04x}", sym.name(), pcv);
 
                 asm.set_symbol_value(*symbol_id, pcv as usize).unwrap();
+                self.label_values.push((id, pcv));
+
+                if node.first_child().is_none() {
+                    self.set_symbol_bank(asm, *symbol_id);
+                }
             }
 
             TokenizedFile(..) => {
+                // Every file starts off targetting the project's cpu
+                let cpu = self.cpu_asm.get_cpu();
+                self.cpu_asm.set_cpu(asm.opts.cpu);
+
                 for c in asm.get_node_children(node) {
-                    self.size_node(asm, c)?;
+                    self.size_line(asm, c)?;
                 }
+
+                self.cpu_asm.set_cpu(cpu);
             }
 
             Fdb(num_of_words) => self.advance_pc(*num_of_words * 2),
@@ -167,19 +361,19 @@ impl<'a> Sizer<'a> {
 
             Zmb => {
                 let (v, _) = asm.eval_first_arg(node, current_scope_id)?;
-                assert!(v >= 0);
+                check_positive(asm, v, "Argument for ZMB must be positive", node)?;
                 self.advance_pc(v as usize)
             }
 
             Zmd => {
                 let (v, _) = asm.eval_first_arg(node, current_scope_id)?;
-                assert!(v >= 0);
-                self.advance_pc((v * 2) as usize)
+                check_positive(asm, v, "Argument for ZMD must be positive", node)?;
+                self.advance_pc(v as usize * 2)
             }
 
             Fill => {
                 let (size, _val) = asm.eval_two_args(node, current_scope_id)?;
-                assert!(size >= 0);
+                check_positive(asm, size, "Fill size must be positive", node)?;
                 self.advance_pc(size as usize);
             }
 
@@ -203,10 +397,239 @@ impl<'a> Sizer<'a> {
             }
         };
 
+        self.check_address_space(asm, id, start_pc)?;
+        asm.fixer_upper.set_end_pc(current_scope_id, id, self.get_pc());
+
+        self.check_section_overflow(asm, id)
+    }
+
+    /// Error if a node takes the PC past the end of the address space
+    /// The PC goes back to where it was before the node so sizing can carry on
+    fn check_address_space(
+        &mut self,
+        asm: &Assembler,
+        id: AstNodeId,
+        start_pc: usize,
+    ) -> GResult<()> {
+        if self.pc > ADDRESS_SPACE {
+            let msg = format!("PC ${:X} is past the end of the 64K address space", self.pc);
+            self.pc = start_pc;
+            return Err(asm.make_user_error(msg, self.get_node(id), true).into());
+        }
+
         Ok(())
     }
 
-    pub fn get_node(&self, id: AstNodeId) -> AstNodeRef {
+    /// An org has to stay inside the current section
+    fn check_org(&self, asm: &Assembler, id: AstNodeId, pc: usize) -> GResult<()> {
+        if let Some(section) = self.section {
+            let s = asm.asm_out.sections.get(section);
+
+            if !s.logical_range.contains(&pc) {
+                let e = SectionError::OrgOutsideSectionBounds {
+                    name: s.name.clone(),
+                    bounds: s.logical_range.clone(),
+                    org: pc,
+                };
+                return Err(asm.make_user_error(e.to_string(), self.get_node(id), true).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Error the first time a pass goes past the end of the current section
+    fn check_section_overflow(&mut self, asm: &Assembler, id: AstNodeId) -> GResult<()> {
+        if let Some(section) = self.section {
+            let s = asm.asm_out.sections.get(section);
+            let end = s.logical_range.end;
+
+            if self.pc > end && self.overflowed.insert(section) {
+                let e = SectionError::Overflow(s.clone(), self.pc - end);
+                return Err(asm.make_user_error(e.to_string(), self.get_node(id), true).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bank of the section code is being placed in
+    fn bank(&self, asm: &Assembler) -> Option<usize> {
+        self.section.and_then(|s| asm.asm_out.sections.get(s).bank)
+    }
+
+    /// A label set from the PC is in the bank of the current section
+    fn set_symbol_bank(&self, asm: &mut Assembler, symbol_id: SymbolScopeId) {
+        match self.bank(asm) {
+            Some(bank) => asm.asm_out.symbol_banks.insert(symbol_id, bank),
+            None => asm.asm_out.symbol_banks.remove(&symbol_id),
+        };
+    }
+
+    /// Warn about labels an instruction uses that are in a different bank,
+    /// or in any bank if the instruction is in common memory
+    /// Taking `bank(label)` of a label in another bank is fine
+    fn check_cross_bank(&mut self, asm: &Assembler, id: AstNodeId) {
+        let bank = self.bank(asm);
+
+        for n in self.get_node(id).descendants() {
+            let in_bank_fn = n.parent().map(|p| matches!(p.value().item, AstNodeKind::Bank));
+
+            if let AstNodeKind::Label(LabelDefinition::Scoped(sym)) = &n.value().item {
+                match asm.asm_out.symbol_banks.get(sym) {
+                    Some(other) if Some(*other) != bank && in_bank_fn != Some(true) => {
+                        let name = asm.get_symbols().get_symbol_info_from_id(*sym).unwrap().name();
+                        let msg = match bank {
+                            Some(bank) => format!("{name} is in bank {other}, not bank {bank}"),
+                            None => format!("{name} is in bank {other}, not common memory"),
+                        };
+                        self.add_warning(WarningKind::CrossBank, msg, id);
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    pub fn get_node(&self, id: AstNodeId) -> AstNodeRef<'a> {
         self.tree.as_ref().get(id).expect("Can't fetch node")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::MAX_PASSES;
+    use crate::{
+        assembler::{
+            testing::{assemble_err, assemble_ok, bytes, cpu_opts, symbol, warnings},
+            AccessType,
+        },
+        cpukind::CpuKind,
+        opts::Opts,
+        sections::SerializedSection,
+    };
+
+    #[test]
+    fn test_forward_ref_shrinks() {
+        // setdp and lda use labels defined later so lda is extended on the first pass,
+        // the next pass finds data in the direct page and moves it down a byte
+        let text = "
+    org $1000
+    setdp page
+    lda data
+    lda far
+    rts
+data fcb 1
+page equ $10
+far equ $2000
+";
+        let asm = assemble_ok(text, Opts::default());
+
+        assert_eq!(symbol(&asm, "data"), Some(0x1006));
+        let desired = [0x96, 0x06, 0xb6, 0x20, 0x00, 0x39, 0x01];
+        assert_eq!(bytes(&asm, 0x1000, desired.len()), desired);
+    }
+
+    #[test]
+    fn test_oscillating_layout() {
+        // Extended addressing puts target where the operand is direct,
+        // direct addressing moves it back out again
+        let text = "
+    org $fd
+    ldaa $1ff-target
+target fcb 1
+";
+        let errs = assemble_err(text, cpu_opts(CpuKind::Cpu6800));
+
+        assert_eq!(errs.len(), 1);
+        let msg = format!("Label values did not settle after {MAX_PASSES} sizing passes");
+        assert!(errs[0].starts_with(&msg));
+        assert!(errs[0].contains("$0100") && errs[0].contains("$00FF"), "{errs:?}");
+    }
+
+    #[test]
+    fn test_banks() {
+        let text = "
+    section main
+    jsr func1
+    lda #bank(func2)
+    section rom1
+func1 jsr func2
+    rts
+    section rom2
+func2 rts
+";
+        // Common memory at $1000 and two banks at $8000
+        let section = |name: &str, start: usize, org: usize, bank| SerializedSection {
+            name: name.into(),
+            start,
+            size: 0x100,
+            org: Some(org),
+            access: AccessType::ReadWrite,
+            bank,
+            cpu: None,
+        };
+
+        let opts = Opts {
+            mem_size: 0x20000,
+            sections: vec![
+                section("main", 0x1000, 0x1000, None),
+                section("rom1", 0x10000, 0x8000, Some(1)),
+                section("rom2", 0x10100, 0x8000, Some(2)),
+            ],
+            ..Opts::default()
+        };
+
+        let asm = assemble_ok(text, opts);
+
+        assert_eq!(symbol(&asm, "func1"), Some(0x8000));
+        assert_eq!(symbol(&asm, "func2"), Some(0x8000));
+
+        assert_eq!(bytes(&asm, 0x1000, 5), [0xbd, 0x80, 0x00, 0x86, 0x02]);
+        assert_eq!(bytes(&asm, 0x10000, 4), [0xbd, 0x80, 0x00, 0x39]);
+        assert_eq!(bytes(&asm, 0x10100, 1), [0x39]);
+
+        // bank(func2) is fine from anywhere
+        assert_eq!(
+            warnings(&asm),
+            [
+                "cross-bank: func1 is in bank 1, not common memory",
+                "cross-bank: func2 is in bank 2, not bank 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bad_sizes() {
+        // Each bad line is an error and sizing carries on after it,
+        // zmb is a fill
+        let text = "
+    zmb -1
+    zmd -2
+    rmb -3
+    org $fff0
+    zmb $20
+    fcb 1
+    org $10001
+";
+        let errs = assemble_err(text, Opts::default());
+
+        assert_eq!(
+            errs,
+            [
+                "Fill size must be positive",
+                "Argument for ZMD must be positive",
+                "Argument for RMB must be positive",
+                "PC $10010 is past the end of the 64K address space",
+                "PC $10001 is past the end of the 64K address space",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_bank() {
+        let errs = assemble_err("    lda #bank(label)\nlabel rts\n", Opts::default());
+        assert_eq!(errs.len(), 1, "{errs:?}");
+    }
+}
+
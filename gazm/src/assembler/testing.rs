@@ -0,0 +1,120 @@
+#![forbid(unused_imports)]
+//! Assembling source text in tests
+//! Text is written to a temp directory of its own so it can be assembled as a project
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::Assembler;
+
+use crate::{
+    cpukind::CpuKind,
+    error::{ErrorCollectorTrait, ErrorMessage, GResult, GazmErrorKind},
+    opts::Opts,
+};
+
+static DIR_NUM: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh directory for a test's files
+pub fn test_dir() -> PathBuf {
+    let n = DIR_NUM.fetch_add(1, Ordering::SeqCst);
+    let dir = std::env::temp_dir().join(format!("gazm-test-{}-{n}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Can't create test dir");
+    dir
+}
+
+/// Write files into a fresh directory, returns the path of the first one
+pub fn write_files(files: &[(&str, &str)]) -> PathBuf {
+    let dir = test_dir();
+
+    for (name, text) in files {
+        std::fs::write(dir.join(name), text).expect("Can't write test file");
+    }
+
+    dir.join(files[0].0)
+}
+
+/// Default options for assembling for this cpu
+pub fn cpu_opts(cpu: CpuKind) -> Opts {
+    Opts {
+        cpu,
+        ..Opts::default()
+    }
+}
+
+/// Assemble these files, the first is the project file
+pub fn assemble_files(files: &[(&str, &str)], mut opts: Opts) -> (Assembler, GResult<()>) {
+    opts.project_file = write_files(files);
+    opts.no_async = true;
+
+    let mut asm = Assembler::new(opts);
+    let res = asm.assemble();
+    (asm, res)
+}
+
+/// Assemble source text
+pub fn assemble_with(text: &str, opts: Opts) -> (Assembler, GResult<()>) {
+    assemble_files(&[("test.gazm", text)], opts)
+}
+
+/// Assemble source text that has to assemble without errors
+pub fn assemble_ok(text: &str, opts: Opts) -> Assembler {
+    let (asm, res) = assemble_with(text, opts);
+
+    if let Err(e) = res {
+        panic!("Failed to assemble:\n{text}\n{:?}", messages(&e))
+    }
+
+    asm
+}
+
+/// Assemble source text that has to fail, returns the error messages
+pub fn assemble_err(text: &str, opts: Opts) -> Vec<String> {
+    let (_, res) = assemble_with(text, opts);
+    messages(&res.expect_err("Should have failed to assemble"))
+}
+
+/// Bytes written from this physical address
+pub fn bytes(asm: &Assembler, addr: usize, len: usize) -> Vec<u8> {
+    asm.get_binary().get_bytes(addr, len).expect("Bytes out of range").to_vec()
+}
+
+/// Value of a symbol in the root scope
+pub fn symbol(asm: &Assembler, name: &str) -> Option<i64> {
+    let syms = asm.get_symbols();
+    syms.get_reader(syms.get_root_scope_id())
+        .get_symbol_info(name)
+        .ok()?
+        .value
+}
+
+/// Messages of the warnings an assembly produced
+pub fn warnings(asm: &Assembler) -> Vec<String> {
+    asm.asm_out
+        .errors
+        .warnings
+        .iter()
+        .map(|w| format!("{}: {}", w.kind, message_text(&w.data.message)))
+        .collect()
+}
+
+fn message_text(m: &ErrorMessage) -> String {
+    match m {
+        ErrorMessage::Plain(txt) | ErrorMessage::Markdown(txt, _) => txt.clone(),
+    }
+}
+
+/// Plain text of every error, one per user error
+pub fn messages(err: &GazmErrorKind) -> Vec<String> {
+    match err {
+        GazmErrorKind::UserErrors(errs) => errs
+            .clone()
+            .to_vec()
+            .iter()
+            .map(|e| message_text(&e.as_ref().message))
+            .collect(),
+        GazmErrorKind::UserError(e) => vec![message_text(&e.as_ref().message)],
+        _ => vec![err.to_string()],
+    }
+}
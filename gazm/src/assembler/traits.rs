@@ -8,6 +8,22 @@ use crate::{
 
 use super::{Assembler, Sizer};
 
+/// An addressing mode an opcode supports
+/// Cycles are None if the instruction tables don't have them
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodeModeInfo {
+    pub addr_mode: String,
+    pub cycles: Option<usize>,
+}
+
+impl OpcodeModeInfo {
+    pub fn new<S: Into<String>>(addr_mode: S, cycles: Option<usize>) -> Self {
+        Self {
+            addr_mode: addr_mode.into(),
+            cycles,
+        }
+    }
+}
 
 pub trait AssemblerCpuTrait {
 
@@ -50,4 +66,14 @@ pub trait AssemblerCpuTrait {
     }
 
     fn lex_identifier(&self,_id: &str) -> TokenKind;
+
+    /// Addressing modes and cycle counts of an opcode, used by the LSP
+    fn get_opcode_info(&self, _text: &str) -> Vec<OpcodeModeInfo> {
+        vec![]
+    }
+
+    /// All mnemonics this cpu assembles, used for LSP completion
+    fn get_mnemonics(&self) -> Vec<String> {
+        vec![]
+    }
 }
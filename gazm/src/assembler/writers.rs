@@ -1,9 +1,9 @@
 #![forbid(unused_imports)]
-use super::Assembler;
+use super::{make_rom_image, to_intel_hex, to_srec, Assembler, LstItem};
 
 use crate::{
     astformat, debug_mess,
-    error::GResult,
+    error::{GResult, GazmErrorKind},
     gazmsymbols::Serializable,
     info_mess, interesting_mess,
     messages::{info, status},
@@ -33,6 +33,10 @@ impl Assembler {
             self.write_sym_file()?;
             self.write_deps_file()?;
             self.write_ast_file()?;
+            self.write_lst_file()?;
+            self.write_hex_files()?;
+            self.write_rom_images()?;
+            self.write_bank_files()?;
             Ok(())
         })
     }
@@ -73,6 +77,145 @@ impl Assembler {
         Ok(())
     }
 
+    pub fn write_lst_file(&mut self) -> GResult<()> {
+        if let Some(lst_file) = self.opts.lst_file.clone() {
+            interesting_mess!("Writing listing: {}", lst_file.to_string_lossy());
+            let text = self.lst_text();
+            self.write_file(lst_file, &text)?;
+        }
+        Ok(())
+    }
+
+    /// The listing as text
+    /// Each line is the address, bytes written, cycles, line number and source
+    /// Lines from macro expansions are marked with a `+`
+    fn lst_text(&self) -> String {
+        const BYTES_PER_LINE: usize = 8;
+        const MAX_LINES: usize = 4;
+
+        let to_hex = |bytes: &[u8]| {
+            let hex: Vec<_> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            hex.join(" ")
+        };
+
+        let mut ret = vec![];
+
+        for item in &self.asm_out.lst_file.items {
+            match item {
+                LstItem::FileStart(file) => ret.push(format!("; >>> {}", file.to_string_lossy())),
+                LstItem::FileEnd(file) => ret.push(format!("; <<< {}", file.to_string_lossy())),
+
+                LstItem::Source {
+                    pos,
+                    addr,
+                    bytes,
+                    cycles,
+                    in_macro,
+                    note,
+                } => {
+                    let si = match self.get_source_info(pos) {
+                        Ok(si) => si,
+                        Err(_) => continue,
+                    };
+
+                    let mut chunks = bytes.chunks(BYTES_PER_LINE);
+                    let hex = chunks.next().map(to_hex).unwrap_or_default();
+                    let cycles = cycles.map(|c| format!("[{c}]")).unwrap_or_default();
+                    let (line, _) = pos.line_col_from_one();
+                    let marker = if *in_macro { '+' } else { ' ' };
+                    let text = si.line_str.trim_end();
+                    let note = note.as_ref().map(|n| format!(" ; {n}")).unwrap_or_default();
+
+                    ret.push(format!(
+                        "{addr:04X}  {hex:<23} {cycles:>5} {line:>5}{marker} {text}{note}"
+                    ));
+
+                    for (i, chunk) in chunks.enumerate() {
+                        if i + 1 == MAX_LINES {
+                            ret.push("      ...".to_owned());
+                            break;
+                        }
+                        let addr = addr + (i + 1) * BYTES_PER_LINE;
+                        ret.push(format!("{addr:04X}  {}", to_hex(chunk)));
+                    }
+                }
+            }
+        }
+
+        let mut text = ret.join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// Intel HEX and S-record images of the written memory
+    pub fn write_hex_files(&mut self) -> GResult<()> {
+        let exec_addr = self.asm_out.exec_addr;
+
+        if let Some(hex_file) = self.opts.hex_file.clone() {
+            interesting_mess!("Writing Intel HEX: {}", hex_file.to_string_lossy());
+            let text = to_intel_hex(self.get_binary(), exec_addr);
+            self.write_file(hex_file, &text)?;
+        }
+
+        if let Some(srec_file) = self.opts.srec_file.clone() {
+            interesting_mess!("Writing S-records: {}", srec_file.to_string_lossy());
+            let text = to_srec(self.get_binary(), self.opts.srec_kind, exec_addr)?;
+            self.write_file(srec_file, &text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the images from `[[output]]` tables in `gazm.toml`
+    pub fn write_rom_images(&mut self) -> GResult<()> {
+        for out in self.opts.outputs.clone() {
+            let image = make_rom_image(self.get_binary(), &out)?;
+            let file = self.expand_path_to_deprecate(&out.file)?;
+
+            interesting_mess!("Writing ROM image: {}", file.to_string_lossy());
+
+            fs::write(&file, image)
+                .with_context(|| format!("Unable to write {}", file.to_string_lossy()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write each bank's sections to its own binary, unwritten bytes are $ff
+    /// Sections go in physical order, leaving out any other bank's in between
+    /// The file name has `{}` replaced with the bank number
+    pub fn write_bank_files(&mut self) -> GResult<()> {
+        let template = match &self.opts.bank_file {
+            Some(file) => file.to_string_lossy().into_owned(),
+            None => return Ok(()),
+        };
+
+        if !template.contains("{}") {
+            let msg = format!("bank-file {template} needs a {{}} for the bank number");
+            return Err(GazmErrorKind::Misc(msg));
+        }
+
+        for bank in self.asm_out.sections.banks() {
+            let ranges = self.asm_out.sections.bank_ranges(bank);
+            let binary = self.get_binary();
+
+            let image: Vec<u8> = ranges
+                .into_iter()
+                .flatten()
+                .map(|a| if binary.is_written(a) { binary.data[a] } else { 0xff })
+                .collect();
+
+            let file = self.expand_path_to_deprecate(template.replace("{}", &bank.to_string()))?;
+
+            interesting_mess!("Writing bank {bank}: {}", file.to_string_lossy());
+
+            fs::write(&file, image)
+                .with_context(|| format!("Unable to write {}", file.to_string_lossy()))?;
+        }
+
+        Ok(())
+    }
+
     pub fn write_deps_file(&mut self) -> GResult<()> {
         if let Some(deps) = &self.opts.deps_file {
             if let Some(sym_file) = &self.opts.source_mapping {
@@ -150,3 +293,107 @@ impl Assembler {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembler::{
+            testing::{assemble_ok, test_dir},
+            AccessType,
+        },
+        opts::Opts,
+        sections::SerializedSection,
+    };
+
+    fn listing(text: &str) -> Vec<String> {
+        let opts = Opts {
+            lst_file: Some("test.lst".into()),
+            ..Opts::default()
+        };
+
+        let asm = assemble_ok(text, opts);
+        asm.lst_text().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_listing() {
+        // The first line keeps the bytes it wrote though they're later overwritten
+        let text = "    org $1000
+    lda #1
+    fcb 1,2,3,4,5,6,7,8,9
+    org $1000
+    fcb $ff
+";
+        let desired = "\
+1000                                    1      org $1000
+1000  86 01                     [2]     2      lda #1
+1002  01 02 03 04 05 06 07 08           3      fcb 1,2,3,4,5,6,7,8,9
+100A  09
+1000                                    4      org $1000
+1000  FF                                5      fcb $ff";
+
+        let lines = listing(text);
+        let (first, rest) = lines.split_first().unwrap();
+        let (last, body) = rest.split_last().unwrap();
+
+        assert!(first.starts_with("; >>> ") && first.ends_with("test.gazm"));
+        assert!(last.starts_with("; <<< ") && last.ends_with("test.gazm"));
+        assert_eq!(body.join("\n"), desired);
+    }
+
+    #[test]
+    fn test_listing_z80_cycles() {
+        let lines = listing("    org $1000\n    target z80\n    ld a,b\n    ld hl,($1234)\n");
+
+        assert!(lines.iter().any(|l| l.starts_with("1000  78") && l.contains("[4]")));
+        assert!(lines.iter().any(|l| l.starts_with("1001  2A 34 12") && l.contains("[16]")));
+    }
+
+    #[test]
+    fn test_bank_files() {
+        let section = |name: &str, start: usize, bank| SerializedSection {
+            name: name.into(),
+            start,
+            size: 4,
+            org: Some(0x8000 + start - 0x10000),
+            access: AccessType::ReadWrite,
+            bank: Some(bank),
+            cpu: None,
+        };
+
+        let opts = Opts {
+            mem_size: 0x20000,
+            sections: vec![
+                section("rom1", 0x10000, 1),
+                section("rom2", 0x10004, 2),
+                section("rom1b", 0x10008, 1),
+            ],
+            ..Opts::default()
+        };
+
+        let text = "
+    section rom1
+    fcb 1,2
+    section rom2
+    fcb 3
+    section rom1b
+    fcb 4
+";
+        let mut asm = assemble_ok(text, opts);
+
+        let dir = test_dir();
+        asm.opts.bank_file = Some(dir.join("bank{}.bin"));
+        asm.write_bank_files().unwrap();
+
+        let read = |n: usize| std::fs::read(dir.join(format!("bank{n}.bin"))).unwrap();
+        // Bank 2's section in the middle of bank 1 is left out
+        assert_eq!(read(1), [1, 2, 0xff, 0xff, 4, 0xff, 0xff, 0xff]);
+        assert_eq!(read(2), [3, 0xff, 0xff, 0xff]);
+
+        // Every bank would be written to the same file
+        asm.opts.bank_file = Some(dir.join("banks.bin"));
+        assert!(asm.write_bank_files().is_err());
+        assert!(!dir.join("banks.bin").exists());
+    }
+}
+
@@ -2,7 +2,8 @@ use std::{collections::HashMap, path::PathBuf};
 
 use crate::{
     lsp::LspConfig,
-    opts::{CheckSum, Opts},
+    opts::{CheckSum, Opts, OutputImage},
+    sections::SerializedSection,
 };
 
 use serde::Deserialize;
@@ -12,6 +13,8 @@ struct LoadedTomlConfig {
     opts: Option<Opts>,
     vars: Option<HashMap<String, String>>,
     checksums: Option<HashMap<String, CheckSum>>,
+    output: Option<Vec<OutputImage>>,
+    section: Option<Vec<SerializedSection>>,
     lsp: Option<LspConfig>,
 }
 
@@ -65,6 +68,8 @@ impl TomlConfig {
                     .into();
 
                 opts.checksums = toml.checksums.clone().unwrap_or_default();
+                opts.outputs = toml.output.unwrap_or_default();
+                opts.sections = toml.section.unwrap_or_default();
                 opts.assemble_dir = run_dir;
                 opts.lsp_config = toml.lsp.unwrap_or_default();
 
@@ -104,4 +109,49 @@ mod test {
         // let _y = YamlConfig::new();
         // print!("{:#?}", _y);
     }
+
+    #[test]
+    fn warnings_test() {
+        use crate::error::WarningKind::*;
+
+        let text = "\
+[opts]
+warnings-as-errors = true
+
+[opts.warnings]
+unused-label = true
+truncated = false
+";
+        let opts = toml::from_str::<LoadedTomlConfig>(text).unwrap().opts.unwrap();
+
+        assert!(opts.warnings_as_errors);
+        assert!(opts.is_warning_enabled(UnusedLabel));
+        assert!(!opts.is_warning_enabled(Truncated));
+        assert!(opts.is_warning_enabled(DpForced));
+    }
+
+    #[test]
+    fn output_test() {
+        let text = "\
+[[output]]
+file = \"rom_even.bin\"
+addr = 0x8000
+size = 0x4000
+interleave = \"even\"
+
+[[output]]
+file = \"rom.bin\"
+addr = 0xc000
+size = 0x3000
+pad-to = 0x4000
+fill = 0
+";
+        let outputs = toml::from_str::<LoadedTomlConfig>(text).unwrap().output.unwrap();
+
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].fill, 0xff);
+        assert_eq!(outputs[0].interleave, Some(crate::opts::Interleave::Even));
+        assert_eq!(outputs[1].pad_to, Some(0x4000));
+        assert_eq!(outputs[1].fill, 0);
+    }
 }
@@ -41,8 +41,16 @@ impl Opts {
             Some(("lsp", m)) => load_opts_with_build_type(m, BuildType::Lsp)?,
 
             Some(("fmt", m)) => {
-                let mut o = load_opts_with_build_type(m, BuildType::Format)?;
-                o.project_file = m.get_one::<String>("fmt-file").map(PathBuf::from).unwrap();
+                // Formatting doesn't need a config file, it's only used for the cpu
+                let mut o = match load_opts_with_build_type(m, BuildType::Format) {
+                    Err(ConfigErrorType::MissingConfigFile(_)) => Opts {
+                        build_type: BuildType::Format,
+                        ..Default::default()
+                    },
+                    o => o?,
+                };
+                o.project_file = m.get_one::<PathBuf>("fmt-file").unwrap().into();
+                o.fmt_check = m.get_flag("check");
                 o
             }
 
@@ -59,34 +67,32 @@ impl Opts {
 
             Some(("asm", m)) => {
                 let mut opts = Opts {
-                    deps_file: m.get_one::<String>("deps").map(PathBuf::from),
-                    source_mapping: m.get_one::<String>("source-mapping").map(PathBuf::from),
-                    as6809_sym: m.get_one::<String>("as6809-sym").map(PathBuf::from),
+                    deps_file: m.get_one::<PathBuf>("deps").cloned(),
+                    source_mapping: m.get_one::<PathBuf>("source-mapping").cloned(),
+                    as6809_sym: m.get_one::<PathBuf>("as6809-sym").cloned(),
                     ignore_relative_offset_errors: m.contains_id("ignore-relative-offset-errors"),
-                    project_file: m.get_one::<String>("project-file").unwrap().into(),
-                    ast_file: m.get_one::<String>("ast-file").map(PathBuf::from),
+                    relax_branches: m.get_flag("relax-branches"),
+                    project_file: m.get_one::<PathBuf>("project-file").unwrap().into(),
+                    ast_file: m.get_one::<PathBuf>("ast-file").cloned(),
+                    lst_file: m.get_one::<PathBuf>("lst-file").cloned(),
                     assemble_dir: Some(std::env::current_dir().unwrap()),
                     ..Default::default()
                 };
 
-                let to_usize = |s: &String| s.parse::<usize>().ok();
-
-                if m.contains_id("mem-size") {
-                    opts.mem_size = m.get_one::<String>("mem-size").and_then(to_usize).unwrap();
+                if let Some(mem_size) = m.get_one::<usize>("mem-size") {
+                    opts.mem_size = *mem_size;
                 }
 
-                if m.contains_id("max-errors") {
-                    opts.max_errors = m
-                        .get_one::<String>("max-errors")
-                        .and_then(to_usize)
-                        .unwrap();
+                if let Some(max_errors) = m.get_one::<usize>("max-errors") {
+                    opts.max_errors = *max_errors;
                 }
 
                 if let Some(vals) = m.get_occurrences("set") {
                     let vals: Vec<Vec<&String>> = vals.map(Iterator::collect).collect();
                     for x in vals {
-                        opts.vars
-                            .set_var(x.get(0).unwrap().as_str(), x.get(1).unwrap().as_str())
+                        let (var, value) = (x.get(0).unwrap().as_str(), x.get(1).unwrap().as_str());
+                        opts.vars.set_var(var, value);
+                        opts.defines.set_var(var, value);
                     }
                 }
                 opts
@@ -107,6 +113,17 @@ impl Opts {
 
         opts.verbose_errors = orig_matches.get_flag("verbose-errors");
 
+        // Also settable in the config file
+        opts.warnings_as_errors |= orig_matches.get_flag("warnings-as-errors");
+
+        if let Some(format) = orig_matches.get_one::<String>("message-format") {
+            opts.message_format = match format.as_str() {
+                "json" => MessageFormat::Json,
+                "sarif" => MessageFormat::Sarif,
+                _ => MessageFormat::Human,
+            };
+        }
+
         opts.no_async = *orig_matches.get_one("no-async").unwrap();
 
         opts.update_vars();
@@ -129,6 +146,10 @@ fn make_config_file_arg() -> Arg {
 ////////////////////////////////////////////////////////////////////////////////
 
 pub fn parse_command_line() -> ArgMatches {
+    make_command().get_matches()
+}
+
+fn make_command() -> Command {
     use super::styling::{get_banner, get_styles};
 
     Command::new(clap::crate_name!())
@@ -152,6 +173,21 @@ pub fn parse_command_line() -> ArgMatches {
                 .action(ArgAction::Count)
                 .short('v'),
         )
+        .arg(
+            Arg::new("warnings-as-errors")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .long("warnings-as-errors")
+                .help("Fail the build if there are any warnings"),
+        )
+        .arg(
+            Arg::new("message-format")
+                .value_parser(["human", "json", "sarif"])
+                .global(true)
+                .long("message-format")
+                .num_args(1)
+                .help("How errors and warnings are reported"),
+        )
         .arg(
             Arg::new("no-async")
                 .action(ArgAction::SetTrue)
@@ -183,6 +219,27 @@ pub fn parse_command_line() -> ArgMatches {
                 .about("Launch LSP using config file")
                 .arg(make_config_file_arg()),
         )
+        .subcommand(
+            Command::new("fmt")
+                .about("Format a source file")
+                .arg(
+                    Arg::new("fmt-file")
+                        .value_parser(PathBufValueParser::new())
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("config-file")
+                        .help("load config file")
+                        .long("config")
+                        .default_value("gazm.toml"),
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("Don't write, fail if the file isn't formatted"),
+                ),
+        )
         .subcommand(
             Command::new("test").about("Some test shit").arg(
                 Arg::new("project-file")
@@ -212,6 +269,12 @@ pub fn parse_command_line() -> ArgMatches {
                         .long("ignore-relative-offset-errors")
                         .help("ignore relative offset errors"),
                 )
+                .arg(
+                    Arg::new("relax-branches")
+                        .long("relax-branches")
+                        .action(ArgAction::SetTrue)
+                        .help("Promote short branches that are out of range to long branches"),
+                )
                 .arg(
                     Arg::new("trailing-comments")
                         .long("trailing-comments")
@@ -238,6 +301,13 @@ pub fn parse_command_line() -> ArgMatches {
                         .help("Write a Makefile compatible deps file")
                         .num_args(1),
                 )
+                .arg(
+                    Arg::new("source-mapping")
+                        .value_parser(PathBufValueParser::new())
+                        .long("source-mapping")
+                        .help("Write a source mapping file")
+                        .num_args(1),
+                )
                 .arg(
                     Arg::new("set")
                         .long("set")
@@ -277,5 +347,26 @@ pub fn parse_command_line() -> ArgMatches {
                         .num_args(1),
                 ),
         )
-        .get_matches()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn asm_opts(args: &[&str]) -> Opts {
+        let args = ["gazm", "asm", "main.gazm"].iter().chain(args);
+        Opts::from_arg_matches(make_command().get_matches_from(args)).unwrap()
+    }
+
+    #[test]
+    fn test_asm_args() {
+        let opts = asm_opts(&[]);
+        assert_eq!(opts.mem_size, 0x10000);
+        assert_eq!(opts.max_errors, 5);
+
+        let opts = asm_opts(&["--mem-size", "131072", "--max-errors", "2", "--deps", "deps.d"]);
+        assert_eq!(opts.mem_size, 0x20000);
+        assert_eq!(opts.max_errors, 2);
+        assert_eq!(opts.deps_file, Some(PathBuf::from("deps.d")));
+    }
 }
@@ -0,0 +1,120 @@
+use crate::{
+    assembler::{Assembler, BinaryError},
+    cpu6502::{
+        assembler::{AddrModeEnum, OpcodeData},
+        frontend::NodeKind6502,
+    },
+    error::GResult,
+    semantic::AstNodeRef,
+};
+
+impl Assembler {
+    pub fn compile_operand_6502(
+        &mut self,
+        node: AstNodeRef,
+        ins: &OpcodeData,
+        pc: i64,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        use AddrModeEnum::*;
+
+        match ins.addr_mode {
+            Implied | Accumulator => (),
+
+            Immediate => {
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                self.write_byte_check_size(arg, node)?;
+            }
+
+            ZeroPage | ZeroPageX | ZeroPageY | IndexedIndirect | IndirectIndexed
+            | ZeroPageIndirect => {
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                let res = self.get_binary_mut().write_ubyte_check_size(arg);
+                self.binary_error_map(node, res)?;
+            }
+
+            Absolute | AbsoluteX | AbsoluteY | Indirect | AbsoluteIndexedIndirect => {
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                let res = self.get_binary_mut().write_uword_check_size(arg);
+                self.binary_error_map(node, res)?;
+            }
+
+            Relative => {
+                let (arg, arg_n) = self.eval_first_arg_n(node, current_scope_id)?;
+                self.compile_relative_6502(node, arg_n, arg, pc, ins)?;
+            }
+
+            ZeroPageRelative => {
+                let (zp, _) = self.eval_first_arg(node, current_scope_id)?;
+                let res = self.get_binary_mut().write_ubyte_check_size(zp);
+                self.binary_error_map(node, res)?;
+
+                let (arg, arg_n) = self.eval_nth_arg_n(node, 1, current_scope_id)?;
+                self.compile_relative_6502(node, arg_n, arg, pc, ins)?;
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Write a branch offset
+    fn compile_relative_6502(
+        &mut self,
+        node: AstNodeRef,
+        arg_n: AstNodeRef,
+        arg: i64,
+        pc: i64,
+        ins: &OpcodeData,
+    ) -> GResult<()> {
+        // offset is from PC after Instruction and operand has been fetched
+        let val = arg - (pc + ins.size as i64);
+        let res = self.get_binary_mut().write_ibyte_check_size(val);
+
+        match res {
+            Ok(_) => Ok(()),
+            Err(BinaryError::DoesNotFit { .. }) => {
+                if self.opts.ignore_relative_offset_errors {
+                    let res = self.get_binary_mut().write_ibyte_check_size(0);
+                    self.binary_error_map(node, res)?;
+                    Ok(())
+                } else {
+                    Err(self.relative_error(arg_n, val, 8))
+                }
+            }
+            Err(e) => Err(self.binary_error(node, e)),
+        }
+    }
+
+    /// Compile an opcode
+    pub fn compile_opcode_6502(
+        &mut self,
+        node: AstNodeRef,
+        ins: &OpcodeData,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        let pc = self.get_binary().get_write_address() as i64;
+        self.write_byte(ins.opcode, node)?;
+        self.compile_operand_6502(node, ins, pc, current_scope_id)
+    }
+
+    /// Compile a node
+    pub fn compile_node_6502(
+        &mut self,
+        node_kind: NodeKind6502,
+        node: AstNodeRef,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        use NodeKind6502::*;
+
+        match node_kind {
+            OpCode(_, ins, _) => self.compile_opcode_6502(node, &ins, current_scope_id)?,
+            Illegal => return Err(self.make_user_error("Illegal instruction", node, true).into()),
+            Operand(_) => {
+                let msg = "Operand without an instruction";
+                return Err(self.make_user_error(msg, node, true).into());
+            }
+        }
+
+        Ok(())
+    }
+}
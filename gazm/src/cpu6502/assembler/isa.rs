@@ -0,0 +1,322 @@
+/// Instruction set database for the 6502 and 65C02
+/// There is no emulator crate for the 6502 to pull this from
+/// so the opcode tables live here
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AddrModeEnum {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+    Relative,
+    /// 65C02 (zp)
+    ZeroPageIndirect,
+    /// 65C02 jmp (abs,x)
+    AbsoluteIndexedIndirect,
+    /// 65C02 bbr / bbs zp,rel
+    ZeroPageRelative,
+}
+
+impl AddrModeEnum {
+    /// Number of operand bytes that follow the opcode
+    pub fn operand_size(&self) -> usize {
+        use AddrModeEnum::*;
+        match self {
+            Implied | Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndexedIndirect | IndirectIndexed
+            | Relative | ZeroPageIndirect => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect | AbsoluteIndexedIndirect
+            | ZeroPageRelative => 2,
+        }
+    }
+
+    /// The zero page equivalent of an absolute addressing mode
+    pub fn to_zero_page(&self) -> Option<AddrModeEnum> {
+        use AddrModeEnum::*;
+        match self {
+            Absolute => Some(ZeroPage),
+            AbsoluteX => Some(ZeroPageX),
+            AbsoluteY => Some(ZeroPageY),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OpcodeData {
+    pub opcode: u8,
+    pub addr_mode: AddrModeEnum,
+    pub size: usize,
+    pub cycles: usize,
+    /// Only available on the 65C02
+    pub is_65c02: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub opcodes: Vec<OpcodeData>,
+}
+
+impl Instruction {
+    pub fn get_opcode_data(&self, amode: AddrModeEnum) -> Option<&OpcodeData> {
+        self.opcodes.iter().find(|o| o.addr_mode == amode)
+    }
+
+    pub fn supports(&self, amode: AddrModeEnum) -> bool {
+        self.get_opcode_data(amode).is_some()
+    }
+
+    /// Is every form of this instruction 65C02 only?
+    pub fn is_65c02(&self) -> bool {
+        self.opcodes.iter().all(|o| o.is_65c02)
+    }
+}
+
+pub struct Dbase {
+    instructions: HashMap<&'static str, Instruction>,
+    lookup: HashMap<u8, &'static str>,
+}
+
+impl Dbase {
+    /// Create from opcode tables, each flagged with whether it is 65C02 only
+    pub fn new(tables: &[(&[(&'static str, AddrModeEnum, u8, usize)], bool)]) -> Self {
+        let mut instructions: HashMap<&'static str, Instruction> = HashMap::new();
+        let mut lookup = HashMap::new();
+
+        let entries = tables
+            .iter()
+            .flat_map(|(table, is_65c02)| table.iter().map(move |e| (e, *is_65c02)));
+
+        for ((mnemonic, addr_mode, opcode, cycles), is_65c02) in entries {
+            let data = OpcodeData {
+                opcode: *opcode,
+                addr_mode: *addr_mode,
+                size: 1 + addr_mode.operand_size(),
+                cycles: *cycles,
+                is_65c02,
+            };
+
+            instructions
+                .entry(mnemonic)
+                .or_insert_with(|| Instruction {
+                    mnemonic,
+                    opcodes: vec![],
+                })
+                .opcodes
+                .push(data);
+
+            lookup.insert(*opcode, *mnemonic);
+        }
+
+        Self {
+            instructions,
+            lookup,
+        }
+    }
+
+    pub fn get_opcode(&self, text: &str) -> Option<&Instruction> {
+        self.instructions.get(text)
+    }
+
+    pub fn get_instruction_from_opcode(&self, opcode: u8) -> Option<&Instruction> {
+        self.lookup.get(&opcode).and_then(|m| self.get_opcode(m))
+    }
+
+    pub fn get_opcode_data_from_opcode(&self, opcode: u8) -> Option<&OpcodeData> {
+        self.get_instruction_from_opcode(opcode)
+            .and_then(|i| i.opcodes.iter().find(|o| o.opcode == opcode))
+    }
+}
+
+lazy_static! {
+    pub static ref DBASE: Dbase = Dbase::new(&[(OPCODES_6502, false), (OPCODES_65C02, true)]);
+}
+
+use AddrModeEnum::*;
+
+/// Mnemonic, addressing mode, opcode, cycles
+#[rustfmt::skip]
+pub static OPCODES_6502: &[(&str, AddrModeEnum, u8, usize)] = &[
+    ("adc", Immediate, 0x69, 2), ("adc", ZeroPage, 0x65, 3), ("adc", ZeroPageX, 0x75, 4),
+    ("adc", Absolute, 0x6d, 4), ("adc", AbsoluteX, 0x7d, 4), ("adc", AbsoluteY, 0x79, 4),
+    ("adc", IndexedIndirect, 0x61, 6), ("adc", IndirectIndexed, 0x71, 5),
+
+    ("and", Immediate, 0x29, 2), ("and", ZeroPage, 0x25, 3), ("and", ZeroPageX, 0x35, 4),
+    ("and", Absolute, 0x2d, 4), ("and", AbsoluteX, 0x3d, 4), ("and", AbsoluteY, 0x39, 4),
+    ("and", IndexedIndirect, 0x21, 6), ("and", IndirectIndexed, 0x31, 5),
+
+    ("asl", Accumulator, 0x0a, 2), ("asl", ZeroPage, 0x06, 5), ("asl", ZeroPageX, 0x16, 6),
+    ("asl", Absolute, 0x0e, 6), ("asl", AbsoluteX, 0x1e, 7),
+
+    ("bcc", Relative, 0x90, 2), ("bcs", Relative, 0xb0, 2), ("beq", Relative, 0xf0, 2),
+    ("bmi", Relative, 0x30, 2), ("bne", Relative, 0xd0, 2), ("bpl", Relative, 0x10, 2),
+    ("bvc", Relative, 0x50, 2), ("bvs", Relative, 0x70, 2),
+
+    ("bit", ZeroPage, 0x24, 3), ("bit", Absolute, 0x2c, 4),
+
+    ("brk", Implied, 0x00, 7),
+
+    ("clc", Implied, 0x18, 2), ("cld", Implied, 0xd8, 2), ("cli", Implied, 0x58, 2),
+    ("clv", Implied, 0xb8, 2),
+
+    ("cmp", Immediate, 0xc9, 2), ("cmp", ZeroPage, 0xc5, 3), ("cmp", ZeroPageX, 0xd5, 4),
+    ("cmp", Absolute, 0xcd, 4), ("cmp", AbsoluteX, 0xdd, 4), ("cmp", AbsoluteY, 0xd9, 4),
+    ("cmp", IndexedIndirect, 0xc1, 6), ("cmp", IndirectIndexed, 0xd1, 5),
+
+    ("cpx", Immediate, 0xe0, 2), ("cpx", ZeroPage, 0xe4, 3), ("cpx", Absolute, 0xec, 4),
+    ("cpy", Immediate, 0xc0, 2), ("cpy", ZeroPage, 0xc4, 3), ("cpy", Absolute, 0xcc, 4),
+
+    ("dec", ZeroPage, 0xc6, 5), ("dec", ZeroPageX, 0xd6, 6), ("dec", Absolute, 0xce, 6),
+    ("dec", AbsoluteX, 0xde, 7),
+
+    ("dex", Implied, 0xca, 2), ("dey", Implied, 0x88, 2),
+
+    ("eor", Immediate, 0x49, 2), ("eor", ZeroPage, 0x45, 3), ("eor", ZeroPageX, 0x55, 4),
+    ("eor", Absolute, 0x4d, 4), ("eor", AbsoluteX, 0x5d, 4), ("eor", AbsoluteY, 0x59, 4),
+    ("eor", IndexedIndirect, 0x41, 6), ("eor", IndirectIndexed, 0x51, 5),
+
+    ("inc", ZeroPage, 0xe6, 5), ("inc", ZeroPageX, 0xf6, 6), ("inc", Absolute, 0xee, 6),
+    ("inc", AbsoluteX, 0xfe, 7),
+
+    ("inx", Implied, 0xe8, 2), ("iny", Implied, 0xc8, 2),
+
+    ("jmp", Absolute, 0x4c, 3), ("jmp", Indirect, 0x6c, 5),
+    ("jsr", Absolute, 0x20, 6),
+
+    ("lda", Immediate, 0xa9, 2), ("lda", ZeroPage, 0xa5, 3), ("lda", ZeroPageX, 0xb5, 4),
+    ("lda", Absolute, 0xad, 4), ("lda", AbsoluteX, 0xbd, 4), ("lda", AbsoluteY, 0xb9, 4),
+    ("lda", IndexedIndirect, 0xa1, 6), ("lda", IndirectIndexed, 0xb1, 5),
+
+    ("ldx", Immediate, 0xa2, 2), ("ldx", ZeroPage, 0xa6, 3), ("ldx", ZeroPageY, 0xb6, 4),
+    ("ldx", Absolute, 0xae, 4), ("ldx", AbsoluteY, 0xbe, 4),
+
+    ("ldy", Immediate, 0xa0, 2), ("ldy", ZeroPage, 0xa4, 3), ("ldy", ZeroPageX, 0xb4, 4),
+    ("ldy", Absolute, 0xac, 4), ("ldy", AbsoluteX, 0xbc, 4),
+
+    ("lsr", Accumulator, 0x4a, 2), ("lsr", ZeroPage, 0x46, 5), ("lsr", ZeroPageX, 0x56, 6),
+    ("lsr", Absolute, 0x4e, 6), ("lsr", AbsoluteX, 0x5e, 7),
+
+    ("nop", Implied, 0xea, 2),
+
+    ("ora", Immediate, 0x09, 2), ("ora", ZeroPage, 0x05, 3), ("ora", ZeroPageX, 0x15, 4),
+    ("ora", Absolute, 0x0d, 4), ("ora", AbsoluteX, 0x1d, 4), ("ora", AbsoluteY, 0x19, 4),
+    ("ora", IndexedIndirect, 0x01, 6), ("ora", IndirectIndexed, 0x11, 5),
+
+    ("pha", Implied, 0x48, 3), ("php", Implied, 0x08, 3), ("pla", Implied, 0x68, 4),
+    ("plp", Implied, 0x28, 4),
+
+    ("rol", Accumulator, 0x2a, 2), ("rol", ZeroPage, 0x26, 5), ("rol", ZeroPageX, 0x36, 6),
+    ("rol", Absolute, 0x2e, 6), ("rol", AbsoluteX, 0x3e, 7),
+
+    ("ror", Accumulator, 0x6a, 2), ("ror", ZeroPage, 0x66, 5), ("ror", ZeroPageX, 0x76, 6),
+    ("ror", Absolute, 0x6e, 6), ("ror", AbsoluteX, 0x7e, 7),
+
+    ("rti", Implied, 0x40, 6), ("rts", Implied, 0x60, 6),
+
+    ("sbc", Immediate, 0xe9, 2), ("sbc", ZeroPage, 0xe5, 3), ("sbc", ZeroPageX, 0xf5, 4),
+    ("sbc", Absolute, 0xed, 4), ("sbc", AbsoluteX, 0xfd, 4), ("sbc", AbsoluteY, 0xf9, 4),
+    ("sbc", IndexedIndirect, 0xe1, 6), ("sbc", IndirectIndexed, 0xf1, 5),
+
+    ("sec", Implied, 0x38, 2), ("sed", Implied, 0xf8, 2), ("sei", Implied, 0x78, 2),
+
+    ("sta", ZeroPage, 0x85, 3), ("sta", ZeroPageX, 0x95, 4), ("sta", Absolute, 0x8d, 4),
+    ("sta", AbsoluteX, 0x9d, 5), ("sta", AbsoluteY, 0x99, 5),
+    ("sta", IndexedIndirect, 0x81, 6), ("sta", IndirectIndexed, 0x91, 6),
+
+    ("stx", ZeroPage, 0x86, 3), ("stx", ZeroPageY, 0x96, 4), ("stx", Absolute, 0x8e, 4),
+    ("sty", ZeroPage, 0x84, 3), ("sty", ZeroPageX, 0x94, 4), ("sty", Absolute, 0x8c, 4),
+
+    ("tax", Implied, 0xaa, 2), ("tay", Implied, 0xa8, 2), ("tsx", Implied, 0xba, 2),
+    ("txa", Implied, 0x8a, 2), ("txs", Implied, 0x9a, 2), ("tya", Implied, 0x98, 2),
+];
+
+/// Additions and new addressing modes from the 65C02
+/// including the Rockwell bit instructions
+#[rustfmt::skip]
+pub static OPCODES_65C02: &[(&str, AddrModeEnum, u8, usize)] = &[
+    ("adc", ZeroPageIndirect, 0x72, 5), ("and", ZeroPageIndirect, 0x32, 5),
+    ("cmp", ZeroPageIndirect, 0xd2, 5), ("eor", ZeroPageIndirect, 0x52, 5),
+    ("lda", ZeroPageIndirect, 0xb2, 5), ("ora", ZeroPageIndirect, 0x12, 5),
+    ("sbc", ZeroPageIndirect, 0xf2, 5), ("sta", ZeroPageIndirect, 0x92, 5),
+
+    ("bit", Immediate, 0x89, 2), ("bit", ZeroPageX, 0x34, 4), ("bit", AbsoluteX, 0x3c, 4),
+
+    ("bra", Relative, 0x80, 3),
+
+    ("dec", Accumulator, 0x3a, 2), ("inc", Accumulator, 0x1a, 2),
+
+    ("jmp", AbsoluteIndexedIndirect, 0x7c, 6),
+
+    ("phx", Implied, 0xda, 3), ("phy", Implied, 0x5a, 3), ("plx", Implied, 0xfa, 4),
+    ("ply", Implied, 0x7a, 4),
+
+    ("stz", ZeroPage, 0x64, 3), ("stz", ZeroPageX, 0x74, 4), ("stz", Absolute, 0x9c, 4),
+    ("stz", AbsoluteX, 0x9e, 5),
+
+    ("trb", ZeroPage, 0x14, 5), ("trb", Absolute, 0x1c, 6),
+    ("tsb", ZeroPage, 0x04, 5), ("tsb", Absolute, 0x0c, 6),
+
+    ("bbr0", ZeroPageRelative, 0x0f, 5), ("bbr1", ZeroPageRelative, 0x1f, 5),
+    ("bbr2", ZeroPageRelative, 0x2f, 5), ("bbr3", ZeroPageRelative, 0x3f, 5),
+    ("bbr4", ZeroPageRelative, 0x4f, 5), ("bbr5", ZeroPageRelative, 0x5f, 5),
+    ("bbr6", ZeroPageRelative, 0x6f, 5), ("bbr7", ZeroPageRelative, 0x7f, 5),
+
+    ("bbs0", ZeroPageRelative, 0x8f, 5), ("bbs1", ZeroPageRelative, 0x9f, 5),
+    ("bbs2", ZeroPageRelative, 0xaf, 5), ("bbs3", ZeroPageRelative, 0xbf, 5),
+    ("bbs4", ZeroPageRelative, 0xcf, 5), ("bbs5", ZeroPageRelative, 0xdf, 5),
+    ("bbs6", ZeroPageRelative, 0xef, 5), ("bbs7", ZeroPageRelative, 0xff, 5),
+
+    ("rmb0", ZeroPage, 0x07, 5), ("rmb1", ZeroPage, 0x17, 5),
+    ("rmb2", ZeroPage, 0x27, 5), ("rmb3", ZeroPage, 0x37, 5),
+    ("rmb4", ZeroPage, 0x47, 5), ("rmb5", ZeroPage, 0x57, 5),
+    ("rmb6", ZeroPage, 0x67, 5), ("rmb7", ZeroPage, 0x77, 5),
+
+    ("smb0", ZeroPage, 0x87, 5), ("smb1", ZeroPage, 0x97, 5),
+    ("smb2", ZeroPage, 0xa7, 5), ("smb3", ZeroPage, 0xb7, 5),
+    ("smb4", ZeroPage, 0xc7, 5), ("smb5", ZeroPage, 0xd7, 5),
+    ("smb6", ZeroPage, 0xe7, 5), ("smb7", ZeroPage, 0xf7, 5),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_opcode_count() {
+        // The NMOS 6502 has 151 documented opcodes
+        assert_eq!(OPCODES_6502.len(), 151);
+        assert_eq!(DBASE.lookup.len(), 151 + OPCODES_65C02.len());
+    }
+
+    #[test]
+    fn test_65c02() {
+        assert!(DBASE.get_opcode("stz").unwrap().is_65c02());
+        let lda = DBASE.get_opcode("lda").unwrap();
+        assert!(!lda.is_65c02());
+        assert!(lda.get_opcode_data(ZeroPageIndirect).unwrap().is_65c02);
+        assert_eq!(DBASE.get_opcode("bbs3").unwrap().opcodes[0].size, 3);
+    }
+
+    #[test]
+    fn test_zero_page_sizes() {
+        let lda = DBASE.get_opcode("lda").unwrap();
+        assert_eq!(lda.get_opcode_data(ZeroPage).unwrap().size, 2);
+        assert_eq!(lda.get_opcode_data(Absolute).unwrap().size, 3);
+        assert_eq!(
+            DBASE.get_opcode_data_from_opcode(0xb5).unwrap().addr_mode,
+            ZeroPageX
+        );
+    }
+}
@@ -0,0 +1,9 @@
+#![forbid(unused_imports)]
+
+mod isa;
+mod sizer;
+mod compiler;
+
+pub use isa::*;
+pub use sizer::*;
+pub use compiler::*;
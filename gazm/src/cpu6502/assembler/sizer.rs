@@ -0,0 +1,144 @@
+use crate::debug_mess;
+use crate::{
+    assembler::{Assembler, Sizer},
+    error::GResult,
+    semantic::AstNodeId,
+};
+
+use crate::cpu6502::{
+    assembler::DBASE,
+    frontend::{AddrModeParseType, NodeKind6502},
+};
+
+impl Assembler {
+    pub fn size_node_6502(
+        &mut self,
+        sizer: &mut Sizer,
+        id: AstNodeId,
+        node_kind: NodeKind6502,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        size_node_internal(sizer, self, id, node_kind, current_scope_id)
+    }
+}
+
+fn size_node_internal(
+    sizer: &mut Sizer,
+    asm: &mut Assembler,
+    id: AstNodeId,
+    node_kind: NodeKind6502,
+    current_scope_id: u64,
+) -> GResult<()> {
+    let node = sizer.get_node(id);
+
+    use NodeKind6502::*;
+
+    match &node_kind {
+        Illegal => return Err(asm.make_user_error("Illegal instruction", node, true).into()),
+
+        OpCode(text, ins, amode) => {
+            let mut size = ins.size;
+
+            if amode.can_be_zero_page() {
+                // Unforced absolute addressing
+                // If the instruction has a zero page equivalent and the
+                // operand evaluates to an address in the zero page
+                // we can use the shorter opcode
+
+                let zp_amode = match amode {
+                    AddrModeParseType::AbsoluteX(_) => AddrModeParseType::ZeroPageX,
+                    AddrModeParseType::AbsoluteY(_) => AddrModeParseType::ZeroPageY,
+                    _ => AddrModeParseType::ZeroPage,
+                };
+
+                let new_ins = DBASE
+                    .get_opcode(text)
+                    .and_then(|i| i.get_opcode_data(zp_amode.to_addr_mode()));
+
+                if let Some(new_ins) = new_ins {
+                    if let Ok((value, _)) = asm.eval_first_arg(node, current_scope_id) {
+                        if (0..0x100).contains(&value) {
+                            let src = asm.get_source_info(&node.value().pos);
+
+                            if let Ok(src) = src {
+                                debug_mess!("Xformed from Absolute to Zero Page :  {}", src.line_str);
+                            }
+
+                            size = new_ins.size;
+                            let new_item = OpCode(text.clone(), new_ins.clone().into(), zp_amode);
+                            asm.add_fixup(id, new_item, current_scope_id);
+                        }
+                    }
+                }
+            }
+
+            sizer.advance_pc(size)
+        }
+
+        Operand(_) => {
+            let msg = "Operand without an instruction";
+            return Err(asm.make_user_error(msg, node, true).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembler::testing::*, cpukind::CpuKind};
+
+    #[test]
+    fn test_zero_page_selection() {
+        let text = "
+            org $1000
+            lda $10
+            lda $1234
+            lda >$10
+            lda <$1234
+            lda $10,x
+            ldx $10,y
+            lda $10,y
+            ";
+
+        let asm = assemble_ok(text, cpu_opts(CpuKind::Cpu6502));
+
+        let desired = [
+            0xa5, 0x10, // lda zp
+            0xad, 0x34, 0x12, // lda abs
+            0xad, 0x10, 0x00, // lda >abs
+            0xa5, 0x34, // lda <zp
+            0xb5, 0x10, // lda zp,x
+            0xb6, 0x10, // ldx zp,y
+            0xb9, 0x10, 0x00, // lda abs,y, there's no lda zp,y
+        ];
+
+        assert_eq!(bytes(&asm, 0x1000, desired.len()), desired);
+    }
+
+    #[test]
+    fn test_forward_reference_shrinks() {
+        let text = "
+            org 0
+            lda zp_data
+            rts
+zp_data
+            fcb $42
+
+            org $1000
+            lda abs_data
+            rts
+abs_data
+            fcb $43
+            ";
+
+        let asm = assemble_ok(text, cpu_opts(CpuKind::Cpu6502));
+
+        // First pass sizes lda as absolute, zp_data at 4 is then in the
+        // zero page so lda shrinks and moves it to 3
+        assert_eq!(symbol(&asm, "zp_data"), Some(3));
+        assert_eq!(bytes(&asm, 0, 4), [0xa5, 0x03, 0x60, 0x42]);
+
+        assert_eq!(symbol(&asm, "abs_data"), Some(0x1004));
+        assert_eq!(bytes(&asm, 0x1000, 5), [0xad, 0x04, 0x10, 0x60, 0x43]);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    assembler::{Assembler, AssemblerCpuTrait, OpcodeModeInfo, Sizer},
+    cpukind::CpuKind,
+    error::GResult,
+    frontend::{CpuSpecific, Node, PResult, TSpan, TokenKind},
+    semantic::{AstNodeId, AstNodeRef},
+};
+
+use super::{
+    assembler::{OpcodeData, DBASE, OPCODES_6502, OPCODES_65C02},
+    frontend::{lex_identifier, lex_identifier_65c02, parse_commands, parse_multi_opcode_vec},
+};
+
+/// Assembler for the 6502 and 65C02
+/// Both share nodes, the cpu only decides which instructions are allowed
+#[derive(PartialEq, Debug, Clone)]
+pub struct Asm6502 {
+    cpu: CpuKind,
+}
+
+impl Default for Asm6502 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Asm6502 {
+    pub fn new() -> Self {
+        Self {
+            cpu: CpuKind::Cpu6502,
+        }
+    }
+
+    pub fn new_65c02() -> Self {
+        Self {
+            cpu: CpuKind::Cpu65c02,
+        }
+    }
+}
+
+impl AssemblerCpuTrait for Asm6502 {
+    fn get_cpu_name(&self) -> &'static str {
+        match self.cpu {
+            CpuKind::Cpu65c02 => "65C02",
+            _ => "6502",
+        }
+    }
+
+    fn size_node(
+        &self,
+        sizer: &mut Sizer,
+        asm: &mut Assembler,
+        id: AstNodeId,
+        node_kind: CpuSpecific,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        match node_kind {
+            CpuSpecific::Cpu6502(node_kind) => {
+                asm.size_node_6502(sizer, id, node_kind, current_scope_id)
+            }
+            _ => panic!(),
+        }
+    }
+
+    fn compile_node(
+        &self,
+        asm: &mut Assembler,
+        node: AstNodeRef,
+        node_kind: CpuSpecific,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        match node_kind {
+            CpuSpecific::Cpu6502(node_kind) => {
+                asm.compile_node_6502(node_kind, node, current_scope_id)
+            }
+            _ => panic!(),
+        }
+    }
+
+    fn parse_multi_opcode_vec(&self, input: TSpan) -> PResult<Vec<Node>> {
+        parse_multi_opcode_vec(input)
+    }
+
+    fn parse_commands(&self, input: TSpan) -> PResult<Node> {
+        parse_commands(input)
+    }
+
+    fn lex_identifier(&self, id: &str) -> TokenKind {
+        match self.cpu {
+            CpuKind::Cpu65c02 => lex_identifier_65c02(id),
+            _ => lex_identifier(id),
+        }
+    }
+
+    fn get_opcode_info(&self, text: &str) -> Vec<OpcodeModeInfo> {
+        let to_info = |d: &OpcodeData| {
+            let addr_mode = if d.is_65c02 {
+                format!("{:?} (65C02)", d.addr_mode)
+            } else {
+                format!("{:?}", d.addr_mode)
+            };
+            OpcodeModeInfo::new(addr_mode, Some(d.cycles))
+        };
+
+        // The 6502 doesn't have the 65C02's extra modes
+        let is_available = |d: &&OpcodeData| !d.is_65c02 || self.cpu == CpuKind::Cpu65c02;
+
+        DBASE
+            .get_opcode(text)
+            .map(|ins| ins.opcodes.iter().filter(is_available).map(to_info).collect())
+            .unwrap_or_default()
+    }
+
+    fn get_mnemonics(&self) -> Vec<String> {
+        let extra: &[_] = match self.cpu {
+            CpuKind::Cpu65c02 => OPCODES_65C02,
+            _ => &[],
+        };
+
+        let mut ret: Vec<_> = OPCODES_6502
+            .iter()
+            .chain(extra)
+            .map(|(m, ..)| m.to_string())
+            .collect();
+
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+}
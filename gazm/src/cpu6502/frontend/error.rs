@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+use super::AddrModeParseType;
+
+// TODO Remove all of this, replace with help text
+#[derive(Debug, Error, Clone, PartialEq, Copy)]
+pub enum AssemblyErrorKind6502 {
+    #[error("This {0:?} is not supported for this opcode")]
+    ThisAddrModeUnsupported(AddrModeParseType),
+    #[error("Addressing mode is not supported for this opcode")]
+    AddrModeUnsupported,
+    #[error("This instruction only supports inherent mode addressing")]
+    OnlySupports(AddrModeParseType),
+    #[error("This instruction is only available on the 65C02, set the target cpu to 65c02")]
+    Only65c02,
+}
@@ -0,0 +1,25 @@
+use crate::cpu6502::assembler::DBASE;
+use crate::cpukind::CpuKind;
+use crate::frontend::TokenKind;
+
+/// 65C02 mnemonics are lexed as opcodes for the plain 6502 as well
+/// so the parser can report they need the 65C02
+fn lex(cpu: CpuKind, text: &str) -> TokenKind {
+    use TokenKind::*;
+
+    let text = text.to_lowercase();
+
+    if DBASE.get_opcode(&text).is_some() {
+        OpCode(cpu)
+    } else {
+        Label
+    }
+}
+
+pub fn lex_identifier(text: &str) -> TokenKind {
+    lex(CpuKind::Cpu6502, text)
+}
+
+pub fn lex_identifier_65c02(text: &str) -> TokenKind {
+    lex(CpuKind::Cpu65c02, text)
+}
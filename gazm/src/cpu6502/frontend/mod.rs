@@ -0,0 +1,13 @@
+mod lexer;
+mod error;
+mod commands;
+mod parse_opcode;
+mod register;
+mod nodekind;
+
+pub use lexer::*;
+pub use parse_opcode::*;
+pub use error::*;
+pub use commands::*;
+pub use register::*;
+pub use nodekind::*;
@@ -0,0 +1,92 @@
+use crate::cpu6502::assembler::{AddrModeEnum, OpcodeData};
+use crate::frontend::{AstNodeKind, CpuSpecific};
+
+/// Addressing mode as parsed from source
+/// Absolute modes carry a flag saying if they were forced with '>'
+/// forced modes are never demoted to zero page
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AddrModeParseType {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute(bool),
+    AbsoluteX(bool),
+    AbsoluteY(bool),
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+    Relative,
+    ZeroPageIndirect,
+    AbsoluteIndexedIndirect,
+    ZeroPageRelative,
+}
+
+impl AddrModeParseType {
+    pub fn to_addr_mode(&self) -> AddrModeEnum {
+        use AddrModeParseType as PT;
+        match self {
+            PT::Implied => AddrModeEnum::Implied,
+            PT::Accumulator => AddrModeEnum::Accumulator,
+            PT::Immediate => AddrModeEnum::Immediate,
+            PT::ZeroPage => AddrModeEnum::ZeroPage,
+            PT::ZeroPageX => AddrModeEnum::ZeroPageX,
+            PT::ZeroPageY => AddrModeEnum::ZeroPageY,
+            PT::Absolute(..) => AddrModeEnum::Absolute,
+            PT::AbsoluteX(..) => AddrModeEnum::AbsoluteX,
+            PT::AbsoluteY(..) => AddrModeEnum::AbsoluteY,
+            PT::Indirect => AddrModeEnum::Indirect,
+            PT::IndexedIndirect => AddrModeEnum::IndexedIndirect,
+            PT::IndirectIndexed => AddrModeEnum::IndirectIndexed,
+            PT::Relative => AddrModeEnum::Relative,
+            PT::ZeroPageIndirect => AddrModeEnum::ZeroPageIndirect,
+            PT::AbsoluteIndexedIndirect => AddrModeEnum::AbsoluteIndexedIndirect,
+            PT::ZeroPageRelative => AddrModeEnum::ZeroPageRelative,
+        }
+    }
+
+    /// Can the sizer swap this for a zero page mode?
+    pub fn can_be_zero_page(&self) -> bool {
+        use AddrModeParseType as PT;
+        matches!(
+            self,
+            PT::Absolute(false) | PT::AbsoluteX(false) | PT::AbsoluteY(false)
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum NodeKind6502 {
+    #[default]
+    Illegal,
+    OpCode(Box<String>, Box<OpcodeData>, AddrModeParseType),
+    Operand(AddrModeParseType),
+}
+
+impl NodeKind6502 {
+    pub fn opcode<T: Into<String>>(
+        name: T,
+        opcode_data: &OpcodeData,
+        amode: AddrModeParseType,
+    ) -> Self {
+        NodeKind6502::OpCode(
+            Box::new(name.into()),
+            Box::new(opcode_data.clone()),
+            amode,
+        )
+    }
+}
+
+impl From<NodeKind6502> for AstNodeKind {
+    fn from(value: NodeKind6502) -> Self {
+        AstNodeKind::TargetSpecific(CpuSpecific::Cpu6502(value))
+    }
+}
+
+impl From<AddrModeParseType> for AstNodeKind {
+    fn from(value: AddrModeParseType) -> Self {
+        NodeKind6502::Operand(value).into()
+    }
+}
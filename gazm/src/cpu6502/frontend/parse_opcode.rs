@@ -0,0 +1,374 @@
+use crate::frontend::{
+    err_fatal, err_kind_nomatch, err_nomatch, fatal, from_item_kid_tspan, from_item_kids_tspan,
+    from_item_tspan, get_text, parse_expr, AstNodeKind, CpuSpecific, FeResult, Node, PResult,
+    TSpan, TokenKind,
+};
+
+use crate::cpukind::CpuKind;
+
+use crate::cpu6502::{
+    assembler::{AddrModeEnum, Instruction, OpcodeData, DBASE},
+    frontend::{
+        get_register, get_this_reg, AddrModeParseType,
+        AssemblyErrorKind6502::{AddrModeUnsupported, Only65c02, ThisAddrModeUnsupported},
+        NodeKind6502, RegEnum,
+    },
+};
+
+use crate::help::ErrCode::ExpectedRegister6502;
+
+use unraveler::{
+    alt, map, match_span as ms, opt, pair, preceded, sep_list, sep_pair, tag, tuple, Collection,
+};
+
+/// Forcing of an address with '<' or '>'
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Force {
+    ZeroPage,
+    Absolute,
+}
+
+/// The opcode along with the cpu it was lexed for
+fn get_opcode(input: TSpan) -> PResult<(TSpan, String, &Instruction, CpuKind)> {
+    use CpuKind::{Cpu6502, Cpu65c02};
+    use TokenKind::OpCode;
+
+    let (rest, (sp, matched)) = ms(alt((OpCode(Cpu6502), OpCode(Cpu65c02))))(input)?;
+    let text = get_text(matched).to_lowercase();
+    let info = DBASE
+        .get_opcode(text.as_str())
+        .ok_or(err_kind_nomatch(sp))?;
+    let cpu = match matched.first().map(|t| t.kind) {
+        Some(OpCode(cpu)) => cpu,
+        _ => Cpu6502,
+    };
+    Ok((rest, (sp, text, info, cpu)))
+}
+
+/// Check this opcode is available on the cpu being assembled for
+fn check_cpu(sp: TSpan, cpu: CpuKind, data: &OpcodeData) -> FeResult<()> {
+    if data.is_65c02 && cpu != CpuKind::Cpu65c02 {
+        Err(fatal(sp, Only65c02))
+    } else {
+        Ok(())
+    }
+}
+
+/// Is this the end of an operand?
+/// Either the end of the line or the start of another opcode
+fn is_end_of_operand(input: TSpan) -> bool {
+    input
+        .first()
+        .map(|t| t.kind == TokenKind::Colon)
+        .unwrap_or(true)
+}
+
+fn parse_opcode_no_arg(input: TSpan) -> PResult<Node> {
+    use AddrModeParseType::*;
+
+    let (rest, (sp, text, ins, cpu)) = get_opcode(input)?;
+
+    let data = ins
+        .get_opcode_data(AddrModeEnum::Implied)
+        .map(|d| (d, Implied))
+        .or_else(|| {
+            ins.get_opcode_data(AddrModeEnum::Accumulator)
+                .map(|d| (d, Accumulator))
+        });
+
+    if let Some((data, amode)) = data {
+        check_cpu(sp, cpu, data)?;
+        let node = from_item_tspan(NodeKind6502::opcode(text, data, amode), sp);
+        Ok((rest, node))
+    } else {
+        err_fatal(sp, AddrModeUnsupported)
+    }
+}
+
+fn parse_force(input: TSpan) -> PResult<Force> {
+    use TokenKind::{GreaterThan, LessThan};
+    alt((
+        map(tag(LessThan), |_| Force::ZeroPage),
+        map(tag(GreaterThan), |_| Force::Absolute),
+    ))(input)
+}
+
+/// Parses for accumulator addressing
+/// ```    asl a```
+fn parse_accumulator(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, _)) = ms(get_this_reg(RegEnum::A))(input)?;
+
+    if is_end_of_operand(rest) {
+        Ok((rest, from_item_tspan(AddrModeParseType::Accumulator, sp)))
+    } else {
+        err_nomatch(input)
+    }
+}
+
+/// Parses for immediate addressing
+/// ```    #expr```
+fn parse_immediate(input: TSpan) -> PResult<Node> {
+    use TokenKind::Hash;
+    let (rest, (sp, matched)) = ms(preceded(Hash, parse_expr))(input)?;
+    let node = from_item_kid_tspan(AddrModeParseType::Immediate, matched, sp);
+    Ok((rest, node))
+}
+
+/// Parses for indexed indirect addressing
+/// ```    (zp,x)```
+fn parse_indexed_indirect(input: TSpan) -> PResult<Node> {
+    use TokenKind::{CloseBracket, Comma, OpenBracket};
+    let (rest, (sp, (_, matched, _, _, _))) = ms(tuple((
+        OpenBracket,
+        parse_expr,
+        Comma,
+        get_this_reg(RegEnum::X),
+        CloseBracket,
+    )))(input)?;
+    let node = from_item_kid_tspan(AddrModeParseType::IndexedIndirect, matched, sp);
+    Ok((rest, node))
+}
+
+/// Parses for indirect indexed addressing
+/// ```    (zp),y```
+fn parse_indirect_indexed(input: TSpan) -> PResult<Node> {
+    use TokenKind::{CloseBracket, Comma, OpenBracket};
+    let (rest, (sp, (_, matched, _, _, _))) = ms(tuple((
+        OpenBracket,
+        parse_expr,
+        CloseBracket,
+        Comma,
+        get_this_reg(RegEnum::Y),
+    )))(input)?;
+    let node = from_item_kid_tspan(AddrModeParseType::IndirectIndexed, matched, sp);
+    Ok((rest, node))
+}
+
+/// Parses for indirect addressing
+/// ```    (addr)```
+/// Only matches if the brackets enclose the whole operand
+/// otherwise this is a bracketed expression
+fn parse_indirect(input: TSpan) -> PResult<Node> {
+    use TokenKind::{CloseBracket, OpenBracket};
+    let (rest, (sp, (_, matched, _))) =
+        ms(tuple((OpenBracket, parse_expr, CloseBracket)))(input)?;
+
+    if is_end_of_operand(rest) {
+        let node = from_item_kid_tspan(AddrModeParseType::Indirect, matched, sp);
+        Ok((rest, node))
+    } else {
+        err_nomatch(input)
+    }
+}
+
+/// Parses for absolute, zero page and their indexed variants
+/// ```    addr```
+/// ```    <addr,x```
+/// ```    >addr,y```
+fn parse_address(input: TSpan) -> PResult<Node> {
+    use AddrModeParseType::*;
+    use TokenKind::Comma;
+
+    let (rest, (sp, ((force, matched), reg))) = ms(pair(
+        pair(opt(parse_force), parse_expr),
+        opt(preceded(Comma, get_register)),
+    ))(input)?;
+
+    let forced = force == Some(Force::Absolute);
+    let zero_page = force == Some(Force::ZeroPage);
+
+    let amode = match reg {
+        None if zero_page => ZeroPage,
+        None => Absolute(forced),
+        Some(RegEnum::X) if zero_page => ZeroPageX,
+        Some(RegEnum::X) => AbsoluteX(forced),
+        Some(RegEnum::Y) if zero_page => ZeroPageY,
+        Some(RegEnum::Y) => AbsoluteY(forced),
+        Some(RegEnum::A) => return err_fatal(sp, ExpectedRegister6502),
+    };
+
+    let node = from_item_kid_tspan(amode, matched, sp);
+    Ok((rest, node))
+}
+
+/// Parses for the zero page and branch target of bbr / bbs
+/// ```    zp,addr```
+fn parse_zero_page_relative(input: TSpan) -> PResult<Node> {
+    use TokenKind::Comma;
+    let (rest, (sp, (zp, target))) = ms(sep_pair(parse_expr, Comma, parse_expr))(input)?;
+    let node = from_item_kids_tspan(AddrModeParseType::ZeroPageRelative, &[zp, target], sp);
+    Ok((rest, node))
+}
+
+fn parse_opcode_arg(input: TSpan) -> PResult<Node> {
+    let (rest, matched) = alt((
+        parse_accumulator,
+        parse_immediate,
+        parse_indexed_indirect,
+        parse_indirect_indexed,
+        parse_indirect,
+        parse_address,
+    ))(input)?;
+
+    Ok((rest, matched))
+}
+
+/// Find the opcode for this parsed addressing mode
+/// Returns the addressing mode actually used as unforced absolute modes
+/// fall back to zero page if that is all the instruction supports
+/// and to relative for branches
+/// Bracketed modes fall back to the 65C02 (zp) and (abs,x) modes
+pub fn get_instruction(
+    amode: AddrModeParseType,
+    info: &Instruction,
+) -> Option<(&OpcodeData, AddrModeParseType)> {
+    use AddrModeParseType as PT;
+
+    let get = |amode: PT| info.get_opcode_data(amode.to_addr_mode()).map(|d| (d, amode));
+
+    match amode {
+        PT::Absolute(false) if info.supports(AddrModeEnum::Relative) => get(PT::Relative),
+        PT::Absolute(false) => get(amode).or_else(|| get(PT::ZeroPage)),
+        PT::AbsoluteX(false) => get(amode).or_else(|| get(PT::ZeroPageX)),
+        PT::AbsoluteY(false) => get(amode).or_else(|| get(PT::ZeroPageY)),
+        PT::Indirect => get(amode).or_else(|| get(PT::ZeroPageIndirect)),
+        PT::IndexedIndirect => get(amode).or_else(|| get(PT::AbsoluteIndexedIndirect)),
+        _ => get(amode),
+    }
+}
+
+fn parse_opcode_with_arg(input: TSpan) -> PResult<Node> {
+    use CpuSpecific::Cpu6502;
+    use NodeKind6502::Operand;
+
+    let (rest, (sp, text, info, cpu)) = get_opcode(input)?;
+
+    let (rest, arg) = if info.supports(AddrModeEnum::ZeroPageRelative) {
+        parse_zero_page_relative(rest)?
+    } else {
+        parse_opcode_arg(rest)?
+    };
+
+    if let AstNodeKind::TargetSpecific(Cpu6502(Operand(parsed_addressing_mode))) = arg.item {
+        if let Some((instruction, amode)) = get_instruction(parsed_addressing_mode, info) {
+            check_cpu(sp, cpu, instruction)?;
+            let item = NodeKind6502::opcode(text, instruction, amode);
+            let node = from_item_tspan(item, sp).take_others_children(arg);
+            Ok((rest, node))
+        } else {
+            err_fatal(sp, ThisAddrModeUnsupported(parsed_addressing_mode))
+        }
+    } else {
+        panic!()
+    }
+}
+
+fn parse_opcode(input: TSpan) -> PResult<Node> {
+    let (rest, item) = alt((parse_opcode_with_arg, parse_opcode_no_arg))(input)?;
+    Ok((rest, item))
+}
+
+pub fn parse_multi_opcode_vec(input: TSpan) -> PResult<Vec<Node>> {
+    let (rest, matched) = sep_list(parse_opcode, tag(TokenKind::Colon))(input)?;
+    Ok((rest, matched))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assembler::testing::cpu_opts, frontend::*};
+    use AddrModeParseType::*;
+
+    /// Addressing mode the parser picked for the first opcode in `text`
+    fn parse_amode(text: &str) -> AddrModeParseType {
+        let opts = cpu_opts(CpuKind::Cpu6502);
+        let sf = create_source_file(text);
+        let tokens = to_tokens_no_comment(&sf, opts.cpu);
+        let span = make_tspan(&tokens, &sf, &opts);
+        let (_, nodes) = parse_multi_opcode_vec(span).unwrap();
+
+        match &nodes[0].item {
+            AstNodeKind::TargetSpecific(CpuSpecific::Cpu6502(NodeKind6502::OpCode(_, _, am))) => {
+                *am
+            }
+            x => panic!("Not an opcode {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_zero_page() {
+        // Unforced addresses are left for the sizer to shrink
+        assert_eq!(parse_amode("lda $10"), Absolute(false));
+        assert_eq!(parse_amode("lda $10,x"), AbsoluteX(false));
+        assert_eq!(parse_amode("lda $10,y"), AbsoluteY(false));
+
+        assert_eq!(parse_amode("lda <$1234"), ZeroPage);
+        assert_eq!(parse_amode("lda <$10,x"), ZeroPageX);
+        assert_eq!(parse_amode("ldx <$10,y"), ZeroPageY);
+
+        assert_eq!(parse_amode("lda >$10"), Absolute(true));
+        assert_eq!(parse_amode("lda >$10,x"), AbsoluteX(true));
+
+        // stx only has zero page,y
+        assert_eq!(parse_amode("stx $10,y"), ZeroPageY);
+        assert_eq!(parse_amode("bne $10"), Relative);
+        assert_eq!(parse_amode("lda ($10),y"), IndirectIndexed);
+        assert_eq!(parse_amode("lda #$10"), Immediate);
+        assert_eq!(parse_amode("asl a"), Accumulator);
+    }
+
+    #[test]
+    fn test_65c02_opcodes() {
+        use crate::assembler::testing::{assemble_ok, bytes};
+
+        let text = "
+            org $1000
+            bra skip
+            stz $10
+            stz $1234
+            trb $10
+            tsb $1234
+            lda ($10)
+            bbr0 $10,skip
+            bbs7 $20,skip
+skip        rts
+            ";
+
+        let desired = [
+            0x80, 0x12, // bra
+            0x64, 0x10, // stz zp
+            0x9c, 0x34, 0x12, // stz abs
+            0x14, 0x10, // trb zp
+            0x0c, 0x34, 0x12, // tsb abs
+            0xb2, 0x10, // lda (zp)
+            0x0f, 0x10, 0x03, // bbr0 zp,rel
+            0xff, 0x20, 0x00, // bbs7 zp,rel
+            0x60, // rts
+        ];
+
+        let opts = cpu_opts(CpuKind::Cpu65c02);
+
+        let asm = assemble_ok(text, opts);
+        assert_eq!(bytes(&asm, 0x1000, desired.len()), desired);
+
+        // Or switched to from a plain 6502
+        let opts = cpu_opts(CpuKind::Cpu6502);
+
+        let text = "    org $1000\n    target 65c02\n    stz $10\n    phx\n";
+        let asm = assemble_ok(text, opts);
+        assert_eq!(bytes(&asm, 0x1000, 3), [0x64, 0x10, 0xda]);
+    }
+
+    #[test]
+    fn test_65c02_only() {
+        use crate::assembler::testing::assemble_err;
+
+        let opts = cpu_opts(CpuKind::Cpu6502);
+
+        for text in ["stz $10", "bra 0", "phx", "lda ($10)", "bbr0 $10,0", "trb $10"] {
+            let errs = assemble_err(&format!("    org $1000\n    {text}\n"), opts.clone());
+            assert_eq!(errs.len(), 1, "{text}");
+            assert!(errs[0].contains("only available on the 65C02"), "{text}");
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::str::FromStr;
+use strum_macros::EnumString;
+
+use crate::{
+    frontend::{err_error, error, get_label_string, PResult, TSpan},
+    help::ErrCode::ExpectedRegister6502,
+};
+
+use unraveler::match_span as ms;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum RegEnum {
+    A,
+    X,
+    Y,
+}
+
+pub fn parse_this_reg_local(input: TSpan, r: RegEnum) -> PResult<RegEnum> {
+    let (rest, (sp, matched)) = ms(get_register)(input)?;
+
+    if matched != r {
+        err_error(sp, ExpectedRegister6502)
+    } else {
+        Ok((rest, matched))
+    }
+}
+
+pub fn get_this_reg(r: RegEnum) -> impl FnMut(TSpan) -> PResult<RegEnum> + Copy {
+    move |i| parse_this_reg_local(i, r)
+}
+
+/// Parse a single register
+pub fn get_register(input: TSpan) -> PResult<RegEnum> {
+    let (rest, (sp, text)) = ms(get_label_string)(input)?;
+
+    RegEnum::from_str(text.as_str())
+        .map(|reg| (rest, reg))
+        .map_err(|_| error(sp, ExpectedRegister6502))
+}
@@ -0,0 +1,5 @@
+mod assembler6502;
+pub mod frontend;
+pub mod assembler;
+
+pub use assembler6502::*;
@@ -1,12 +1,12 @@
-use crate::{cpu6800::frontend::NodeKind6800, semantic::AstNodeRef, };
-
-use emu6800::cpu_core::{AddrModeEnum, InstructionInfo, DBASE};
-
 use crate::{
     assembler::{Assembler, BinaryError},
-    error::{GResult, GazmErrorKind},
+    cpu6800::{
+        assembler::{AddrModeEnum, InstructionInfo, DBASE},
+        frontend::NodeKind6800,
+    },
+    error::GResult,
+    semantic::AstNodeRef,
 };
-/// Compile an opcode
 
 impl Assembler {
     pub fn compile_operand_6800(
@@ -17,33 +17,53 @@ impl Assembler {
         current_scope_id: u64,
     ) -> GResult<()> {
         match ins.addr_mode {
-            AddrModeEnum::Indexed | AddrModeEnum::Direct | AddrModeEnum::Immediate8 => {
-                let (arg, _id) = self.eval_first_arg(node, current_scope_id)?;
-                self.get_binary_mut().write_byte_check_size(arg)?;
+            AddrModeEnum::Immediate8 => {
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                self.write_byte_check_size(arg, node)?;
+            }
+
+            AddrModeEnum::Indexed | AddrModeEnum::Direct => {
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                let res = self.get_binary_mut().write_ubyte_check_size(arg);
+                self.binary_error_map(node, res)?;
             }
 
             AddrModeEnum::Extended => {
-                let (arg, _id) = self.eval_first_arg(node, current_scope_id)?;
-                self.get_binary_mut().write_word_check_size(arg)?;
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                let res = self.get_binary_mut().write_uword_check_size(arg);
+                self.binary_error_map(node, res)?;
             }
 
             AddrModeEnum::Immediate16 => {
-                let (arg, _id) = self.eval_first_arg(node, current_scope_id)?;
-                self.get_binary_mut().write_word_check_size(arg)?;
+                let (arg, _) = self.eval_first_arg(node, current_scope_id)?;
+                self.write_word_check_size(arg, node)?;
             }
 
             AddrModeEnum::Inherent => (),
 
             AddrModeEnum::Relative => {
-                let size = ins.opcode_data.size as i64;
-                let (arg, _arg_id) = self.eval_first_arg(node, current_scope_id)?;
-
-                let val = arg - (pc + size);
-                let binary = &mut self.asm_out.binary;
-                binary.write_ibyte_check_size(val)?;
+                let (arg, arg_n) = self.eval_first_arg_n(node, current_scope_id)?;
+                // offset is from PC after Instruction and operand has been fetched
+                let val = arg - (pc + ins.opcode_data.size as i64);
+                let res = self.get_binary_mut().write_ibyte_check_size(val);
+
+                match res {
+                    Ok(_) => (),
+                    Err(BinaryError::DoesNotFit { .. }) => {
+                        if self.opts.ignore_relative_offset_errors {
+                            let res = self.get_binary_mut().write_ibyte_check_size(0);
+                            self.binary_error_map(node, res)?;
+                        } else {
+                            return Err(self.relative_error(arg_n, val, 8));
+                        }
+                    }
+                    Err(e) => return Err(self.binary_error(node, e)),
+                }
             }
 
-            AddrModeEnum::Illegal => todo!(),
+            AddrModeEnum::Illegal => {
+                return Err(self.make_user_error("Illegal instruction", node, true).into())
+            }
         };
 
         Ok(())
@@ -57,22 +77,8 @@ impl Assembler {
         current_scope_id: u64,
     ) -> GResult<()> {
         let pc = self.get_binary().get_write_address() as i64;
-        self.get_binary_mut()
-            .write_byte(ins.opcode_data.opcode as u8)?;
-
-        let ret = self.compile_operand_6800(node, ins, pc, current_scope_id);
-
-        match ret {
-            Ok(()) => Ok(()),
-            Err(GazmErrorKind::BinaryError(BinaryError::DoesNotMatchReference(..))) => {
-                eprintln!("Waring!");
-                Ok(())
-            }
-            Err(_) => {
-                println!("ret {ret:?}");
-                ret
-            }
-        }
+        self.write_byte(ins.opcode_data.opcode as u8, node)?;
+        self.compile_operand_6800(node, ins, pc, current_scope_id)
     }
 
     /// Compile a node
@@ -84,9 +90,8 @@ impl Assembler {
     ) -> GResult<()> {
         use NodeKind6800::*;
 
-
         match node_kind {
-            OpCode(_, ins) => {
+            OpCode(_, ins, _) => {
                 let ins = DBASE.get_instruction_info_from_opcode(ins.opcode).unwrap();
                 self.compile_opcode_6800(node, ins, current_scope_id)?;
             }
@@ -1 +1,30 @@
+/// Instruction set database for the 6800
+/// The opcode tables live in the emulator crate
+pub use emu6800::cpu_core::{AddrModeEnum, Instruction, InstructionInfo, OpcodeData, DBASE};
 
+use itertools::Itertools;
+
+/// Every 6800 mnemonic the instruction database knows, used for completion
+pub fn mnemonics() -> Vec<String> {
+    DBASE
+        .all_instructions()
+        .iter()
+        .map(|i| i.mnemonic.to_lowercase())
+        .filter(|m| DBASE.get_opcode(m).is_some())
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// The direct addressing version of an instruction, if it has one
+pub fn get_direct_opcode(text: &str) -> Option<&'static OpcodeData> {
+    DBASE
+        .get_opcode(text)
+        .and_then(|i| i.get_opcode_data(AddrModeEnum::Direct))
+}
+
+/// Can this address be reached with direct addressing?
+/// The 6800 direct page is always page zero
+pub fn is_direct_page(addr: i64) -> bool {
+    (0..0x100).contains(&addr)
+}
@@ -1,6 +1,3 @@
-use emu6800::cpu::ISA_DBASE;
-use emu6800::cpu_core::{AddrModeEnum, DBASE};
-
 use crate::debug_mess;
 use crate::{
     assembler::{Assembler, Sizer},
@@ -8,7 +5,10 @@ use crate::{
     semantic::AstNodeId,
 };
 
-use crate::cpu6800::frontend::NodeKind6800;
+use crate::cpu6800::{
+    assembler::{get_direct_opcode, is_direct_page},
+    frontend::{AddrModeParseType, NodeKind6800},
+};
 
 impl Assembler {
     pub fn size_node_6800(
@@ -36,36 +36,31 @@ fn size_node_internal(
     match &node_kind {
         Illegal => todo!(),
 
-        OpCode(text, ins) => {
+        OpCode(text, ins, amode) => {
             // get the size of this instruction
             let mut size = ins.size;
 
-            let ins_info = ISA_DBASE
-                .get_instruction_info_from_opcode(ins.opcode)
-                .unwrap();
-
-            if ins_info.addr_mode == AddrModeEnum::Extended {
-                // Is this extend addressing and we support direct?
-                // If see evaluate the operand and see if the result is
+            if amode.can_be_direct() {
+                // Is this extended addressing and we support direct?
+                // If so evaluate the operand and see if the result is
                 // in the first page
                 // If it is we can do direct addressing
 
-                let new_ins = DBASE
-                    .get_instruction_info_from_opcode(ins.opcode)
-                    .and_then(|i_type| i_type.instruction.get_opcode_data(AddrModeEnum::Direct));
-
-                if let Some(new_ins) = new_ins {
+                if let Some(new_ins) = get_direct_opcode(text) {
                     if let Ok((value, _)) = asm.eval_first_arg(node, current_scope_id) {
-                        if ((value as u64) >> 8) & 0xff == 0 {
+                        if is_direct_page(value) {
                             let src = asm.get_source_info(&node.value().pos);
 
                             if let Ok(src) = src {
                                 debug_mess!("Xformed from Extended to Direct :  {}", src.line_str);
                             }
 
-                            let new_ins = new_ins.clone();
                             size = new_ins.size;
-                            let new_item = OpCode(text.clone(), new_ins.into());
+                            let new_item = OpCode(
+                                text.clone(),
+                                new_ins.clone().into(),
+                                AddrModeParseType::Direct,
+                            );
 
                             asm.add_fixup(id, new_item, current_scope_id);
                         }
@@ -80,3 +75,78 @@ fn size_node_internal(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{assembler::testing::*, cpukind::CpuKind};
+
+    #[test]
+    fn test_direct_selection() {
+        let text = "
+            org $100
+            ldaa $10
+            ldaa $1234
+            ldaa >$10
+            ldaa <$10
+            ldaa #$10
+            ";
+
+        let asm = assemble_ok(text, cpu_opts(CpuKind::Cpu6800));
+
+        let desired = [
+            0x96, 0x10, // ldaa direct
+            0xb6, 0x12, 0x34, // ldaa extended
+            0xb6, 0x00, 0x10, // ldaa >extended
+            0x96, 0x10, // ldaa <direct
+            0x86, 0x10, // ldaa #imm
+        ];
+
+        assert_eq!(bytes(&asm, 0x100, desired.len()), desired);
+    }
+
+    #[test]
+    fn test_forward_reference_to_direct() {
+        let text = "
+            org 0
+            staa later
+            rts
+later
+            fcb 1
+            ";
+
+        let asm = assemble_ok(text, cpu_opts(CpuKind::Cpu6800));
+        assert_eq!(symbol(&asm, "later"), Some(3));
+        assert_eq!(bytes(&asm, 0, 4), [0x97, 0x03, 0x39, 0x01]);
+    }
+
+    #[test]
+    fn test_mixed_6809_6800() {
+        // setdp only moves the 6809's direct page
+        // the 6800's is always page zero
+        let text = "
+            org $1000
+            setdp $20
+            lda $2010
+            lda $10
+            target 6800
+            staa $10
+            staa $2010
+            target 6809
+            sta $2010
+            sta $10
+            ";
+
+        let asm = assemble_ok(text, cpu_opts(CpuKind::Cpu6809));
+
+        let desired = [
+            0x96, 0x10, // 6809 lda direct
+            0xb6, 0x00, 0x10, // 6809 lda extended
+            0x97, 0x10, // 6800 staa direct
+            0xb7, 0x20, 0x10, // 6800 staa extended
+            0x97, 0x10, // 6809 sta direct
+            0xb7, 0x00, 0x10, // 6809 sta extended
+        ];
+
+        assert_eq!(bytes(&asm, 0x1000, desired.len()), desired);
+    }
+}
@@ -1,50 +1,43 @@
-use std::env::current_exe;
-
-use num_traits::cast;
-
 use crate::{
-    assembler::{ AssemblerCpuTrait,Assembler,Compiler },
+    assembler::{Assembler, AssemblerCpuTrait, OpcodeModeInfo, Sizer},
     error::GResult,
-    frontend::{PResult, TSpan, TokenKind, CpuSpecific, AstNodeKind, Node},
-    semantic::{ AstNodeRef, AstNodeId },
+    frontend::{CpuSpecific, Node, PResult, TSpan, TokenKind},
+    semantic::{AstNodeId, AstNodeRef},
 };
 
 use super::{
-    frontend::{lex_identifier, parse_multi_opcode_vec, NodeKind6800},
+    assembler::{mnemonics, AddrModeEnum, DBASE},
+    frontend::{lex_identifier, parse_commands, parse_multi_opcode_vec},
 };
 
-
 #[derive(PartialEq, Debug, Default, Clone)]
 pub struct Asm6800 {}
 
 impl Asm6800 {
     pub fn new() -> Self {
-        panic!()
+        Self {}
     }
 }
 
-
 impl AssemblerCpuTrait for Asm6800 {
-    // type NodeKind = NodeKind6800;
-
     fn get_cpu_name(&self) -> &'static str {
         "6800"
     }
 
     fn size_node(
         &self,
-        _sizer: &mut crate::assembler::Sizer,
-        _asm: &mut Assembler,
-        _id: crate::semantic::AstNodeId,
-        _node_kind: CpuSpecific,
-        _current_scope_id: u64
-
+        sizer: &mut Sizer,
+        asm: &mut Assembler,
+        id: AstNodeId,
+        node_kind: CpuSpecific,
+        current_scope_id: u64,
     ) -> GResult<()> {
-        panic!()
-        // match node_kind {
-        //     CpuSpecific::Cpu6800(node_kind) => size_node_internal(sizer, asm, id, node_kind),
-        //     _ => panic!(),
-        // }
+        match node_kind {
+            CpuSpecific::Cpu6800(node_kind) => {
+                asm.size_node_6800(sizer, id, node_kind, current_scope_id)
+            }
+            _ => panic!(),
+        }
     }
 
     fn compile_node(
@@ -52,21 +45,45 @@ impl AssemblerCpuTrait for Asm6800 {
         asm: &mut Assembler,
         node: AstNodeRef,
         node_kind: CpuSpecific,
-        current_scope_id: u64
-    ) -> crate::error::GResult<()> {
-
+        current_scope_id: u64,
+    ) -> GResult<()> {
         match node_kind {
-            CpuSpecific::Cpu6800(node_kind) => asm.compile_node_6800( node_kind, node,current_scope_id),
+            CpuSpecific::Cpu6800(node_kind) => {
+                asm.compile_node_6800(node_kind, node, current_scope_id)
+            }
             _ => panic!(),
         }
     }
 
-    fn parse_multi_opcode_vec(&self,_input: crate::frontend::TSpan) -> PResult<Vec<Node>> {
-        todo!()
-        // parse_multi_opcode_vec(_input)
+    fn parse_multi_opcode_vec(&self, input: TSpan) -> PResult<Vec<Node>> {
+        parse_multi_opcode_vec(input)
+    }
+
+    fn parse_commands(&self, input: TSpan) -> PResult<Node> {
+        parse_commands(input)
+    }
+
+    fn lex_identifier(&self, id: &str) -> TokenKind {
+        lex_identifier(id)
+    }
+
+    fn get_opcode_info(&self, text: &str) -> Vec<OpcodeModeInfo> {
+        use AddrModeEnum::*;
+        let modes = [Inherent, Immediate8, Immediate16, Direct, Indexed, Extended, Relative];
+
+        DBASE
+            .get_opcode(text)
+            .map(|ins| {
+                modes
+                    .iter()
+                    .filter_map(|m| ins.get_opcode_data(*m))
+                    .map(|d| OpcodeModeInfo::new(format!("{:?}", d.addr_mode), Some(d.cycles)))
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
-    fn lex_identifier(&self,_id: &str) -> TokenKind {
-        lex_identifier(_id)
+    fn get_mnemonics(&self) -> Vec<String> {
+        mnemonics()
     }
 }
@@ -1,9 +1,7 @@
-use crate::error::ParseError;
-use crate::frontend::{Node,CommandKind, AstNodeKind, PResult, TSpan, FrontEndErrorKind, err_fatal, err_error};
+use crate::frontend::{err_error, Node, PResult, TSpan};
 
+use unraveler::ParseErrorKind;
 
-use unraveler::{cut, match_span as ms, preceded, ParseErrorKind, };
-
-pub fn parse_commands(_input: TSpan) -> PResult<Node> {
-    err_error(_input, ParseErrorKind::NoMatch)
+pub fn parse_commands(input: TSpan) -> PResult<Node> {
+    err_error(input, ParseErrorKind::NoMatch)
 }
@@ -2,8 +2,6 @@ use thiserror::Error;
 
 use crate::cpu6800::frontend::AddrModeParseType;
 
-// use super::AddrModeParseType;
-
 // TODO Remove all of this, replace with help text
 #[derive(Debug, Error, Clone, PartialEq, Copy)]
 pub enum AssemblyErrorKind6800 {
@@ -1,7 +1,6 @@
-
 use emu6800::cpu_core::DBASE;
 
-use crate::frontend::CommandKind;
+use crate::cpukind::CpuKind;
 use crate::frontend::TokenKind;
 
 pub fn lex_identifier(text: &str) -> TokenKind {
@@ -10,7 +9,7 @@ pub fn lex_identifier(text: &str) -> TokenKind {
     let text = text.to_lowercase();
 
     if DBASE.get_opcode(&text).is_some() {
-        TokenKind::OpCode(crate::cpukind::CpuKind::Cpu6800)
+        OpCode(CpuKind::Cpu6800)
     } else {
         Label
     }
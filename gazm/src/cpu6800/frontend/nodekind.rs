@@ -1,30 +1,46 @@
-use emu6800::cpu_core::{AddrModeEnum, OpcodeData, RegEnum, DBASE};
-
-use crate::cpu6800::Asm6800;
-use crate::frontend::{ GazmParser, CpuSpecific, AstNodeKind, Node };
+use emu6800::cpu_core::OpcodeData;
 
+use crate::frontend::{AstNodeKind, CpuSpecific};
 
+/// Addressing mode as parsed from source
+/// Extended carries a flag saying if it was forced with '>'
+/// forced extended addressing is never demoted to direct
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum AddrModeParseType {
     Indexed,
     Direct,
-    Extended,
+    Extended(bool),
     Relative,
     Inherent,
     Immediate,
 }
 
+impl AddrModeParseType {
+    /// Can the sizer swap this for direct addressing?
+    pub fn can_be_direct(&self) -> bool {
+        matches!(self, AddrModeParseType::Extended(false))
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Default)]
 pub enum NodeKind6800 {
     #[default]
     Illegal,
-    OpCode(Box<String>, Box<OpcodeData>),
+    OpCode(Box<String>, Box<OpcodeData>, AddrModeParseType),
     Operand(AddrModeParseType),
 }
 
 impl NodeKind6800 {
-    pub fn opcode<T: Into<String>>(name : T, opcode_data: &OpcodeData) -> Self {
-        NodeKind6800::OpCode(Box::new(name.into()), Box::new(opcode_data.clone()))
+    pub fn opcode<T: Into<String>>(
+        name: T,
+        opcode_data: &OpcodeData,
+        amode: AddrModeParseType,
+    ) -> Self {
+        NodeKind6800::OpCode(
+            Box::new(name.into()),
+            Box::new(opcode_data.clone()),
+            amode,
+        )
     }
 }
 
@@ -39,4 +55,3 @@ impl From<AddrModeParseType> for AstNodeKind {
         NodeKind6800::Operand(value).into()
     }
 }
-
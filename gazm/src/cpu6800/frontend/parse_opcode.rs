@@ -1,22 +1,17 @@
 use crate::frontend::{
-    err_fatal, err_kind_nomatch, err_nomatch, from_item_tspan, get_text, is_parsing_macro_def,
-    parse_expr, AstNodeKind, FrontEndError, FrontEndErrorKind, Node, PResult, TSpan, TokenKind,
+    err_fatal, err_kind_nomatch, from_item_tspan, get_text, parse_expr, AstNodeKind, CpuSpecific,
+    Node, PResult, TSpan, TokenKind,
 };
 
 use crate::cpukind::CpuKind::Cpu6800 as Cpu;
 
-use crate::cpu6800::{
-    frontend::{
-        error::AssemblyErrorKind6800::OnlySupports,
-        get_this_reg, AddrModeParseType,
-        NodeKind6800::{self, OpCode, Operand},
-    },
-    Asm6800,
+use crate::cpu6800::frontend::{
+    error::AssemblyErrorKind6800::{OnlySupports, ThisAddrModeUnsupported},
+    get_this_reg, AddrModeParseType, NodeKind6800,
 };
 
-use emu6800::cpu_core::{AddrModeEnum, Instruction, InstructionInfo, OpcodeData, RegEnum, DBASE};
+use emu6800::cpu_core::{AddrModeEnum, Instruction, OpcodeData, RegEnum, DBASE};
 
-use serde_json::value::Index;
 use unraveler::{alt, match_span as ms, opt, preceded, sep_list, sep_pair, tag};
 
 fn get_opcode(input: TSpan) -> PResult<(TSpan, String, &Instruction)> {
@@ -29,14 +24,15 @@ fn get_opcode(input: TSpan) -> PResult<(TSpan, String, &Instruction)> {
 }
 
 fn parse_opcode_no_arg(input: TSpan) -> PResult<Node> {
+    use AddrModeParseType::Inherent;
+
     let (rest, (sp, text, ins)) = get_opcode(input)?;
 
     if let Some(ins) = ins.get_opcode_data(AddrModeEnum::Inherent) {
-        let oc = OpCode(text.into(), ins.clone().into());
-        let node = from_item_tspan(oc, sp);
+        let node = from_item_tspan(NodeKind6800::opcode(text, ins, Inherent), sp);
         Ok((rest, node))
     } else {
-        err_fatal(sp, OnlySupports(AddrModeParseType::Inherent))
+        err_fatal(sp, OnlySupports(Inherent))
     }
 }
 
@@ -75,14 +71,14 @@ fn parse_force_extended(input: TSpan) -> PResult<Node> {
     use AddrModeParseType::*;
     use TokenKind::GreaterThan;
     let (rest, (sp, matched)) = ms(preceded(GreaterThan, parse_expr))(input)?;
-    let node = from_item_tspan(Extended, sp).with_child(matched);
+    let node = from_item_tspan(Extended(true), sp).with_child(matched);
     Ok((rest, node))
 }
 
 fn parse_extended(input: TSpan) -> PResult<Node> {
     use AddrModeParseType::*;
     let (rest, (sp, matched)) = ms(parse_expr)(input)?;
-    let node = from_item_tspan(Extended, sp).with_child(matched);
+    let node = from_item_tspan(Extended(false), sp).with_child(matched);
     Ok((rest, node))
 }
 
@@ -98,44 +94,51 @@ fn parse_opcode_arg(input: TSpan) -> PResult<Node> {
     Ok((rest, matched))
 }
 
-fn get_instruction(amode: AddrModeParseType, info: &Instruction) -> Option<&OpcodeData> {
+/// Find the opcode for this parsed addressing mode
+/// Returns the addressing mode actually used as unforced extended
+/// addressing is relative for branches and falls back to direct
+/// if that is all the instruction supports
+fn get_instruction(
+    amode: AddrModeParseType,
+    info: &Instruction,
+) -> Option<(&OpcodeData, AddrModeParseType)> {
     use AddrModeEnum::*;
     use AddrModeParseType as PT;
     let get = |amode| info.get_opcode_data(amode);
 
     match amode {
-        PT::Indexed => get(Indexed),
-        PT::Direct => get(Direct),
-        PT::Extended => get(Extended),
-        PT::Relative => get(Relative),
-        PT::Inherent => get(Inherent),
-        PT::Immediate => get(Immediate8).or_else(|| get(Immediate16)),
+        PT::Extended(false) if info.supports(Relative) => {
+            get(Relative).map(|d| (d, PT::Relative))
+        }
+        PT::Extended(false) => get(Extended)
+            .map(|d| (d, amode))
+            .or_else(|| get(Direct).map(|d| (d, PT::Direct))),
+        PT::Extended(true) => get(Extended).map(|d| (d, amode)),
+        PT::Indexed => get(Indexed).map(|d| (d, amode)),
+        PT::Direct => get(Direct).map(|d| (d, amode)),
+        PT::Relative => get(Relative).map(|d| (d, amode)),
+        PT::Inherent => get(Inherent).map(|d| (d, amode)),
+        PT::Immediate => get(Immediate8)
+            .or_else(|| get(Immediate16))
+            .map(|d| (d, amode)),
     }
 }
 
 fn parse_opcode_with_arg(input: TSpan) -> PResult<Node> {
-    use crate::frontend::CpuSpecific;
-    use CpuSpecific::Cpu6800 as Cpu;
-    use NodeKind6800::{OpCode, Operand};
+    use CpuSpecific::Cpu6800;
+    use NodeKind6800::Operand;
 
     let (rest, (sp, text, info)) = get_opcode(input)?;
 
     let (rest, arg) = parse_opcode_arg(rest)?;
 
-    if let AstNodeKind::TargetSpecific(Cpu(Operand(parsed_addressing_mode))) = arg.item {
-        if info.supports(AddrModeEnum::Relative)
-            && parsed_addressing_mode == AddrModeParseType::Extended
-        {
-            let instruction = get_instruction(AddrModeParseType::Relative, info).unwrap();
-            let item = NodeKind6800::opcode(text, instruction);
-            let node = from_item_tspan(item, sp).take_others_children(arg);
-            Ok((rest, node))
-        } else if let Some(instruction) = get_instruction(parsed_addressing_mode, info) {
-            let item = NodeKind6800::opcode(text, instruction);
+    if let AstNodeKind::TargetSpecific(Cpu6800(Operand(parsed_addressing_mode))) = arg.item {
+        if let Some((instruction, amode)) = get_instruction(parsed_addressing_mode, info) {
+            let item = NodeKind6800::opcode(text, instruction, amode);
             let node = from_item_tspan(item, sp).take_others_children(arg);
             Ok((rest, node))
         } else {
-            err_fatal(sp, FrontEndErrorKind::Unexpected)
+            err_fatal(sp, ThisAddrModeUnsupported(parsed_addressing_mode))
         }
     } else {
         panic!()
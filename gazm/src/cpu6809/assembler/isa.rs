@@ -1,5 +1,18 @@
 use emu6809::isa::Dbase;
+use itertools::Itertools;
 
 lazy_static::lazy_static! {
     pub static ref ISA_DBASE : Dbase = Dbase::new();
 }
+
+/// Every 6809 mnemonic the instruction database knows, used for completion
+pub fn mnemonics() -> Vec<String> {
+    ISA_DBASE
+        .all_instructions()
+        .iter()
+        .map(|i| i.action.to_lowercase())
+        .filter(|m| ISA_DBASE.get_opcode(m).is_some())
+        .sorted()
+        .dedup()
+        .collect()
+}
@@ -0,0 +1,263 @@
+/// Instruction set additions for the Hitachi 6309
+/// The 6309 runs all 6809 code, these are only the extra opcodes
+/// The 6809 opcode tables come from the emulator crate
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+/// Transfer mode of TFM
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TfmMode {
+    /// r0+,r1+
+    IncInc,
+    /// r0-,r1-
+    DecDec,
+    /// r0+,r1
+    IncNone,
+    /// r0,r1+
+    NoneInc,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AddrMode6309 {
+    Inherent,
+    Immediate8,
+    Immediate16,
+    Immediate32,
+    Direct,
+    Indexed,
+    Extended,
+    RegisterPair,
+    Tfm(TfmMode),
+    /// band a,1,2,<dp
+    BitDirect,
+    /// aim #imm,<dp
+    ImmDirect,
+    /// aim #imm,idx
+    ImmIndexed,
+    /// aim #imm,addr
+    ImmExtended,
+}
+
+impl AddrMode6309 {
+    /// Number of operand bytes that follow the opcode
+    /// Indexed modes only count the post byte, the sizer adds any offset
+    pub fn operand_size(&self) -> usize {
+        use AddrMode6309::*;
+        match self {
+            Inherent => 0,
+            Immediate8 | Direct | Indexed | RegisterPair | Tfm(..) => 1,
+            Immediate16 | Extended | BitDirect | ImmDirect | ImmIndexed => 2,
+            ImmExtended => 3,
+            Immediate32 => 4,
+        }
+    }
+
+    /// The direct page equivalent of an extended addressing mode
+    pub fn to_direct(&self) -> Option<AddrMode6309> {
+        use AddrMode6309::*;
+        match self {
+            Extended => Some(Direct),
+            ImmExtended => Some(ImmDirect),
+            _ => None,
+        }
+    }
+
+    /// Does this mode have an immediate byte as well as a memory operand?
+    pub fn is_in_memory(&self) -> bool {
+        use AddrMode6309::*;
+        matches!(self, ImmDirect | ImmIndexed | ImmExtended)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction6309 {
+    pub mnemonic: &'static str,
+    pub opcode: u16,
+    pub addr_mode: AddrMode6309,
+    pub size: usize,
+}
+
+impl Instruction6309 {
+    fn new(mnemonic: &'static str, addr_mode: AddrMode6309, opcode: u16) -> Self {
+        let opcode_size = if opcode > 0xff { 2 } else { 1 };
+        Self {
+            mnemonic,
+            opcode,
+            addr_mode,
+            size: opcode_size + addr_mode.operand_size(),
+        }
+    }
+}
+
+pub struct Dbase6309 {
+    instructions: HashMap<&'static str, Vec<Instruction6309>>,
+}
+
+impl Dbase6309 {
+    pub fn new(table: &[(&'static str, AddrMode6309, u16)]) -> Self {
+        let mut instructions: HashMap<&'static str, Vec<Instruction6309>> = HashMap::new();
+
+        for (mnemonic, addr_mode, opcode) in table {
+            instructions
+                .entry(mnemonic)
+                .or_default()
+                .push(Instruction6309::new(mnemonic, *addr_mode, *opcode));
+        }
+
+        Self { instructions }
+    }
+
+    pub fn get_opcode(&self, text: &str) -> Option<&[Instruction6309]> {
+        self.instructions.get(text).map(|v| v.as_slice())
+    }
+
+    pub fn mnemonics(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.instructions.keys().copied()
+    }
+
+    pub fn get_instruction(&self, text: &str, amode: AddrMode6309) -> Option<&Instruction6309> {
+        self.get_opcode(text)
+            .and_then(|v| v.iter().find(|i| i.addr_mode == amode))
+    }
+
+    pub fn supports(&self, text: &str, amode: AddrMode6309) -> bool {
+        self.get_instruction(text, amode).is_some()
+    }
+
+    /// Is this a memory operation with an immediate byte, aim, oim etc?
+    pub fn is_in_memory(&self, text: &str) -> bool {
+        self.get_opcode(text)
+            .map(|v| v.iter().any(|i| i.addr_mode.is_in_memory()))
+            .unwrap_or(false)
+    }
+}
+
+lazy_static! {
+    pub static ref DBASE_6309: Dbase6309 = Dbase6309::new(OPCODES_6309);
+}
+
+use AddrMode6309::*;
+
+/// Mnemonic, addressing mode, opcode
+#[rustfmt::skip]
+pub static OPCODES_6309: &[(&str, AddrMode6309, u16)] = &[
+    ("sexw", Inherent, 0x14),
+
+    ("oim", ImmDirect, 0x01), ("oim", ImmIndexed, 0x61), ("oim", ImmExtended, 0x71),
+    ("aim", ImmDirect, 0x02), ("aim", ImmIndexed, 0x62), ("aim", ImmExtended, 0x72),
+    ("eim", ImmDirect, 0x05), ("eim", ImmIndexed, 0x65), ("eim", ImmExtended, 0x75),
+    ("tim", ImmDirect, 0x0b), ("tim", ImmIndexed, 0x6b), ("tim", ImmExtended, 0x7b),
+
+    ("ldq", Immediate32, 0xcd),
+
+    ("addr", RegisterPair, 0x1030), ("adcr", RegisterPair, 0x1031),
+    ("subr", RegisterPair, 0x1032), ("sbcr", RegisterPair, 0x1033),
+    ("andr", RegisterPair, 0x1034), ("orr", RegisterPair, 0x1035),
+    ("eorr", RegisterPair, 0x1036), ("cmpr", RegisterPair, 0x1037),
+
+    ("pshsw", Inherent, 0x1038), ("pulsw", Inherent, 0x1039),
+    ("pshuw", Inherent, 0x103a), ("puluw", Inherent, 0x103b),
+
+    ("negd", Inherent, 0x1040), ("comd", Inherent, 0x1043), ("lsrd", Inherent, 0x1044),
+    ("rord", Inherent, 0x1046), ("asrd", Inherent, 0x1047), ("asld", Inherent, 0x1048),
+    ("lsld", Inherent, 0x1048), ("rold", Inherent, 0x1049), ("decd", Inherent, 0x104a),
+    ("incd", Inherent, 0x104c), ("tstd", Inherent, 0x104d), ("clrd", Inherent, 0x104f),
+
+    ("comw", Inherent, 0x1053), ("lsrw", Inherent, 0x1054), ("rorw", Inherent, 0x1056),
+    ("rolw", Inherent, 0x1059), ("decw", Inherent, 0x105a), ("incw", Inherent, 0x105c),
+    ("tstw", Inherent, 0x105d), ("clrw", Inherent, 0x105f),
+
+    ("subw", Immediate16, 0x1080), ("subw", Direct, 0x1090),
+    ("subw", Indexed, 0x10a0), ("subw", Extended, 0x10b0),
+    ("cmpw", Immediate16, 0x1081), ("cmpw", Direct, 0x1091),
+    ("cmpw", Indexed, 0x10a1), ("cmpw", Extended, 0x10b1),
+    ("sbcd", Immediate16, 0x1082), ("sbcd", Direct, 0x1092),
+    ("sbcd", Indexed, 0x10a2), ("sbcd", Extended, 0x10b2),
+    ("andd", Immediate16, 0x1084), ("andd", Direct, 0x1094),
+    ("andd", Indexed, 0x10a4), ("andd", Extended, 0x10b4),
+    ("bitd", Immediate16, 0x1085), ("bitd", Direct, 0x1095),
+    ("bitd", Indexed, 0x10a5), ("bitd", Extended, 0x10b5),
+    ("ldw", Immediate16, 0x1086), ("ldw", Direct, 0x1096),
+    ("ldw", Indexed, 0x10a6), ("ldw", Extended, 0x10b6),
+    ("stw", Direct, 0x1097), ("stw", Indexed, 0x10a7), ("stw", Extended, 0x10b7),
+    ("eord", Immediate16, 0x1088), ("eord", Direct, 0x1098),
+    ("eord", Indexed, 0x10a8), ("eord", Extended, 0x10b8),
+    ("adcd", Immediate16, 0x1089), ("adcd", Direct, 0x1099),
+    ("adcd", Indexed, 0x10a9), ("adcd", Extended, 0x10b9),
+    ("ord", Immediate16, 0x108a), ("ord", Direct, 0x109a),
+    ("ord", Indexed, 0x10aa), ("ord", Extended, 0x10ba),
+    ("addw", Immediate16, 0x108b), ("addw", Direct, 0x109b),
+    ("addw", Indexed, 0x10ab), ("addw", Extended, 0x10bb),
+
+    ("ldq", Direct, 0x10dc), ("ldq", Indexed, 0x10ec), ("ldq", Extended, 0x10fc),
+    ("stq", Direct, 0x10dd), ("stq", Indexed, 0x10ed), ("stq", Extended, 0x10fd),
+
+    ("band", BitDirect, 0x1130), ("biand", BitDirect, 0x1131),
+    ("bor", BitDirect, 0x1132), ("bior", BitDirect, 0x1133),
+    ("beor", BitDirect, 0x1134), ("bieor", BitDirect, 0x1135),
+    ("ldbt", BitDirect, 0x1136), ("stbt", BitDirect, 0x1137),
+
+    ("tfm", Tfm(TfmMode::IncInc), 0x1138), ("tfm", Tfm(TfmMode::DecDec), 0x1139),
+    ("tfm", Tfm(TfmMode::IncNone), 0x113a), ("tfm", Tfm(TfmMode::NoneInc), 0x113b),
+
+    ("bitmd", Immediate8, 0x113c), ("ldmd", Immediate8, 0x113d),
+
+    ("come", Inherent, 0x1143), ("dece", Inherent, 0x114a), ("ince", Inherent, 0x114c),
+    ("tste", Inherent, 0x114d), ("clre", Inherent, 0x114f),
+
+    ("comf", Inherent, 0x1153), ("decf", Inherent, 0x115a), ("incf", Inherent, 0x115c),
+    ("tstf", Inherent, 0x115d), ("clrf", Inherent, 0x115f),
+
+    ("sube", Immediate8, 0x1180), ("sube", Direct, 0x1190),
+    ("sube", Indexed, 0x11a0), ("sube", Extended, 0x11b0),
+    ("cmpe", Immediate8, 0x1181), ("cmpe", Direct, 0x1191),
+    ("cmpe", Indexed, 0x11a1), ("cmpe", Extended, 0x11b1),
+    ("lde", Immediate8, 0x1186), ("lde", Direct, 0x1196),
+    ("lde", Indexed, 0x11a6), ("lde", Extended, 0x11b6),
+    ("ste", Direct, 0x1197), ("ste", Indexed, 0x11a7), ("ste", Extended, 0x11b7),
+    ("adde", Immediate8, 0x118b), ("adde", Direct, 0x119b),
+    ("adde", Indexed, 0x11ab), ("adde", Extended, 0x11bb),
+
+    ("divd", Immediate8, 0x118d), ("divd", Direct, 0x119d),
+    ("divd", Indexed, 0x11ad), ("divd", Extended, 0x11bd),
+    ("divq", Immediate16, 0x118e), ("divq", Direct, 0x119e),
+    ("divq", Indexed, 0x11ae), ("divq", Extended, 0x11be),
+    ("muld", Immediate16, 0x118f), ("muld", Direct, 0x119f),
+    ("muld", Indexed, 0x11af), ("muld", Extended, 0x11bf),
+
+    ("subf", Immediate8, 0x11c0), ("subf", Direct, 0x11d0),
+    ("subf", Indexed, 0x11e0), ("subf", Extended, 0x11f0),
+    ("cmpf", Immediate8, 0x11c1), ("cmpf", Direct, 0x11d1),
+    ("cmpf", Indexed, 0x11e1), ("cmpf", Extended, 0x11f1),
+    ("ldf", Immediate8, 0x11c6), ("ldf", Direct, 0x11d6),
+    ("ldf", Indexed, 0x11e6), ("ldf", Extended, 0x11f6),
+    ("stf", Direct, 0x11d7), ("stf", Indexed, 0x11e7), ("stf", Extended, 0x11f7),
+    ("addf", Immediate8, 0x11cb), ("addf", Direct, 0x11db),
+    ("addf", Indexed, 0x11eb), ("addf", Extended, 0x11fb),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        let size = |text, amode| DBASE_6309.get_instruction(text, amode).unwrap().size;
+        assert_eq!(size("sexw", Inherent), 1);
+        assert_eq!(size("ldq", Immediate32), 5);
+        assert_eq!(size("ldw", Immediate16), 4);
+        assert_eq!(size("lde", Immediate8), 3);
+        assert_eq!(size("aim", ImmDirect), 3);
+        assert_eq!(size("aim", ImmExtended), 4);
+        assert_eq!(size("band", BitDirect), 4);
+        assert_eq!(size("tfm", Tfm(TfmMode::IncInc)), 3);
+    }
+
+    #[test]
+    fn test_in_memory() {
+        assert!(DBASE_6309.is_in_memory("tim"));
+        assert!(!DBASE_6309.is_in_memory("ldw"));
+        assert!(DBASE_6309.get_opcode("lda").is_none());
+    }
+}
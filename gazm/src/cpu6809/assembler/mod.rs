@@ -1,8 +1,10 @@
 mod sizer;
 mod isa;
+mod isa6309;
 mod opcodes;
 
 pub use sizer::*;
 pub use isa::*;
+pub use isa6309::*;
 pub use opcodes::*;
 
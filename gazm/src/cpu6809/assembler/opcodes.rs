@@ -2,10 +2,13 @@ use crate::{assembler::BinaryError::*, error::GResult, semantic::AstNodeRef};
 
 use crate::assembler::Assembler;
 use crate::cpu6809::{
+    assembler::{AddrMode6309, Instruction6309},
     frontend::{AddrModeParseType, IndexParseType, NodeKind6809},
-    regutils::{reg_pair_to_flags, registers_to_flags},
+    regutils::{reg_pair_to_flags, reg_to_reg_num, registers_to_flags},
 };
 
+use emu6809::cpu::RegEnum;
+
 use emu6809::isa;
 
 pub fn compile_indexed(
@@ -21,7 +24,7 @@ pub fn compile_indexed(
     asm.write_byte(idx_byte, node)?;
 
     match imode {
-        PCOffset | ConstantOffset(..) => {
+        PCOffset(..) | ConstantOffset(..) => {
             panic!("Should not happen")
         }
 
@@ -41,6 +44,11 @@ pub fn compile_indexed(
             let res = asm.get_binary_mut().write_ibyte_check_size(val as i64);
             asm.binary_error_map(node, res)?;
         }
+
+        WordOffsetW => {
+            let (val, _) = asm.eval_first_arg(node, current_scope_id)?;
+            asm.write_word_check_size(val, node)?;
+        }
         _ => (),
     }
 
@@ -59,7 +67,12 @@ pub fn compile_node(
             compile_opcode(asm, node, &ins, amode, current_scope_id)?;
         }
 
-        SetDp => eprintln!("Warning! SetDP node not compiled!"),
+        OpCode6309(_, ins, amode) => {
+            compile_opcode_6309(asm, node, &ins, amode, current_scope_id)?;
+        }
+
+        // Only used while sizing
+        SetDp | Relax => (),
 
         Illegal => todo!(),
         Operand(_) => todo!(),
@@ -69,6 +82,20 @@ pub fn compile_node(
     Ok(())
 }
 
+fn compile_reg_pair(
+    asm: &mut Assembler,
+    node: AstNodeRef,
+    amode: AddrModeParseType,
+) -> GResult<()> {
+    if let AddrModeParseType::RegisterPair(a, b) = amode {
+        let val = reg_pair_to_flags(a, b)
+            .ok_or_else(|| asm.make_user_error("Register can't be transferred", node, true))?;
+        asm.write_byte(val, node)
+    } else {
+        panic!("Whut!")
+    }
+}
+
 /// Compile an opcode
 pub fn compile_opcode(
     asm: &mut Assembler,
@@ -119,9 +146,7 @@ pub fn compile_opcode(
                 .binary
                 .write_ibyte_check_size(val)
                 .map_err(|x| match x {
-                    DoesNotFit { .. } => {
-                        panic!()
-                    }
+                    DoesNotFit { val, .. } => asm.relative_error(arg_n, val, 8),
                     DoesNotMatchReference { .. } => asm.binary_error(node, x),
                     _ => asm.make_user_error(format!("{x:?}"), arg_n, false).into(),
                 });
@@ -159,12 +184,7 @@ pub fn compile_opcode(
         AddrModeEnum::Inherent => {}
 
         AddrModeEnum::RegisterPair => {
-            if let AddrModeParseType::RegisterPair(a, b) = amode {
-                let val = reg_pair_to_flags(a, b);
-                asm.write_byte(val, node)?;
-            } else {
-                panic!("Whut!")
-            }
+            compile_reg_pair(asm, node, amode)?;
         }
 
         AddrModeEnum::RegisterSet => {
@@ -186,3 +206,110 @@ pub fn compile_opcode(
     // compiler.add_mapping(asm, phys_range, range, node.id(), ItemType::OpCode);
     Ok(())
 }
+
+/// Evaluate a bit number for the bit transfer instructions
+fn eval_bit_number(
+    asm: &Assembler,
+    node: AstNodeRef,
+    n: usize,
+    current_scope_id: u64,
+) -> GResult<u8> {
+    let (bit, arg_n) = asm.eval_nth_arg_n(node, n, current_scope_id)?;
+
+    if (0..8).contains(&bit) {
+        Ok(bit as u8)
+    } else {
+        Err(asm.make_user_error("Bit number must be 0 to 7", arg_n, true).into())
+    }
+}
+
+/// Compile a 6309 opcode
+pub fn compile_opcode_6309(
+    asm: &mut Assembler,
+    node: AstNodeRef,
+    ins: &Instruction6309,
+    amode: AddrModeParseType,
+    current_scope_id: u64,
+) -> GResult<()> {
+    use AddrMode6309::*;
+
+    if ins.opcode > 0xff {
+        asm.write_word(ins.opcode, node)
+    } else {
+        asm.write_byte(ins.opcode as u8, node)
+    }?;
+
+    // aim, oim etc have their immediate value as the last child
+    if ins.addr_mode.is_in_memory() {
+        let n = node.children().count() - 1;
+        let (imm, _) = asm.eval_nth_arg_n(node, n, current_scope_id)?;
+        asm.write_byte_check_size(imm, node)?;
+    }
+
+    match ins.addr_mode {
+        Inherent => (),
+
+        Immediate8 => {
+            let (arg, _) = asm.eval_first_arg(node, current_scope_id)?;
+            asm.write_byte_check_size(arg, node)?
+        }
+
+        Immediate16 => {
+            let (arg, _) = asm.eval_first_arg(node, current_scope_id)?;
+            asm.write_word_check_size(arg, node)?
+        }
+
+        Immediate32 => {
+            let (arg, _) = asm.eval_first_arg(node, current_scope_id)?;
+            asm.write_word_check_size((arg >> 16) & 0xffff, node)?;
+            asm.write_word_check_size(arg & 0xffff, node)?;
+        }
+
+        Direct | ImmDirect => {
+            let (arg, _) = asm.eval_first_arg(node, current_scope_id)?;
+            asm.write_byte_check_size(arg & 0xff, node)?
+        }
+
+        Extended | ImmExtended => {
+            let (arg, _) = asm.eval_first_arg(node, current_scope_id)?;
+            asm.write_word_check_size(arg, node)?;
+        }
+
+        Indexed | ImmIndexed => {
+            if let AddrModeParseType::Indexed(imode, indirect) = amode {
+                compile_indexed(asm, node, imode, indirect, current_scope_id)?;
+            }
+        }
+
+        RegisterPair => compile_reg_pair(asm, node, amode)?,
+
+        Tfm(_) => {
+            if let AddrModeParseType::Tfm(a, b, _) = amode {
+                let val = (reg_to_reg_num(a) << 4) | reg_to_reg_num(b);
+                asm.write_byte(val, node)?;
+            } else {
+                panic!("Whut!")
+            }
+        }
+
+        BitDirect => {
+            if let AddrModeParseType::BitTransfer(reg) = amode {
+                let reg = match reg {
+                    RegEnum::CC => 0,
+                    RegEnum::A => 1,
+                    _ => 2,
+                };
+                let src = eval_bit_number(asm, node, 0, current_scope_id)?;
+                let dst = eval_bit_number(asm, node, 1, current_scope_id)?;
+                asm.write_byte((reg << 6) | (src << 3) | dst, node)?;
+
+                let (addr, _) = asm.eval_nth_arg_n(node, 2, current_scope_id)?;
+                asm.write_byte_check_size(addr & 0xff, node)?;
+            } else {
+                panic!("Whut!")
+            }
+        }
+    };
+
+    Ok(())
+}
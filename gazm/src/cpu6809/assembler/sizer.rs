@@ -1,8 +1,11 @@
 #![forbid(unused_imports)]
 
-use crate::cpu6809::frontend::{
-    get_opcode_info, AddrModeParseType, IndexParseType,
-    NodeKind6809::{self, OpCode},
+use crate::cpu6809::{
+    assembler::DBASE_6309,
+    frontend::{
+        get_opcode_info, AddrModeParseType, IndexParseType, OffsetSize, OPCODES_REC,
+        NodeKind6809::{self, OpCode, OpCode6309},
+    },
 };
 
 
@@ -10,41 +13,79 @@ use emu6809::isa::{AddrModeEnum, Instruction};
 
 use crate::{
     assembler::{ByteSize, ByteSizes, Assembler,Sizer},
-    error::GResult,
+    error::{GResult, WarningKind},
     semantic::AstNodeId,
 };
 
-fn size_indexed(
+/// Size of the offset for an indexed mode
+/// Auto picks the shortest that fits, unknown offsets get a word until a later pass
+fn size_offset(
+    sizer: &mut Sizer,
+    asm: &Assembler,
+    id: AstNodeId,
+    force: OffsetSize,
+) -> GResult<ByteSizes> {
+    let value = sizer.try_eval_first_arg(asm, id)?;
+
+    let bs = match (force, value) {
+        (OffsetSize::Auto, Some(v)) => v.byte_size(),
+        (OffsetSize::Auto, None) => ByteSizes::Word(0),
+        (OffsetSize::Word, v) => ByteSizes::Word(v.unwrap_or(0) as i16),
+        (OffsetSize::Byte, None) => ByteSizes::Byte(0),
+
+        (OffsetSize::Byte, Some(v)) => match i8::try_from(v) {
+            Ok(v) => ByteSizes::Byte(v),
+            Err(_) if sizer.final_pass => {
+                let msg = format!("Offset {v} does not fit in 8 bits");
+                return Err(asm.make_user_error(msg, sizer.get_node(id), true).into());
+            }
+            Err(_) => {
+                // May fit once label values settle
+                sizer.unresolved += 1;
+                ByteSizes::Byte(0)
+            }
+        },
+    };
+
+    Ok(bs)
+}
+
+/// Size an indexed instruction
+/// make_item creates the opcode node for a resolved index mode
+fn size_indexed<F>(
     sizer: &mut Sizer,
     asm: &mut Assembler,
     id: AstNodeId,
     pmode: IndexParseType,
     indirect: bool,
-    text: &str,
-    ins: &Instruction,
-) -> GResult<()> {
+    size: usize,
+    make_item: F,
+) -> GResult<()>
+where
+    F: Fn(AddrModeParseType) -> NodeKind6809,
+{
     {
         let current_scope_id = sizer.scopes.scope();
-        let ins = Box::new(ins.clone());
 
-        sizer.advance_pc(ins.size);
+        sizer.advance_pc(size);
         use IndexParseType::*;
 
         match pmode {
             Zero(..) | AddA(..) | AddB(..) | AddD(..) | PostInc(..) | PostIncInc(..)
             | PreDec(..) | PreDecDec(..) => (),
 
+            ZeroW | PostIncIncW | PreDecDecW | AddE(..) | AddF(..) | AddW(..) => (),
+
+            WordOffsetW => sizer.advance_pc(2),
+
             ConstantByteOffset(..)
             | PcOffsetByte(..)
             | PcOffsetWord(..)
             | ConstantWordOffset(..)
             | Constant5BitOffset(..) => {}
 
-            ConstantOffset(r) => {
-                let node = sizer.get_node(id);
-                let (v, _) = asm.eval_first_arg(node, current_scope_id)?;
-
-                let mut bs = v.byte_size();
+            ConstantOffset(r, force) => {
+                let mut bs = size_offset(sizer, asm, id, force)?;
 
                 if let ByteSizes::Bits5(val) = bs {
                     if indirect {
@@ -67,21 +108,15 @@ fn size_indexed(
                     }
                 };
 
-                let new_item = OpCode(
-                    text.to_string(),
-                    ins,
-                    AddrModeParseType::Indexed(new_amode, indirect),
-                );
-
+                let new_item = make_item(AddrModeParseType::Indexed(new_amode, indirect));
                 asm.add_fixup(id, new_item, current_scope_id);
             }
 
-            PCOffset => {
-                let node = sizer.get_node(id);
-                let (v, _) = asm.eval_first_arg(node, current_scope_id)?;
+            PCOffset(force) => {
+                let bs = size_offset(sizer, asm, id, force)?;
                 sizer.advance_pc(1);
 
-                let new_amode = match v.byte_size() {
+                let new_amode = match bs {
                     ByteSizes::Zero => PcOffsetByte(0),
                     ByteSizes::Bits5(v) | ByteSizes::Byte(v) => PcOffsetByte(v),
                     ByteSizes::Word(v) => {
@@ -90,11 +125,7 @@ fn size_indexed(
                     }
                 };
 
-                let new_item = OpCode(
-                    text.to_string(),
-                    ins,
-                    AddrModeParseType::Indexed(new_amode, indirect),
-                );
+                let new_item = make_item(AddrModeParseType::Indexed(new_amode, indirect));
                 asm.add_fixup(id, new_item, current_scope_id);
             }
 
@@ -106,6 +137,76 @@ fn size_indexed(
     }
 }
 
+/// Warn if a `<` forced direct page operand isn't in the direct page
+/// With no `setdp` warn if the top byte is thrown away
+fn check_forced_direct(sizer: &mut Sizer, asm: &Assembler, id: AstNodeId) {
+    let value = match asm.eval_first_arg(sizer.get_node(id), sizer.scopes.scope()) {
+        Ok((value, _)) => value,
+        Err(_) => return,
+    };
+
+    match asm.asm_out.direct_page {
+        Some(dp) if value >> 8 != dp as i64 => {
+            let msg = format!("${value:04X} forced to direct page but it is set to ${dp:02X}");
+            sizer.add_warning(WarningKind::DpForced, msg, id);
+        }
+        None if !(0..256).contains(&value) => {
+            let msg = format!("${value:04X} truncated to ${:02X} for direct page", value & 0xff);
+            sizer.add_warning(WarningKind::Truncated, msg, id);
+        }
+        _ => (),
+    }
+}
+
+/// Size a short branch, relaxing it to the long version if the target is out of range
+/// Once relaxed it stays long so sizing can't flip between the two
+fn size_relaxed_branch(
+    sizer: &mut Sizer,
+    asm: &mut Assembler,
+    id: AstNodeId,
+    text: &str,
+    ins: &Instruction,
+    amode: &AddrModeParseType,
+) -> GResult<()> {
+    let current_scope_id = sizer.scopes.scope();
+    let key = (current_scope_id, id);
+
+    let long_text = format!("l{text}");
+    let long_ins = OPCODES_REC
+        .get_opcode(&long_text)
+        .and_then(|info| info.get_instruction(&AddrModeEnum::Relative16));
+
+    let long_ins = match long_ins {
+        Some(long_ins) => long_ins,
+        None => {
+            sizer.advance_pc(ins.size);
+            return Ok(());
+        }
+    };
+
+    if !sizer.long_branches.contains(&key) {
+        let pc = (sizer.get_pc() + ins.size) as i64;
+
+        // A target that isn't known yet is assumed to be in range
+        let in_range = sizer
+            .try_eval_first_arg(asm, id)?
+            .map(|target| i8::try_from(target - pc).is_ok())
+            .unwrap_or(true);
+
+        if in_range {
+            sizer.advance_pc(ins.size);
+            return Ok(());
+        }
+
+        sizer.long_branches.insert(key);
+    }
+
+    let new_item = OpCode(long_text, Box::new(long_ins.clone()), amode.clone());
+    asm.add_fixup(id, new_item, current_scope_id);
+    sizer.advance_pc(long_ins.size);
+    Ok(())
+}
+
 pub fn size_node_internal(
     sizer: &mut Sizer,
     asm: &mut Assembler,
@@ -115,17 +216,26 @@ pub fn size_node_internal(
     let current_scope_id = sizer.scopes.scope();
     let node = sizer.get_node(id);
     match &node_kind {
-        NodeKind6809::SetDp => {
-            let (dp, _) = asm.eval_first_arg(node, current_scope_id)?;
-            if dp < 0 {
-                panic!("Less than zerp?!?!?");
+        NodeKind6809::SetDp => match sizer.try_eval_first_arg(asm, id)? {
+            Some(dp) if (0..256).contains(&dp) => asm.asm_out.set_dp(dp as u8),
+            Some(dp) => {
+                let msg = format!("Direct page must be between 0 and 255 (is {dp})");
+                return Err(asm.make_user_error(msg, node, true).into());
             }
+            // Not known yet so no direct addressing until a later pass
+            None => asm.asm_out.reset_dp(),
+        },
 
-            if dp >= 256 {
-                panic!("Too big!")
+        NodeKind6809::Relax => {
+            if let Some(relax) = sizer.try_eval_first_arg(asm, id)? {
+                sizer.relax_branches = relax != 0;
             }
+        }
 
-            asm.asm_out.set_dp(((dp as u64) & 0xff) as u8);
+        NodeKind6809::OpCode(text, ins, amode)
+            if sizer.relax_branches && matches!(ins.addr_mode, AddrModeEnum::Relative) =>
+        {
+            size_relaxed_branch(sizer, asm, id, text, ins, amode)?;
         }
 
         NodeKind6809::OpCode(text, ins, amode) => {
@@ -163,8 +273,14 @@ pub fn size_node_internal(
                     sizer.advance_pc(size);
                 }
 
+                AddrModeParseType::Direct => {
+                    check_forced_direct(sizer, asm, id);
+                    sizer.advance_pc(ins.size);
+                }
+
                 AddrModeParseType::Indexed(pmode, indirect) => {
-                    size_indexed(sizer, asm, id, *pmode, *indirect, text, ins)?;
+                    let make_item = |amode| OpCode(text.clone(), ins.clone(), amode);
+                    size_indexed(sizer, asm, id, *pmode, *indirect, ins.size, make_item)?;
                 }
 
                 _ => {
@@ -173,7 +289,131 @@ pub fn size_node_internal(
             };
         }
 
-        _ => panic!(),
+        NodeKind6809::OpCode6309(text, ins, amode) => {
+            match amode {
+                AddrModeParseType::Extended(false) => {
+                    // Same as the 6809, use direct addressing if the operand
+                    // is in the direct page
+                    let mut size = ins.size;
+
+                    let dp_info = ins
+                        .addr_mode
+                        .to_direct()
+                        .and_then(|m| DBASE_6309.get_instruction(text, m))
+                        .and_then(|ins| asm.asm_out.direct_page.map(|dp| (ins, dp)));
+
+                    if let Some((new_ins, dp)) = dp_info {
+                        if let Ok((value, _)) = asm.eval_first_arg(node, current_scope_id) {
+                            let top_byte = ((value >> 8) & 0xff) as u8;
+
+                            if top_byte == dp {
+                                size = new_ins.size;
+                                let new_item = OpCode6309(
+                                    text.clone(),
+                                    Box::new(new_ins.clone()),
+                                    AddrModeParseType::Direct,
+                                );
+                                asm.add_fixup(id, new_item, current_scope_id);
+                            }
+                        }
+                    }
+                    sizer.advance_pc(size);
+                }
+
+                AddrModeParseType::Direct => {
+                    check_forced_direct(sizer, asm, id);
+                    sizer.advance_pc(ins.size);
+                }
+
+                AddrModeParseType::Indexed(pmode, indirect) => {
+                    let make_item = |amode| OpCode6309(text.clone(), ins.clone(), amode);
+                    size_indexed(sizer, asm, id, *pmode, *indirect, ins.size, make_item)?;
+                }
+
+                _ => {
+                    sizer.advance_pc(ins.size);
+                }
+            };
+        }
+
+        NodeKind6809::Illegal => {
+            return Err(asm.make_user_error("Illegal instruction", node, true).into());
+        }
+
+        NodeKind6809::Operand(..)
+        | NodeKind6809::OperandIndexed(..)
+        | NodeKind6809::RegisterSet(..) => {
+            let msg = "Operand without an instruction";
+            return Err(asm.make_user_error(msg, node, true).into());
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembler::testing::{assemble_err, assemble_ok, bytes},
+        opts::Opts,
+    };
+
+    #[test]
+    fn test_relax_branches() {
+        let text = "
+    org $1000
+    beq far
+    bra far
+    beq near
+near zmb 200
+far rts
+";
+        let opts = Opts {
+            relax_branches: true,
+            ..Opts::default()
+        };
+        let asm = assemble_ok(text, opts);
+        let expected = [0x10, 0x27, 0x00, 0xcd, 0x16, 0x00, 0xca, 0x27, 0x00];
+        assert_eq!(bytes(&asm, 0x1000, 9), expected);
+    }
+
+    #[test]
+    fn test_branch_out_of_range() {
+        let text = "
+    org $1000
+    beq far
+    zmb 200
+far rts
+";
+        let errs = assemble_err(text, Opts::default());
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("Branch out of range by 73 bytes (200)"), "{errs:?}");
+    }
+
+    #[test]
+    fn test_relax_pseudo_op() {
+        let text = "
+    org $1000
+    relax 1
+    beq far
+    relax 0
+    bra near
+near zmb 200
+far rts
+";
+        let asm = assemble_ok(text, Opts::default());
+        assert_eq!(bytes(&asm, 0x1000, 6), [0x10, 0x27, 0x00, 0xca, 0x20, 0x00]);
+
+        let text = "
+    org $1000
+    relax 0
+    beq far
+    zmb 200
+far rts
+";
+        let opts = Opts {
+            relax_branches: true,
+            ..Opts::default()
+        };
+        assert_eq!(assemble_err(text, opts).len(), 1);
+    }
+}
@@ -1,6 +1,6 @@
 #[deny(unused_imports)]
 use crate::{
-    assembler::{Assembler, AssemblerCpuTrait,  Sizer},
+    assembler::{Assembler, AssemblerCpuTrait, OpcodeModeInfo, Sizer},
     error::GResult,
     frontend::{self, PResult},
     semantic::{ AstNodeRef, AstNodeId },
@@ -8,47 +8,38 @@ use crate::{
 
 use crate::{
     cpu6809::{
-        assembler::{compile_node, size_node_internal},
-        frontend::{ lex_identifier, },
+        assembler::{compile_node, size_node_internal, DBASE_6309, ISA_DBASE, mnemonics},
+        frontend::{lex_identifier, lex_identifier_6309, parse_commands, parse_multi_opcode_vec},
     },
+    cpukind::CpuKind,
     frontend::CpuSpecific,
 };
 
-impl Assembler {
-    pub fn compile_node_6809(
-        &mut self,
-        _node_kind: NodeKind6809,
-        _node: AstNodeRef,
-        _current_scope_id: u64,
-    ) -> GResult<()> { 
-        panic!()
-}
-
-    pub fn size_node_6809(
-        &mut self,
-        _sizer: &mut Sizer,
-        _id: AstNodeId,
-        _node_kind: NodeKind6809,
-        _current_scope_id: u64,
-    ) -> GResult<()> {
-        panic!()
-    }
-
-}
-
 use frontend::Node;
 
-use super::frontend::NodeKind6809;
 pub type NodeKind = frontend::AstNodeKind;
 
+/// Assembler for the 6809 and 6309
+/// Both share nodes, the cpu only decides which instructions are allowed
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Asm6809 {
     dp: Option<u8>,
+    cpu: CpuKind,
 }
 
 impl Asm6809 {
     pub fn new() -> Self {
-        Self { dp: None }
+        Self {
+            dp: None,
+            cpu: CpuKind::Cpu6809,
+        }
+    }
+
+    pub fn new_6309() -> Self {
+        Self {
+            dp: None,
+            cpu: CpuKind::Cpu6309,
+        }
     }
 }
 
@@ -56,25 +47,60 @@ impl AssemblerCpuTrait for Asm6809 {
     // type NodeKind = NodeKind6809;
 
     fn get_cpu_name(&self) -> &'static str {
-        "6809"
+        match self.cpu {
+            CpuKind::Cpu6309 => "6309",
+            _ => "6809",
+        }
     }
 
     // fn new() -> Self {
     //     Self { dp: None }
     // }
 
-    fn lex_identifier(&self, _id: &str) -> crate::frontend::TokenKind {
-        lex_identifier(_id)
+    fn lex_identifier(&self, id: &str) -> crate::frontend::TokenKind {
+        match self.cpu {
+            CpuKind::Cpu6309 => lex_identifier_6309(id),
+            _ => lex_identifier(id),
+        }
+    }
+
+    /// Lists the 6309 additions as well, they have no cycle counts
+    fn get_opcode_info(&self, text: &str) -> Vec<OpcodeModeInfo> {
+        let base = ISA_DBASE
+            .get_opcode(text)
+            .into_iter()
+            .flat_map(|info| info.iter())
+            .map(|i| OpcodeModeInfo::new(format!("{:?}", i.addr_mode), Some(i.cycles as usize)));
+
+        // Only the 6309 has the extra instructions
+        let extra = DBASE_6309
+            .get_opcode(text)
+            .filter(|_| self.cpu == CpuKind::Cpu6309)
+            .unwrap_or_default()
+            .iter()
+            .map(|i| OpcodeModeInfo::new(format!("{:?} (6309)", i.addr_mode), None));
+
+        base.chain(extra).collect()
+    }
+
+    fn get_mnemonics(&self) -> Vec<String> {
+        let mut ret = mnemonics();
+
+        if self.cpu == CpuKind::Cpu6309 {
+            ret.extend(DBASE_6309.mnemonics().map(String::from));
+            ret.sort();
+            ret.dedup();
+        }
+
+        ret
     }
 
-    fn parse_multi_opcode_vec(&self, _input: crate::frontend::TSpan) -> PResult<Vec<Node>> {
-        todo!()
-        // parse_multi_opcode_vec(input)
+    fn parse_multi_opcode_vec(&self, input: crate::frontend::TSpan) -> PResult<Vec<Node>> {
+        parse_multi_opcode_vec(input)
     }
 
-    fn parse_commands(&self, _input: crate::frontend::TSpan) -> PResult<Node> {
-        todo!()
-        // parse_commands(input)
+    fn parse_commands(&self, input: crate::frontend::TSpan) -> PResult<Node> {
+        parse_commands(input)
     }
 
     fn compile_node(
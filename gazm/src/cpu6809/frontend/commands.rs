@@ -4,7 +4,7 @@ use crate::frontend::{
 
 use super::NodeKind6809;
 
-use unraveler::{cut, match_span as ms, preceded};
+use unraveler::{alt, cut, match_span as ms, preceded};
 
 pub fn parse_set_dp(input: TSpan) -> PResult<Node> {
     let (rest, (sp, matched)) = ms(preceded(CommandKind::SetDp, cut(parse_expr)))(input)?;
@@ -16,6 +16,18 @@ pub fn parse_set_dp(input: TSpan) -> PResult<Node> {
     Ok((rest, node))
 }
 
+/// RELAX expr
+/// Short branches that can't reach are relaxed to long ones while expr is non zero
+pub fn parse_relax(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, matched)) = ms(preceded(CommandKind::Relax, cut(parse_expr)))(input)?;
+    let node = from_item_kid_tspan(
+        AstNodeKind::TargetSpecific(NodeKind6809::Relax.into()),
+        matched,
+        sp,
+    );
+    Ok((rest, node))
+}
+
 pub fn parse_commands(input: TSpan) -> PResult<Node> {
-    parse_set_dp(input)
+    alt((parse_set_dp, parse_relax))(input)
 }
@@ -11,4 +11,6 @@ pub enum Cpu6809AssemblyErrorKind {
     AddrModeUnsupported,
     #[error("This instruction only supports inherent mode addressing")]
     OnlySupports(AddrModeParseType),
+    #[error("This is only available on the 6309, set the target cpu to 6309")]
+    Only6309,
 }
@@ -56,13 +56,34 @@ fn get_post_inc_inc(input: TSpan) -> PResult<IndexParseType> {
     )(input)
 }
 
+/// Parses for ,--W
+fn get_pre_dec_dec_w(input: TSpan) -> PResult<IndexParseType> {
+    map(
+        preceded(tag([Minus, Minus]), get_this_reg_6309(Reg6309::W)),
+        |_| IndexParseType::PreDecDecW,
+    )(input)
+}
+
+/// Parses for ,W++
+fn get_post_inc_inc_w(input: TSpan) -> PResult<IndexParseType> {
+    map(
+        succeeded(get_this_reg_6309(Reg6309::W), tag([Plus, Plus])),
+        |_| IndexParseType::PostIncIncW,
+    )(input)
+}
+
+/// Parses for ,W
+fn get_zero_w(input: TSpan) -> PResult<IndexParseType> {
+    map(get_this_reg_6309(Reg6309::W), |_| IndexParseType::ZeroW)(input)
+}
+
 /// Parses for ,<index reg>
 fn get_zero(input: TSpan) -> PResult<IndexParseType> {
     map(cut(get_index_reg), IndexParseType::Zero)(input)
 }
 
 fn get_pc_offset(input: TSpan) -> PResult<IndexParseType> {
-    map(get_this_reg(RegEnum::PC), |_| IndexParseType::PCOffset)(input)
+    map(get_this_reg(RegEnum::PC), |_| IndexParseType::PCOffset(OffsetSize::Auto))(input)
 }
 
 fn check_for_illegal_indirect<'a>(
@@ -82,6 +103,9 @@ fn get_indexed_direct(input: TSpan) -> PResult<IndexParseType> {
     preceded(
         Comma,
         cut(alt((
+            get_pre_dec_dec_w,
+            get_post_inc_inc_w,
+            get_zero_w,
             get_pre_dec_dec,
             get_pre_dec,
             get_post_inc_inc,
@@ -116,10 +140,35 @@ fn get_abd_indexed(input: TSpan) -> PResult<IndexParseType> {
     Ok((rest, matched))
 }
 
+/// Parse for e,<ireg>, f,<ireg> or w,<ireg>
+/// 6309 only, other 6309 registers are left to be parsed as labels
+fn get_efw_indexed(input: TSpan) -> PResult<IndexParseType> {
+    use {IndexParseType::*, Reg6309::*};
+
+    let (rest, efw_reg) = succeeded(get_register_6309, Comma)(input)?;
+
+    let add: fn(RegEnum) -> IndexParseType = match efw_reg {
+        E => AddE,
+        F => AddF,
+        W => AddW,
+        _ => return err_nomatch(input),
+    };
+
+    let (rest, idx_reg) = cut(get_index_reg)(rest)?;
+    Ok((rest, add(idx_reg)))
+}
+
+/// Indexed modes without an offset expression
 pub fn get_indexed(input: TSpan) -> PResult<IndexParseType> {
     alt((get_abd_indexed, get_indexed_direct))(input)
 }
 
+/// Indexed modes for the 6309, which can also use e, f or w as an offset
+/// On a 6809 these are labels
+pub fn get_indexed_6309(input: TSpan) -> PResult<IndexParseType> {
+    alt((get_abd_indexed, get_efw_indexed, get_indexed_direct))(input)
+}
+
 #[allow(unused_imports)]
 #[allow(unused_variables)]
 mod test {
@@ -1,20 +1,31 @@
-use crate::cpu6809::assembler::ISA_DBASE;
+use crate::cpu6809::assembler::{DBASE_6309, ISA_DBASE};
 
-use crate::frontend::TokenKind;
-use crate::frontend::CommandKind;
 use crate::cpukind::CpuKind;
+use crate::frontend::CommandKind;
+use crate::frontend::TokenKind;
 
-pub fn lex_identifier(text: &str) -> TokenKind {
+/// 6309 mnemonics are lexed as opcodes for the 6809 as well
+/// so the parser can report they need the 6309
+fn lex(cpu: CpuKind, text: &str) -> TokenKind {
     use TokenKind::*;
 
     let text = text.to_lowercase();
 
-    if ISA_DBASE.get_opcode(&text).is_some() {
-        TokenKind::OpCode(CpuKind::Cpu6809)
+    if ISA_DBASE.get_opcode(&text).is_some() || DBASE_6309.get_opcode(&text).is_some() {
+        OpCode(cpu)
     } else {
         match text.as_str() {
             "setdp" => Command(CommandKind::SetDp),
+            "relax" => Command(CommandKind::Relax),
             _ => Label,
         }
     }
 }
+
+pub fn lex_identifier(text: &str) -> TokenKind {
+    lex(CpuKind::Cpu6809, text)
+}
+
+pub fn lex_identifier_6309(text: &str) -> TokenKind {
+    lex(CpuKind::Cpu6309, text)
+}
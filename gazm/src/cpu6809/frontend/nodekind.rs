@@ -1,5 +1,11 @@
 #![forbid(unused_imports)]
-use crate::{cpu6809::NodeKind, frontend::CpuSpecific};
+use crate::{
+    cpu6809::{
+        assembler::{Instruction6309, TfmMode},
+        NodeKind,
+    },
+    frontend::CpuSpecific,
+};
 use std::collections::HashSet;
 
 use emu6809::{
@@ -7,9 +13,20 @@ use emu6809::{
     isa::Instruction,
 };
 
+use super::PairReg;
+
+/// Offset size for indexed modes, forced with a `<` or `>` before the offset
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum OffsetSize {
+    #[default]
+    Auto, // shortest that fits
+    Byte, // <arg,R
+    Word, // >arg,R
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum IndexParseType {
-    ConstantOffset(RegEnum), // arg,R
+    ConstantOffset(RegEnum, OffsetSize), // arg,R
     PostInc(RegEnum),        // ,R+                    2 0 |
     PostIncInc(RegEnum),     // ,R++                   3 0 |
     PreDec(RegEnum),         // ,-R                    2 0 |
@@ -18,13 +35,21 @@ pub enum IndexParseType {
     AddB(RegEnum),           // (+/- B),R              1 0 |
     AddA(RegEnum),           // (+/- A),R              1 0 |
     AddD(RegEnum),           // (+/- D),R              4 0 |
-    PCOffset,                // (+/- 7 bit offset),PC  1 1 |
+    PCOffset(OffsetSize),    // (+/- 7 bit offset),PC  1 1 |
     ExtendedIndirect,        //  [expr]
     Constant5BitOffset(RegEnum, i8),
     ConstantByteOffset(RegEnum, i8),
     ConstantWordOffset(RegEnum, i16),
     PcOffsetWord(i16),
     PcOffsetByte(i8),
+    // 6309 only
+    ZeroW,           // ,W
+    WordOffsetW,     // arg,W
+    PostIncIncW,     // ,W++
+    PreDecDecW,      // ,--W
+    AddE(RegEnum),   // E,R
+    AddF(RegEnum),   // F,R
+    AddW(RegEnum),   // W,R
 }
 
 impl IndexParseType {
@@ -50,15 +75,31 @@ impl IndexParseType {
             AddB(..) => false,          // (+/- B),R              1 0 |
             AddA(..) => false,          // (+/- A),R              1 0 |
             AddD(..) => false,          // (+/- D),R              4 0 |
-            PCOffset => true,           // (+/- 7 bit offset),PC  1 1 |
+            PCOffset(..) => true,       // (+/- 7 bit offset),PC  1 1 |
             ExtendedIndirect => true,   // [expr]
             Constant5BitOffset(..) => true,
             ConstantByteOffset(..) => true,
             ConstantWordOffset(..) => true,
             PcOffsetWord(..) => true,
             PcOffsetByte(..) => true,
+            ZeroW => false,
+            WordOffsetW => true,
+            PostIncIncW => false,
+            PreDecDecW => false,
+            AddE(..) => false,
+            AddF(..) => false,
+            AddW(..) => false,
         }
     }
+
+    /// Is this indexed mode only available on the 6309?
+    pub fn is_6309(&self) -> bool {
+        use IndexParseType::*;
+        matches!(
+            self,
+            ZeroW | WordOffsetW | PostIncIncW | PreDecDecW | AddE(..) | AddF(..) | AddW(..)
+        )
+    }
 }
 
 fn rbits(r: RegEnum) -> u8 {
@@ -86,6 +127,16 @@ fn add_ind(bits: u8, ind: bool) -> u8 {
     (bits & !ind_bit) | ind_val
 }
 
+/// W indexing has no indirect bit
+/// the indirect post byte is the one after the direct one
+fn add_ind_w(bits: u8, ind: bool) -> u8 {
+    if ind {
+        bits + 1
+    } else {
+        bits
+    }
+}
+
 impl IndexParseType {
     pub fn get_index_byte(&self, indirect: bool) -> u8 {
         use IndexParseType::*;
@@ -181,7 +232,33 @@ impl IndexParseType {
                 bits
             }
 
-            PCOffset | ConstantOffset(..) => panic!("Internal error"),
+            ZeroW => add_ind_w(0b1000_1111, indirect),
+            WordOffsetW => add_ind_w(0b1010_1111, indirect),
+            PostIncIncW => add_ind_w(0b1100_1111, indirect),
+            PreDecDecW => add_ind_w(0b1110_1111, indirect),
+
+            AddE(r) => {
+                let mut bits = 0b1000_0111;
+                bits = add_reg(bits, r);
+                bits = add_ind(bits, indirect);
+                bits
+            }
+
+            AddF(r) => {
+                let mut bits = 0b1000_1010;
+                bits = add_reg(bits, r);
+                bits = add_ind(bits, indirect);
+                bits
+            }
+
+            AddW(r) => {
+                let mut bits = 0b1000_1110;
+                bits = add_reg(bits, r);
+                bits = add_ind(bits, indirect);
+                bits
+            }
+
+            PCOffset(..) | ConstantOffset(..) => panic!("Internal error"),
         }
     }
 }
@@ -195,7 +272,10 @@ pub enum AddrModeParseType {
     Inherent,
     Immediate,
     RegisterSet,
-    RegisterPair(RegEnum, RegEnum),
+    RegisterPair(PairReg, PairReg),
+    // 6309 only
+    Tfm(RegEnum, RegEnum, TfmMode),
+    BitTransfer(RegEnum),
 }
 
 impl From<AddrModeParseType> for NodeKind {
@@ -217,6 +297,20 @@ impl AddrModeParseType {
             RegisterSet => true,
             RegisterPair(..) => false,
             Indexed(x, _) => x.has_operand(),
+            Tfm(..) => false,
+            BitTransfer(..) => true,
+        }
+    }
+
+    /// Is this addressing mode only available on the 6309?
+    pub fn is_6309(&self) -> bool {
+        use AddrModeParseType::*;
+
+        match self {
+            Indexed(x, _) => x.is_6309(),
+            RegisterPair(a, b) => a.is_6309() || b.is_6309(),
+            Tfm(..) | BitTransfer(..) => true,
+            _ => false,
         }
     }
 }
@@ -232,7 +326,9 @@ pub enum NodeKind6809 {
     #[default]
     Illegal,
     SetDp,
+    Relax,
     OpCode(String, Box<Instruction>, AddrModeParseType),
+    OpCode6309(String, Box<Instruction6309>, AddrModeParseType),
     Operand(AddrModeParseType),
     OperandIndexed(IndexParseType, bool),
     RegisterSet(HashSet<RegEnum>),
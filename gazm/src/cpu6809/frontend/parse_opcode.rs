@@ -1,21 +1,27 @@
 #![deny(unused_imports)]
 use crate::cpu6809::frontend::NodeKind6809;
 use crate::frontend::{
-    err_fatal, from_item_tspan, get_text, parse_expr, AstNodeKind, CpuSpecific, Node, PResult,
-    TSpan, TokenKind,
+    err_fatal, err_kind_nomatch, fatal, from_item_kids_tspan, from_item_tspan, get_text,
+    parse_expr, AstNodeKind, CpuSpecific, FeResult, Node, PResult, TSpan, TokenKind,
 };
 
 use crate::cpukind::CpuKind;
+use crate::cpu6809::assembler::{AddrMode6309, Instruction6309, TfmMode, DBASE_6309};
+use crate::help::ErrCode::ExpectedRegister6809;
 
 use super::{
-    parse_indexed, parse_opcode_reg_pair, parse_reg_set_operand, AddrModeParseType,
+    get_register, parse_indexed, parse_indexed_6309, parse_opcode_reg_pair, parse_reg_set_operand,
+    AddrModeParseType,
     AddrModeParseType::Inherent as ParseInherent,
     Cpu6809AssemblyErrorKind,
-    NodeKind6809::{OpCode, Operand, OperandIndexed},
+    NodeKind6809::{OpCode, OpCode6309, Operand, OperandIndexed},
 };
 
-use emu6809::isa::{AddrModeEnum, Dbase, Instruction, InstructionInfo};
-use unraveler::{alt, match_span as ms, preceded, sep_list};
+use emu6809::{
+    cpu::RegEnum,
+    isa::{AddrModeEnum, Dbase, Instruction, InstructionInfo},
+};
+use unraveler::{alt, map, match_span as ms, opt, pair, preceded, sep_list, sep_pair, tag, tuple};
 
 lazy_static::lazy_static! {
     pub static ref OPCODES_REC: Dbase = Dbase::new();
@@ -56,9 +62,15 @@ fn parse_extended(_input: TSpan) -> PResult<Node> {
     Ok((rest, node))
 }
 
-fn parse_opcode_arg(input: TSpan) -> PResult<Node> {
+/// Operand for an opcode lexed for this cpu
+fn parse_opcode_arg(input: TSpan, cpu: CpuKind) -> PResult<Node> {
+    let indexed: fn(TSpan) -> PResult<Node> = match cpu {
+        CpuKind::Cpu6309 => parse_indexed_6309,
+        _ => parse_indexed,
+    };
+
     let (rest, matched) = alt((
-        parse_indexed,
+        indexed,
         parse_immediate,
         parse_force_dp,
         parse_force_extended,
@@ -68,26 +80,41 @@ fn parse_opcode_arg(input: TSpan) -> PResult<Node> {
     Ok((rest, matched))
 }
 
+/// Get the parsed addressing mode from an operand node
+fn get_amode(sp: TSpan, arg: &Node) -> FeResult<AddrModeParseType> {
+    match arg.item {
+        AstNodeKind::TargetSpecific(CpuSpecific::Cpu6809(Operand(amode))) => Ok(amode),
+        AstNodeKind::TargetSpecific(CpuSpecific::Cpu6809(OperandIndexed(amode, indirect))) => {
+            Ok(AddrModeParseType::Indexed(amode, indirect))
+        }
+        _ => Err(fatal(sp, Cpu6809AssemblyErrorKind::AddrModeUnsupported)),
+    }
+}
+
+/// Check 6309 only features are not used when assembling for a 6809
+fn check_cpu(sp: TSpan, cpu: CpuKind, is_6309: bool) -> FeResult<()> {
+    if is_6309 && cpu != CpuKind::Cpu6309 {
+        Err(fatal(sp, Cpu6809AssemblyErrorKind::Only6309))
+    } else {
+        Ok(())
+    }
+}
+
 fn parse_opcode_with_arg(input: TSpan) -> PResult<Node> {
-    let (rest, (sp, text, info)) = get_opcode(input)?;
+    let (rest, (sp, text, info, cpu)) = get_opcode(input)?;
 
     let (rest, arg) = if info.supports_addr_mode(AddrModeEnum::RegisterSet) {
         parse_reg_set_operand(rest)
     } else if info.supports_addr_mode(AddrModeEnum::RegisterPair) {
         parse_opcode_reg_pair(rest)
     } else {
-        parse_opcode_arg(rest)
+        parse_opcode_arg(rest, cpu)
     }?;
 
-    let amode = match arg.item {
-        AstNodeKind::TargetSpecific(CpuSpecific::Cpu6809(Operand(amode))) => amode,
-        AstNodeKind::TargetSpecific(CpuSpecific::Cpu6809(OperandIndexed(amode, indirect))) => {
-            AddrModeParseType::Indexed(amode, indirect)
-        }
-        _ => return err_fatal(sp, Cpu6809AssemblyErrorKind::AddrModeUnsupported),
-    };
+    let amode = get_amode(sp, &arg)?;
 
     if let Some(instruction) = get_instruction(amode, info) {
+        check_cpu(sp, cpu, amode.is_6309())?;
         let item = OpCode(text.to_string(), Box::new(instruction.clone()), amode);
         let node = from_item_tspan(item, sp).take_others_children(arg);
         Ok((rest, node))
@@ -98,7 +125,7 @@ fn parse_opcode_with_arg(input: TSpan) -> PResult<Node> {
 
 fn parse_opcode_no_arg(input: TSpan) -> PResult<Node> {
     use Cpu6809AssemblyErrorKind::OnlySupports;
-    let (rest, (sp, text, ins)) = get_opcode(input)?;
+    let (rest, (sp, text, ins, _)) = get_opcode(input)?;
 
     if let Some(ins) = ins.get_boxed_instruction(AddrModeEnum::Inherent) {
         let oc = NodeKind6809::OpCode(text, ins, ParseInherent);
@@ -108,24 +135,202 @@ fn parse_opcode_no_arg(input: TSpan) -> PResult<Node> {
         err_fatal(sp, OnlySupports(AddrModeParseType::Inherent))
     }
 }
+
+/// Parses a TFM register and the optional + or - after it
+/// Returns the register and the step, 1, -1 or 0
+fn get_tfm_reg(input: TSpan) -> PResult<(RegEnum, i8)> {
+    use TokenKind::{Minus, Plus};
+    let (rest, (sp, (reg, step))) = ms(pair(
+        get_register,
+        opt(alt((map(tag(Plus), |_| 1), map(tag(Minus), |_| -1)))),
+    ))(input)?;
+
+    if matches!(reg, RegEnum::D | RegEnum::X | RegEnum::Y | RegEnum::U | RegEnum::S) {
+        Ok((rest, (reg, step.unwrap_or(0))))
+    } else {
+        err_fatal(sp, ExpectedRegister6809)
+    }
+}
+
+/// Parses for the TFM block transfer
+/// ```    tfm x+,y+```
+fn parse_tfm(input: TSpan) -> PResult<Node> {
+    use TfmMode::*;
+    use TokenKind::Comma;
+
+    let (rest, (sp, ((a, a_step), (b, b_step)))) =
+        ms(sep_pair(get_tfm_reg, Comma, get_tfm_reg))(input)?;
+
+    let mode = match (a_step, b_step) {
+        (1, 1) => IncInc,
+        (-1, -1) => DecDec,
+        (1, 0) => IncNone,
+        (0, 1) => NoneInc,
+        _ => return err_fatal(sp, Cpu6809AssemblyErrorKind::AddrModeUnsupported),
+    };
+
+    let node = from_item_tspan(Operand(AddrModeParseType::Tfm(a, b, mode)), sp);
+    Ok((rest, node))
+}
+
+/// Parses for the bit transfer instructions
+/// ```    band a,src bit,dest bit,<addr```
+fn parse_bit_transfer(input: TSpan) -> PResult<Node> {
+    use TokenKind::{Comma, LessThan};
+
+    let (rest, (sp, (reg, _, src, _, dst, _, _, addr))) = ms(tuple((
+        ms(get_register),
+        Comma,
+        parse_expr,
+        Comma,
+        parse_expr,
+        Comma,
+        opt(LessThan),
+        parse_expr,
+    )))(input)?;
+
+    let (reg_sp, reg) = reg;
+
+    if !matches!(reg, RegEnum::CC | RegEnum::A | RegEnum::B) {
+        return err_fatal(reg_sp, ExpectedRegister6809);
+    }
+
+    let item = Operand(AddrModeParseType::BitTransfer(reg));
+    let node = from_item_kids_tspan(item, &[src, dst, addr], sp);
+    Ok((rest, node))
+}
+
+/// Parses for the memory operations aim, oim, eim and tim
+/// ```    aim #imm,operand```
+/// The immediate value is added as the last child
+/// so the operand's own children keep their positions
+fn parse_in_memory(input: TSpan) -> PResult<Node> {
+    use TokenKind::{Comma, Hash};
+
+    let arg_6309 = |i| parse_opcode_arg(i, CpuKind::Cpu6309);
+    let (rest, (imm, arg)) = sep_pair(preceded(Hash, parse_expr), Comma, arg_6309)(input)?;
+
+    let mut children = arg.children.clone();
+    children.push(imm);
+    Ok((rest, arg.with_children_vec(children)))
+}
+
+/// Find the 6309 opcode for this parsed addressing mode
+fn get_instruction_6309<'a>(
+    amode: AddrModeParseType,
+    text: &str,
+    info: &'a [Instruction6309],
+) -> Option<&'a Instruction6309> {
+    use AddrMode6309 as M;
+    use AddrModeParseType as PT;
+
+    let get = |m: M| info.iter().find(|i| i.addr_mode == m);
+
+    match amode {
+        PT::Indexed(..) if DBASE_6309.is_in_memory(text) => get(M::ImmIndexed),
+        PT::Direct if DBASE_6309.is_in_memory(text) => get(M::ImmDirect),
+        PT::Extended(_) if DBASE_6309.is_in_memory(text) => get(M::ImmExtended),
+        PT::Indexed(..) => get(M::Indexed),
+        PT::Direct => get(M::Direct),
+        PT::Extended(_) => get(M::Extended),
+        PT::Immediate => get(M::Immediate8)
+            .or_else(|| get(M::Immediate16))
+            .or_else(|| get(M::Immediate32)),
+        PT::Inherent => get(M::Inherent),
+        PT::RegisterPair(..) => get(M::RegisterPair),
+        PT::Tfm(_, _, mode) => get(M::Tfm(mode)),
+        PT::BitTransfer(..) => get(M::BitDirect),
+        PT::Relative | PT::RegisterSet => None,
+    }
+}
+
+fn parse_opcode_6309_with_arg(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, text, info, cpu)) = get_opcode_6309(input)?;
+    check_cpu(sp, cpu, true)?;
+
+    let supports = |m| DBASE_6309.supports(&text, m);
+
+    let (rest, arg) = if supports(AddrMode6309::RegisterPair) {
+        parse_opcode_reg_pair(rest)
+    } else if supports(AddrMode6309::Tfm(TfmMode::IncInc)) {
+        parse_tfm(rest)
+    } else if supports(AddrMode6309::BitDirect) {
+        parse_bit_transfer(rest)
+    } else if DBASE_6309.is_in_memory(&text) {
+        parse_in_memory(rest)
+    } else {
+        parse_opcode_arg(rest, cpu)
+    }?;
+
+    let amode = get_amode(sp, &arg)?;
+
+    if let Some(instruction) = get_instruction_6309(amode, &text, info) {
+        let item = OpCode6309(text, Box::new(instruction.clone()), amode);
+        let node = from_item_tspan(item, sp).take_others_children(arg);
+        Ok((rest, node))
+    } else {
+        err_fatal(sp, Cpu6809AssemblyErrorKind::ThisAddrModeUnsupported(amode))
+    }
+}
+
+fn parse_opcode_6309_no_arg(input: TSpan) -> PResult<Node> {
+    use Cpu6809AssemblyErrorKind::OnlySupports;
+    let (rest, (sp, text, info, cpu)) = get_opcode_6309(input)?;
+    check_cpu(sp, cpu, true)?;
+
+    if let Some(ins) = get_instruction_6309(ParseInherent, &text, info) {
+        let oc = OpCode6309(text, Box::new(ins.clone()), ParseInherent);
+        let node = from_item_tspan(oc, sp);
+        Ok((rest, node))
+    } else {
+        err_fatal(sp, OnlySupports(AddrModeParseType::Inherent))
+    }
+}
+
 pub fn parse_opcode(input: TSpan) -> PResult<Node> {
-    let (rest, item) = alt((parse_opcode_with_arg, parse_opcode_no_arg))(input)?;
+    let (rest, item) = alt((
+        parse_opcode_with_arg,
+        parse_opcode_no_arg,
+        parse_opcode_6309_with_arg,
+        parse_opcode_6309_no_arg,
+    ))(input)?;
     Ok((rest, item))
 }
 
 pub fn parse_multi_opcode_vec(input: TSpan) -> PResult<Vec<Node>> {
-    use unraveler::tag;
     use TokenKind::Colon;
     let (rest, matched) = sep_list(parse_opcode, tag(Colon))(input)?;
     Ok((rest, matched))
 }
 
-fn get_opcode(input: TSpan) -> PResult<(TSpan, String, &InstructionInfo)> {
+/// The opcode text along with the cpu it was lexed for
+fn get_opcode_text(input: TSpan) -> PResult<(TSpan, String, CpuKind)> {
+    use CpuKind::{Cpu6309, Cpu6809};
     use TokenKind::OpCode;
-    let (rest, (sp, matched)) = ms(OpCode(CpuKind::Cpu6809))(input)?;
-    let text = get_text(matched);
-    let info = OPCODES_REC.get_opcode(text.as_str()).unwrap();
-    Ok((rest, (sp, text, info)))
+
+    let (rest, (sp, matched)) = ms(alt((OpCode(Cpu6809), OpCode(Cpu6309))))(input)?;
+    let text = get_text(matched).to_lowercase();
+    let cpu = match matched.first().map(|t| t.kind) {
+        Some(OpCode(cpu)) => cpu,
+        _ => Cpu6809,
+    };
+    Ok((rest, (sp, text, cpu)))
+}
+
+fn get_opcode(input: TSpan) -> PResult<(TSpan, String, &InstructionInfo, CpuKind)> {
+    let (rest, (sp, text, cpu)) = get_opcode_text(input)?;
+    let info = OPCODES_REC
+        .get_opcode(text.as_str())
+        .ok_or(err_kind_nomatch(sp))?;
+    Ok((rest, (sp, text, info, cpu)))
+}
+
+fn get_opcode_6309(input: TSpan) -> PResult<(TSpan, String, &[Instruction6309], CpuKind)> {
+    let (rest, (sp, text, cpu)) = get_opcode_text(input)?;
+    let info = DBASE_6309
+        .get_opcode(text.as_str())
+        .ok_or(err_kind_nomatch(sp))?;
+    Ok((rest, (sp, text, info, cpu)))
 }
 
 fn get_instruction(amode: AddrModeParseType, info: &InstructionInfo) -> Option<&Instruction> {
@@ -149,6 +354,8 @@ fn get_instruction(amode: AddrModeParseType, info: &InstructionInfo) -> Option<&
         AddrModeParseType::RegisterPair(..) => get(RegisterPair),
 
         AddrModeParseType::RegisterSet => get(RegisterSet),
+
+        AddrModeParseType::Tfm(..) | AddrModeParseType::BitTransfer(..) => None,
     }
 }
 
@@ -1,33 +1,62 @@
 #![deny(unused_imports)]
-use unraveler::{alt, match_span as ms, pair, sep_pair, succeeded};
+use unraveler::{alt, map, match_span as ms, opt, pair, sep_pair, succeeded, tag};
 
 use crate::frontend::{
     err_fatal, from_item_kid_tspan, from_item_tspan, parse_expr, parse_sq_bracketed, AstNodeKind,
-    CpuSpecific, Node, PResult, TSpan, TokenKind::Comma,
+    CpuSpecific, Node, PResult, TSpan,
+    TokenKind::{Comma, GreaterThan, LessThan},
 };
 
 use super::{
-    get_index_reg, get_this_reg, indexed::get_indexed, IndexParseType, NodeKind6809,
-    NodeKind6809::OperandIndexed,
+    get_index_reg, get_this_reg, get_this_reg_6309,
+    indexed::{get_indexed, get_indexed_6309},
+    IndexParseType,
+    NodeKind6809, NodeKind6809::OperandIndexed, OffsetSize, Reg6309,
 };
 
 use crate::help::ErrCode;
 
+/// Parses for an optional `<` or `>` forcing the size of an offset
+fn get_offset_size(input: TSpan) -> PResult<OffsetSize> {
+    let (rest, size) = opt(alt((
+        map(tag(LessThan), |_| OffsetSize::Byte),
+        map(tag(GreaterThan), |_| OffsetSize::Word),
+    )))(input)?;
+    Ok((rest, size.unwrap_or_default()))
+}
+
 /// Parses for simple offset indexed addressing
 /// ```    addr,<index reg>```
+/// A `<` or `>` before addr forces an 8 or 16 bit offset
 fn parse_offset(input: TSpan) -> PResult<Node> {
-    let (rest, (sp, (expr, reg))) = ms(sep_pair(parse_expr, Comma, get_index_reg))(input)?;
-    let offset = IndexParseType::ConstantOffset(reg);
+    let (rest, (sp, (size, (expr, reg)))) = ms(pair(
+        get_offset_size,
+        sep_pair(parse_expr, Comma, get_index_reg),
+    ))(input)?;
+    let offset = IndexParseType::ConstantOffset(reg, size);
     let item = NodeKind6809::operand_from_index_mode(offset, false);
     Ok((rest, from_item_kid_tspan(item, expr, sp)))
 }
 
+/// Parses for W offset indexed addressing, 6309 only
+/// ```    addr,w```
+fn parse_offset_w(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, (expr, _))) =
+        ms(sep_pair(parse_expr, Comma, get_this_reg_6309(Reg6309::W)))(input)?;
+    let item = NodeKind6809::operand_from_index_mode(IndexParseType::WordOffsetW, false);
+    Ok((rest, from_item_kid_tspan(item, expr, sp)))
+}
+
 /// Parses for simple pc offset addressing
 /// ```    offset,pc```
+/// A `<` or `>` before offset forces an 8 or 16 bit offset
 fn parse_pc_offset(input: TSpan) -> PResult<Node> {
     use emu6809::cpu::RegEnum::*;
-    let (rest, (sp, expr)) = ms(succeeded(parse_expr, pair(Comma, get_this_reg(PC))))(input)?;
-    let item = NodeKind6809::operand_from_index_mode(IndexParseType::PCOffset, false);
+    let (rest, (sp, (size, expr))) = ms(pair(
+        get_offset_size,
+        succeeded(parse_expr, pair(Comma, get_this_reg(PC))),
+    ))(input)?;
+    let item = NodeKind6809::operand_from_index_mode(IndexParseType::PCOffset(size), false);
     let matched = from_item_kid_tspan(item, expr, sp);
     Ok((rest, matched))
 }
@@ -41,19 +70,31 @@ fn parse_extended_indirect(input: TSpan) -> PResult<Node> {
     Ok((rest, matched))
 }
 
+/// Parser for the part of an indexed operand after any offset expression
+/// The 6309 has extra modes that would be labels on a 6809
+type IndexParser = fn(TSpan) -> PResult<IndexParseType>;
+
 /// Pares for addr mode without an offset
 ///     ,y
 ///     ,-u
-fn parse_index_only(input: TSpan) -> PResult<Node> {
-    let (rest, (sp, matched)) = ms(get_indexed)(input)?;
+fn parse_index_only(input: TSpan, get: IndexParser) -> PResult<Node> {
+    let (rest, (sp, matched)) = ms(get)(input)?;
     let matched = from_item_tspan(OperandIndexed(matched, false), sp);
     Ok((rest, matched))
 }
 
-fn parse_no_arg_indexed_allowed_indirect(input: TSpan) -> PResult<Node> {
+/// Makes a parser from a parse function that takes an `IndexParser`
+fn with_index(
+    p: fn(TSpan, IndexParser) -> PResult<Node>,
+    get: IndexParser,
+) -> impl FnMut(TSpan) -> PResult<Node> + Copy {
+    move |i| p(i, get)
+}
+
+fn parse_no_arg_indexed_allowed_indirect(input: TSpan, get: IndexParser) -> PResult<Node> {
     use ErrCode::*;
 
-    let (rest, (sp, matched)) = ms(get_indexed)(input)?;
+    let (rest, (sp, matched)) = ms(get)(input)?;
 
     match matched {
         IndexParseType::PostInc(_) => err_fatal(sp, IndexModeNotValidIndirect6809),
@@ -65,12 +106,13 @@ fn parse_no_arg_indexed_allowed_indirect(input: TSpan) -> PResult<Node> {
     }
 }
 
-fn parse_indexed_indirect(input: TSpan) -> PResult<Node> {
+fn parse_indexed_indirect(input: TSpan, get: IndexParser) -> PResult<Node> {
     use AstNodeKind::TargetSpecific;
     use CpuSpecific::Cpu6809;
     let indexed_indirect = alt((
-        parse_no_arg_indexed_allowed_indirect,
+        with_index(parse_no_arg_indexed_allowed_indirect, get),
         parse_pc_offset,
+        parse_offset_w,
         parse_offset,
     ));
     let (rest, mut matched) = parse_sq_bracketed(indexed_indirect)(input)?;
@@ -84,14 +126,177 @@ fn parse_indexed_indirect(input: TSpan) -> PResult<Node> {
     Ok((rest, matched))
 }
 
-fn parse_indexed_direct(input: TSpan) -> PResult<Node> {
-    alt((parse_index_only, parse_pc_offset, parse_offset))(input)
+fn parse_indexed_direct(input: TSpan, get: IndexParser) -> PResult<Node> {
+    let index_only = with_index(parse_index_only, get);
+    alt((index_only, parse_pc_offset, parse_offset_w, parse_offset))(input)
 }
 
-pub fn parse_indexed(input: TSpan) -> PResult<Node> {
+fn parse_indexed_with(input: TSpan, get: IndexParser) -> PResult<Node> {
     alt((
-        parse_indexed_indirect,
+        with_index(parse_indexed_indirect, get),
         parse_extended_indirect,
-        parse_indexed_direct,
+        with_index(parse_indexed_direct, get),
     ))(input)
 }
+
+pub fn parse_indexed(input: TSpan) -> PResult<Node> {
+    parse_indexed_with(input, get_indexed)
+}
+
+/// Indexed operands for the 6309, `e,x` adds e to x rather than using a label
+pub fn parse_indexed_6309(input: TSpan) -> PResult<Node> {
+    parse_indexed_with(input, get_indexed_6309)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembler::testing::*,
+        cpu6809::frontend::{parse_multi_opcode_vec, AddrModeParseType, NodeKind6809, OffsetSize},
+        cpukind::CpuKind,
+        frontend::{create_source_file, make_tspan, to_tokens_no_comment, AstNodeKind, CpuSpecific},
+        opts::Opts,
+    };
+    use emu6809::cpu::RegEnum::X;
+
+    const SOURCE: &str = "
+e           equ 2
+w           equ 4
+            org $1000
+            lda e,x
+            lda w,u
+            ";
+
+    #[test]
+    fn test_efw_labels_on_6809() {
+        // e and w are labels on a 6809 so these are constant offsets
+        let asm = assemble_ok(SOURCE, Opts::default());
+        assert_eq!(bytes(&asm, 0x1000, 4), [0xa6, 0x02, 0xa6, 0x44]);
+    }
+
+    #[test]
+    fn test_efw_registers_on_6309() {
+        let asm = assemble_ok(SOURCE, cpu_opts(CpuKind::Cpu6309));
+        assert_eq!(bytes(&asm, 0x1000, 4), [0xa6, 0x87, 0xa6, 0xce]);
+    }
+
+    #[test]
+    fn test_other_6309_registers_as_labels() {
+        // v and q can't be offsets so they're labels, even on a 6309
+        let text = "
+v           equ 2
+q           equ 4
+            org $1000
+            lda v,x
+            lda q,u
+            ";
+        let asm = assemble_ok(text, cpu_opts(CpuKind::Cpu6309));
+        assert_eq!(bytes(&asm, 0x1000, 4), [0xa6, 0x02, 0xa6, 0x44]);
+    }
+
+    /// Addressing mode the parser picked for the first opcode in `text`
+    fn parse_amode(text: &str) -> AddrModeParseType {
+        let opts = Opts::default();
+        let sf = create_source_file(text);
+        let tokens = to_tokens_no_comment(&sf, opts.cpu);
+        let span = make_tspan(&tokens, &sf, &opts);
+        let (_, nodes) = parse_multi_opcode_vec(span).unwrap();
+
+        match &nodes[0].item {
+            AstNodeKind::TargetSpecific(CpuSpecific::Cpu6809(NodeKind6809::OpCode(_, _, am))) => {
+                *am
+            }
+            x => panic!("Not an opcode {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_forced_offsets() {
+        use super::IndexParseType::*;
+        use AddrModeParseType::*;
+        use OffsetSize::*;
+
+        assert_eq!(parse_amode("lda 2,x"), Indexed(ConstantOffset(X, Auto), false));
+        assert_eq!(parse_amode("lda <2,x"), Indexed(ConstantOffset(X, Byte), false));
+        assert_eq!(parse_amode("lda >2,x"), Indexed(ConstantOffset(X, Word), false));
+        assert_eq!(parse_amode("lda [<2,x]"), Indexed(ConstantOffset(X, Byte), true));
+        assert_eq!(parse_amode("lda [>2,x]"), Indexed(ConstantOffset(X, Word), true));
+
+        assert_eq!(parse_amode("lda 2,pc"), Indexed(PCOffset(Auto), false));
+        assert_eq!(parse_amode("lda <2,pc"), Indexed(PCOffset(Byte), false));
+        assert_eq!(parse_amode("lda >2,pc"), Indexed(PCOffset(Word), false));
+
+        // Without an index register they still force direct / extended
+        assert_eq!(parse_amode("lda $10"), Extended(false));
+        assert_eq!(parse_amode("lda <$10"), Direct);
+        assert_eq!(parse_amode("lda >$10"), Extended(true));
+    }
+
+    #[test]
+    fn test_size_forced_offsets() {
+        let text = "
+            org $1000
+            lda 2,x
+            lda <2,x
+            lda >2,x
+            lda [<2,x]
+            lda [>2,x]
+            lda 2,pc
+            lda <2,pc
+            lda >2,pc
+            lda <$10
+            lda >$10
+            ";
+        let asm = assemble_ok(text, Opts::default());
+
+        let expected = [
+            0xa6, 0x02, // 5 bit
+            0xa6, 0x88, 0x02, // 8 bit
+            0xa6, 0x89, 0x00, 0x02, // 16 bit
+            0xa6, 0x98, 0x02, // 8 bit indirect
+            0xa6, 0x99, 0x00, 0x02, // 16 bit indirect
+            0xa6, 0x8c, 0x02, // pc 8 bit
+            0xa6, 0x8c, 0x02, // pc 8 bit
+            0xa6, 0x8d, 0x00, 0x02, // pc 16 bit
+            0x96, 0x10, // direct
+            0xb6, 0x00, 0x10, // extended
+        ];
+        assert_eq!(bytes(&asm, 0x1000, expected.len()), expected);
+    }
+
+    #[test]
+    fn test_forward_struct_offsets() {
+        // Members of a struct defined later shrink to the smallest offset that fits
+        let text = "
+            org $1000
+            lda obj.small,x
+            lda obj.big,y
+            lda obj.huge,u
+            rts
+struct obj {
+    pad : byte,
+    small : byte,
+    gap : byte[40],
+    big : byte,
+    buf : byte[200],
+    huge : byte,
+}
+            ";
+        let asm = assemble_ok(text, Opts::default());
+
+        let expected = [
+            0xa6, 0x01, // 5 bit
+            0xa6, 0xa8, 0x2a, // 8 bit
+            0xa6, 0xc9, 0x00, 0xf3, // 16 bit
+            0x39,
+        ];
+        assert_eq!(bytes(&asm, 0x1000, expected.len()), expected);
+    }
+
+    #[test]
+    fn test_forced_byte_offset_too_big() {
+        let errs = assemble_err("    lda <200,x\n", Opts::default());
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("Offset 200 does not fit in 8 bits"), "{errs:?}");
+    }
+}
@@ -2,11 +2,13 @@
 use crate::help::ErrCode::*;
 use emu6809::cpu::RegEnum;
 use std::collections::HashSet;
-use unraveler::{cut, match_span as ms, sep_list, sep_pair};
+use strum_macros::EnumString;
+use unraveler::{alt, cut, map, match_item, match_span as ms, sep_list, sep_pair};
 
 use crate::frontend::{
     err_error, err_fatal, error, get_label_string, PResult, TSpan,
     Node,
+    Token,
     TokenKind::*,
     from_item_tspan,
 };
@@ -19,6 +21,33 @@ use crate::cpu6809::{
     NodeKind,
 };
 
+/// Registers only found on the 6309
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum Reg6309 {
+    E,
+    F,
+    W,
+    V,
+    Q,
+    /// The zero register, written as 0
+    #[strum(disabled)]
+    Zero,
+}
+
+/// A register that can be used in an inter register instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PairReg {
+    Reg(RegEnum),
+    Reg6309(Reg6309),
+}
+
+impl PairReg {
+    pub fn is_6309(&self) -> bool {
+        matches!(self, PairReg::Reg6309(..))
+    }
+}
+
 pub fn get_comma_sep_reg_pair(input: TSpan) -> PResult<(TSpan, RegEnum, TSpan, RegEnum)> {
     let (rest, ((sp_r1, r1), (sp_r2, r2))) =
         sep_pair(ms(get_register), Comma, ms(get_register))(input)?;
@@ -44,7 +73,7 @@ pub fn parse_reg_set(input: TSpan) -> PResult<Node> {
 /// eg tfr a,b
 pub fn parse_opcode_reg_pair(input: TSpan) -> PResult<Node> {
     use AddrModeParseType::RegisterPair;
-    let (rest, (sp, (a, b))) = ms(sep_pair(get_register, Comma, cut(get_register)))(input)?;
+    let (rest, (sp, (a, b))) = ms(sep_pair(get_pair_reg, Comma, cut(get_pair_reg)))(input)?;
     let node = from_item_tspan(Operand(RegisterPair(a, b)), sp);
     Ok((rest, node))
 }
@@ -97,6 +126,44 @@ pub fn get_index_reg(input: TSpan) -> PResult<RegEnum> {
         .ok_or(error(sp, ExpectedIndexRegister6809))
 }
 
+/// Parse a 6309 only register
+pub fn get_register_6309(input: TSpan) -> PResult<Reg6309> {
+    let (rest, (sp, text)) = ms(get_label_string)(input)?;
+
+    text.as_str()
+        .parse::<Reg6309>()
+        .map(|reg| (rest, reg))
+        .map_err(|_| error(sp, ExpectedRegister6809))
+}
+
+pub fn get_this_reg_6309(r: Reg6309) -> impl FnMut(TSpan) -> PResult<Reg6309> + Copy {
+    move |i| {
+        let (rest, (sp, matched)) = ms(get_register_6309)(i)?;
+        if matched != r {
+            err_error(sp, ExpectedRegister6809)
+        } else {
+            Ok((rest, matched))
+        }
+    }
+}
+
+/// Parse for the 6309 zero register
+/// ```    0```
+fn get_zero_reg(input: TSpan) -> PResult<Reg6309> {
+    let is_zero = |t: &Token| matches!(t.kind, Number((0, _)));
+    map(match_item(is_zero), |_| Reg6309::Zero)(input)
+}
+
+/// Parse a register for an inter register instruction
+/// Includes the 6309 registers, the parser checks they are allowed
+pub fn get_pair_reg(input: TSpan) -> PResult<PairReg> {
+    alt((
+        map(get_register, PairReg::Reg),
+        map(get_register_6309, PairReg::Reg6309),
+        map(get_zero_reg, PairReg::Reg6309),
+    ))(input)
+}
+
 /// Parse a single register
 pub fn get_register(input: TSpan) -> PResult<RegEnum> {
     let (rest, (sp, text)) = ms(get_label_string)(input)?;
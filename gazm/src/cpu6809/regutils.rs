@@ -3,6 +3,8 @@
 use emu6809::cpu::RegEnum;
 use std::collections::HashSet;
 
+use crate::cpu6809::frontend::{PairReg, Reg6309};
+
 pub fn reg_to_reg_num(a: RegEnum) -> u8 {
     use RegEnum::*;
 
@@ -20,10 +22,31 @@ pub fn reg_to_reg_num(a: RegEnum) -> u8 {
     }
 }
 
-pub fn reg_pair_to_flags(source: RegEnum, dest: RegEnum) -> u8 {
-    let a = reg_to_reg_num(source);
-    let b = reg_to_reg_num(dest);
-    (a << 4) | b
+/// Register number of a 6309 register, Q can't be transferred
+pub fn reg_6309_to_reg_num(a: Reg6309) -> Option<u8> {
+    use Reg6309::*;
+
+    match a {
+        W => Some(0b0110),
+        V => Some(0b0111),
+        Zero => Some(0b1100),
+        E => Some(0b1110),
+        F => Some(0b1111),
+        Q => None,
+    }
+}
+
+pub fn pair_reg_to_reg_num(a: PairReg) -> Option<u8> {
+    match a {
+        PairReg::Reg(r) => Some(reg_to_reg_num(r)),
+        PairReg::Reg6309(r) => reg_6309_to_reg_num(r),
+    }
+}
+
+pub fn reg_pair_to_flags(source: PairReg, dest: PairReg) -> Option<u8> {
+    let a = pair_reg_to_reg_num(source)?;
+    let b = pair_reg_to_reg_num(dest)?;
+    Some((a << 4) | b)
 }
 
 pub fn registers_to_flags(regs: &HashSet<RegEnum>) -> u8 {
@@ -1,42 +1,97 @@
-use std::usize;
+use std::{rc::Rc, usize};
 
-use crate::{assembler::AssemblerCpuTrait, cpu6800::Asm6800, cpu6809::Asm6809};
+use crate::{
+    assembler::{AssemblerCpuTrait, Endian},
+    cpu6502::Asm6502,
+    cpu6800::Asm6800,
+    cpu6809::Asm6809,
+    cpuz80::AsmZ80,
+    frontend::CpuSpecific,
+};
 use emu6800::emucore::sha1::digest::DynDigest;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum::{EnumCount, IntoEnumIterator, };
 
 use strum_macros::{EnumCount as EnumCountMacro, EnumIter, EnumString};
 
-#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Default, EnumCountMacro, EnumIter, EnumString, Eq)]
+#[derive(
+    Debug,
+    PartialEq,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    Default,
+    EnumCountMacro,
+    EnumIter,
+    EnumString,
+    Eq,
+)]
 #[repr(usize)]
+#[strum(ascii_case_insensitive)]
 pub enum CpuKind {
     #[default]
+    #[strum(serialize = "Cpu6809", serialize = "6809")]
+    #[serde(alias = "6809")]
     Cpu6809,
+    #[strum(serialize = "Cpu6800", serialize = "6800")]
+    #[serde(alias = "6800")]
     Cpu6800,
+    #[strum(serialize = "Cpu6502", serialize = "6502")]
+    #[serde(alias = "6502")]
     Cpu6502,
+    #[strum(serialize = "Cpu65c02", serialize = "65c02")]
+    #[serde(alias = "65c02", alias = "65C02")]
     Cpu65c02,
+    #[strum(serialize = "CpuZ80", serialize = "z80")]
+    #[serde(alias = "z80", alias = "Z80")]
     CpuZ80,
+    #[strum(serialize = "Cpu6309", serialize = "6309")]
+    #[serde(alias = "6309")]
+    Cpu6309,
+}
+
+impl CpuKind {
+    /// Byte order words are written in for this cpu
+    pub fn endian(&self) -> Endian {
+        match self {
+            CpuKind::Cpu6809 | CpuKind::Cpu6309 | CpuKind::Cpu6800 => Endian::Big,
+            CpuKind::Cpu6502 | CpuKind::Cpu65c02 | CpuKind::CpuZ80 => Endian::Little,
+        }
+    }
+
+    /// The cpu this one extends, or itself
+    pub fn family(&self) -> CpuKind {
+        match self {
+            CpuKind::Cpu6309 => CpuKind::Cpu6809,
+            CpuKind::Cpu65c02 => CpuKind::Cpu6502,
+            cpu => *cpu,
+        }
+    }
 }
 
 impl From<CpuKind> for Box<dyn AssemblerCpuTrait> {
     fn from(cpu: CpuKind) -> Box<dyn AssemblerCpuTrait> {
         match cpu {
             CpuKind::Cpu6809 => Box::new(Asm6809::new()),
-            CpuKind::Cpu6800 => Box::new(Asm6809::new()),
-            CpuKind::Cpu6502 => todo!(),
-            CpuKind::Cpu65c02 => todo!(),
-            CpuKind::CpuZ80 => todo!(),
+            CpuKind::Cpu6800 => Box::new(Asm6800::new()),
+            CpuKind::Cpu6502 => Box::new(Asm6502::new()),
+            CpuKind::Cpu65c02 => Box::new(Asm6502::new_65c02()),
+            CpuKind::CpuZ80 => Box::new(AsmZ80::new()),
+            CpuKind::Cpu6309 => Box::new(Asm6809::new_6309()),
         }
     }
 }
 
 pub struct Assemblers {
-    asm: [Box<dyn AssemblerCpuTrait>; CpuKind::COUNT],
+    asm: [Rc<dyn AssemblerCpuTrait>; CpuKind::COUNT],
 }
 
 impl Default for Assemblers {
     fn default() -> Self {
-        let res: Vec<Box<dyn AssemblerCpuTrait>> = CpuKind::iter().map(|x| x.into()).collect();
+        let res: Vec<Rc<dyn AssemblerCpuTrait>> = CpuKind::iter()
+            .map(|x| Box::<dyn AssemblerCpuTrait>::from(x).into())
+            .collect();
 
         Self {
             asm: res.try_into().unwrap_or_else(|_| panic!()),
@@ -49,43 +104,55 @@ impl Assemblers {
         Self::default()
     }
 
-    pub fn asm(&self, cpu: CpuKind) -> &dyn AssemblerCpuTrait {
-        self.asm.get(cpu as usize).unwrap().as_ref()
-    }
-    pub fn asm_mut(&mut self, cpu: CpuKind) -> &mut dyn AssemblerCpuTrait {
-        self.asm.get_mut(cpu as usize).unwrap().as_mut()
+    /// Shared so the assembler can be used while the sizer or compiler is borrowed
+    pub fn asm(&self, cpu: CpuKind) -> Rc<dyn AssemblerCpuTrait> {
+        self.asm.get(cpu as usize).unwrap().clone()
     }
 }
 
+/// Tracks the cpu being assembled for while sizing and compiling
+/// `target` switches it, the parser switches back at the end of the block it's in
 pub struct CpuAssmbler {
-    cpu: Option<CpuKind>,
+    cpu: CpuKind,
     assemblers: Assemblers,
 }
 
 impl Default for CpuAssmbler {
     fn default() -> Self {
+        Self::new(CpuKind::default())
+    }
+}
+
+impl CpuAssmbler {
+    pub fn new(cpu: CpuKind) -> Self {
         Self {
-            cpu: None,
+            cpu,
             assemblers: Assemblers::new(),
         }
     }
-}
 
-impl CpuAssmbler {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn get_cpu(&self) -> CpuKind {
+        self.cpu
+    }
+
+    pub fn set_cpu(&mut self, cpu: CpuKind) {
+        self.cpu = cpu
     }
 
-    pub fn asm(&self) -> &dyn AssemblerCpuTrait {
-        let cpu = self.cpu.unwrap();
-        let ret = self.assemblers.asm(cpu);
-        ret
+    pub fn asm(&self) -> Rc<dyn AssemblerCpuTrait> {
+        self.assemblers.asm(self.cpu)
     }
 
-    pub fn asm_mut(&mut self) -> &mut dyn AssemblerCpuTrait {
-        let cpu = self.cpu.unwrap();
-        let ret = self.assemblers.asm_mut(cpu);
-        ret
+    /// The assembler for a node, the current cpu's unless the node is from another family
+    /// Nodes parsed for another cpu are assembled by that cpu's assembler
+    pub fn asm_for(&self, node: &CpuSpecific) -> Rc<dyn AssemblerCpuTrait> {
+        let cpu = node.get_cpu_kind();
+
+        if cpu.family() == self.cpu.family() {
+            self.asm()
+        } else {
+            self.assemblers.asm(cpu)
+        }
     }
 }
 
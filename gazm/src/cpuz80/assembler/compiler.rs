@@ -0,0 +1,119 @@
+use crate::{
+    assembler::{Assembler, BinaryError},
+    cpuz80::frontend::{InstructionZ80, NodeKindZ80, OpByte},
+    error::GResult,
+    semantic::AstNodeRef,
+};
+
+impl Assembler {
+    /// Compile an opcode
+    pub fn compile_opcode_z80(
+        &mut self,
+        node: AstNodeRef,
+        ins: &InstructionZ80,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        use OpByte::*;
+
+        let pc = self.get_binary().get_write_address() as i64;
+        let size = ins.size() as i64;
+
+        for b in &ins.bytes {
+            match *b {
+                Byte(b) => self.write_byte(b, node)?,
+
+                Imm8(i) => {
+                    let (arg, _) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    self.write_byte_check_size(arg, node)?;
+                }
+
+                Imm16(i) => {
+                    let (arg, _) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    let res = self.get_binary_mut().write_uword_check_size(arg);
+                    self.binary_error_map(node, res)?;
+                }
+
+                Disp(i) => {
+                    let (arg, arg_n) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    let res = self.get_binary_mut().write_ibyte_check_size(arg);
+                    self.binary_error_map(arg_n, res)?;
+                }
+
+                Rel(i) => {
+                    let (arg, arg_n) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    // offset is from PC after Instruction and operand has been fetched
+                    let val = arg - (pc + size);
+                    let res = self.get_binary_mut().write_ibyte_check_size(val);
+
+                    match res {
+                        Ok(_) => (),
+                        Err(BinaryError::DoesNotFit { .. }) => {
+                            if self.opts.ignore_relative_offset_errors {
+                                let res = self.get_binary_mut().write_ibyte_check_size(0);
+                                self.binary_error_map(node, res)?;
+                            } else {
+                                return Err(self.relative_error(arg_n, val, 8));
+                            }
+                        }
+                        Err(e) => return Err(self.binary_error(node, e)),
+                    }
+                }
+
+                Rst(i) => {
+                    let (arg, arg_n) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    if arg & !0x38 != 0 {
+                        let msg = format!("Invalid rst vector ${arg:x}, must be one of $00, $08 .. $38");
+                        return Err(self.make_user_error(msg, arg_n, true).into());
+                    }
+                    self.write_byte(0xc7 | arg as u8, node)?;
+                }
+
+                Im(i) => {
+                    let (arg, arg_n) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    let b = match arg {
+                        0 => 0x46,
+                        1 => 0x56,
+                        2 => 0x5e,
+                        _ => {
+                            let msg = format!("Invalid interrupt mode {arg}, must be 0, 1 or 2");
+                            return Err(self.make_user_error(msg, arg_n, true).into());
+                        }
+                    };
+                    self.write_byte(b, node)?;
+                }
+
+                Bit(base, i) => {
+                    let (arg, arg_n) = self.eval_nth_arg_n(node, i, current_scope_id)?;
+                    if !(0..8).contains(&arg) {
+                        let msg = format!("Invalid bit number {arg}, must be 0 to 7");
+                        return Err(self.make_user_error(msg, arg_n, true).into());
+                    }
+                    self.write_byte(base | (arg as u8) << 3, node)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile a node
+    pub fn compile_node_z80(
+        &mut self,
+        node_kind: NodeKindZ80,
+        node: AstNodeRef,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        use NodeKindZ80::*;
+
+        match node_kind {
+            OpCode(_, ins) => self.compile_opcode_z80(node, &ins, current_scope_id)?,
+            Illegal => return Err(self.make_user_error("Illegal instruction", node, true).into()),
+            Operand(_) => {
+                let msg = "Operand without an instruction";
+                return Err(self.make_user_error(msg, node, true).into());
+            }
+        }
+
+        Ok(())
+    }
+}
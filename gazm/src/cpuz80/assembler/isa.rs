@@ -0,0 +1,612 @@
+/// Instruction encoder for the documented Z80 instruction set
+/// Z80 encodings are regular enough that they are built from the
+/// operands rather than looked up in a table
+use crate::cpuz80::frontend::{
+    Condition, OpByte,
+    OpByte::*,
+    OperandZ80::{self, *},
+    RegEnum::{self, *},
+};
+
+/// Instructions with no operands
+#[rustfmt::skip]
+static IMPLIED: &[(&str, &[u8])] = &[
+    ("nop", &[0x00]), ("halt", &[0x76]), ("di", &[0xf3]), ("ei", &[0xfb]),
+    ("exx", &[0xd9]), ("daa", &[0x27]), ("cpl", &[0x2f]), ("ccf", &[0x3f]),
+    ("scf", &[0x37]), ("rlca", &[0x07]), ("rla", &[0x17]), ("rrca", &[0x0f]),
+    ("rra", &[0x1f]), ("ret", &[0xc9]),
+    ("neg", &[0xed, 0x44]), ("reti", &[0xed, 0x4d]), ("retn", &[0xed, 0x45]),
+    ("rld", &[0xed, 0x6f]), ("rrd", &[0xed, 0x67]),
+    ("ldi", &[0xed, 0xa0]), ("ldir", &[0xed, 0xb0]), ("ldd", &[0xed, 0xa8]),
+    ("lddr", &[0xed, 0xb8]), ("cpi", &[0xed, 0xa1]), ("cpir", &[0xed, 0xb1]),
+    ("cpd", &[0xed, 0xa9]), ("cpdr", &[0xed, 0xb9]), ("ini", &[0xed, 0xa2]),
+    ("inir", &[0xed, 0xb2]), ("ind", &[0xed, 0xaa]), ("indr", &[0xed, 0xba]),
+    ("outi", &[0xed, 0xa3]), ("otir", &[0xed, 0xb3]), ("outd", &[0xed, 0xab]),
+    ("otdr", &[0xed, 0xbb]),
+];
+
+/// Instructions that take operands
+#[rustfmt::skip]
+static WITH_OPERANDS: &[&str] = &[
+    "ld", "push", "pop", "ex", "add", "adc", "sub", "sbc", "and", "xor", "or", "cp",
+    "inc", "dec", "rlc", "rrc", "rl", "rr", "sla", "sra", "srl", "bit", "res", "set",
+    "jp", "jr", "djnz", "call", "rst", "im", "in", "out",
+];
+
+/// T-states for each form of an instruction
+/// Conditional jumps, calls, returns and repeating block instructions list both timings
+#[rustfmt::skip]
+static TIMINGS: &[(&str, &str, usize)] = &[
+    ("nop", "implied", 4), ("halt", "implied", 4), ("di", "implied", 4), ("ei", "implied", 4),
+    ("exx", "implied", 4), ("daa", "implied", 4), ("cpl", "implied", 4), ("ccf", "implied", 4),
+    ("scf", "implied", 4), ("rlca", "implied", 4), ("rla", "implied", 4),
+    ("rrca", "implied", 4), ("rra", "implied", 4), ("neg", "implied", 8),
+    ("reti", "implied", 14), ("retn", "implied", 14), ("rld", "implied", 18),
+    ("rrd", "implied", 18),
+    ("ldi", "implied", 16), ("ldd", "implied", 16), ("cpi", "implied", 16),
+    ("cpd", "implied", 16), ("ini", "implied", 16), ("ind", "implied", 16),
+    ("outi", "implied", 16), ("outd", "implied", 16),
+    ("ldir", "repeating", 21), ("ldir", "last", 16), ("lddr", "repeating", 21),
+    ("lddr", "last", 16), ("cpir", "repeating", 21), ("cpir", "last", 16),
+    ("cpdr", "repeating", 21), ("cpdr", "last", 16), ("inir", "repeating", 21),
+    ("inir", "last", 16), ("indr", "repeating", 21), ("indr", "last", 16),
+    ("otir", "repeating", 21), ("otir", "last", 16), ("otdr", "repeating", 21),
+    ("otdr", "last", 16),
+
+    ("ld", "r,r'", 4), ("ld", "r,n", 7), ("ld", "r,(hl)", 7), ("ld", "r,(ix+d)", 19),
+    ("ld", "(hl),r", 7), ("ld", "(ix+d),r", 19), ("ld", "(hl),n", 10),
+    ("ld", "(ix+d),n", 19), ("ld", "a,(bc)", 7), ("ld", "a,(de)", 7), ("ld", "a,(nn)", 13),
+    ("ld", "(bc),a", 7), ("ld", "(de),a", 7), ("ld", "(nn),a", 13), ("ld", "a,i", 9),
+    ("ld", "a,R", 9), ("ld", "i,a", 9), ("ld", "R,a", 9), ("ld", "dd,nn", 10),
+    ("ld", "ix,nn", 14), ("ld", "hl,(nn)", 16), ("ld", "dd,(nn)", 20),
+    ("ld", "ix,(nn)", 20), ("ld", "(nn),hl", 16), ("ld", "(nn),dd", 20),
+    ("ld", "(nn),ix", 20), ("ld", "sp,hl", 6), ("ld", "sp,ix", 10),
+
+    ("push", "qq", 11), ("push", "ix", 15), ("pop", "qq", 10), ("pop", "ix", 14),
+    ("ex", "de,hl", 4), ("ex", "af,af'", 4), ("ex", "(sp),hl", 19), ("ex", "(sp),ix", 23),
+
+    ("add", "r", 4), ("add", "n", 7), ("add", "(hl)", 7), ("add", "(ix+d)", 19),
+    ("add", "hl,ss", 11), ("add", "ix,pp", 15),
+    ("adc", "r", 4), ("adc", "n", 7), ("adc", "(hl)", 7), ("adc", "(ix+d)", 19),
+    ("adc", "hl,ss", 15),
+    ("sub", "r", 4), ("sub", "n", 7), ("sub", "(hl)", 7), ("sub", "(ix+d)", 19),
+    ("sbc", "r", 4), ("sbc", "n", 7), ("sbc", "(hl)", 7), ("sbc", "(ix+d)", 19),
+    ("sbc", "hl,ss", 15),
+    ("and", "r", 4), ("and", "n", 7), ("and", "(hl)", 7), ("and", "(ix+d)", 19),
+    ("xor", "r", 4), ("xor", "n", 7), ("xor", "(hl)", 7), ("xor", "(ix+d)", 19),
+    ("or", "r", 4), ("or", "n", 7), ("or", "(hl)", 7), ("or", "(ix+d)", 19),
+    ("cp", "r", 4), ("cp", "n", 7), ("cp", "(hl)", 7), ("cp", "(ix+d)", 19),
+
+    ("inc", "r", 4), ("inc", "(hl)", 11), ("inc", "(ix+d)", 23), ("inc", "ss", 6),
+    ("inc", "ix", 10),
+    ("dec", "r", 4), ("dec", "(hl)", 11), ("dec", "(ix+d)", 23), ("dec", "ss", 6),
+    ("dec", "ix", 10),
+
+    ("rlc", "r", 8), ("rlc", "(hl)", 15), ("rlc", "(ix+d)", 23),
+    ("rrc", "r", 8), ("rrc", "(hl)", 15), ("rrc", "(ix+d)", 23),
+    ("rl", "r", 8), ("rl", "(hl)", 15), ("rl", "(ix+d)", 23),
+    ("rr", "r", 8), ("rr", "(hl)", 15), ("rr", "(ix+d)", 23),
+    ("sla", "r", 8), ("sla", "(hl)", 15), ("sla", "(ix+d)", 23),
+    ("sra", "r", 8), ("sra", "(hl)", 15), ("sra", "(ix+d)", 23),
+    ("srl", "r", 8), ("srl", "(hl)", 15), ("srl", "(ix+d)", 23),
+
+    ("bit", "b,r", 8), ("bit", "b,(hl)", 12), ("bit", "b,(ix+d)", 20),
+    ("res", "b,r", 8), ("res", "b,(hl)", 15), ("res", "b,(ix+d)", 23),
+    ("set", "b,r", 8), ("set", "b,(hl)", 15), ("set", "b,(ix+d)", 23),
+
+    ("jp", "nn", 10), ("jp", "cc,nn", 10), ("jp", "(hl)", 4), ("jp", "(ix)", 8),
+    ("jr", "e", 12), ("jr", "cc,e taken", 12), ("jr", "cc,e not taken", 7),
+    ("djnz", "e taken", 13), ("djnz", "e not taken", 8),
+    ("call", "nn", 17), ("call", "cc,nn taken", 17), ("call", "cc,nn not taken", 10),
+    ("ret", "implied", 10), ("ret", "cc taken", 11), ("ret", "cc not taken", 5),
+    ("rst", "p", 11), ("im", "0/1/2", 8),
+    ("in", "a,(n)", 11), ("in", "r,(c)", 12), ("out", "(n),a", 11), ("out", "(c),r", 12),
+];
+
+/// The forms of an instruction with their T-states
+pub fn timings(text: &str) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+    TIMINGS
+        .iter()
+        .filter(move |(m, ..)| *m == text)
+        .map(|(_, form, cycles)| (*form, *cycles))
+}
+
+/// Names an operand can have in the timing forms, most specific first
+/// `R` is the refresh register, `r` any 8 bit register
+fn operand_forms(op: &OperandZ80) -> &'static [&'static str] {
+    match op {
+        Register(A) => &["a", "r", "r'"],
+        Register(C) => &["r", "r'", "cc"],
+        Register(B | D | E | H | L) => &["r", "r'"],
+        Register(I) => &["i"],
+        Register(R) => &["R"],
+        Register(AF) => &["af", "qq"],
+        Register(AFPrime) => &["af'"],
+        Register(HL) => &["hl", "ss", "dd", "qq"],
+        Register(DE) => &["de", "ss", "dd", "qq", "pp"],
+        Register(BC) => &["ss", "dd", "qq", "pp"],
+        Register(SP) => &["sp", "ss", "dd", "pp"],
+        Register(IX | IY) => &["ix", "pp"],
+        RegisterIndirect(HL) => &["(hl)"],
+        RegisterIndirect(BC) => &["(bc)"],
+        RegisterIndirect(DE) => &["(de)"],
+        RegisterIndirect(SP) => &["(sp)"],
+        RegisterIndirect(C) => &["(c)"],
+        RegisterIndirect(..) | Indexed(..) => &["(ix+d)", "(ix)"],
+        Memory => &["(nn)", "(n)"],
+        Expr(Some(_)) => &["cc"],
+        Expr(None) => &["n", "nn", "e", "b", "p", "0/1/2"],
+    }
+}
+
+/// T-states for an instruction with these operands
+/// Branches that vary give the taken time, repeating block instructions a repeat
+pub fn cycles(text: &str, ops: &[OperandZ80]) -> Option<usize> {
+    let find = |form: &str| {
+        timings(text)
+            .find(|(f, _)| f.strip_prefix(form).is_some_and(|r| r.is_empty() || r.starts_with(' ')))
+            .map(|(_, c)| c)
+    };
+
+    let find_one = |op: &OperandZ80| operand_forms(op).iter().find_map(|f| find(f));
+
+    match ops {
+        [] => timings(text).next().map(|(_, c)| c),
+        [a] => find_one(a),
+        [a, b] => operand_forms(a)
+            .iter()
+            .find_map(|fa| operand_forms(b).iter().find_map(|fb| find(&format!("{fa},{fb}"))))
+            // The a of 8 bit arithmetic is optional
+            .or_else(|| matches!(a, Register(A)).then(|| find_one(b)).flatten()),
+        _ => None,
+    }
+}
+
+/// Every mnemonic the encoder knows
+pub fn mnemonics() -> impl Iterator<Item = &'static str> {
+    IMPLIED.iter().map(|(m, _)| *m).chain(WITH_OPERANDS.iter().copied())
+}
+
+pub fn is_mnemonic(text: &str) -> bool {
+    WITH_OPERANDS.contains(&text) || IMPLIED.iter().any(|(m, _)| *m == text)
+}
+
+/// Instructions whose first operand can be a condition code
+pub fn takes_condition(text: &str) -> bool {
+    matches!(text, "jp" | "jr" | "call" | "ret")
+}
+
+fn reg8(r: RegEnum) -> Option<u8> {
+    match r {
+        B => Some(0),
+        C => Some(1),
+        D => Some(2),
+        E => Some(3),
+        H => Some(4),
+        L => Some(5),
+        A => Some(7),
+        _ => None,
+    }
+}
+
+/// Register pair code used by ld, inc, dec and 16 bit arithmetic
+fn reg16(r: RegEnum) -> Option<u8> {
+    match r {
+        BC => Some(0),
+        DE => Some(1),
+        HL => Some(2),
+        SP => Some(3),
+        _ => None,
+    }
+}
+
+/// Register pair code used by push and pop
+fn reg16_af(r: RegEnum) -> Option<u8> {
+    match r {
+        AF => Some(3),
+        _ => reg16(r).filter(|c| *c != 3),
+    }
+}
+
+fn index_prefix(r: RegEnum) -> Option<u8> {
+    match r {
+        IX => Some(0xdd),
+        IY => Some(0xfd),
+        _ => None,
+    }
+}
+
+fn get_condition(op: &OperandZ80) -> Option<Condition> {
+    match op {
+        Register(C) => Some(Condition::C),
+        Expr(cond) => *cond,
+        _ => None,
+    }
+}
+
+/// An 8 bit location, either r, (hl) or (ix+d)
+struct Loc {
+    prefix: Option<u8>,
+    code: u8,
+    disp: Option<usize>,
+}
+
+impl Loc {
+    /// Emit this location with the opcode, and any displacement
+    fn emit(&self, opcode: u8) -> Vec<OpByte> {
+        let mut ret: Vec<_> = self.prefix.map(Byte).into_iter().collect();
+        ret.push(Byte(opcode));
+        ret.extend(self.disp_byte());
+        ret
+    }
+
+    /// Emit this location for a cb prefixed opcode
+    /// indexed forms place the displacement before the opcode
+    fn emit_cb(&self, opcode: OpByte) -> Vec<OpByte> {
+        let mut ret: Vec<_> = self.prefix.map(Byte).into_iter().collect();
+        ret.push(Byte(0xcb));
+        ret.extend(self.disp_byte());
+        ret.push(opcode);
+        ret
+    }
+
+    fn disp_byte(&self) -> Option<OpByte> {
+        match (self.prefix, self.disp) {
+            (_, Some(i)) => Some(Disp(i)),
+            // (ix) with no displacement
+            (Some(_), None) => Some(Byte(0)),
+            _ => None,
+        }
+    }
+}
+
+/// Operand at index i as (hl), (ix) or (ix+d)
+fn mem8(ops: &[OperandZ80], i: usize) -> Option<Loc> {
+    match ops.get(i)? {
+        RegisterIndirect(HL) => Some(Loc {
+            prefix: None,
+            code: 6,
+            disp: None,
+        }),
+        RegisterIndirect(r) => index_prefix(*r).map(|prefix| Loc {
+            prefix: Some(prefix),
+            code: 6,
+            disp: None,
+        }),
+        Indexed(r) => index_prefix(*r).map(|prefix| Loc {
+            prefix: Some(prefix),
+            code: 6,
+            disp: Some(i),
+        }),
+        _ => None,
+    }
+}
+
+/// Operand at index i as r, (hl), (ix) or (ix+d)
+fn loc8(ops: &[OperandZ80], i: usize) -> Option<Loc> {
+    match ops.get(i)? {
+        Register(r) => reg8(*r).map(|code| Loc {
+            prefix: None,
+            code,
+            disp: None,
+        }),
+        _ => mem8(ops, i),
+    }
+}
+
+fn bytes(b: &[u8]) -> Vec<OpByte> {
+    b.iter().map(|b| Byte(*b)).collect()
+}
+
+fn alu_op(text: &str) -> Option<u8> {
+    ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"]
+        .iter()
+        .position(|m| *m == text)
+        .map(|p| p as u8)
+}
+
+fn rot_op(text: &str) -> Option<u8> {
+    match text {
+        "rlc" => Some(0),
+        "rrc" => Some(1),
+        "rl" => Some(2),
+        "rr" => Some(3),
+        "sla" => Some(4),
+        "sra" => Some(5),
+        "srl" => Some(7),
+        _ => None,
+    }
+}
+
+fn encode_ld(ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    let ret = match ops {
+        [Register(A), Register(I)] => bytes(&[0xed, 0x57]),
+        [Register(A), Register(R)] => bytes(&[0xed, 0x5f]),
+        [Register(I), Register(A)] => bytes(&[0xed, 0x47]),
+        [Register(R), Register(A)] => bytes(&[0xed, 0x4f]),
+
+        [Register(SP), Register(HL)] => bytes(&[0xf9]),
+        [Register(SP), Register(r)] => bytes(&[index_prefix(*r)?, 0xf9]),
+
+        [Register(d), Register(s)] => bytes(&[0x40 | reg8(*d)? << 3 | reg8(*s)?]),
+
+        [Register(A), RegisterIndirect(BC)] => bytes(&[0x0a]),
+        [Register(A), RegisterIndirect(DE)] => bytes(&[0x1a]),
+        [RegisterIndirect(BC), Register(A)] => bytes(&[0x02]),
+        [RegisterIndirect(DE), Register(A)] => bytes(&[0x12]),
+
+        [Register(d), RegisterIndirect(..) | Indexed(..)] => {
+            mem8(ops, 1)?.emit(0x46 | reg8(*d)? << 3)
+        }
+
+        [RegisterIndirect(..) | Indexed(..), Register(s)] => mem8(ops, 0)?.emit(0x70 | reg8(*s)?),
+
+        [RegisterIndirect(..) | Indexed(..), Expr(_)] => {
+            let mut ret = mem8(ops, 0)?.emit(0x36);
+            ret.push(Imm8(1));
+            ret
+        }
+
+        [Register(A), Memory] => vec![Byte(0x3a), Imm16(1)],
+        [Memory, Register(A)] => vec![Byte(0x32), Imm16(0)],
+
+        [Register(HL), Memory] => vec![Byte(0x2a), Imm16(1)],
+        [Memory, Register(HL)] => vec![Byte(0x22), Imm16(0)],
+
+        [Register(r), Memory] => match index_prefix(*r) {
+            Some(p) => vec![Byte(p), Byte(0x2a), Imm16(1)],
+            None => vec![Byte(0xed), Byte(0x4b | reg16(*r)? << 4), Imm16(1)],
+        },
+
+        [Memory, Register(r)] => match index_prefix(*r) {
+            Some(p) => vec![Byte(p), Byte(0x22), Imm16(0)],
+            None => vec![Byte(0xed), Byte(0x43 | reg16(*r)? << 4), Imm16(0)],
+        },
+
+        [Register(r), Expr(_)] => {
+            if let Some(code) = reg8(*r) {
+                vec![Byte(0x06 | code << 3), Imm8(1)]
+            } else if let Some(p) = index_prefix(*r) {
+                vec![Byte(p), Byte(0x21), Imm16(1)]
+            } else {
+                vec![Byte(0x01 | reg16(*r)? << 4), Imm16(1)]
+            }
+        }
+
+        _ => return None,
+    };
+
+    Some(ret)
+}
+
+fn encode_alu(op: u8, ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    // 16 bit arithmetic
+    match (op, ops) {
+        // add hl,ss
+        (0, [Register(HL), Register(r)]) => return Some(bytes(&[0x09 | reg16(*r)? << 4])),
+        // add ix,pp : pp is bc, de, the same index register or sp
+        (0, [Register(ix), Register(r)]) if index_prefix(*ix).is_some() => {
+            let pp = match r {
+                r if r == ix => 2,
+                HL => return None,
+                _ => reg16(*r)?,
+            };
+            return Some(bytes(&[index_prefix(*ix)?, 0x09 | pp << 4]));
+        }
+        // adc hl,ss
+        (1, [Register(HL), Register(r)]) => return Some(bytes(&[0xed, 0x4a | reg16(*r)? << 4])),
+        // sbc hl,ss
+        (3, [Register(HL), Register(r)]) => return Some(bytes(&[0xed, 0x42 | reg16(*r)? << 4])),
+        _ => (),
+    }
+
+    // 8 bit arithmetic, the leading a, is optional
+    let (ops, first) = match ops {
+        [Register(A), _] => (ops, 1),
+        [_] => (ops, 0),
+        _ => return None,
+    };
+
+    let ret = match &ops[first] {
+        Expr(_) => vec![Byte(0xc6 | op << 3), Imm8(first)],
+        _ => {
+            let loc = loc8(ops, first)?;
+            loc.emit(0x80 | op << 3 | loc.code)
+        }
+    };
+
+    Some(ret)
+}
+
+fn encode_inc_dec(is_dec: bool, ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    let d = is_dec as u8;
+
+    let ret = match ops {
+        [Register(r)] => {
+            if let Some(code) = reg8(*r) {
+                bytes(&[0x04 | code << 3 | d])
+            } else if let Some(p) = index_prefix(*r) {
+                bytes(&[p, 0x23 | d << 3])
+            } else {
+                bytes(&[0x03 | reg16(*r)? << 4 | d << 3])
+            }
+        }
+        [_] => mem8(ops, 0)?.emit(0x34 | d),
+        _ => return None,
+    };
+
+    Some(ret)
+}
+
+fn encode_bit(base: u8, ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    match ops {
+        [Expr(_), _] => {
+            let loc = loc8(ops, 1)?;
+            Some(loc.emit_cb(Bit(base | loc.code, 0)))
+        }
+        _ => None,
+    }
+}
+
+fn encode_jp(ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    let ret = match ops {
+        [RegisterIndirect(HL)] => bytes(&[0xe9]),
+        [RegisterIndirect(r)] => bytes(&[index_prefix(*r)?, 0xe9]),
+        [Expr(_)] => vec![Byte(0xc3), Imm16(0)],
+        [cc, Expr(_)] => vec![Byte(0xc2 | get_condition(cc)?.code() << 3), Imm16(1)],
+        _ => return None,
+    };
+    Some(ret)
+}
+
+fn encode_jr(ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    let ret = match ops {
+        [Expr(_)] => vec![Byte(0x18), Rel(0)],
+        [cc, Expr(_)] => {
+            let cc = get_condition(cc)?.code();
+            // Only nz, z, nc and c are allowed
+            if cc > 3 {
+                return None;
+            }
+            vec![Byte(0x20 | cc << 3), Rel(1)]
+        }
+        _ => return None,
+    };
+    Some(ret)
+}
+
+fn encode_in_out(is_out: bool, ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    let ret = match (is_out, ops) {
+        (false, [Register(A), Memory]) => vec![Byte(0xdb), Imm8(1)],
+        (false, [Register(r), RegisterIndirect(C)]) => bytes(&[0xed, 0x40 | reg8(*r)? << 3]),
+        (true, [Memory, Register(A)]) => vec![Byte(0xd3), Imm8(0)],
+        (true, [RegisterIndirect(C), Register(r)]) => bytes(&[0xed, 0x41 | reg8(*r)? << 3]),
+        _ => return None,
+    };
+    Some(ret)
+}
+
+/// Encode an instruction
+/// Operand fields of the returned bytes are indices into `ops`
+pub fn encode(text: &str, ops: &[OperandZ80]) -> Option<Vec<OpByte>> {
+    if ops.is_empty() {
+        if let Some((_, b)) = IMPLIED.iter().find(|(m, _)| *m == text) {
+            return Some(bytes(b));
+        }
+    }
+
+    if let Some(op) = alu_op(text) {
+        return encode_alu(op, ops);
+    }
+
+    if let Some(op) = rot_op(text) {
+        return match ops {
+            [_] => {
+                let loc = loc8(ops, 0)?;
+                Some(loc.emit_cb(Byte(op << 3 | loc.code)))
+            }
+            _ => None,
+        };
+    }
+
+    let ret = match (text, ops) {
+        ("ld", _) => return encode_ld(ops),
+
+        ("push", [Register(r)]) => match index_prefix(*r) {
+            Some(p) => bytes(&[p, 0xe5]),
+            None => bytes(&[0xc5 | reg16_af(*r)? << 4]),
+        },
+
+        ("pop", [Register(r)]) => match index_prefix(*r) {
+            Some(p) => bytes(&[p, 0xe1]),
+            None => bytes(&[0xc1 | reg16_af(*r)? << 4]),
+        },
+
+        ("ex", [Register(DE), Register(HL)]) => bytes(&[0xeb]),
+        ("ex", [Register(AF), Register(AFPrime)]) => bytes(&[0x08]),
+        ("ex", [RegisterIndirect(SP), Register(HL)]) => bytes(&[0xe3]),
+        ("ex", [RegisterIndirect(SP), Register(r)]) => bytes(&[index_prefix(*r)?, 0xe3]),
+
+        ("inc", _) => return encode_inc_dec(false, ops),
+        ("dec", _) => return encode_inc_dec(true, ops),
+
+        ("bit", _) => return encode_bit(0x40, ops),
+        ("res", _) => return encode_bit(0x80, ops),
+        ("set", _) => return encode_bit(0xc0, ops),
+
+        ("jp", _) => return encode_jp(ops),
+        ("jr", _) => return encode_jr(ops),
+        ("djnz", [Expr(_)]) => vec![Byte(0x10), Rel(0)],
+
+        ("call", [Expr(_)]) => vec![Byte(0xcd), Imm16(0)],
+        ("call", [cc, Expr(_)]) => vec![Byte(0xc4 | get_condition(cc)?.code() << 3), Imm16(1)],
+
+        ("ret", [cc]) => bytes(&[0xc0 | get_condition(cc)?.code() << 3]),
+
+        ("rst", [Expr(_)]) => vec![Rst(0)],
+        ("im", [Expr(_)]) => vec![Byte(0xed), Im(0)],
+
+        ("in", _) => return encode_in_out(false, ops),
+        ("out", _) => return encode_in_out(true, ops),
+
+        _ => return None,
+    };
+
+    Some(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn size(text: &str, ops: &[OperandZ80]) -> usize {
+        encode(text, ops).unwrap().iter().map(|b| b.size()).sum()
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("ld", &[Register(A), Register(B)]), Some(bytes(&[0x78])));
+        assert_eq!(
+            encode("ld", &[Indexed(IY), Expr(None)]),
+            Some(vec![Byte(0xfd), Byte(0x36), Disp(0), Imm8(1)])
+        );
+        assert_eq!(
+            encode("bit", &[Expr(None), Indexed(IX)]),
+            Some(vec![Byte(0xdd), Byte(0xcb), Disp(1), Bit(0x46, 0)])
+        );
+        assert_eq!(encode("add", &[Register(IX), Register(IX)]), Some(bytes(&[0xdd, 0x29])));
+        assert_eq!(encode("add", &[Register(IX), Register(HL)]), None);
+        assert_eq!(encode("jr", &[Expr(Some(Condition::PO)), Expr(None)]), None);
+        assert_eq!(encode("ret", &[Register(C)]), Some(bytes(&[0xd8])));
+        assert_eq!(encode("ex", &[Register(AF), Register(AFPrime)]), Some(bytes(&[0x08])));
+    }
+
+    #[test]
+    fn test_cycles() {
+        assert_eq!(cycles("nop", &[]), Some(4));
+        assert_eq!(cycles("ldir", &[]), Some(21));
+        assert_eq!(cycles("ld", &[Register(A), Register(B)]), Some(4));
+        assert_eq!(cycles("ld", &[Register(A), Register(R)]), Some(9));
+        assert_eq!(cycles("ld", &[Register(HL), Memory]), Some(16));
+        assert_eq!(cycles("ld", &[Register(DE), Memory]), Some(20));
+        assert_eq!(cycles("ld", &[Register(BC), Expr(None)]), Some(10));
+        assert_eq!(cycles("ld", &[Indexed(IX), Expr(None)]), Some(19));
+        assert_eq!(cycles("add", &[Register(A), RegisterIndirect(HL)]), Some(7));
+        assert_eq!(cycles("add", &[Register(IX), Register(DE)]), Some(15));
+        assert_eq!(cycles("push", &[Register(AF)]), Some(11));
+        assert_eq!(cycles("jp", &[RegisterIndirect(IX)]), Some(8));
+        assert_eq!(cycles("jr", &[Expr(Some(Condition::NZ)), Expr(None)]), Some(12));
+        assert_eq!(cycles("ret", &[Register(C)]), Some(11));
+        assert_eq!(cycles("bit", &[Expr(None), Indexed(IY)]), Some(20));
+        assert_eq!(cycles("in", &[Register(A), Memory]), Some(11));
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(size("ld", &[Register(HL), Expr(None)]), 3);
+        assert_eq!(size("ld", &[Register(DE), Memory]), 4);
+        assert_eq!(size("ld", &[Register(A), Indexed(IX)]), 3);
+        assert_eq!(size("srl", &[Indexed(IY)]), 4);
+        assert_eq!(size("sub", &[Expr(None)]), 2);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{
+    assembler::{Assembler, Sizer},
+    cpuz80::frontend::NodeKindZ80,
+    error::GResult,
+    semantic::AstNodeId,
+};
+
+impl Assembler {
+    /// Z80 instruction sizes are fixed at parse time
+    pub fn size_node_z80(
+        &mut self,
+        sizer: &mut Sizer,
+        id: AstNodeId,
+        node_kind: NodeKindZ80,
+        _current_scope_id: u64,
+    ) -> GResult<()> {
+        use NodeKindZ80::*;
+
+        match &node_kind {
+            OpCode(_, ins) => sizer.advance_pc(ins.size()),
+            Illegal => {
+                let node = sizer.get_node(id);
+                return Err(self.make_user_error("Illegal instruction", node, true).into());
+            }
+            Operand(_) => {
+                let node = sizer.get_node(id);
+                let msg = "Operand without an instruction";
+                return Err(self.make_user_error(msg, node, true).into());
+            }
+        }
+
+        Ok(())
+    }
+}
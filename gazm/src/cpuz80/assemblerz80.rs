@@ -0,0 +1,79 @@
+use crate::{
+    assembler::{Assembler, AssemblerCpuTrait, OpcodeModeInfo, Sizer},
+    error::GResult,
+    frontend::{CpuSpecific, Node, PResult, TSpan, TokenKind},
+    semantic::{AstNodeId, AstNodeRef},
+};
+
+use super::{
+    assembler::{mnemonics, timings},
+    frontend::{lex_identifier, parse_commands, parse_multi_opcode_vec},
+};
+
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct AsmZ80 {}
+
+impl AsmZ80 {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl AssemblerCpuTrait for AsmZ80 {
+    fn get_cpu_name(&self) -> &'static str {
+        "Z80"
+    }
+
+    fn size_node(
+        &self,
+        sizer: &mut Sizer,
+        asm: &mut Assembler,
+        id: AstNodeId,
+        node_kind: CpuSpecific,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        match node_kind {
+            CpuSpecific::CpuZ80(node_kind) => {
+                asm.size_node_z80(sizer, id, node_kind, current_scope_id)
+            }
+            _ => panic!(),
+        }
+    }
+
+    fn compile_node(
+        &self,
+        asm: &mut Assembler,
+        node: AstNodeRef,
+        node_kind: CpuSpecific,
+        current_scope_id: u64,
+    ) -> GResult<()> {
+        match node_kind {
+            CpuSpecific::CpuZ80(node_kind) => {
+                asm.compile_node_z80(node_kind, node, current_scope_id)
+            }
+            _ => panic!(),
+        }
+    }
+
+    fn parse_multi_opcode_vec(&self, input: TSpan) -> PResult<Vec<Node>> {
+        parse_multi_opcode_vec(input)
+    }
+
+    fn parse_commands(&self, input: TSpan) -> PResult<Node> {
+        parse_commands(input)
+    }
+
+    fn lex_identifier(&self, id: &str) -> TokenKind {
+        lex_identifier(id)
+    }
+
+    fn get_opcode_info(&self, text: &str) -> Vec<OpcodeModeInfo> {
+        timings(text)
+            .map(|(form, cycles)| OpcodeModeInfo::new(form, Some(cycles)))
+            .collect()
+    }
+
+    fn get_mnemonics(&self) -> Vec<String> {
+        mnemonics().map(String::from).collect()
+    }
+}
@@ -0,0 +1,8 @@
+use crate::frontend::{err_error, Node, PResult, TSpan};
+
+use unraveler::ParseErrorKind;
+
+/// The Z80 has no cpu specific commands
+pub fn parse_commands(input: TSpan) -> PResult<Node> {
+    err_error(input, ParseErrorKind::NoMatch)
+}
@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+// TODO Remove all of this, replace with help text
+#[derive(Debug, Error, Clone, PartialEq, Copy)]
+pub enum AssemblyErrorKindZ80 {
+    #[error("Invalid operands for this opcode")]
+    InvalidOperands,
+    #[error("Expected a condition code")]
+    ExpectedCondition,
+}
@@ -0,0 +1,15 @@
+use crate::cpukind::CpuKind;
+use crate::cpuz80::assembler::is_mnemonic;
+use crate::frontend::TokenKind;
+
+pub fn lex_identifier(text: &str) -> TokenKind {
+    use TokenKind::*;
+
+    let text = text.to_lowercase();
+
+    if is_mnemonic(&text) {
+        OpCode(CpuKind::CpuZ80)
+    } else {
+        Label
+    }
+}
@@ -0,0 +1,112 @@
+use crate::frontend::{AstNodeKind, CpuSpecific};
+
+use super::{Condition, RegEnum};
+
+/// Operands as parsed from source
+/// Operands with an expression have that expression as their child
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum OperandZ80 {
+    /// a, hl, af' etc
+    Register(RegEnum),
+    /// (hl), (bc), (de), (sp), (c), (ix), (iy)
+    RegisterIndirect(RegEnum),
+    /// (ix+d), (iy+d)
+    Indexed(RegEnum),
+    /// (nn)
+    Memory,
+    /// nn, may also be a condition code for jumps, calls and returns
+    Expr(Option<Condition>),
+}
+
+/// A byte of an assembled instruction
+/// Operand fields hold the index of the child expression that supplies them
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum OpByte {
+    Byte(u8),
+    Imm8(usize),
+    Imm16(usize),
+    /// Signed index register displacement
+    Disp(usize),
+    /// Signed offset relative to the end of the instruction
+    Rel(usize),
+    /// rst vector
+    Rst(usize),
+    /// im mode
+    Im(usize),
+    /// Opcode with a bit number encoded in bits 3-5
+    Bit(u8, usize),
+}
+
+impl OpByte {
+    pub fn size(&self) -> usize {
+        match self {
+            OpByte::Imm16(..) => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn arg(&self) -> Option<usize> {
+        use OpByte::*;
+        match self {
+            Byte(..) => None,
+            Imm8(i) | Imm16(i) | Disp(i) | Rel(i) | Rst(i) | Im(i) | Bit(_, i) => Some(*i),
+        }
+    }
+
+    pub fn map_arg<F: Fn(usize) -> usize>(self, f: F) -> Self {
+        use OpByte::*;
+        match self {
+            Byte(b) => Byte(b),
+            Imm8(i) => Imm8(f(i)),
+            Imm16(i) => Imm16(f(i)),
+            Disp(i) => Disp(f(i)),
+            Rel(i) => Rel(f(i)),
+            Rst(i) => Rst(f(i)),
+            Im(i) => Im(f(i)),
+            Bit(b, i) => Bit(b, f(i)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct InstructionZ80 {
+    pub bytes: Vec<OpByte>,
+    /// T-states, the longest if it varies
+    pub cycles: Option<usize>,
+}
+
+impl InstructionZ80 {
+    pub fn new(bytes: Vec<OpByte>, cycles: Option<usize>) -> Self {
+        Self { bytes, cycles }
+    }
+
+    pub fn size(&self) -> usize {
+        self.bytes.iter().map(|b| b.size()).sum()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum NodeKindZ80 {
+    #[default]
+    Illegal,
+    OpCode(Box<String>, Box<InstructionZ80>),
+    Operand(OperandZ80),
+}
+
+impl NodeKindZ80 {
+    pub fn opcode<T: Into<String>>(name: T, ins: InstructionZ80) -> Self {
+        NodeKindZ80::OpCode(Box::new(name.into()), Box::new(ins))
+    }
+}
+
+impl From<NodeKindZ80> for AstNodeKind {
+    fn from(value: NodeKindZ80) -> Self {
+        AstNodeKind::TargetSpecific(CpuSpecific::CpuZ80(value))
+    }
+}
+
+impl From<OperandZ80> for AstNodeKind {
+    fn from(value: OperandZ80) -> Self {
+        NodeKindZ80::Operand(value).into()
+    }
+}
@@ -0,0 +1,211 @@
+use std::str::FromStr;
+
+use crate::frontend::{
+    err_nomatch, fatal, from_item_kid_tspan, from_item_tspan, get_text, parse_expr, AstNodeKind,
+    CpuSpecific, FeResult, Node, PResult, TSpan, TokenKind,
+};
+
+use crate::cpukind::CpuKind::CpuZ80 as Cpu;
+
+use crate::cpuz80::{
+    assembler::{cycles, encode},
+    frontend::{
+        get_index_reg, get_register, AssemblyErrorKindZ80::InvalidOperands, Condition,
+        InstructionZ80, NodeKindZ80, OperandZ80,
+    },
+};
+
+use unraveler::{alt, match_span as ms, preceded, sep_list, sep_list0, tag, tuple, Collection};
+
+fn get_opcode(input: TSpan) -> PResult<(TSpan, String)> {
+    let (rest, (sp, matched)) = ms(TokenKind::OpCode(Cpu))(input)?;
+    let text = get_text(matched).to_lowercase();
+    Ok((rest, (sp, text)))
+}
+
+/// Is this the end of an operand?
+/// Either the end of the line, the next operand or the start of another opcode
+fn is_end_of_operand(input: TSpan) -> bool {
+    input
+        .first()
+        .map(|t| t.kind == TokenKind::Comma || t.kind == TokenKind::Colon)
+        .unwrap_or(true)
+}
+
+/// Parses a register
+/// ```    hl```
+fn parse_register(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, reg)) = ms(get_register)(input)?;
+
+    if is_end_of_operand(rest) {
+        Ok((rest, from_item_tspan(OperandZ80::Register(reg), sp)))
+    } else {
+        err_nomatch(input)
+    }
+}
+
+/// Parses a register used as a pointer
+/// ```    (hl)```
+fn parse_register_indirect(input: TSpan) -> PResult<Node> {
+    use TokenKind::{CloseBracket, OpenBracket};
+    let (rest, (sp, (_, reg, _))) = ms(tuple((OpenBracket, get_register, CloseBracket)))(input)?;
+    Ok((rest, from_item_tspan(OperandZ80::RegisterIndirect(reg), sp)))
+}
+
+/// Parses a negative displacement, the minus is part of the expression
+fn parse_negative_disp(input: TSpan) -> PResult<Node> {
+    match input.first() {
+        Some(t) if t.kind == TokenKind::Minus => parse_expr(input),
+        _ => err_nomatch(input),
+    }
+}
+
+/// Parses an index register with a displacement
+/// ```    (ix+d)```
+/// ```    (iy-d)```
+fn parse_indexed(input: TSpan) -> PResult<Node> {
+    use TokenKind::{CloseBracket, OpenBracket, Plus};
+    let (rest, (sp, (_, reg, disp, _))) = ms(tuple((
+        OpenBracket,
+        get_index_reg,
+        alt((preceded(Plus, parse_expr), parse_negative_disp)),
+        CloseBracket,
+    )))(input)?;
+
+    Ok((rest, from_item_kid_tspan(OperandZ80::Indexed(reg), disp, sp)))
+}
+
+/// Parses a memory reference
+/// ```    (addr)```
+/// Only matches if the brackets enclose the whole operand
+/// otherwise this is a bracketed expression
+fn parse_memory(input: TSpan) -> PResult<Node> {
+    use TokenKind::{CloseBracket, OpenBracket};
+    let (rest, (sp, (_, matched, _))) =
+        ms(tuple((OpenBracket, parse_expr, CloseBracket)))(input)?;
+
+    if is_end_of_operand(rest) {
+        Ok((rest, from_item_kid_tspan(OperandZ80::Memory, matched, sp)))
+    } else {
+        err_nomatch(input)
+    }
+}
+
+/// Parses an expression
+/// A lone label that names a condition code is marked as possibly being one
+fn parse_expr_operand(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, matched)) = ms(parse_expr)(input)?;
+
+    let cond = if sp.length() == 1 {
+        Condition::from_str(&get_text(sp)).ok()
+    } else {
+        None
+    };
+
+    Ok((rest, from_item_kid_tspan(OperandZ80::Expr(cond), matched, sp)))
+}
+
+fn parse_operand(input: TSpan) -> PResult<Node> {
+    alt((
+        parse_register,
+        parse_register_indirect,
+        parse_indexed,
+        parse_memory,
+        parse_expr_operand,
+    ))(input)
+}
+
+/// Build the opcode node from the parsed operands
+/// Only expressions the encoding uses become children of the opcode
+fn make_opcode(sp: TSpan, text: String, operands: Vec<Node>) -> FeResult<Node> {
+    use CpuSpecific::CpuZ80;
+    use NodeKindZ80::Operand;
+
+    let kinds: Vec<OperandZ80> = operands
+        .iter()
+        .map(|n| match n.item {
+            AstNodeKind::TargetSpecific(CpuZ80(Operand(op))) => op,
+            _ => panic!(),
+        })
+        .collect();
+
+    let bytes = encode(&text, &kinds).ok_or_else(|| fatal(sp, InvalidOperands))?;
+    let t_states = cycles(&text, &kinds);
+
+    let mut children = vec![];
+    let mut mapping = vec![None; operands.len()];
+
+    for (i, op) in operands.into_iter().enumerate() {
+        if bytes.iter().any(|b| b.arg() == Some(i)) {
+            mapping[i] = Some(children.len());
+            let child = op.children.first().expect("Operand has no expression");
+            children.push(child.clone());
+        }
+    }
+
+    let bytes = bytes
+        .into_iter()
+        .map(|b| b.map_arg(|i| mapping[i].unwrap()))
+        .collect();
+
+    let item = NodeKindZ80::opcode(text, InstructionZ80::new(bytes, t_states));
+    Ok(from_item_tspan(item, sp).with_children_vec(children))
+}
+
+fn parse_opcode(input: TSpan) -> PResult<Node> {
+    let (rest, (sp, text)) = get_opcode(input)?;
+    let (rest, operands) = sep_list0(parse_operand, TokenKind::Comma)(rest)?;
+    let node = make_opcode(sp, text, operands)?;
+    Ok((rest, node))
+}
+
+pub fn parse_multi_opcode_vec(input: TSpan) -> PResult<Vec<Node>> {
+    let (rest, matched) = sep_list(parse_opcode, tag(TokenKind::Colon))(input)?;
+    Ok((rest, matched))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembler::testing::*, cpukind::CpuKind};
+
+    #[test]
+    fn test_assemble_z80() {
+        let text = "
+            org $100
+            ld a,b
+            ld hl,$1234
+            ld (ix+5),a
+            ld a,(iy-2)
+            ex af,af'
+            ex AF,AF'
+            jp nz,target
+            jr target
+            push bc : pop de
+target
+            ret
+            ";
+
+        let asm = assemble_ok(text, cpu_opts(CpuKind::CpuZ80));
+
+        let desired = [
+            0x78, // ld a,b
+            0x21, 0x34, 0x12, // ld hl,$1234
+            0xdd, 0x77, 0x05, // ld (ix+5),a
+            0xfd, 0x7e, 0xfe, // ld a,(iy-2)
+            0x08, 0x08, // ex af,af'
+            0xc2, 0x13, 0x01, // jp nz,target
+            0x18, 0x02, // jr target
+            0xc5, 0xd1, // push bc : pop de
+            0xc9, // ret
+        ];
+
+        assert_eq!(symbol(&asm, "target"), Some(0x113));
+        assert_eq!(bytes(&asm, 0x100, desired.len()), desired);
+    }
+
+    #[test]
+    fn test_z80_operand_errors() {
+        assert!(!assemble_err("    ld af',a\n", cpu_opts(CpuKind::CpuZ80)).is_empty());
+        assert!(!assemble_err("    ex af,hl\n", cpu_opts(CpuKind::CpuZ80)).is_empty());
+    }
+}
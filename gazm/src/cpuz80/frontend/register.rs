@@ -0,0 +1,89 @@
+use std::str::FromStr;
+use strum_macros::EnumString;
+
+use crate::{
+    frontend::{err_error, error, get_label_string, PResult, TSpan, TokenKind},
+    help::ErrCode::ExpectedRegisterZ80,
+};
+
+use unraveler::{alt, map, match_span as ms, tag};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum RegEnum {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    I,
+    R,
+    AF,
+    /// The alternate AF register, written as af'
+    #[strum(disabled)]
+    AFPrime,
+    BC,
+    DE,
+    HL,
+    SP,
+    IX,
+    IY,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+    PO,
+    PE,
+    P,
+    M,
+}
+
+impl Condition {
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+}
+
+pub fn parse_this_reg_local(input: TSpan, r: RegEnum) -> PResult<RegEnum> {
+    let (rest, (sp, matched)) = ms(get_register)(input)?;
+
+    if matched != r {
+        err_error(sp, ExpectedRegisterZ80)
+    } else {
+        Ok((rest, matched))
+    }
+}
+
+pub fn get_this_reg(r: RegEnum) -> impl FnMut(TSpan) -> PResult<RegEnum> + Copy {
+    move |i| parse_this_reg_local(i, r)
+}
+
+/// Parse a single register
+pub fn get_register(input: TSpan) -> PResult<RegEnum> {
+    alt((map(tag(TokenKind::AltAf), |_| RegEnum::AFPrime), get_named_register))(input)
+}
+
+fn get_named_register(input: TSpan) -> PResult<RegEnum> {
+    let (rest, (sp, text)) = ms(get_label_string)(input)?;
+
+    RegEnum::from_str(text.as_str())
+        .map(|reg| (rest, reg))
+        .map_err(|_| error(sp, ExpectedRegisterZ80))
+}
+
+/// Parse an index register, IX or IY
+pub fn get_index_reg(input: TSpan) -> PResult<RegEnum> {
+    let (rest, (sp, reg)) = ms(get_register)(input)?;
+
+    match reg {
+        RegEnum::IX | RegEnum::IY => Ok((rest, reg)),
+        _ => err_error(sp, ExpectedRegisterZ80),
+    }
+}
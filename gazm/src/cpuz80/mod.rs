@@ -0,0 +1,5 @@
+mod assemblerz80;
+pub mod frontend;
+pub mod assembler;
+
+pub use assemblerz80::*;
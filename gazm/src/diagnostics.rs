@@ -0,0 +1,226 @@
+#![forbid(unused_imports)]
+//! Machine readable errors and warnings for `--message-format`
+//!
+//! `json` writes a JSON object per diagnostic, one per line.
+//! `sarif` writes a single SARIF 2.1.0 log for CI tools.
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    error::{
+        ErrorCollectorTrait, ErrorMessage, GResult, GazmErrorKind, UserError, UserErrorData,
+        UserWarning,
+    },
+    opts::MessageFormat,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Where in a source file a diagnostic is
+/// Lines and columns count from one, the span is in bytes from the start of the file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub span: std::ops::Range<usize>,
+    pub source_line: String,
+}
+
+impl Location {
+    /// Start and end columns in UTF-16 code units, SARIF's default column kind
+    /// The end is clipped to the end of the line
+    fn utf16_columns(&self) -> (usize, usize) {
+        let utf16 = |bytes: usize| {
+            let bytes = bytes.min(self.source_line.len());
+            let text = self.source_line.get(..bytes).unwrap_or_default();
+            text.encode_utf16().count() + 1
+        };
+
+        let start = self.column.saturating_sub(1);
+        (utf16(start), utf16(start + self.span.len()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The warning's name, errors don't have one
+    pub code: Option<String>,
+    pub message: String,
+    /// Longer markdown help text if there is any
+    pub help: Option<String>,
+    /// Errors that aren't from a line of source have no location
+    pub location: Option<Location>,
+}
+
+impl Diagnostic {
+    fn from_data(severity: Severity, code: Option<String>, data: &UserErrorData) -> Self {
+        let (message, help) = match &data.message {
+            ErrorMessage::Plain(text) => (text.clone(), None),
+            ErrorMessage::Markdown(short, full) => (short.clone(), Some(full.clone())),
+        };
+
+        let (line, column) = data.pos.line_col_from_one();
+
+        let location = Location {
+            file: data.file.clone(),
+            line,
+            column,
+            span: data.pos.range(),
+            source_line: data.line.clone(),
+        };
+
+        Self {
+            severity,
+            code,
+            message,
+            help,
+            location: Some(location),
+        }
+    }
+
+    pub fn from_warning(w: &UserWarning) -> Self {
+        Self::from_data(Severity::Warning, Some(w.kind.to_string()), &w.data)
+    }
+
+    /// An error can hold many user errors
+    pub fn from_error(e: &GazmErrorKind) -> Vec<Self> {
+        match e {
+            GazmErrorKind::UserError(ue) => {
+                vec![Self::from_data(Severity::Error, None, ue.as_ref())]
+            }
+            GazmErrorKind::UserErrors(ues) => {
+                let from_ue = |ue: &UserError| Self::from_data(Severity::Error, None, ue.as_ref());
+                ues.clone().to_vec().iter().map(from_ue).collect()
+            }
+            _ => vec![Self {
+                severity: Severity::Error,
+                code: None,
+                message: e.to_string(),
+                help: None,
+                location: None,
+            }],
+        }
+    }
+
+    fn to_sarif(&self) -> serde_json::Value {
+        let mut ret = json!({
+            "level": self.severity,
+            "message": { "text": self.message },
+        });
+
+        if let Some(code) = &self.code {
+            ret["ruleId"] = json!(code);
+        }
+
+        if let Some(loc) = &self.location {
+            let (start, end) = loc.utf16_columns();
+            ret["locations"] = json!([{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": loc.file.to_string_lossy() },
+                    "region": {
+                        "startLine": loc.line,
+                        "startColumn": start,
+                        "endColumn": end,
+                    }
+                }
+            }]);
+        }
+
+        ret
+    }
+}
+
+/// Errors in a build's result followed by its warnings
+pub fn collect(res: &GResult<()>, warnings: &[UserWarning]) -> Vec<Diagnostic> {
+    let errors = res.as_ref().err().map(Diagnostic::from_error).unwrap_or_default();
+    let warnings = warnings.iter().map(Diagnostic::from_warning);
+    errors.into_iter().chain(warnings).collect()
+}
+
+/// A SARIF 2.1.0 log with a single run
+pub fn to_sarif(diags: &[Diagnostic]) -> serde_json::Value {
+    let results: Vec<_> = diags.iter().map(Diagnostic::to_sarif).collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "gazm",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// Render diagnostics in a machine readable format
+/// Human output is printed by the errors themselves so gives nothing here
+pub fn render(format: MessageFormat, diags: &[Diagnostic]) -> String {
+    let to_json = |v: serde_json::Value| v.to_string() + "\n";
+
+    match format {
+        MessageFormat::Human => String::new(),
+        MessageFormat::Json => diags.iter().map(|d| to_json(json!(d))).collect(),
+        MessageFormat::Sarif => to_json(to_sarif(diags)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sarif() {
+        let err = GazmErrorKind::Misc("Can't write".to_string());
+        let diags = collect(&Err(err), &[]);
+        assert_eq!(diags.len(), 1);
+
+        let sarif = to_sarif(&diags);
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["level"], "error");
+        assert_eq!(result["message"]["text"], "Misc: Can't write");
+        assert!(result.get("locations").is_none());
+
+        let json = render(MessageFormat::Json, &diags);
+        assert_eq!(json.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_user_error_location() {
+        use crate::{assembler::testing::assemble_with, opts::Opts};
+
+        let text = "    org $1000\n    lda #'é' : lda <200,x\n";
+        let (_, res) = assemble_with(text, Opts::default());
+        let diags = collect(&res, &[]);
+        assert_eq!(diags.len(), 1);
+
+        let json: serde_json::Value = serde_json::from_str(&render(MessageFormat::Json, &diags))
+            .expect("Can't parse json");
+        let loc = &json["location"];
+        assert!(loc["file"].as_str().unwrap().ends_with("test.gazm"));
+        assert_eq!(loc["line"], 2);
+        assert_eq!(loc["column"], 17);
+        assert_eq!(loc["span"]["start"], 30);
+        assert_eq!(loc["span"]["end"], 40);
+        assert_eq!(loc["source_line"].as_str().unwrap().trim_end(), "    lda #'é' : lda <200,x");
+
+        // SARIF columns are in UTF-16 code units
+        let sarif = to_sarif(&diags);
+        let region = &sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 2);
+        assert_eq!(region["startColumn"], 16);
+        assert_eq!(region["endColumn"], 26);
+    }
+}
@@ -3,6 +3,7 @@
 use crate::{
     assembler::{self, AssemblerCpuTrait},
     frontend::{FrontEndError, FrontEndErrorKind},
+    sections,
     semantic::{AstNodeId, AstNodeRef},
     vars::VarsErrorKind,
 };
@@ -14,6 +15,7 @@ use grl_sources::{
     TextEditTrait,
 };
 
+use serde::Deserialize;
 use thiserror::Error;
 
 pub type GResult<T> = Result<T, GazmErrorKind>;
@@ -37,11 +39,15 @@ pub enum GazmErrorKind {
     #[error(transparent)]
     BinaryError(#[from] assembler::BinaryError),
     #[error(transparent)]
+    SectionError(#[from] sections::SectionError),
+    #[error(transparent)]
     EditError(#[from] EditErrorKind),
     #[error(transparent)]
     FileError(#[from] FileError),
     #[error("Not implemented {0}")]
     NotImplemented(String),
+    #[error("{0} needs formatting")]
+    NeedsFormatting(String),
     #[error("{0} : {1}")]
     WithContext(String, Box<GazmErrorKind>),
 }
@@ -138,14 +144,84 @@ pub struct UserError {
     pub data: Box<UserErrorData>,
 }
 
+/// Kinds of warning, named in `gazm.toml` to turn them on or off
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// A label set from the PC that nothing refers to
+    UnusedLabel,
+    /// A `<` forced direct page operand outside of the direct page
+    DpForced,
+    /// A value that lost bits to fit its destination
+    Truncated,
+    /// Memory that has already been written to
+    OverlappingWrite,
+    /// A reference to a label in another bank
+    CrossBank,
+}
+
+impl WarningKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UnusedLabel => "unused-label",
+            Self::DpForced => "dp-forced",
+            Self::Truncated => "truncated",
+            Self::OverlappingWrite => "overlapping-write",
+            Self::CrossBank => "cross-bank",
+        }
+    }
+
+    /// Unused labels are noisy in big projects so have to be asked for
+    pub fn default_enabled(&self) -> bool {
+        !matches!(self, Self::UnusedLabel)
+    }
+}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 #[derive(PartialEq, Clone, Error)]
 pub struct UserWarning {
+    pub kind: WarningKind,
     pub data: Box<UserErrorData>,
 }
 
+impl UserWarning {
+    pub fn from_text<S>(kind: WarningKind, msg: S, info: &SourceInfo) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            kind,
+            data: UserErrorData::from_text(msg, info, false).into(),
+        }
+    }
+
+    pub fn print_pretty(&self) {
+        let heading = format!("\nWarning[{}]", self.kind).bold().yellow();
+        self.data.print_pretty_heading(heading, false)
+    }
+
+    /// The same message as an error, for `--warnings-as-errors`
+    pub fn to_error(&self) -> UserError {
+        let mut data = self.data.as_ref().clone();
+        data.message = match data.message {
+            ErrorMessage::Plain(txt) => ErrorMessage::Plain(format!("{txt} [{}]", self.kind)),
+            ErrorMessage::Markdown(short, full) => {
+                ErrorMessage::Markdown(format!("{short} [{}]", self.kind), full)
+            }
+        };
+        data.into()
+    }
+}
+
 impl std::fmt::Display for UserWarning {
-    fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        panic!()
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let heading = format!("warning[{}]", self.kind).bold().yellow();
+        write!(f, "{}", self.data.pretty_heading(heading))
     }
 }
 impl std::fmt::Debug for UserWarning {
@@ -228,6 +304,10 @@ impl UserErrorData {
     }
 
     pub fn print_pretty(&self, _verbose_errors: bool) {
+        self.print_pretty_heading("\nError".bold().red(), _verbose_errors)
+    }
+
+    pub fn print_pretty_heading(&self, error: ColoredString, _verbose_errors: bool) {
         use termimad::*;
         let skin = MadSkin::default();
 
@@ -239,8 +319,6 @@ impl UserErrorData {
         let bar = format!("{spaces}|").info();
         let bar_line = format!("{line_num} |").info();
 
-        let error = "\nError".bold().red();
-
         match &self.message {
             ErrorMessage::Plain(txt) => {
                 println!("{error}: {}", txt.bold());
@@ -270,6 +348,10 @@ impl UserErrorData {
     }
 
     pub fn pretty(&self) -> GResult<String> {
+        Ok(self.pretty_heading("error".bold().red()))
+    }
+
+    pub fn pretty_heading(&self, error: ColoredString) -> String {
         use std::fmt::Write as FmtWrite;
 
         let mut s = String::new();
@@ -282,8 +364,6 @@ impl UserErrorData {
         let bar = format!("{spaces}|").info();
         let bar_line = format!("{line_num} |").info();
 
-        let error = "error".bold().red();
-
         match &self.message {
             ErrorMessage::Plain(txt) => {
                 writeln!(&mut s, "{error}: {}", txt.bold()).expect("lklkl");
@@ -304,7 +384,7 @@ impl UserErrorData {
         writeln!(s, "{bar}").expect("kj");
         writeln!(s, "{bar_line} {}", self.line).expect("kj");
         writeln!(s, "{bar}{}^", " ".repeat(col)).expect("kj");
-        Ok(s)
+        s
     }
 }
 
@@ -356,19 +436,17 @@ impl UserError {
 
 #[derive(Clone)]
 pub struct ErrorCollector {
-    _max_errors: usize,
+    max_errors: usize,
     pub errors: ThinVec<GazmErrorKind>,
-    _warnings: ThinVec<UserWarning>,
-    errors_remaining: usize,
+    pub warnings: ThinVec<UserWarning>,
 }
 
 impl Default for ErrorCollector {
     fn default() -> Self {
         Self {
-            _max_errors: 10,
+            max_errors: 10,
             errors: Default::default(),
-            _warnings: Default::default(),
-            errors_remaining: Default::default(),
+            warnings: Default::default(),
         }
     }
 }
@@ -390,10 +468,9 @@ impl std::fmt::Debug for ErrorCollector {
 impl std::error::Error for ErrorCollector {}
 
 impl ErrorCollector {
-    pub fn new(_max_errors: usize) -> Self {
+    pub fn new(max_errors: usize) -> Self {
         Self {
-            _max_errors,
-            errors_remaining: _max_errors,
+            max_errors,
             ..Default::default()
         }
     }
@@ -422,10 +499,25 @@ impl ErrorCollector {
         self.num_of_errors() != 0
     }
 
+    pub fn is_over_max_errors(&self) -> bool {
+        self.num_of_errors() >= self.max_errors
+    }
+
+    /// Everything collected so far as one error
+    /// User errors are gathered into `UserErrors`, anything else is returned as is
     pub fn raise_errors(&self) -> GResult<()> {
-        if self.has_errors() {
-            panic!()
-            // Err(GazmErrorKind::TooManyErrors(self.clone()))
+        let mut user_errors = NewErrorCollector::new(self.max_errors);
+
+        for e in &self.errors {
+            match e {
+                GazmErrorKind::UserError(ue) => user_errors.add(ue.clone()),
+                GazmErrorKind::UserErrors(ues) => user_errors.add_errors(ues.clone()),
+                _ => return Err(e.clone()),
+            }
+        }
+
+        if user_errors.has_errors() {
+            Err(GazmErrorKind::UserErrors(user_errors))
         } else {
             Ok(())
         }
@@ -444,17 +536,22 @@ impl ErrorCollector {
         self.add_error(err, failure)
     }
 
-    pub fn add_waring(&mut self, _warning: UserWarning) -> GResult<()> {
-        unimplemented!()
+    pub fn add_warning(&mut self, warning: UserWarning) {
+        self.warnings.push(warning)
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
     }
 
+    /// Add an error, returns all errors collected so far
+    /// if it was a failure or there are now too many
     pub fn add_error(&mut self, err: GazmErrorKind, failure: bool) -> GResult<()> {
-        self.errors.push(err.clone());
+        self.errors.push(err);
 
-        if self.errors_remaining == 0 || failure {
-            Err(err)
+        if failure || self.is_over_max_errors() {
+            self.raise_errors()
         } else {
-            self.errors_remaining -= 1;
             Ok(())
         }
     }
@@ -0,0 +1,386 @@
+#![forbid(unused_imports)]
+//! Source formatter used by `gazm fmt`
+//!
+//! Lines are re-emitted with labels, opcodes, operands and comments in aligned columns.
+//! Opcodes and directives are lower cased, operands, macro calls and comments are kept
+//! as written. Lines the lexer can't make sense of are left untouched.
+use std::{iter, path::Path, str::FromStr};
+
+use grl_sources::SourceFile;
+use logos::Logos;
+
+use crate::{
+    cpukind::CpuKind,
+    error::{GResult, GazmErrorKind},
+    frontend::{map_token, CommandKind, ParseText, Token, TokenKind},
+    opts::Opts,
+};
+
+/// Columns things are aligned to
+/// Anything too wide for its column is followed by a single space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FmtOpts {
+    pub opcode_col: usize,
+    pub operand_col: usize,
+    pub comment_col: usize,
+}
+
+impl Default for FmtOpts {
+    fn default() -> Self {
+        Self {
+            opcode_col: 12,
+            operand_col: 20,
+            comment_col: 44,
+        }
+    }
+}
+
+/// The parts of a line, in column order
+#[derive(Default, Debug)]
+struct Columns<'a> {
+    label: Option<String>,
+    opcode: Option<String>,
+    opcode_at_start: bool,
+    operands: Option<&'a str>,
+    comment: Option<&'a str>,
+    comment_at_start: bool,
+}
+
+impl<'a> Columns<'a> {
+    fn new(source: &'a str, line_start: usize, toks: &[&Token]) -> Self {
+        use TokenKind::*;
+
+        let range = |t: &Token| t.extra.as_range();
+        let text = |t: &Token| token_text(source, t);
+        let is_label = |k: TokenKind| matches!(k, Label | Identifier | LocalIdentifier);
+        let is_opcode = |k: TokenKind| matches!(k, OpCode(..) | Command(..));
+        let is_call = |toks: &[&Token]| {
+            matches!(toks, [name, open, ..] if is_label(name.kind) && open.kind == OpenBracket)
+        };
+
+        let mut ret = Self::default();
+        let mut toks = toks;
+
+        if let Some((last, rest)) = toks.split_last() {
+            if matches!(last.kind, Comment | DocComment) {
+                ret.comment = Some(text(last).trim_end());
+                ret.comment_at_start = range(last).start == line_start;
+                toks = rest;
+            }
+        }
+
+        // A label is in the first column or followed by an opcode
+        if let Some((first, rest)) = toks.split_first() {
+            let next = rest.first().map(|t| t.kind);
+            let at_start = range(first).start == line_start;
+            let followed = matches!(next, Some(k) if is_opcode(k) || k == Colon) || is_call(rest);
+
+            if is_label(first.kind) && (at_start || followed) && !is_call(toks) {
+                let mut label = text(first).to_owned();
+                toks = rest;
+
+                if let Some((colon, rest)) = toks.split_first() {
+                    if colon.kind == Colon {
+                        label.push(':');
+                        toks = rest;
+                    }
+                }
+
+                ret.label = Some(label);
+            }
+        }
+
+        // Directives, macro calls and block braces written in the first column stay there
+        if let Some((first, rest)) = toks.split_first() {
+            let opcode = if is_call(toks) {
+                let last = toks.last().unwrap();
+                source[range(first).start..range(last).end].to_owned()
+            } else if is_opcode(first.kind) {
+                text(first).to_lowercase()
+            } else {
+                text(first).to_owned()
+            };
+
+            ret.opcode_at_start = ret.label.is_none() && range(first).start == line_start;
+            toks = if is_call(toks) { &[] } else { rest };
+            ret.opcode = Some(opcode);
+        }
+
+        if let (Some(first), Some(last)) = (toks.first(), toks.last()) {
+            ret.operands = Some(&source[range(first).start..range(last).end]);
+        }
+
+        ret
+    }
+
+    fn render(&self, opts: &FmtOpts) -> String {
+        let mut ret = String::new();
+
+        if let Some(label) = &self.label {
+            ret.push_str(label);
+        }
+
+        if let Some(opcode) = &self.opcode {
+            let col = if self.opcode_at_start { 0 } else { opts.opcode_col };
+            pad_to(&mut ret, col);
+            ret.push_str(opcode);
+        }
+
+        if let Some(operands) = self.operands {
+            pad_to(&mut ret, opts.operand_col);
+            ret.push_str(operands);
+        }
+
+        if let Some(comment) = self.comment {
+            let col = match (ret.is_empty(), self.comment_at_start) {
+                (true, true) => 0,
+                (true, false) => opts.opcode_col,
+                _ => opts.comment_col,
+            };
+            pad_to(&mut ret, col);
+            ret.push_str(comment);
+        }
+
+        ret
+    }
+}
+
+fn token_text<'a>(source: &'a str, t: &Token) -> &'a str {
+    &source[t.extra.as_range()]
+}
+
+/// Pad with spaces up to this column, or a single space if already past it
+fn pad_to(text: &mut String, col: usize) {
+    let len = text.chars().count();
+
+    if len < col {
+        text.extend(iter::repeat(' ').take(col - len));
+    } else if len > 0 {
+        text.push(' ');
+    }
+}
+
+/// Every token in the file, identifiers are lexed for `cpu` until a `target` command
+/// switches to another
+fn to_tokens_following_target(source_file: &SourceFile, mut cpu: CpuKind) -> Vec<Token> {
+    let source = &source_file.get_text().source;
+    let mut ret = vec![];
+
+    for (tok_res, pos) in TokenKind::lexer(source).spanned() {
+        let (kind, pos) = match tok_res {
+            Ok(kind) => map_token(kind, pos, source_file, cpu),
+            Err(_) => (TokenKind::Error, pos),
+        };
+
+        if kind == TokenKind::Command(CommandKind::Target) {
+            let rest = source[pos.end..].lines().next().unwrap_or("");
+            let name = rest.split(';').next().unwrap_or("").trim();
+            cpu = CpuKind::from_str(name).unwrap_or(cpu);
+        }
+
+        ret.push(Token::new(kind, ParseText::new(source_file, pos)));
+    }
+
+    ret
+}
+
+/// Format a source file, identifiers are lexed for `cpu` until a `target` command
+pub fn format_source(source_file: &SourceFile, cpu: CpuKind, opts: &FmtOpts) -> String {
+    let source = &source_file.get_text().source;
+    let tokens = to_tokens_following_target(source_file, cpu);
+
+    let line_starts: Vec<usize> = iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+
+    let line_of = |offset: usize| line_starts.partition_point(|s| *s <= offset) - 1;
+    let line_text = |line: usize| {
+        let start = line_starts[line];
+        let end = line_starts.get(line + 1).map(|e| e - 1).unwrap_or(source.len());
+        source[start..end].trim_end()
+    };
+
+    let num_of_lines = source.lines().count();
+    let mut toks = tokens.iter().peekable();
+    let mut ret: Vec<String> = vec![];
+    let mut line = 0;
+
+    while line < num_of_lines {
+        let mut line_toks = vec![];
+
+        while let Some(t) = toks.next_if(|t| line_of(t.extra.as_range().start) == line) {
+            line_toks.push(t);
+        }
+
+        // Tokens like doc blocks can run over several lines
+        let last_line = line_toks
+            .iter()
+            .map(|t| {
+                let r = t.extra.as_range();
+                line_of(r.end.saturating_sub(1).max(r.start))
+            })
+            .max()
+            .unwrap_or(line);
+
+        let has_error = line_toks.iter().any(|t| t.kind == TokenKind::Error);
+
+        if last_line > line || has_error {
+            ret.extend((line..=last_line).map(|l| line_text(l).to_owned()));
+        } else {
+            let cols = Columns::new(source, line_starts[line], &line_toks);
+            ret.push(cols.render(opts));
+        }
+
+        line = last_line + 1;
+    }
+
+    while ret.last().map(|l| l.is_empty()).unwrap_or(false) {
+        ret.pop();
+    }
+
+    let mut text = ret.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Format the file in `opts.project_file` in place
+/// In check mode nothing is written
+/// Returns true if the file was already formatted
+pub fn fmt_file(opts: &Opts) -> GResult<bool> {
+    let path: &Path = &opts.project_file;
+
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| GazmErrorKind::Misc(format!("Can't read {}: {e}", path.display())))?;
+
+    let sf = SourceFile::new(path, &text, grl_sources::AsmSource::FileId(0));
+    let formatted = format_source(&sf, opts.cpu, &FmtOpts::default());
+    let unchanged = formatted == text;
+
+    if !unchanged && !opts.fmt_check {
+        std::fs::write(path, formatted)
+            .map_err(|e| GazmErrorKind::Misc(format!("Can't write {}: {e}", path.display())))?;
+    }
+
+    Ok(unchanged)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::create_source_file;
+
+    #[test]
+    fn test_format() {
+        let text = "START equ *\n  LDA #10 ; load\nloop: DECA\n\tbne loop\n; done\n\n";
+        let desired = "\
+START       equ     *
+            lda     #10                     ; load
+loop:       deca
+            bne     loop
+; done
+";
+        let opts = FmtOpts::default();
+        let formatted = format_source(&create_source_file(text), CpuKind::Cpu6809, &opts);
+        assert_eq!(formatted, desired);
+
+        let again = format_source(&create_source_file(&formatted), CpuKind::Cpu6809, &opts);
+        assert_eq!(again, formatted);
+    }
+
+    #[test]
+    fn test_format_idempotent() {
+        let text = "\
+;;; Docs
+  org $1000
+a_very_long_label_name lda #1
+loop: DECB ; count
+ ldx #table,y ; index
+ macro thing(a) {
+    fcb a
+ }
+\tfcc \"hello\"
+";
+        let opts = FmtOpts::default();
+        let once = format_source(&create_source_file(text), CpuKind::Cpu6809, &opts);
+        let twice = format_source(&create_source_file(&once), CpuKind::Cpu6809, &opts);
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn test_format_alignment() {
+        let text = "\
+a_very_long_label_name lda #1
+    ; indented comment
+; first column comment
+ ldx a_very_long_label_name+$1234,y ; comment after long operands
+ ; comment
+lbl: nop
+";
+        let desired = "\
+a_very_long_label_name lda #1
+            ; indented comment
+; first column comment
+            ldx     a_very_long_label_name+$1234,y ; comment after long operands
+            ; comment
+lbl:        nop
+";
+        let opts = FmtOpts::default();
+        let formatted = format_source(&create_source_file(text), CpuKind::Cpu6809, &opts);
+        assert_eq!(formatted, desired);
+    }
+
+    #[test]
+    fn test_fmt_check() {
+        use crate::assembler::testing::write_files;
+
+        let formatted = "            lda     #1                      ; load\n";
+        let unformatted = " LDA #1 ; load\n";
+
+        let mut opts = Opts {
+            fmt_check: true,
+            ..Opts::default()
+        };
+
+        // Check mode never writes the file
+        opts.project_file = write_files(&[("ok.gazm", formatted)]);
+        assert!(fmt_file(&opts).unwrap());
+
+        opts.project_file = write_files(&[("bad.gazm", unformatted)]);
+        assert!(!fmt_file(&opts).unwrap());
+        assert_eq!(std::fs::read_to_string(&opts.project_file).unwrap(), unformatted);
+
+        opts.fmt_check = false;
+        assert!(!fmt_file(&opts).unwrap());
+        assert_eq!(std::fs::read_to_string(&opts.project_file).unwrap(), formatted);
+        assert!(fmt_file(&opts).unwrap());
+    }
+
+    #[test]
+    fn test_format_target() {
+        // z80 opcodes are lower cased once the target is followed
+        let text = " LDA #1\n target z80\n LD A,B\n ex af,af'\n";
+        let desired = "\
+            lda     #1
+            target  z80
+            ld      A,B
+            ex      af,af'
+";
+        let opts = FmtOpts::default();
+        let formatted = format_source(&create_source_file(text), CpuKind::Cpu6809, &opts);
+        assert_eq!(formatted, desired);
+    }
+
+    #[test]
+    fn test_format_macro_call() {
+        let text = " clear(screen)\nlbl clear(screen)\n CLEAR (screen, 2)\nclear(screen)\n";
+        let desired = "\
+            clear(screen)
+lbl         clear(screen)
+            CLEAR (screen, 2)
+clear(screen)
+";
+        let opts = FmtOpts::default();
+        let formatted = format_source(&create_source_file(text), CpuKind::Cpu6809, &opts);
+        assert_eq!(formatted, desired);
+    }
+}
@@ -1,17 +1,19 @@
 #![deny(unused_imports)]
 
-use crate::cpukind::CpuKind;
+use crate::{cpu6502, cpu6800, cpu6809, cpukind::CpuKind, cpuz80, debug_mess};
 
 use super::{
-    from_item_kids_tspan, from_item_tspan, get_label_string, get_text, parse_expr,
-    AstNodeKind, CommandKind, FeResult, FrontEndError, Node, PResult, TSpan,
+    fatal, from_item_kids_tspan, from_item_tspan, get_cpu, get_label_string, get_text, parse_expr,
+    AstNodeKind, CommandKind, FeResult, FrontEndError, FrontEndErrorKind, Node, PResult, TSpan,
     TokenKind, TokenKind::Comma,
 };
 
 use core::panic;
 use std::{path::PathBuf, str::FromStr};
 
-use unraveler::{alt, cut, many0, match_span as ms, opt, pair, preceded, sep_pair, tuple, Parser};
+use unraveler::{
+    alt, cut, many0, map, match_span as ms, opt, pair, preceded, sep_pair, tuple, Parser,
+};
 
 fn get_quoted_string(input: TSpan) -> PResult<String> {
     let (rest, matched) = TokenKind::QuotedString.parse(input)?;
@@ -54,6 +56,11 @@ impl GazmParser {
         let (rest, (sp, name)) = ms(preceded(CommandKind::Scope, get_label_string))(input)?;
         Ok((rest, from_item_tspan(AstNodeKind::Scope(name), sp)))
     }
+    /// SECTION name
+    pub(crate) fn parse_section(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, name)) = ms(preceded(CommandKind::Section, get_label_string))(input)?;
+        Ok((rest, from_item_tspan(AstNodeKind::Section(name), sp)))
+    }
     pub(crate) fn parse_require(input: TSpan) -> PResult<Node> {
         command_with_file(input, CommandKind::Require)
             .map(|(rest, (sp, file))| (rest, from_item_tspan(AstNodeKind::Require(file), sp)))
@@ -65,9 +72,19 @@ impl GazmParser {
         })
     }
 
+    /// TARGET cpu
+    /// The cpu can be a label or a name starting with a digit
+    /// ```    target 65c02```
     pub(crate) fn parse_target(input: TSpan) -> PResult<Node> {
-        let (rest, (sp, cpu)) = ms(preceded(CommandKind::Target, get_label_string))(input)?;
-        let kind = CpuKind::from_str(&cpu).unwrap();
+        let cpu_name = alt((
+            map(get_label_string, |_| ()),
+            map(pair(Self::parse_number, opt(get_label_string)), |_| ()),
+        ));
+
+        let (rest, (sp, (name_sp, _))) = ms(preceded(CommandKind::Target, ms(cpu_name)))(input)?;
+        let name = get_text(name_sp);
+        let kind = CpuKind::from_str(&name)
+            .map_err(|_| fatal(name_sp, FrontEndErrorKind::UnknownCpu(name)))?;
         Ok((rest, from_item_tspan(AstNodeKind::Cpu(kind), sp)))
     }
 
@@ -195,34 +212,47 @@ impl GazmParser {
         Self::simple_command(CommandKind::Exec, AstNodeKind::Exec)(_input)
     }
 
-    pub fn parse_command(_input: TSpan) -> PResult<Node> {
-        todo!();
-        // let (rest, matched) = alt((
-        //     Self::parse_scope,
-        //     Self::parse_put,
-        //     Self::parse_writebin,
-        //     Self::parse_incbin,
-        //     Self::parse_incbin_ref,
-        //     // C::parse_commands,
-        //     Self::parse_various_fills,
-        //     Self::parse_fill,
-        //     Self::parse_fcb,
-        //     Self::parse_fdb,
-        //     Self::parse_fcc,
-        //     Self::parse_zmd,
-        //     Self::parse_rmb,
-        //     Self::parse_rmd,
-        //     Self::parse_org,
-        //     Self::parse_include,
-        //     Self::parse_exec,
-        //     Self::parse_require,
-        //     Self::parse_import,
-        //     Self::parse_grabmem,
-        // ))(input)?;
-
-        // debug_mess!("Parse command: {:?}", matched.item);
-
-        // Ok((rest, matched))
+    /// Commands only the cpu being parsed for has
+    /// ```    setdp $d0```
+    pub(crate) fn parse_cpu_commands(input: TSpan) -> PResult<Node> {
+        use CpuKind::*;
+
+        match get_cpu(input) {
+            Cpu6809 | Cpu6309 => cpu6809::frontend::parse_commands(input),
+            Cpu6800 => cpu6800::frontend::parse_commands(input),
+            Cpu6502 | Cpu65c02 => cpu6502::frontend::parse_commands(input),
+            CpuZ80 => cpuz80::frontend::parse_commands(input),
+        }
+    }
+
+    pub fn parse_command(input: TSpan) -> PResult<Node> {
+        let (rest, matched) = alt((
+            alt((Self::parse_scope, Self::parse_section)),
+            Self::parse_put,
+            Self::parse_writebin,
+            Self::parse_incbin,
+            Self::parse_incbin_ref,
+            Self::parse_cpu_commands,
+            Self::parse_various_fills,
+            Self::parse_fill,
+            Self::parse_fcb,
+            Self::parse_fdb,
+            Self::parse_fcc,
+            Self::parse_zmd,
+            Self::parse_rmb,
+            Self::parse_rmd,
+            Self::parse_org,
+            Self::parse_include,
+            Self::parse_exec,
+            Self::parse_require,
+            Self::parse_import,
+            Self::parse_grabmem,
+            Self::parse_target,
+        ))(input)?;
+
+        debug_mess!("Parse command: {:?}", matched.item);
+
+        Ok((rest, matched))
     }
 }
 
@@ -0,0 +1,239 @@
+#![deny(unused_imports)]
+//! `if` / `elif` / `else` / `endif` and `ifdef` / `ifndef` blocks
+//! All branches are parsed, the semantic pass chooses which one is kept
+use unraveler::{alt, map, match_span as ms, preceded, Collection, Parser};
+
+use super::{
+    fatal, from_item_kids_tspan, from_item_tspan, get_label_string, parse_expr, parse_line,
+    take_line, AstNodeKind, CommandKind, FrontEndErrorKind, GazmParser, Node, PResult, TSpan,
+    TokenKind,
+};
+
+type LineParser = fn(TSpan) -> PResult<Node>;
+
+impl GazmParser {
+    /// IF expr
+    fn parse_if_expr(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, expr)) = ms(preceded(CommandKind::If, parse_expr))(input)?;
+        Ok((rest, from_item_kids_tspan(AstNodeKind::IfBranch, &[expr], sp)))
+    }
+
+    /// IFDEF label | IFNDEF label
+    fn parse_ifdef(input: TSpan) -> PResult<Node> {
+        let ifdef = map(preceded(CommandKind::IfDef, get_label_string), |n| (n, true));
+        let ifndef = map(preceded(CommandKind::IfNDef, get_label_string), |n| (n, false));
+
+        let (rest, (sp, (name, defined))) = ms(alt((ifdef, ifndef)))(input)?;
+        Ok((rest, from_item_tspan(AstNodeKind::IfDefBranch(name, defined), sp)))
+    }
+
+    fn parse_if_line(input: TSpan) -> PResult<Node> {
+        alt((Self::parse_if_expr, Self::parse_ifdef))(input)
+    }
+
+    /// ELIF expr
+    fn parse_elif(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, expr)) = ms(preceded(CommandKind::Elif, parse_expr))(input)?;
+        Ok((rest, from_item_kids_tspan(AstNodeKind::IfBranch, &[expr], sp)))
+    }
+
+    fn parse_else(input: TSpan) -> PResult<Node> {
+        let (rest, sp) = CommandKind::Else.parse(input)?;
+        Ok((rest, from_item_tspan(AstNodeKind::ElseBranch, sp)))
+    }
+
+    /// Source up to the next ELIF, ELSE or ENDIF
+    /// Stops at a close brace too so an IF in a macro without an ENDIF is reported
+    /// A `target` in the body only lasts until its end
+    fn parse_cond_body(input: TSpan) -> PResult<Vec<Node>> {
+        use CommandKind::*;
+
+        Self::parse_chunks_until(input, |kind| {
+            matches!(kind, TokenKind::Command(Elif | Else | Endif) | TokenKind::CloseBrace)
+        })
+    }
+
+    /// A branch's line followed by its body
+    fn parse_branch(input: TSpan, line: LineParser) -> PResult<Node> {
+        let (rest, mut branch) = parse_line(line)(input)?;
+        let (rest, body) = Self::parse_cond_body(rest)?;
+        branch.children.extend(body);
+        Ok((rest, branch))
+    }
+
+    fn parse_branches(input: TSpan) -> PResult<Vec<Node>> {
+        use CommandKind::*;
+
+        let (mut rest, first) = Self::parse_branch(input, Self::parse_if_line)?;
+        let mut branches = vec![first];
+
+        loop {
+            let line: LineParser = match rest.first().map(|t| t.kind) {
+                Some(TokenKind::Command(Elif)) => Self::parse_elif,
+                Some(TokenKind::Command(Else)) => Self::parse_else,
+                _ => break,
+            };
+
+            let (next, branch) = Self::parse_branch(rest, line)?;
+            let is_else = branch.item == AstNodeKind::ElseBranch;
+
+            branches.push(branch);
+            rest = next;
+
+            if is_else {
+                break;
+            }
+        }
+
+        let (rest, _) = parse_line(|i| Endif.parse(i))(rest)
+            .map_err(|_| fatal(take_line(input), FrontEndErrorKind::NoEndIf))?;
+
+        Ok((rest, branches))
+    }
+
+    /// A conditional block, up to and including its ENDIF
+    /// ```text
+    ///     if REV2
+    ///     lda #1
+    ///     elif REV3
+    ///     lda #2
+    ///     else
+    ///     lda #3
+    ///     endif
+    /// ```
+    pub fn parse_conditional(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, branches)) = ms(Self::parse_branches)(input)?;
+        Ok((rest, from_item_kids_tspan(AstNodeKind::Conditional, &branches, sp)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{frontend::*, opts::Opts};
+
+    #[test]
+    fn test_conditional() {
+        use AstNodeKind::*;
+
+        let text = r#"
+        ifdef REV2
+        fcb 1
+        if REV3
+        fcb 2
+        endif
+        elif 1
+        fcb 3
+        else
+        fcb 4
+        fcb 5
+        endif"#;
+
+        let opts = Opts::default();
+        let sf = create_source_file(text);
+        let tokens = to_tokens_no_comment(&sf, opts.cpu);
+        let span = make_tspan(&tokens, &sf, &opts);
+
+        let (rest, matched) = GazmParser::parse_conditional(span).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(matched.item, Conditional);
+
+        let branches: Vec<_> = matched.children.iter().map(|c| &c.item).collect();
+        let desired = [&IfDefBranch("REV2".into(), true), &IfBranch, &ElseBranch];
+        assert_eq!(branches, desired);
+
+        let lens: Vec<_> = matched.children.iter().map(|c| c.children.len()).collect();
+        assert_eq!(lens, [2, 2, 2]);
+        assert_eq!(matched.children[0].children[1].item, Conditional);
+
+        let sf = create_source_file("if 1\n fcb 1\n");
+        let tokens = to_tokens_no_comment(&sf, opts.cpu);
+        let span = make_tspan(&tokens, &sf, &opts);
+        let err = GazmParser::parse_conditional(span).unwrap_err();
+        assert_eq!(err.kind, FrontEndErrorKind::NoEndIf);
+    }
+
+    #[test]
+    fn test_branch_selection() {
+        use crate::assembler::testing::{assemble_ok, bytes};
+
+        let text = "
+REV equ 2
+    org $1000
+    if REV-2
+    fcb 1
+    elif REV-1
+    fcb 2
+    if 0
+    fcb 9
+    else
+    fcb 3
+    endif
+    else
+    fcb 4
+    endif
+    if 0
+    fcb 5
+    elif 0
+    fcb 6
+    endif
+    fcb 7
+";
+        let asm = assemble_ok(text, Opts::default());
+        assert_eq!(bytes(&asm, 0x1000, 3), [2, 3, 7]);
+    }
+
+    #[test]
+    fn test_ifdef_defines() {
+        use crate::assembler::testing::{assemble_ok, bytes, symbol};
+
+        // Only --set definitions are visible, other vars can be used as labels
+        let opts = Opts {
+            vars: vec![("MEM_SIZE", "65536"), ("REV2", "1")].into(),
+            defines: vec![("REV2", "1"), ("MODEL", "b")].into(),
+            ..Opts::default()
+        };
+
+        let text = "
+    org $1000
+    ifdef REV2
+    fcb REV2
+    endif
+    ifndef REV3
+    fcb 2
+    endif
+    ifdef MODEL
+    fcb 3
+    endif
+    ifdef MEM_SIZE
+    fcb 9
+    endif
+MEM_SIZE fcb 4
+";
+        let asm = assemble_ok(text, opts);
+        assert_eq!(bytes(&asm, 0x1000, 4), [1, 2, 3, 4]);
+        assert_eq!(symbol(&asm, "MEM_SIZE"), Some(0x1003));
+    }
+
+    #[test]
+    fn test_conditional_in_macro() {
+        use crate::assembler::testing::{assemble_ok, bytes};
+
+        let text = "
+macro emit(n) {
+    if n
+    fcb n
+    else
+    fcb $ff
+    endif
+}
+    org $1000
+    emit(2)
+    emit(0)
+    emit(3)
+";
+        let asm = assemble_ok(text, Opts::default());
+        assert_eq!(bytes(&asm, 0x1000, 3), [2, 0xff, 3]);
+    }
+}
+
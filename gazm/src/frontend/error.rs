@@ -22,6 +22,10 @@ pub enum FrontEndErrorKind {
     #[error(transparent)]
     AsmErrorKind6800(#[from] crate::cpu6800::frontend::AssemblyErrorKind6800),
     #[error(transparent)]
+    AsmErrorKind6502(#[from] crate::cpu6502::frontend::AssemblyErrorKind6502),
+    #[error(transparent)]
+    AsmErrorKindZ80(#[from] crate::cpuz80::frontend::AssemblyErrorKindZ80),
+    #[error(transparent)]
     SourceError(#[from] SourceErrorType),
     #[error(transparent)]
     FileError(#[from] FileError),
@@ -46,6 +50,11 @@ pub enum FrontEndErrorKind {
     NoCloseSqBracket,
     #[error("Expected close brace '}}'")]
     NoCloseBrace,
+    #[error("No endif for this if")]
+    NoEndIf,
+
+    #[error("Unknown target cpu {0}")]
+    UnknownCpu(String),
 }
 
 pub type FeResult<T> = Result<T, FrontEndError>;
@@ -1,11 +1,11 @@
 #![deny(unused_imports)]
 
 
-use unraveler::{alt, many0, match_span as ms, pair, sep_list, sep_list0};
+use unraveler::{alt, many0, match_span as ms, pair, preceded, sep_list, sep_list0, Parser};
 
 use super::{
-    concat, from_item_kids_tspan, from_item_tspan, parse_bracketed, GazmParser, AstNodeKind, Node,
-    PResult, TSpan,
+    concat, err_nomatch, from_item_kids_tspan, from_item_tspan, get_str, parse_bracketed,
+    GazmParser, AstNodeKind, Node, PResult, TSpan,
     TokenKind::{self, *},
 };
 
@@ -44,11 +44,26 @@ impl GazmParser
         Ok((rest, Self::with_tspan(matched, sp)))
     }
 
+    /// BANK(label), the bank a label is in
+    fn parse_bank(input: TSpan) -> PResult<Node> {
+        let (_, name) = Identifier.parse(input)?;
+
+        if !get_str(&name).eq_ignore_ascii_case("bank") {
+            return err_nomatch(input);
+        }
+
+        let (rest, (sp, label)) =
+            ms(preceded(Identifier, parse_bracketed(Self::parse_label)))(input)?;
+
+        Ok((rest, from_item_kids_tspan(AstNodeKind::Bank, &[label], sp)))
+    }
+
     pub fn parse_non_unary_term(input: TSpan) -> PResult<Node> {
         let parse_pc = |i| Self::op_to_node(i, Star, AstNodeKind::Pc);
         alt((
             Self::parse_bracketed_expr,
             Self::parse_number,
+            Self::parse_bank,
             Self::parse_label,
             parse_pc,
         ))(input)
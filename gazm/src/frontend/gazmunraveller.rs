@@ -1,7 +1,8 @@
 #![deny(unused_imports)]
-use super::{to_pos, Token, TokenKind};
+use super::{to_pos, CpuTokens, Token, TokenKind};
 use crate::{ opts::Opts,cpukind::CpuKind  };
 use grl_sources::{Position, SourceFile};
+use std::collections::HashMap;
 use unraveler::Collection;
 
 #[derive(Default, Clone, Copy, PartialEq)]
@@ -12,6 +13,10 @@ pub struct ParseContext<'a> {
     pub source_file: &'a SourceFile,
     pub is_parsing_macro_def: bool,
     pub cpu_kind: Option<CpuKind>,
+    /// The source's tokens for each cpu, `None` if it was only lexed for one
+    pub cpu_tokens: Option<&'a CpuTokens<'a>>,
+    /// Cpus sections are configured with, by section name
+    pub section_cpus: Option<&'a HashMap<String, CpuKind>>,
     pub opts: &'a Opts,
 }
 
@@ -74,11 +79,55 @@ pub fn make_tspan<'a>(
             is_parsing_macro_def: false,
             opts,
             cpu_kind: None,
+            cpu_tokens: None,
+            section_cpus: None,
         },
     );
     span
 }
 
+/// A span over the tokens lexed for `cpu` that can switch to another cpu's tokens
+pub fn make_cpu_tspan<'a>(
+    cpu_tokens: &'a CpuTokens<'a>,
+    cpu: CpuKind,
+    section_cpus: &'a HashMap<String, CpuKind>,
+    sf: &'a grl_sources::SourceFile,
+    opts: &'a Opts,
+) -> TSpan<'a> {
+    TSpan::from_slice(
+        cpu_tokens.get(cpu),
+        ParseContext {
+            source_file: sf,
+            is_parsing_macro_def: false,
+            opts,
+            cpu_kind: Some(cpu),
+            cpu_tokens: Some(cpu_tokens),
+            section_cpus: Some(section_cpus),
+        },
+    )
+}
+
+/// The cpu this span is being parsed for
+pub fn get_cpu(input: TSpan) -> CpuKind {
+    let ctx = input.extra();
+    ctx.cpu_kind.unwrap_or(ctx.opts.cpu)
+}
+
+/// Carry on parsing from the same place with the tokens lexed for `cpu`
+pub fn with_cpu(input: TSpan, cpu: CpuKind) -> TSpan {
+    let ctx = ParseContext {
+        cpu_kind: Some(cpu),
+        ..*input.extra()
+    };
+
+    match ctx.cpu_tokens {
+        Some(tokens) => TSpan::from_slice(tokens.get(cpu), ctx)
+            .drop(input.offset())
+            .expect("Cpu token lists differ in length"),
+        None => input.lift_extra(|_| ctx),
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 impl unraveler::Item for Token<'_> {
     type Kind = TokenKind;
@@ -1,8 +1,10 @@
 use unraveler::tag;
 
 use crate::{
+    cpu6502::frontend::{lex_identifier as lex6502, lex_identifier_65c02 as lex65c02},
     cpu6800::frontend::lex_identifier as lex6800,
-    cpu6809::frontend::lex_identifier as lex6809,
+    cpu6809::frontend::{lex_identifier as lex6809, lex_identifier_6309 as lex6309},
+    cpuz80::frontend::lex_identifier as lexz80,
     cpukind::CpuKind,
     frontend::{err_nomatch, get_str, get_text},
 };
@@ -20,7 +22,10 @@ pub fn lex_identifier(c: CpuKind, text: &str) -> TokenKind {
     match c {
         Cpu6809 => lex6809(text),
         Cpu6800 => lex6800(text),
-        _ => panic!(),
+        Cpu6502 => lex6502(text),
+        Cpu65c02 => lex65c02(text),
+        CpuZ80 => lexz80(text),
+        Cpu6309 => lex6309(text),
     }
 }
 
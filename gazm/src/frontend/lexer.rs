@@ -2,11 +2,11 @@
 
 use super::{basetoken::Token as BaseToken, ParseText};
 use logos::{Lexer, Logos};
-use std::collections::HashMap;
+use std::{cell::OnceCell, collections::HashMap};
 use strum_macros::EnumIter;
-use strum::IntoEnumIterator;
+use strum::{EnumCount, IntoEnumIterator};
 
-use crate::cpukind::CpuKind;
+use crate::{cpukind::CpuKind, frontend::lex_identifier};
 
 pub type Token<'a> = BaseToken<ParseText<'a>>;
 
@@ -27,6 +27,7 @@ pub enum CommandKind {
     IncBinRef,
     WriteBin,
     SetDp,
+    Relax,
     Bsz,
     Fill,
     Fdb,
@@ -46,6 +47,16 @@ pub enum CommandKind {
     Macro,
     Equ,
     Target,
+    Section,
+    If,
+    Elif,
+    Else,
+    Endif,
+    IfDef,
+    IfNDef,
+    Rept,
+    Irp,
+    While,
 }
 
 lazy_static::lazy_static! {
@@ -121,6 +132,10 @@ pub enum TokenKind {
     #[regex("(?&id)")]
     Identifier,
 
+    /// The Z80's alternate AF register
+    #[token("af'", ignore(ascii_case))]
+    AltAf,
+
     #[regex(r"[0-9][0-9_]*", from_dec)]
     #[regex(r"(?&pre_hex)[0-9a-fA-F][0-9a-fA-F_]*", from_hex)]
     #[regex(r"(?&pre_bin)[0-1][0-1_]*", from_bin)]
@@ -210,17 +225,20 @@ pub fn map_token(
     kind: TokenKind,
     pos: std::ops::Range<usize>,
     source_file: &grl_sources::SourceFile,
+    cpu: CpuKind,
 ) -> (TokenKind, std::ops::Range<usize>)
 {
     let kind = match kind {
+        // Only the z80 has an `af'` register, a quote can't follow a label for other cpus
+        TokenKind::AltAf if cpu != CpuKind::CpuZ80 => TokenKind::Error,
+
         TokenKind::Identifier => {
             let text = &source_file.get_text().source[pos.clone()].to_lowercase();
 
             if let Some(c) = COMS.get(text) {
                 TokenKind::Command(*c)
             } else {
-                todo!()
-                // C::lex_identifier(text.as_str())
+                lex_identifier(cpu, text)
             }
         }
 
@@ -229,31 +247,84 @@ pub fn map_token(
     (kind, pos)
 }
 
-pub fn to_tokens_no_comment(source_file: &grl_sources::SourceFile) -> Vec<Token>
+pub fn to_tokens_no_comment(source_file: &grl_sources::SourceFile, cpu: CpuKind) -> Vec<Token>
 {
     use TokenKind::*;
     let not_comment = |k: &TokenKind| k != &DocComment && k != &Comment;
-    let tokens = to_tokens_filter(source_file, not_comment);
+    let tokens = to_tokens_filter(source_file, cpu, not_comment);
     tokens
 }
 
+/// Lex the source file, identifiers are lexed for `cpu`
 fn to_tokens_kinds(
     source_file: &grl_sources::SourceFile,
+    cpu: CpuKind,
 ) -> Vec<(TokenKind, std::ops::Range<usize>)>
 {
     TokenKind::lexer(&source_file.get_text().source)
         .spanned()
         .map(|(tok_res, pos)| match tok_res {
-            Ok(kind) => map_token(kind, pos, source_file),
+            Ok(kind) => map_token(kind, pos, source_file, cpu),
             Err(_) => (TokenKind::Error, pos),
         })
         .collect()
 }
 
+/// A source file lexed once, its tokens are mapped for a cpu the first time
+/// the parser switches to it
+/// Comments are dropped so each cpu has the same number of tokens
+/// and the parser can switch between them when it hits a `target`
+#[derive(Debug)]
+pub struct CpuTokens<'a> {
+    source_file: &'a grl_sources::SourceFile,
+    kinds: Vec<(TokenKind, std::ops::Range<usize>)>,
+    tokens: [OnceCell<Vec<Token<'a>>>; CpuKind::COUNT],
+}
+
+impl<'a> CpuTokens<'a> {
+    pub fn new(source_file: &'a grl_sources::SourceFile) -> Self {
+        use TokenKind::*;
+
+        let kinds = TokenKind::lexer(&source_file.get_text().source)
+            .spanned()
+            .filter_map(|(tok_res, pos)| match tok_res {
+                Ok(DocComment | Comment) => None,
+                Ok(kind) => Some((kind, pos)),
+                Err(_) => Some((Error, pos)),
+            })
+            .collect();
+
+        Self {
+            source_file,
+            kinds,
+            tokens: Default::default(),
+        }
+    }
+
+    /// The tokens lexed for `cpu`
+    pub fn get(&self, cpu: CpuKind) -> &[Token<'a>] {
+        self.tokens[cpu as usize].get_or_init(|| {
+            self.kinds
+                .iter()
+                .map(|(kind, pos)| {
+                    let (kind, pos) = map_token(*kind, pos.clone(), self.source_file, cpu);
+                    Token::new(kind, ParseText::new(self.source_file, pos))
+                })
+                .collect()
+        })
+    }
+
+    /// Has the source been mapped for `cpu` yet?
+    pub fn is_mapped(&self, cpu: CpuKind) -> bool {
+        self.tokens[cpu as usize].get().is_some()
+    }
+}
+
 /// Converts a source file into a vector of tokens.
 ///
 /// # Arguments
 /// * `source_file`: The source file to convert.
+/// * `cpu`: The cpu identifiers are lexed for.
 /// * `predicate`: A predicate that determines which token kinds to include in the output.
 ///
 /// # Returns
@@ -265,14 +336,18 @@ fn to_tokens_kinds(
 /// use grl_tokens::{TokenKind, Token};
 ///
 /// let source_file = SourceFile::from_str("Hello, world!");
-/// let tokens = to_tokens_filter(&source_file, |tk| *tk == TokenKind::Identifier);
+/// let tokens = to_tokens_filter(&source_file, CpuKind::Cpu6809, |tk| *tk == TokenKind::Identifier);
 /// println!("{:?}", tokens); // Output: [Token { kind: Identifier, text: "Hello" }, Token { kind: Identifier, text: "world!" }]
 /// ```
-pub fn to_tokens_filter< P>(source_file: &grl_sources::SourceFile, predicate: P) -> Vec<Token>
+pub fn to_tokens_filter<P>(
+    source_file: &grl_sources::SourceFile,
+    cpu: CpuKind,
+    predicate: P,
+) -> Vec<Token>
 where
     P: Fn(&TokenKind) -> bool,
 {
-    let ret = to_tokens_kinds(source_file);
+    let ret = to_tokens_kinds(source_file, cpu);
 
     ret.into_iter()
         .filter(|(tk, _)| predicate(tk))
@@ -283,3 +358,46 @@ where
         })
         .collect()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::create_source_file;
+
+    #[test]
+    fn test_all_cpus() {
+        let text = "lda #1 ; load\n ld a,b\n stz 0\n";
+        let sf = create_source_file(text);
+        let tokens = CpuTokens::new(&sf);
+
+        // Only mapped for a cpu when it's asked for
+        assert!(!tokens.is_mapped(CpuKind::CpuZ80));
+        let len = tokens.get(CpuKind::Cpu6809).len();
+        assert!(CpuKind::iter().all(|cpu| tokens.get(cpu).len() == len));
+        assert!(tokens.is_mapped(CpuKind::CpuZ80));
+
+        let opcodes = |cpu: CpuKind| -> Vec<_> {
+            tokens
+                .get(cpu)
+                .iter()
+                .filter(|t| t.kind == TokenKind::OpCode(cpu))
+                .map(|t| t.extra.get_text().to_owned())
+                .collect()
+        };
+
+        assert_eq!(opcodes(CpuKind::Cpu6800), ["lda"]);
+        assert_eq!(opcodes(CpuKind::CpuZ80), ["ld"]);
+        assert_eq!(opcodes(CpuKind::Cpu65c02), ["lda", "stz"]);
+    }
+
+    #[test]
+    fn test_alt_af() {
+        let sf = create_source_file("ex af,af'");
+        let kinds = |cpu| -> Vec<_> {
+            to_tokens_no_comment(&sf, cpu).iter().map(|t| t.kind).collect()
+        };
+
+        assert_eq!(kinds(CpuKind::CpuZ80)[3], TokenKind::AltAf);
+        assert_eq!(kinds(CpuKind::Cpu6809)[3], TokenKind::Error);
+    }
+}
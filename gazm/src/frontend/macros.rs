@@ -1,6 +1,6 @@
 #![deny(unused_imports)]
 
-use unraveler::{many0, match_span as ms, pair, preceded, sep_list0, tuple};
+use unraveler::{match_span as ms, pair, preceded, sep_list0, tuple};
 
 
 use super::{
@@ -25,12 +25,10 @@ impl GazmParser
             tuple((
                 get_label_string,
                 parse_macrodef_args,
-                parse_block(many0(Self::parse_next_source_chunk)),
+                parse_block(Self::parse_block_chunks),
             )),
         ))(input)?;
 
-        let body: Vec<Node> = body.into_iter().flatten().collect();
-
         let node = from_item_kids_tspan(MacroDef(label, args.into()), &body, sp);
         Ok((rest, node))
     }
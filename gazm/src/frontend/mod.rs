@@ -1,4 +1,5 @@
 mod commands;
+mod conditionals;
 mod error;
 mod expr;
 mod gazmunraveller;
@@ -9,6 +10,7 @@ mod node;
 mod nodeiter;
 mod parse;
 mod parsetext;
+mod repeats;
 mod structs;
 mod testit;
 mod token_store;
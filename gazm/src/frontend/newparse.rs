@@ -11,6 +11,7 @@ impl GazmParser {
             CpuKind::Cpu6502 => todo!(),
             CpuKind::Cpu65c02 => todo!(),
             CpuKind::CpuZ80 => todo!(),
+            CpuKind::Cpu6309 => todo!(),
         }
     }
 
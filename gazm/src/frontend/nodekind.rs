@@ -5,7 +5,8 @@ use std::{fmt::Display, path::PathBuf};
 use thin_vec::ThinVec;
 
 use crate::{
-    cpu6800::frontend::NodeKind6800, cpu6809::frontend::NodeKind6809, error::ParseError,
+    cpu6502::frontend::NodeKind6502, cpu6800::frontend::NodeKind6800,
+    cpu6809::frontend::NodeKind6809, cpuz80::frontend::NodeKindZ80, error::ParseError,
     gazmsymbols::SymbolScopeId, semantic::AstNodeId,
 };
 
@@ -13,6 +14,49 @@ use crate::{
 pub enum CpuSpecific {
     Cpu6809(NodeKind6809),
     Cpu6800(NodeKind6800),
+    Cpu6502(NodeKind6502),
+    CpuZ80(NodeKindZ80),
+}
+
+impl CpuSpecific {
+    /// The mnemonic if this is an opcode
+    pub fn get_opcode_text(&self) -> Option<&str> {
+        use CpuSpecific::*;
+        match self {
+            Cpu6809(NodeKind6809::OpCode(text, ..) | NodeKind6809::OpCode6309(text, ..)) => {
+                Some(text.as_str())
+            }
+            Cpu6800(NodeKind6800::OpCode(text, ..)) => Some(text.as_str()),
+            Cpu6502(NodeKind6502::OpCode(text, ..)) => Some(text.as_str()),
+            CpuZ80(NodeKindZ80::OpCode(text, ..)) => Some(text.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Base cycle count if this is an opcode the tables have timings for
+    pub fn get_cycles(&self) -> Option<usize> {
+        use CpuSpecific::*;
+        match self {
+            Cpu6809(NodeKind6809::OpCode(_, ins, _)) => Some(ins.cycles as usize),
+            Cpu6800(NodeKind6800::OpCode(_, data, _)) => Some(data.cycles),
+            Cpu6502(NodeKind6502::OpCode(_, data, _)) => Some(data.cycles),
+            CpuZ80(NodeKindZ80::OpCode(_, ins)) => ins.cycles,
+            _ => None,
+        }
+    }
+
+    /// A cpu whose instruction tables cover this node
+    pub fn get_cpu_kind(&self) -> CpuKind {
+        use CpuSpecific::*;
+        match self {
+            Cpu6809(NodeKind6809::OpCode6309(..)) => CpuKind::Cpu6309,
+            Cpu6809(..) => CpuKind::Cpu6809,
+            Cpu6800(..) => CpuKind::Cpu6800,
+            Cpu6502(NodeKind6502::OpCode(_, data, _)) if data.is_65c02 => CpuKind::Cpu65c02,
+            Cpu6502(..) => CpuKind::Cpu6502,
+            CpuZ80(..) => CpuKind::CpuZ80,
+        }
+    }
 }
 
 use super::{BaseNode, CtxTrait};
@@ -63,11 +107,36 @@ pub enum AstNodeKind {
     Scope(String),
     ScopeId(u64),
 
+    Section(String),
+    /// A section switch, with the PC the section had got to
+    SectionResolved {
+        section: usize,
+        pc: usize,
+    },
+
+    /// `if` / `ifdef` / `ifndef` up to `endif`, children are its branches in order
+    Conditional,
+    /// `if` / `elif` branch, the first child is the condition and the rest its body
+    IfBranch,
+    /// `ifdef` / `ifndef` branch, taken if the symbol being defined matches the flag
+    IfDefBranch(String, bool),
+    /// `else` branch
+    ElseBranch,
+
+    /// `rept count[, index] { body }`, the first child is the count
+    Rept(Option<String>),
+    /// `irp index, values { body }`, the first `usize` children are the values
+    Irp(String, usize),
+    /// `while condition, index { body }`, the first child is the condition
+    While(String),
+
     Expr,
     PostFixExpr,
     BracketedExpr,
 
     UnaryTerm,
+    /// `bank(label)`
+    Bank,
     Label(LabelDefinition),
     LocalLabel(LabelDefinition),
 
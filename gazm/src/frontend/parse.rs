@@ -1,12 +1,13 @@
 use crate::{
-    assembler::AssemblerCpuTrait,
+    cpukind::{Assemblers, CpuKind},
     error::{ErrorCollectorTrait, NewErrorCollector},
 };
 
 // #![deny(unused_imports)]
 use super::{
-    get_text, parse_line, split_at_next_line, AstNodeKind, FrontEndError, FrontEndErrorKind,
-    GazmParser, Node, PResult, TSpan,
+    err_nomatch, from_item_tspan, get_cpu, get_text, parse_line, split_at_next_line,
+    with_cpu, AstNodeKind, FrontEndError, FrontEndErrorKind, GazmParser, Node, PResult, TSpan,
+    TokenKind,
 };
 
 use itertools::Itertools;
@@ -45,16 +46,106 @@ impl<'a> NodeCollector<'a> {
     }
 }
 
+thread_local! {
+    /// One assembler per cpu to parse opcodes with
+    static ASSEMBLERS: Assemblers = Assemblers::new();
+}
+
+/// The cpu in effect at `offset` in a parsed file
+/// Follows the switches to and from cpus in the blocks before the offset
+pub fn get_cpu_at(node: &Node, cpu: CpuKind, offset: usize) -> CpuKind {
+    if node.ctx.range().start > offset {
+        return cpu;
+    }
+
+    let cpu = match node.item {
+        AstNodeKind::Cpu(cpu) => cpu,
+        _ => cpu,
+    };
+
+    node.children
+        .iter()
+        .fold(cpu, |cpu, kid| get_cpu_at(kid, cpu, offset))
+}
+
 // I need isolate parse_command
 // and parse opcode
 
 impl GazmParser {
+    /// Parse opcodes with the assembler for the cpu they were lexed for
+    pub fn parse_multi_opcode_vec(input: TSpan) -> PResult<Vec<Node>> {
+        match input.first().map(|t| t.kind) {
+            Some(TokenKind::OpCode(cpu)) => {
+                ASSEMBLERS.with(|asms| asms.asm(cpu).parse_multi_opcode_vec(input))
+            }
+            _ => err_nomatch(input),
+        }
+    }
+
+    /// Switch to the cpu these nodes leave us on
+    /// `target` switches cpu, as does a `section` configured with a cpu of its own
+    fn follow_cpu<'a>(input: TSpan<'a>, nodes: &mut Vec<Node>) -> TSpan<'a> {
+        let cpu = match nodes.last().map(|n| &n.item) {
+            Some(AstNodeKind::Cpu(cpu)) => *cpu,
+
+            Some(AstNodeKind::Section(name)) => {
+                let section_cpus = input.extra().section_cpus;
+
+                match section_cpus.and_then(|cpus| cpus.get(name)).copied() {
+                    Some(cpu) => {
+                        let pos = nodes.last().unwrap().ctx.clone();
+                        nodes.push(Node::new(AstNodeKind::Cpu(cpu), pos));
+                        cpu
+                    }
+                    None => return input,
+                }
+            }
+
+            _ => return input,
+        };
+
+        with_cpu(input, cpu)
+    }
+
+    /// Source chunks up to a token `is_end` accepts or the end of the source
+    /// A cpu switched to in them only lasts until their end,
+    /// a node switching back to the cpu they started with is added if needed
+    pub fn parse_chunks_until(
+        mut input: TSpan,
+        is_end: fn(TokenKind) -> bool,
+    ) -> PResult<Vec<Node>> {
+        let cpu = get_cpu(input);
+        let mut ret = vec![];
+
+        while let Some(kind) = input.first().map(|t| t.kind) {
+            if is_end(kind) {
+                break;
+            }
+
+            let (rest, mut nodes) = Self::parse_next_source_chunk(input)?;
+            input = Self::follow_cpu(rest, &mut nodes);
+            ret.extend(nodes);
+        }
+
+        if get_cpu(input) != cpu {
+            ret.push(from_item_tspan(AstNodeKind::Cpu(cpu), input));
+            input = with_cpu(input, cpu);
+        }
+
+        Ok((input, ret))
+    }
+
+    /// Source chunks in a block up to its closing brace
+    pub fn parse_block_chunks(input: TSpan) -> PResult<Vec<Node>> {
+        Self::parse_chunks_until(input, |kind| kind == TokenKind::CloseBrace)
+    }
+
     pub fn parse_single_line(input: TSpan) -> PResult<Vec<Node>> {
         parse_line(alt((
             map(Self::parse_macro_call, |n| vec![n]),
             map(Self::parse_equate, |n| vec![n]),
             map(Self::parse_command, |n| vec![n]),
-            // C::parse_multi_opcode_vec,
+            Self::parse_multi_opcode_vec,
         )))(input)
     }
 
@@ -78,6 +169,8 @@ impl GazmParser {
 
         let (rest, matched) = alt((
             Self::parse_single_line,
+            map(Self::parse_conditional, as_vec),
+            map(Self::parse_repeat, as_vec),
             map(Self::parse_macro_def, as_vec),
             map(Self::parse_struct, as_vec),
             map(Self::parse_pc_equate, as_vec),
@@ -106,9 +199,9 @@ impl GazmParser {
                     input = next_line;
                 }
 
-                Ok((rest, matched)) => {
+                Ok((rest, mut matched)) => {
+                    input = Self::follow_cpu(rest, &mut matched);
                     ret.extend(matched);
-                    input = rest;
                 }
             };
         }
@@ -124,3 +217,117 @@ impl GazmParser {
 fn as_vec(n: Node) -> Vec<Node> {
     vec![n]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assembler::{
+            testing::{assemble_err, assemble_ok, bytes},
+            AccessType,
+        },
+        frontend::{create_source_file, make_cpu_tspan, CpuTokens},
+        opts::Opts,
+        sections::SerializedSection,
+    };
+
+    #[test]
+    fn test_target_in_blocks() {
+        // A target lasts until the end of the block it's in
+        // and one in a branch that isn't taken does nothing
+        let text = "
+            org $1000
+            if 0
+            target z80
+            endif
+            lda #1
+            macro sound() {
+                target z80
+                ld a,b
+            }
+            sound()
+            ldd #2
+            if 1
+            target 6800
+            ldaa #3
+            endif
+            ldd #4
+            ";
+
+        let asm = assemble_ok(text, Opts::default());
+
+        let desired = [
+            0x86, 0x01, // 6809 lda
+            0x78, // z80 ld a,b
+            0xcc, 0x00, 0x02, // 6809 ldd
+            0x86, 0x03, // 6800 ldaa
+            0xcc, 0x00, 0x04, // 6809 ldd
+        ];
+
+        assert_eq!(bytes(&asm, 0x1000, desired.len()), desired);
+    }
+
+    #[test]
+    fn test_cpu_commands() {
+        // setdp is only a 6809 command
+        let asm = assemble_ok("    org $1000\n    setdp $10\n    lda $1010\n", Opts::default());
+        assert_eq!(bytes(&asm, 0x1000, 2), [0x96, 0x10]);
+
+        let errs = assemble_err("    target z80\n    setdp $10\n", Opts::default());
+        assert_eq!(errs.len(), 1);
+    }
+
+    #[test]
+    fn test_section_cpu() {
+        let section = |name: &str, start: usize, cpu: CpuKind| SerializedSection {
+            name: name.into(),
+            start,
+            size: 0x100,
+            org: None,
+            access: AccessType::ReadWrite,
+            bank: None,
+            cpu: Some(cpu),
+        };
+
+        let opts = Opts {
+            sections: vec![
+                section("main", 0x1000, CpuKind::Cpu6809),
+                section("sound", 0x2000, CpuKind::CpuZ80),
+            ],
+            ..Default::default()
+        };
+
+        let text = "
+            section main
+            ldd #1
+            section sound
+            ld a,b
+            fdb $1234
+            section main
+            fdb $1234
+            ";
+
+        let asm = assemble_ok(text, opts);
+
+        assert_eq!(bytes(&asm, 0x1000, 5), [0xcc, 0x00, 0x01, 0x12, 0x34]);
+        assert_eq!(bytes(&asm, 0x2000, 3), [0x78, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn test_cpu_at() {
+        let text = "lda #1\n if 0\n target z80\n ld a,b\n endif\n ldd #2\n";
+        let opts = Opts::default();
+        let sf = create_source_file(text);
+        let cpu_tokens = CpuTokens::new(&sf);
+        let section_cpus = Default::default();
+        let span = make_cpu_tspan(&cpu_tokens, opts.cpu, &section_cpus, &sf, &opts);
+
+        let (_, nodes) = GazmParser::parse_all_with_resume(span).unwrap();
+        let file = Node::new_with_children(AstNodeKind::Block, &nodes, span.extra().get_pos(span));
+
+        let at = |text: &str| get_cpu_at(&file, opts.cpu, sf.get_text().source.find(text).unwrap());
+        assert_eq!(at("lda"), CpuKind::Cpu6809);
+        assert_eq!(at("ld a"), CpuKind::CpuZ80);
+        assert_eq!(at("ldd"), CpuKind::Cpu6809);
+    }
+}
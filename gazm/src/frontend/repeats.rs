@@ -0,0 +1,158 @@
+#![deny(unused_imports)]
+//! `rept`, `irp` and `while` blocks
+//! The semantic pass expands them into a copy of the body per iteration
+use unraveler::{alt, match_span as ms, opt, pair, preceded};
+
+use super::{
+    from_item_kids_tspan, get_label_string, parse_block, parse_expr, AstNodeKind, CommandKind,
+    GazmParser, Node, PResult, TSpan, TokenKind::Comma,
+};
+
+impl GazmParser {
+    fn parse_repeat_body(input: TSpan) -> PResult<Vec<Node>> {
+        parse_block(Self::parse_block_chunks)(input)
+    }
+
+    /// REPT count[, index] { body }
+    fn parse_rept(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, ((count, index), body))) = ms(preceded(
+            CommandKind::Rept,
+            pair(
+                pair(parse_expr, opt(preceded(Comma, get_label_string))),
+                Self::parse_repeat_body,
+            ),
+        ))(input)?;
+
+        let kids: Vec<_> = [count].into_iter().chain(body).collect();
+        Ok((rest, from_item_kids_tspan(AstNodeKind::Rept(index), &kids, sp)))
+    }
+
+    /// IRP index, value, value... { body }
+    fn parse_irp(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, ((index, values), body))) = ms(preceded(
+            CommandKind::Irp,
+            pair(
+                pair(get_label_string, preceded(Comma, Self::parse_expr_list)),
+                Self::parse_repeat_body,
+            ),
+        ))(input)?;
+
+        let item = AstNodeKind::Irp(index, values.len());
+        let kids: Vec<_> = values.into_iter().chain(body).collect();
+        Ok((rest, from_item_kids_tspan(item, &kids, sp)))
+    }
+
+    /// WHILE condition, index { body }
+    /// The index is the only thing that changes between iterations so it's needed
+    /// for the condition to ever change
+    fn parse_while(input: TSpan) -> PResult<Node> {
+        let (rest, (sp, ((cond, index), body))) = ms(preceded(
+            CommandKind::While,
+            pair(
+                pair(parse_expr, preceded(Comma, get_label_string)),
+                Self::parse_repeat_body,
+            ),
+        ))(input)?;
+
+        let kids: Vec<_> = [cond].into_iter().chain(body).collect();
+        Ok((rest, from_item_kids_tspan(AstNodeKind::While(index), &kids, sp)))
+    }
+
+    /// A repeat block
+    /// ```text
+    ///     rept 8, i { fcb i*2 }
+    ///     irp reg, 1, 2, 4 { fcb reg }
+    ///     while 16-i, i { fdb table+i*4 }
+    /// ```
+    pub fn parse_repeat(input: TSpan) -> PResult<Node> {
+        alt((Self::parse_rept, Self::parse_irp, Self::parse_while))(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{frontend::*, opts::Opts};
+
+    #[test]
+    fn test_repeat() {
+        use AstNodeKind::*;
+
+        let opts = Opts::default();
+
+        let parse = |text: &str| {
+            let sf = create_source_file(text);
+            let tokens = to_tokens_no_comment(&sf, opts.cpu);
+            let span = make_tspan(&tokens, &sf, &opts);
+            let (_, matched) = GazmParser::parse_repeat(span).unwrap();
+            let kids: Vec<_> = matched.children.iter().map(|c| c.item.clone()).collect();
+            (matched.item, kids.len())
+        };
+
+        assert_eq!(parse("rept 8, i {\n fcb i\n fcb 1\n}"), (Rept(Some("i".into())), 3));
+        assert_eq!(parse("rept 8 { fcb 1 }"), (Rept(None), 2));
+        assert_eq!(parse("irp x, 1, 2, 3 {\n fcb x\n}"), (Irp("x".into(), 3), 4));
+        assert_eq!(parse("while 4-i, i { fcb i }"), (While("i".into()), 2));
+    }
+
+    #[test]
+    fn test_expand_repeats() {
+        use crate::assembler::testing::{assemble_ok, bytes};
+
+        let text = "
+    org $1000
+    rept 3, i { fcb i*2 }
+    rept 2 { fcb $ff }
+    irp reg, 1, 4, 9 { fcb reg }
+    while 3-i, i { fcb $10+i }
+    while 0, i { fcb $ee }
+    fcb $aa
+";
+        let asm = assemble_ok(text, Opts::default());
+        let desired = [0, 2, 4, 0xff, 0xff, 1, 4, 9, 0x10, 0x11, 0x12, 0xaa];
+        assert_eq!(bytes(&asm, 0x1000, desired.len()), desired);
+    }
+
+    #[test]
+    fn test_repeat_scopes() {
+        use crate::assembler::testing::{assemble_ok, bytes, symbol};
+
+        // Each iteration has its own labels and index
+        let text = "
+    org $1000
+    rept 3, i {
+here fdb here
+    }
+    irp i, 5, 6 {
+here fcb i
+    }
+done fcb done-$1000
+";
+        let asm = assemble_ok(text, Opts::default());
+        assert_eq!(bytes(&asm, 0x1000, 9), [0x10, 0, 0x10, 2, 0x10, 4, 5, 6, 8]);
+        assert_eq!(symbol(&asm, "here"), None);
+        assert_eq!(symbol(&asm, "i"), None);
+    }
+
+    #[test]
+    fn test_while_termination() {
+        use crate::assembler::testing::assemble_err;
+
+        let errs = assemble_err("    while 1, i { fcb 1 }", Opts::default());
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("While loop still going after 65536 iterations"));
+
+        // Without an index nothing could change the condition
+        let errs = assemble_err("    while 1 { fcb 1 }", Opts::default());
+        assert!(!errs.is_empty());
+    }
+
+    #[test]
+    fn test_rept_limit() {
+        use crate::assembler::testing::assemble_err;
+
+        let errs = assemble_err("    rept $7fffffff { fcb 1 }", Opts::default());
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("Repeat count of 2147483647 is more than 65536"));
+    }
+}
@@ -102,7 +102,7 @@ mod test {
         let opts = Opts::default();
 
         let sf = create_source_file(text);
-        let tokens = to_tokens_no_comment(&sf);
+        let tokens = to_tokens_no_comment(&sf, opts.cpu);
 
         let ts: Vec<_> = tokens.iter().map(|t| t.kind).collect();
         println!("{:?}", ts);
@@ -6,6 +6,7 @@ use super::{
 
 use crate::{
     assembler::Assembler,
+    cpukind::CpuKind,
     debug_mess,
     error::{ErrorCollectorTrait, NewErrorCollector},
     opts::Opts,
@@ -17,7 +18,10 @@ use grl_sources::{
 };
 
 use itertools::Itertools;
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -29,6 +33,8 @@ pub struct TokenizeRequest {
     pub opts: Opts,
     pub include_stack: IncludeStack,
     pub opt_pos: Option<Position>,
+    /// Cpus sections are configured with, by section name
+    pub section_cpus: HashMap<String, CpuKind>,
 }
 
 impl TokenizeRequest {
@@ -40,6 +46,7 @@ impl TokenizeRequest {
             parent: None,
             include_stack: Default::default(),
             opt_pos: None,
+            section_cpus: Default::default(),
         }
     }
 }
@@ -105,9 +112,11 @@ impl TokenizeRequest {
 
 impl TokenizeRequest {
     pub fn tokenize(&mut self) -> (Node, NewErrorCollector<FrontEndError>) {
-        use crate::frontend::{make_tspan, to_tokens_no_comment};
-        let tokens = to_tokens_no_comment(&self.source_file);
-        let mut span = make_tspan(&tokens, &self.source_file, &self.opts);
+        use crate::frontend::{make_cpu_tspan, CpuTokens};
+        let cpu_tokens = CpuTokens::new(&self.source_file);
+        let cpu = self.opts.cpu;
+        let sf = &self.source_file;
+        let mut span = make_cpu_tspan(&cpu_tokens, cpu, &self.section_cpus, sf, &self.opts);
 
         let mut final_nodes = vec![];
         let mut errors: NewErrorCollector<FrontEndError> =
@@ -228,6 +237,7 @@ impl Assembler {
                 opts: self.opts.clone(),
                 include_stack: Default::default(),
                 opt_pos,
+                section_cpus: self.asm_out.sections.cpus(),
             };
 
             Ok(GetTokensResult::Request(toke_req.into()))
@@ -11,6 +11,9 @@ pub type SymbolTreeWriter<'a> = grl_symbols::symboltreewriter::SymbolTreeWriter<
 pub type SymbolTreeReader<'a> = grl_symbols::symboltreereader::SymbolTreeReader<'a,ScopeId,SymbolId,SymValue>;
 pub type SymbolTree = grl_symbols::SymbolTree<ScopeId,SymbolId,SymValue>;
 
+/// Bank numbers of labels defined in banked sections
+pub type SymbolBanks = std::collections::HashMap<SymbolScopeId, usize>;
+
 pub type Serializable = grl_symbols::serialize::Seriablizable<ScopeId,SymbolId,SymValue>;
 
 pub use grl_symbols::prelude::{ SymbolResolutionBarrier, ScopedName };
@@ -3,6 +3,7 @@
 #![allow(dead_code)]
 pub mod assembler;
 pub mod cli;
+pub mod diagnostics;
 pub mod fmt;
 pub mod frontend;
 pub mod lsp;
@@ -11,13 +12,15 @@ pub mod opts;
 pub mod error;
 pub mod cpu6809;
 pub mod cpu6800;
+pub mod cpu6502;
+pub mod cpuz80;
 pub mod cpukind;
+pub mod sections;
 
 mod astformat;
 mod docs;
 mod gazmsymbols;
 mod lookup;
-mod sections;
 mod semantic;
 mod utils;
 mod vars;
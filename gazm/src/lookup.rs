@@ -10,6 +10,7 @@ use crate::{
 use grl_sources::Position;
 
 use itertools::Itertools;
+use thin_vec::ThinVec;
 
 #[derive(Clone, Debug)]
 pub struct LabelUsageAndDefintions {
@@ -19,6 +20,9 @@ pub struct LabelUsageAndDefintions {
     pos_node_id: Vec<(Position, AstNodeId)>,
     // tree: AstTree,
     docs: HashMap<AstNodeId, String>,
+    scope_pos: Vec<(Position, u64)>,
+    struct_entries: HashMap<String, Vec<String>>,
+    macros: Vec<(String, ThinVec<String>)>,
 }
 
 impl LabelUsageAndDefintions {
@@ -28,6 +32,9 @@ impl LabelUsageAndDefintions {
 
         let mut reference_pos_and_id: Vec<(Position, SymbolScopeId)> = vec![];
         let mut symbol_id_to_definition: HashMap<SymbolScopeId, Position> = HashMap::new();
+        let mut scope_pos: Vec<(Position, u64)> = vec![];
+        let mut struct_entries: HashMap<String, Vec<String>> = HashMap::new();
+        let mut macros = vec![];
 
         for n in iter_refs_recursive(tree.as_ref().root()) {
             let v = n.value();
@@ -43,6 +50,21 @@ impl LabelUsageAndDefintions {
                     symbol_id_to_definition.insert(*id, v.pos);
                 }
 
+                ScopeId(scope_id) => scope_pos.push((v.pos, *scope_id)),
+
+                MacroDef(name, params) => macros.push((name.clone(), params.clone())),
+
+                StructDef(name) => {
+                    let entries = n
+                        .children()
+                        .filter_map(|c| match &c.value().item {
+                            StructEntry(entry) => Some(entry.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    struct_entries.insert(name.clone(), entries);
+                }
+
                 _ => (),
             }
         }
@@ -61,6 +83,9 @@ impl LabelUsageAndDefintions {
             symbol_id_to_definition_pos: symbol_id_to_definition,
             pos_node_id,
             docs,
+            scope_pos,
+            struct_entries,
+            macros,
         }
     }
 
@@ -120,8 +145,30 @@ impl LabelUsageAndDefintions {
             .map(|(_, id)| *id)
     }
 
-    pub fn find_scope_at_pos(&self, _p: &Position) -> u64 {
-        panic!()
+    /// The scope in effect at this position
+    /// This is the last scope opened before it in the same file, or the root scope
+    pub fn find_scope_at_pos(&self, p: &Position) -> u64 {
+        self.scope_pos
+            .iter()
+            .filter(|(sp, _)| sp.src() == p.src() && sp.range().start <= p.range().start)
+            .last()
+            .map(|(_, id)| *id)
+            .unwrap_or_else(|| self.symbols.get_root_scope_id())
+    }
+
+    /// All symbols with a definition in the source
+    pub fn defined_symbols(&self) -> impl Iterator<Item = &SymbolScopeId> {
+        self.symbol_id_to_definition_pos.keys()
+    }
+
+    /// Entry names of a struct, in definition order
+    pub fn find_struct_entries(&self, name: &str) -> Option<&[String]> {
+        self.struct_entries.get(name).map(|v| v.as_slice())
+    }
+
+    /// Names and parameters of all macros defined
+    pub fn macros(&self) -> &[(String, ThinVec<String>)] {
+        &self.macros
     }
 
     pub fn find_symbol_defined_at_pos(&self, pos: &Position) -> Option<SymbolScopeId> {
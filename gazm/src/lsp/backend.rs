@@ -1,10 +1,15 @@
 use crate::{
     assembler::{Assembler, AssemblerCpuTrait},
-    error::{GResult, GazmErrorKind},
-    gazmsymbols::{SymbolInfo, SymbolScopeId},
+    cpukind::CpuKind,
+    error::{
+        ErrorCollectorTrait, ErrorMessage, GResult, GazmErrorKind, UserError, UserErrorData,
+        UserWarning,
+    },
+    frontend::{get_cpu_at, AstNodeKind, LabelDefinition, COMS},
+    gazmsymbols::SymbolScopeId,
     lookup::LabelUsageAndDefintions,
     opts::Opts,
-    semantic::{AstNodeId, ItemWithPos},
+    semantic::{iter_refs_recursive, AstNodeId, AstNodeRef, ItemWithPos},
     utils::with_state,
 };
 
@@ -12,10 +17,12 @@ use grl_sources::{AsmSource, Position as GazmPosition, TextCoords, TextEdit};
 
 use std::{
     cmp::max,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
+use itertools::Itertools;
 use log::{error, info};
 use serde_json::Value;
 
@@ -24,13 +31,17 @@ use tower_lsp::{
     jsonrpc::Result as TResult,
     lsp_types::request::{GotoDeclarationParams, GotoDeclarationResponse},
     lsp_types::Position,
+    lsp_types::TextEdit as LspTextEdit,
     lsp_types::*,
     {Client, LanguageServer},
 };
 
 pub struct Backend {
     pub client: Client,
-    // pub asm_ctx: Arc<Mutex<Assembler>>,
+    pub asm_ctx: Arc<Mutex<Assembler>>,
+    /// Files we've published diagnostics for
+    /// so they can be cleared once fixed
+    diag_files: Arc<Mutex<HashSet<PathBuf>>>,
 }
 
 pub fn to_text_edit<'a>(range: &Range, txt: &'a str) -> TextEdit<'a> {
@@ -130,6 +141,435 @@ impl Assembler {
         })
     }
 
+    /// Hover text for a symbol referenced or defined here
+    /// Shows where it is defined and any doc comments
+    fn symbol_hover(&self, position: &Position, uri: &Url) -> Option<Vec<String>> {
+        let id = self.find_symbol_referenced_or_defined(position, uri)?;
+        let si = self.get_symbols().get_symbol_info_from_id(id).ok()?;
+
+        let value = si
+            .value
+            .map(|x| format!("{x:5} 0x{x:04x}"))
+            .unwrap_or("UNDEFINED".to_owned());
+
+        let defined = self
+            .lookup_ref()
+            .and_then(|lookup| lookup.find_definition(id))
+            .and_then(|pos| {
+                let path = self.asm_source_to_path(&pos.src())?;
+                let (line, _) = pos.line_col_from_one();
+                Some(format!("{}:{line}", path.to_string_lossy()))
+            })
+            .unwrap_or("UNKNOWN".to_owned());
+
+        let to_print = vec![
+            ("full name", si.scoped_name()),
+            ("value", &value),
+            ("defined", &defined),
+        ];
+
+        let mut markup = vec![format!("**Symbol** `{}`", si.name())];
+        markup.append(&mut tabulate(&to_print, 10));
+
+        if let Some(doc_text) = self.find_docs(position, uri) {
+            if !doc_text.is_empty() {
+                markup.push("---".to_string());
+                markup.push(doc_text);
+            }
+        }
+
+        Some(markup)
+    }
+
+    /// The cpu being assembled for here
+    fn cpu_at(&self, position: &Position, uri: &Url) -> Option<CpuKind> {
+        let (pos, path) = self.to_file_path_position(position, uri)?;
+        let tokens = self.get_tokens_from_full_path(path)?;
+        Some(get_cpu_at(&tokens.node, self.opts.cpu, pos.range().start))
+    }
+
+    /// Hover text for an opcode here
+    /// Shows the addressing modes and cycle counts from the cpu's instruction tables
+    fn opcode_hover(&self, position: &Position, uri: &Url) -> Option<Vec<String>> {
+        let ast = self.asm_out.ast.as_ref()?;
+
+        let (text, cpu) = self
+            .find_nodes_at_location(position, uri)?
+            .into_iter()
+            .find_map(|id| match &ast.as_ref().get(id)?.value().item {
+                AstNodeKind::TargetSpecific(cs) => cs
+                    .get_opcode_text()
+                    .map(|text| (text.to_owned(), cs.get_cpu_kind())),
+                _ => None,
+            })?;
+
+        // Described for the cpu being assembled for if it's from the same family
+        // so a 6502 opcode in 65C02 code lists the 65C02's modes
+        let cpu = self
+            .cpu_at(position, uri)
+            .filter(|c| c.family() == cpu.family())
+            .unwrap_or(cpu);
+
+        let cpu_asm: Box<dyn AssemblerCpuTrait> = cpu.into();
+        let modes = cpu_asm.get_opcode_info(&text);
+
+        if modes.is_empty() {
+            return None;
+        }
+
+        let rows: Vec<_> = modes
+            .into_iter()
+            .map(|m| {
+                let cycles = m.cycles.map(|c| format!("{c} cycles")).unwrap_or("? cycles".into());
+                (m.addr_mode, cycles)
+            })
+            .collect();
+
+        let to_print: Vec<_> = rows.iter().map(|(a, c)| (a.as_str(), c.as_str())).collect();
+
+        let mut markup = vec![format!("**Opcode** `{text}` ({})", cpu_asm.get_cpu_name())];
+        markup.append(&mut tabulate(&to_print, 10));
+
+        Some(markup)
+    }
+
+    /// Completions for the word before the cursor
+    /// Struct members after a dot, opcodes, directives and macros in the opcode column,
+    /// otherwise the symbols visible from here
+    fn completions(&self, position: &Position, uri: &Url) -> Option<Vec<CompletionItem>> {
+        let (pos, path) = self.to_file_path_position(position, uri)?;
+        let lookup = self.lookup_ref()?;
+        let (_, sf) = self.sources().get_source(path).ok()?;
+        let source = &sf.get_text().source;
+
+        let index = pos.range().start;
+        let line_start = source[..index].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line = &source[line_start..index];
+
+        let (before, word) = split_word(line);
+
+        let struct_items = word
+            .rsplit_once('.')
+            .and_then(|(name, _)| self.struct_completions(lookup, name));
+
+        let items = if let Some(items) = struct_items {
+            items
+        } else if is_opcode_column(before) {
+            let cpu = self.cpu_at(position, uri).unwrap_or(self.opts.cpu);
+            self.opcode_completions(lookup, cpu)
+        } else {
+            self.symbol_completions(lookup, &pos)
+        };
+
+        Some(items)
+    }
+
+    fn struct_completions(
+        &self,
+        lookup: &LabelUsageAndDefintions,
+        name: &str,
+    ) -> Option<Vec<CompletionItem>> {
+        let entries = lookup.find_struct_entries(name)?;
+        let syms = self.get_symbols();
+        let reader = syms.get_reader(syms.get_root_scope_id());
+
+        let items = entries
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once("size"))
+            .map(|entry| {
+                let value = reader
+                    .get_symbol_info(&format!("{name}.{entry}"))
+                    .ok()
+                    .and_then(|si| si.value);
+                completion_item(entry, CompletionItemKind::FIELD, value.map(value_text))
+            })
+            .collect();
+
+        Some(items)
+    }
+
+    fn opcode_completions(
+        &self,
+        lookup: &LabelUsageAndDefintions,
+        cpu: CpuKind,
+    ) -> Vec<CompletionItem> {
+        use CompletionItemKind as Kind;
+
+        let cpu_asm: Box<dyn AssemblerCpuTrait> = cpu.into();
+        let opcode_detail = format!("{} opcode", cpu_asm.get_cpu_name());
+
+        let opcodes = cpu_asm
+            .get_mnemonics()
+            .into_iter()
+            .map(|m| completion_item(m, Kind::KEYWORD, Some(opcode_detail.clone())));
+
+        let commands = COMS
+            .keys()
+            .sorted()
+            .map(|c| completion_item(c, Kind::KEYWORD, Some("directive".into())));
+
+        let macros = lookup.macros().iter().map(|(name, params)| {
+            let detail = format!("macro({})", params.join(", "));
+            completion_item(name, Kind::FUNCTION, Some(detail))
+        });
+
+        opcodes.chain(commands).chain(macros).collect()
+    }
+
+    /// Symbols defined in the scope at this position or in the root scope
+    fn symbol_completions(
+        &self,
+        lookup: &LabelUsageAndDefintions,
+        pos: &GazmPosition,
+    ) -> Vec<CompletionItem> {
+        let syms = self.get_symbols();
+        let scope = lookup.find_scope_at_pos(pos);
+        let root = syms.get_root_scope_id();
+
+        lookup
+            .defined_symbols()
+            .filter(|id| id.scope_id == scope || id.scope_id == root)
+            .filter_map(|id| syms.get_symbol_info_from_id(*id).ok())
+            .map(|si| (si.name().to_owned(), si.value))
+            .sorted()
+            .dedup_by(|(a, _), (b, _)| a == b)
+            .map(|(name, value)| {
+                completion_item(name, CompletionItemKind::VARIABLE, value.map(value_text))
+            })
+            .collect()
+    }
+
+    /// Where this symbol's name is in the source, for its definition and every reference
+    fn find_symbol_ranges(&self, id: SymbolScopeId) -> Option<Vec<(Url, Range)>> {
+        let lookup = self.lookup_ref()?;
+        let si = self.get_symbols().get_symbol_info_from_id(id).ok()?;
+
+        let ranges = lookup
+            .find_references(id)
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .chain(lookup.find_definition(id).cloned())
+            .filter_map(|pos| self.find_name_range(&pos, si.name()))
+            .unique()
+            .collect();
+
+        Some(ranges)
+    }
+
+    /// The range of `name` within the span of the node at this position
+    /// Scoped references keep their scope, only the name is covered
+    fn find_name_range(&self, pos: &GazmPosition, name: &str) -> Option<(Url, Range)> {
+        let path = self.asm_source_to_path(&pos.src())?;
+        let (_, sf) = self.sources().get_source(&path).ok()?;
+        let source = &sf.get_text().source;
+        let range = text_range(source, name_span(source, pos.range(), name)?);
+
+        Some((Url::from_file_path(path).ok()?, range))
+    }
+
+    fn prepare_rename(&self, position: &Position, uri: &Url) -> Option<PrepareRenameResponse> {
+        let id = self.find_symbol_referenced_or_defined(position, uri)?;
+        let si = self.get_symbols().get_symbol_info_from_id(id).ok()?;
+
+        let (_, range) = self
+            .find_symbol_ranges(id)?
+            .into_iter()
+            .find(|(u, r)| u == uri && r.start <= *position && *position <= r.end)?;
+
+        Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range,
+            placeholder: si.name().to_owned(),
+        })
+    }
+
+    /// Rename the symbol at this position in every file it's used in
+    /// Fails if the new name isn't a label or is already used in the symbol's scope
+    fn rename(
+        &self,
+        position: &Position,
+        uri: &Url,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>, String> {
+        let id = match self.find_symbol_referenced_or_defined(position, uri) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        if !is_label_name(new_name) {
+            return Err(format!("{new_name} is not a valid label"));
+        }
+
+        let syms = self.get_symbols();
+
+        let collides = syms
+            .get_reader(id.scope_id)
+            .get_symbol_info(new_name)
+            .map(|si| si.symbol_id.scope_id == id.scope_id && si.symbol_id != id)
+            .unwrap_or(false);
+
+        if collides {
+            return Err(format!("{new_name} is already defined in this scope"));
+        }
+
+        let mut changes: HashMap<Url, Vec<LspTextEdit>> = HashMap::new();
+
+        for (url, range) in self.find_symbol_ranges(id).unwrap_or_default() {
+            let edit = LspTextEdit::new(range, new_name.to_owned());
+            changes.entry(url).or_default().push(edit);
+        }
+
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    /// Outline of a file
+    /// Labels, equates, macros and structs are nested in the scope they follow
+    fn document_symbols(&self, uri: &Url) -> Option<Vec<DocumentSymbol>> {
+        let path = uri.to_file_path().ok()?;
+        let (file_id, sf) = self.sources().get_source(path).ok()?;
+        let source = &sf.get_text().source;
+        let ast = self.asm_out.ast.as_ref()?;
+        let root_scope = self.get_symbols().get_root_scope_id();
+
+        let mut ret = vec![];
+        let mut scope: Option<DocumentSymbol> = None;
+
+        for n in iter_refs_recursive(ast.as_ref().root()) {
+            let v = n.value();
+
+            if v.pos.src() != AsmSource::FileId(file_id) {
+                continue;
+            }
+
+            if let AstNodeKind::ScopeId(scope_id) = v.item {
+                ret.extend(scope.take());
+
+                if scope_id != root_scope {
+                    let name = self.get_symbols().get_fqn_from_id(scope_id);
+                    let kind = SymbolKind::NAMESPACE;
+                    scope = Some(document_symbol(source, name, kind, None, &v.pos));
+                }
+                continue;
+            }
+
+            if let Some(sym) = self.node_document_symbol(source, n) {
+                if let Some(scope) = scope.as_mut() {
+                    scope.range.end = max(scope.range.end, sym.range.end);
+                    scope.children.get_or_insert_with(Vec::new).push(sym);
+                } else {
+                    ret.push(sym);
+                }
+            }
+        }
+
+        ret.extend(scope);
+        Some(ret)
+    }
+
+    fn node_document_symbol(&self, source: &str, n: AstNodeRef) -> Option<DocumentSymbol> {
+        use AstNodeKind::*;
+        use LabelDefinition::Scoped;
+
+        let v = n.value();
+
+        let sym = match &v.item {
+            AssignmentFromPc(Scoped(id)) | Assignment(Scoped(id)) => {
+                let si = self.get_symbols().get_symbol_info_from_id(*id).ok()?;
+                let detail = si.value.map(value_text);
+                document_symbol(source, si.name(), symbol_kind(&v.item)?, detail, &v.pos)
+            }
+
+            MacroDef(name, params) => {
+                let detail = format!("macro({})", params.join(", "));
+                document_symbol(source, name, symbol_kind(&v.item)?, Some(detail), &v.pos)
+            }
+
+            StructDef(name) => {
+                let entries = n
+                    .children()
+                    .filter_map(|c| match &c.value().item {
+                        StructEntry(entry) => {
+                            let pos = &c.value().pos;
+                            Some(document_symbol(source, entry, SymbolKind::FIELD, None, pos))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+
+                let mut sym = document_symbol(source, name, SymbolKind::STRUCT, None, &v.pos);
+                sym.children = Some(entries);
+                sym
+            }
+
+            _ => return None,
+        };
+
+        Some(sym)
+    }
+
+    /// Fuzzy search of every symbol, macro and struct defined in the project
+    fn workspace_symbols(&self, query: &str) -> Option<Vec<SymbolInformation>> {
+        use AstNodeKind::*;
+        use LabelDefinition::Scoped;
+
+        let ast = self.asm_out.ast.as_ref()?;
+        let syms = self.get_symbols();
+
+        let symbols = iter_refs_recursive(ast.as_ref().root()).flat_map(|n| {
+            let v = n.value();
+            let kind = symbol_kind(&v.item);
+
+            match &v.item {
+                AssignmentFromPc(Scoped(id))
+                | Assignment(Scoped(id))
+                | LocalAssignment(Scoped(id))
+                | LocalAssignmentFromPc(Scoped(id)) => syms
+                    .get_symbol_info_from_id(*id)
+                    .ok()
+                    .map(|si| {
+                        let container = syms.get_fqn_from_id(id.scope_id);
+                        (si.name().to_owned(), kind, v.pos.clone(), Some(container))
+                    })
+                    .into_iter()
+                    .collect(),
+
+                MacroDef(name, _) => vec![(name.clone(), kind, v.pos.clone(), None)],
+
+                StructDef(name) => {
+                    let entries = n.children().filter_map(|c| match &c.value().item {
+                        StructEntry(entry) => Some((
+                            format!("{name}.{entry}"),
+                            symbol_kind(&c.value().item),
+                            c.value().pos.clone(),
+                            Some(name.clone()),
+                        )),
+                        _ => None,
+                    });
+
+                    std::iter::once((name.clone(), kind, v.pos.clone(), None))
+                        .chain(entries)
+                        .collect()
+                }
+
+                _ => vec![],
+            }
+        });
+
+        let ret = symbols
+            .filter_map(|(name, kind, pos, container)| {
+                let score = fuzzy_score(query, &name)?;
+                let short_name = name.rsplit('.').next().unwrap_or(&name);
+                let (uri, range) = self.find_name_range(&pos, short_name)?;
+                let location = Location::new(uri, range);
+                Some((score, symbol_information(name, kind?, location, container)))
+            })
+            .sorted_by(|(a, a_si), (b, b_si)| a.cmp(b).then(a_si.name.cmp(&b_si.name)))
+            .map(|(_, si)| si)
+            .collect();
+
+        Some(ret)
+    }
+
     fn to_file_path_position(
         &self,
         lsp_pos: &Position,
@@ -138,57 +578,95 @@ impl Assembler {
         let pos = position_to_text_pos(lsp_pos);
         uri.to_file_path().ok().and_then(|p| {
             self.sources().get_source(p).ok().and_then(|(id, sf)| {
-                sf.get_text()
-                    .start_pos_to_index(&pos)
-                    .map(|start_pos| {
-                        let p = GazmPosition::new(
-                            pos.line(),
-                            pos.col(),
-                            start_pos..start_pos + 1,
-                            AsmSource::FileId(id),
-                        );
-                        (p, sf.file.clone())
-                    })
-                    .ok()
+                text_offset(&sf.get_text().source, lsp_pos).map(|start_pos| {
+                    let p = GazmPosition::new(
+                        pos.line(),
+                        pos.col(),
+                        start_pos..start_pos + 1,
+                        AsmSource::FileId(id),
+                    );
+                    (p, sf.file.clone())
+                })
             })
         })
     }
 }
 
+/// The range of a span in its source file
+/// Just its start if the source has gone
+fn pos_range(asm: &Assembler, pos: &GazmPosition) -> Range {
+    match asm.sources().get_source_info(pos) {
+        Ok(si) => text_range(&si.source_file.get_text().source, pos.range()),
+        Err(_) => {
+            let (line, character) = pos.line_col();
+            let start = Position::new(line as u32, character as u32);
+            Range::new(start, start)
+        }
+    }
+}
+
+/// An error or warning as a diagnostic covering its span
+fn diagnostic(asm: &Assembler, e: &UserErrorData, severity: DiagnosticSeverity) -> Diagnostic {
+    let message = match &e.message {
+        ErrorMessage::Plain(text) => text.clone(),
+        ErrorMessage::Markdown(short, _) => short.clone(),
+    };
+
+    Diagnostic {
+        range: pos_range(asm, &e.pos),
+        severity: Some(severity),
+        message,
+        ..Default::default()
+    }
+}
+
+fn error_diagnostic(asm: &Assembler, e: &UserError) -> (PathBuf, Diagnostic) {
+    let e = e.as_ref();
+    (e.file.clone(), diagnostic(asm, e, DiagnosticSeverity::ERROR))
+}
+
+/// A warning as a diagnostic, the warning's name is its code
+fn warning_diagnostic(asm: &Assembler, w: &UserWarning) -> (PathBuf, Diagnostic) {
+    let e = w.data.as_ref();
+
+    let diag = Diagnostic {
+        code: Some(NumberOrString::String(w.kind.to_string())),
+        ..diagnostic(asm, e, DiagnosticSeverity::WARNING)
+    };
+
+    (e.file.clone(), diag)
+}
+
 impl Backend {
     pub fn get_ast_node_at_file_pos(
         &self,
-        _position: &Position,
-        _uri: &Url,
+        position: &Position,
+        uri: &Url,
     ) -> Option<(AstNodeId, ItemWithPos)> {
-        panic!()
-
-        // let value = with_state(&self.asm_ctx, |asm_ctx| -> Option<(AstNodeId, ItemWithPos )> {
-        //     if let Some(p) = asm_ctx.find_nodes_at_location(position, uri) {
-        //         if !p.is_empty() {
-        //             if let Some(ast) = asm_ctx.asm_out.ast.as_ref() {
-        //                 let v = ast.as_ref().get(*p.first().unwrap()).unwrap().value();
-        //                 return Some((*p.first().unwrap(),v.clone() ))
-        //             }
-        //         }
-        //     }
-        //     None
-        // });
-
-        // value
+        with_state(&self.asm_ctx, |asm_ctx| -> Option<(AstNodeId, ItemWithPos)> {
+            let id = *asm_ctx.find_nodes_at_location(position, uri)?.first()?;
+            let ast = asm_ctx.asm_out.ast.as_ref()?;
+            let v = ast.as_ref().get(id)?.value();
+            Some((id, v.clone()))
+        })
     }
 
-    pub fn new(_client: Client, _opts: Opts) -> Self {
-        panic!()
-        // info!("Backend created!");
-        // let asm_ctx = Arc::new(Mutex::new(Assembler::new(opts)));
-        // Self { client, asm_ctx }
+    pub fn new(client: Client, opts: Opts) -> Self {
+        info!("Backend created!");
+        let asm_ctx = Arc::new(Mutex::new(Assembler::new(opts)));
+        Self {
+            client,
+            asm_ctx,
+            diag_files: Default::default(),
+        }
     }
 
     fn create_diagnostics(&self, err: GazmErrorKind) -> Vec<(PathBuf, Diagnostic)> {
-        let mut errs = vec![];
+        let mut errs: Vec<UserError> = vec![];
+
         match err {
             GazmErrorKind::UserError(e) => errs.push(e),
+            GazmErrorKind::UserErrors(e) => errs.extend(e.to_vec()),
             GazmErrorKind::TooManyErrors(e) => {
                 for e in e.errors {
                     match e {
@@ -197,64 +675,61 @@ impl Backend {
                     }
                 }
             }
-            _ => (),
+            _ => error!("Unhandled error {err}"),
         };
 
-        let mut diags = vec![];
+        with_state(&self.asm_ctx, |asm| {
+            errs.iter().map(|e| error_diagnostic(asm, e)).collect()
+        })
+    }
+
+    /// Reassemble the project and publish diagnostics for every file with errors
+    /// Files that had diagnostics published last time but are now clean get cleared
+    async fn reassemble_file(&self, uri: Url) {
+        let doc = PathBuf::from(uri.path());
+        info!("Reassmbling {}", doc.to_string_lossy());
+
+        let r = with_state(&self.asm_ctx, |asm| asm.reassemble());
+
+        let mut by_file: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+
+        if let Err(e) = r {
+            for (p, d) in self.create_diagnostics(e) {
+                by_file.entry(p).or_default().push(d)
+            }
+        }
 
-        for e in &errs {
-            let e = e.as_ref();
-            let (line, character) = e.pos.line_col();
-            let position = Position {
-                line: line as u32,
-                character: character as u32,
-            };
-            let range = Range::new(position, position);
-            // let diag = Diagnostic::new_simple(range, e.message.clone());
-            let diag = Diagnostic::new_simple(range, "FIX THIS GAZ".to_owned());
+        // Already published as errors if warnings are errors
+        let warnings: Vec<_> = with_state(&self.asm_ctx, |asm| {
+            let errs = &asm.asm_out.errors;
+            let warnings = (!asm.opts.warnings_as_errors).then_some(&errs.warnings);
+            warnings.into_iter().flatten().map(|w| warning_diagnostic(asm, w)).collect()
+        });
 
-            diags.push((e.file.clone(), diag))
+        for (p, d) in warnings {
+            by_file.entry(p).or_default().push(d)
         }
 
-        diags
-    }
-
-    async fn reassemble_file(&self, _uri: Url) {
-        panic!()
-        // let doc = PathBuf::from(uri.path());
-        // info!("Reassmbling {}", doc.to_string_lossy());
-
-        // let r = with_state(&self.asm_ctx, |asm| asm.reassemble());
-
-        // let diags = match r {
-        //     Ok(_) => vec![],
-        //     Err(e) => {
-        //         // Get any diags for this file
-        //         self.create_diagnostics(e)
-        //             .into_iter()
-        //             .filter_map(|(p, d)| {
-        //                 if p == doc {
-        //                     Some(d)
-        //                 } else {
-        //                     error!(
-        //                         "Shouldn't happen! expected {} got {}",
-        //                         p.to_string_lossy(),
-        //                         doc.to_string_lossy()
-        //                     );
-        //                     None
-        //                 }
-        //             })
-        //             .collect()
-        //     }
-        // };
-
-        // let uri = Url::parse(&format!("file://{}", doc.to_string_lossy()));
-
-        // if let Ok(uri) = uri {
-        //     self.client.publish_diagnostics(uri, diags, None).await;
-        // } else {
-        //     error!("{:?}", uri);
-        // }
+        let cleared: Vec<_> = with_state(&self.diag_files, |files| {
+            let cleared = files
+                .iter()
+                .filter(|p| !by_file.contains_key(*p))
+                .cloned()
+                .collect();
+            *files = by_file.keys().cloned().collect();
+            cleared
+        });
+
+        let to_publish = by_file
+            .into_iter()
+            .chain(cleared.into_iter().map(|p| (p, vec![])));
+
+        for (p, diags) in to_publish {
+            match Url::from_file_path(&p) {
+                Ok(uri) => self.client.publish_diagnostics(uri, diags, None).await,
+                Err(_) => error!("Can't make a uri from {}", p.to_string_lossy()),
+            }
+        }
     }
 }
 
@@ -328,22 +803,51 @@ impl LanguageServer for Backend {
         Err(jsonrpc::Error::method_not_found())
     }
 
-    async fn references(&self, _params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
-        panic!()
-        // info!("Finding references");
-        // let uri = &params.text_document_position.text_document.uri;
-        // let position = &params.text_document_position.position;
+    async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
+        info!("Finding references");
+        let uri = &params.text_document_position.text_document.uri;
+        let position = &params.text_document_position.position;
+
+        let res = with_state(&self.asm_ctx, |asm| asm.find_references(position, uri));
+
+        info!("Done Finding references {:?}", res);
+        Ok(res)
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> TResult<Option<PrepareRenameResponse>> {
+        let uri = &params.text_document.uri;
+        let position = &params.position;
+        let res = with_state(&self.asm_ctx, |asm| asm.prepare_rename(position, uri));
+        Ok(res)
+    }
+
+    async fn rename(&self, params: RenameParams) -> TResult<Option<WorkspaceEdit>> {
+        info!("Renaming to {}", params.new_name);
+        let uri = &params.text_document_position.text_document.uri;
+        let position = &params.text_document_position.position;
 
-        // let res = with_state(
-        //     &self.asm_ctx,
-        //     |asm| -> jsonrpc::Result<Option<Vec<Location>>> {
-        //         let pos = asm.find_references(position, uri);
-        //         Ok(pos)
-        //     },
-        // );
+        with_state(&self.asm_ctx, |asm| asm.rename(position, uri, &params.new_name))
+            .map_err(jsonrpc::Error::invalid_params)
+    }
 
-        // info!("Done Finding references {:?}", res);
-        // res
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> TResult<Option<DocumentSymbolResponse>> {
+        let uri = &params.text_document.uri;
+        let res = with_state(&self.asm_ctx, |asm| asm.document_symbols(uri));
+        Ok(res.map(DocumentSymbolResponse::Nested))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> TResult<Option<Vec<SymbolInformation>>> {
+        let res = with_state(&self.asm_ctx, |asm| asm.workspace_symbols(&params.query));
+        Ok(res)
     }
 
     async fn initialize(&self, _init: InitializeParams) -> TResult<InitializeResult> {
@@ -364,13 +868,18 @@ impl LanguageServer for Backend {
                 //
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
 
-                // completion_provider: Some(CompletionOptions {
-                //     resolve_provider: Some(false),
-                //     trigger_characters: Some(vec![".".to_string()]),
-                //     work_done_progress_options: Default::default(),
-                //     all_commit_characters: None,
-                // }),
+                completion_provider: Some(CompletionOptions {
+                    resolve_provider: Some(false),
+                    trigger_characters: Some(vec![".".to_string()]),
+                    ..CompletionOptions::default()
+                }),
                 execute_command_provider: Some(ExecuteCommandOptions {
                     commands: vec!["dummy.do_something".to_string()],
                     work_done_progress_options: Default::default(),
@@ -427,15 +936,14 @@ impl LanguageServer for Backend {
 
     async fn goto_definition(
         &self,
-        _params: GotoDefinitionParams,
+        params: GotoDefinitionParams,
     ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
-        panic!()
-        // let position = &params.text_document_position_params.position;
-        // let uri = &params.text_document_position_params.text_document.uri;
+        let position = &params.text_document_position_params.position;
+        let uri = &params.text_document_position_params.text_document.uri;
 
-        // let position = with_state(&self.asm_ctx, |asm| asm.find_definition(position, uri));
+        let position = with_state(&self.asm_ctx, |asm| asm.find_definition(position, uri));
 
-        // Ok(position.map(GotoDefinitionResponse::Scalar))
+        Ok(position.map(GotoDefinitionResponse::Scalar))
     }
 
     async fn execute_command(&self, _: ExecuteCommandParams) -> TResult<Option<Value>> {
@@ -453,34 +961,32 @@ impl LanguageServer for Backend {
         Ok(None)
     }
 
-    async fn did_open(&self, _x: DidOpenTextDocumentParams) {
-        panic!()
-        // let doc = x.text_document.uri.path();
-        // info!("did_open {}", doc);
-        // self.reassemble_file::<C>(x.text_document.uri).await;
+    async fn did_open(&self, x: DidOpenTextDocumentParams) {
+        let doc = x.text_document.uri.path();
+        info!("did_open {}", doc);
+        self.reassemble_file(x.text_document.uri).await;
     }
 
-    async fn did_change(&self, _x: DidChangeTextDocumentParams) {
-        panic!()
-        // info!("did change!");
-        // info!("About to apply changes to {}", x.text_document.uri.path());
+    async fn did_change(&self, x: DidChangeTextDocumentParams) {
+        info!("did change!");
+        info!("About to apply changes to {}", x.text_document.uri.path());
 
-        // let uri = x.text_document.uri;
+        let uri = x.text_document.uri;
 
-        // let e = with_state(&self.asm_ctx, |asm| {
-        //     asm.apply_changes(PathBuf::from(uri.path()), &x.content_changes)
-        // });
+        let e = with_state(&self.asm_ctx, |asm| {
+            asm.apply_changes(PathBuf::from(uri.path()), &x.content_changes)
+        });
 
-        // match e {
-        //     Err(e) => {
-        //         info!("Error applying changes! {e}");
-        //         return;
-        //     }
+        match e {
+            Err(e) => {
+                info!("Error applying changes! {e}");
+                return;
+            }
 
-        //     Ok(_) => info!("Applied changes"),
-        // };
+            Ok(_) => info!("Applied changes"),
+        };
 
-        // self.reassemble_file(uri).await;
+        self.reassemble_file(uri).await;
     }
 
     async fn did_save(&self, _: DidSaveTextDocumentParams) {
@@ -497,71 +1003,34 @@ impl LanguageServer for Backend {
             .await;
     }
 
-    async fn completion(&self, _: CompletionParams) -> TResult<Option<CompletionResponse>> {
+    async fn completion(&self, params: CompletionParams) -> TResult<Option<CompletionResponse>> {
         info!("completion");
-        Ok(Some(CompletionResponse::Array(vec![
-            CompletionItem::new_simple("Hello".to_string(), "Some detail".to_string()),
-            CompletionItem::new_simple("Bye".to_string(), "More detail".to_string()),
-        ])))
-    }
-
-    async fn hover(&self, _params: HoverParams) -> TResult<Option<Hover>> {
-        panic!()
-        // info!("hover");
-        // let uri = &params.text_document_position_params.text_document.uri;
-        // let position = &params.text_document_position_params.position;
-
-        // // TODO: LSP get some infotmation about the AST node so we can decide what to do
-        // let ret = with_state(&self.asm_ctx, |asm_ctx| -> Option<SymbolInfo> {
-        //     let id = asm_ctx.find_symbol_id(position, uri)?;
-        //     let reader = asm_ctx.get_symbols().get_reader(id.scope_id);
-        //     let si = reader.get_symbol_info_from_id(id).unwrap();
-        //     Some(si.clone())
-        // });
-
-        // if let Some((_id,value)) = self.get_ast_node_at_file_pos(position, uri) {
-        //     info!("Got node with value {:#?}", value);
-        // }
-
-        // let doc_text = with_state(&self.asm_ctx, |asm_ctx| -> Option<String> {
-        //     asm_ctx.find_docs(position, uri)
-        // })
-        // .unwrap_or("".to_string());
-
-        // let reply = if let Some(si) = ret {
-        //     let mut markup: Vec<String> = vec![];
-
-        //     let value = si
-        //         .value
-        //         .map(|x| format!("{x:5} 0x{x:04x}"))
-        //         .unwrap_or("UNDEFINED".to_owned());
-
-        //     let scoped_name = si.scoped_name();
-        //     let name = si.name();
-
-        //     let to_print = vec![("full name", scoped_name), ("value", &value)];
-        //     let mut text = tabulate(&to_print, 10);
-
-        //     markup.push(format!("**Symbol** `{name}`"));
-        //     markup.append(&mut text);
-
-        //     if !doc_text.is_empty() {
-        //         markup.push("---".to_string());
-        //         markup.push(doc_text.clone());
-        //     }
-
-        //     let markup = markup.into_iter().map(MarkedString::String);
-
-        //     let reply = Hover {
-        //         contents: HoverContents::Array(markup.collect()),
-        //         range: None,
-        //     };
-        //     Some(reply)
-        // } else {
-        //     None
-        // };
-
-        // Ok(reply)
+        let uri = &params.text_document_position.text_document.uri;
+        let position = &params.text_document_position.position;
+
+        let items = with_state(&self.asm_ctx, |asm_ctx| asm_ctx.completions(position, uri));
+
+        Ok(items.map(CompletionResponse::Array))
+    }
+
+    async fn hover(&self, params: HoverParams) -> TResult<Option<Hover>> {
+        info!("hover");
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = &params.text_document_position_params.position;
+
+        // Symbols take precedence as an opcode node covers its operands
+        let markup = with_state(&self.asm_ctx, |asm_ctx| {
+            asm_ctx
+                .symbol_hover(position, uri)
+                .or_else(|| asm_ctx.opcode_hover(position, uri))
+        });
+
+        let reply = markup.map(|markup| Hover {
+            contents: HoverContents::Array(markup.into_iter().map(MarkedString::String).collect()),
+            range: None,
+        });
+
+        Ok(reply)
     }
 }
 
@@ -572,3 +1041,520 @@ fn tabulate(tabs: &[(&str, &str)], min_width: usize) -> Vec<String> {
         .map(|(f, v)| format!("{:width$}{v}", format!("_{f}_:"), width = width))
         .collect::<Vec<_>>()
 }
+
+/// Split a line into the text before the word at its end and the word
+fn split_word(line: &str) -> (&str, &str) {
+    let word_start = line
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == '.'))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+
+    line.split_at(word_start)
+}
+
+/// Is a word after this text in the opcode column?
+/// Either the line is indented or there is a label in the first column
+fn is_opcode_column(before: &str) -> bool {
+    match before.split_whitespace().count() {
+        0 => !before.is_empty(),
+        1 => !before.starts_with(char::is_whitespace) && before.ends_with(char::is_whitespace),
+        _ => false,
+    }
+}
+
+/// Can this be used as a label?
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first_ok = chars.next().map(|c| c.is_alphabetic() || c == '_').unwrap_or(false);
+    first_ok && chars.all(|c| c.is_alphanumeric() || c == '_') && !COMS.contains_key(name)
+}
+
+/// Fuzzy match score of a name against a query, lower is better
+/// The query's characters must all appear in order, the score is how spread out they are
+fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    let mut chars = name.chars().enumerate();
+    let mut score = 0;
+    let mut last = None;
+
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let (i, _) = chars.find(|(_, c)| *c == q)?;
+        score += last.map(|l| i - l - 1).unwrap_or(i);
+        last = Some(i);
+    }
+
+    Some(score)
+}
+
+/// Convert a byte range in the source to an LSP range
+/// LSP columns count UTF-16 code units
+fn text_range(source: &str, range: std::ops::Range<usize>) -> Range {
+    let to_pos = |offset: usize| {
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let col = before[line_start..].encode_utf16().count();
+        Position::new(line as u32, col as u32)
+    };
+
+    Range::new(to_pos(range.start), to_pos(range.end))
+}
+
+/// Byte range of the first use of `name` as a whole word in this span
+fn name_span(
+    source: &str,
+    span: std::ops::Range<usize>,
+    name: &str,
+) -> Option<std::ops::Range<usize>> {
+    let text = source.get(span.clone())?;
+    let is_id = |c: char| c.is_alphanumeric() || c == '_';
+
+    let (start, _) = text.match_indices(name).find(|(i, _)| {
+        let before = text[..*i].chars().next_back();
+        let after = text[i + name.len()..].chars().next();
+        !before.is_some_and(is_id) && !after.is_some_and(is_id)
+    })?;
+
+    let start = span.start + start;
+    Some(start..start + name.len())
+}
+
+/// Convert an LSP position to a byte offset in the source
+fn text_offset(source: &str, p: &Position) -> Option<usize> {
+    let line_start = match p.line as usize {
+        0 => 0,
+        n => source.match_indices('\n').nth(n - 1)?.0 + 1,
+    };
+
+    let line = source[line_start..].split('\n').next().unwrap_or("");
+
+    let col = line
+        .char_indices()
+        .scan(0, |units, (i, c)| {
+            let at = *units;
+            *units += c.len_utf16();
+            Some((i, at))
+        })
+        .find(|(_, at)| *at >= p.character as usize)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+
+    Some(line_start + col)
+}
+
+/// The kind of symbol a node defines
+fn symbol_kind(item: &AstNodeKind) -> Option<SymbolKind> {
+    use AstNodeKind::*;
+
+    let kind = match item {
+        AssignmentFromPc(..) | LocalAssignmentFromPc(..) => SymbolKind::FUNCTION,
+        Assignment(..) => SymbolKind::CONSTANT,
+        LocalAssignment(..) => SymbolKind::VARIABLE,
+        MacroDef(..) => SymbolKind::FUNCTION,
+        StructDef(..) => SymbolKind::STRUCT,
+        StructEntry(..) => SymbolKind::FIELD,
+        _ => return None,
+    };
+
+    Some(kind)
+}
+
+#[allow(deprecated)]
+fn document_symbol<S: Into<String>>(
+    source: &str,
+    name: S,
+    kind: SymbolKind,
+    detail: Option<String>,
+    pos: &GazmPosition,
+) -> DocumentSymbol {
+    let name = name.into();
+    let range = text_range(source, pos.range());
+
+    // Select the name if it's in the item
+    let selection_range = name_span(source, pos.range(), &name)
+        .map(|span| text_range(source, span))
+        .unwrap_or(Range::new(range.start, range.start));
+
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: None,
+    }
+}
+
+#[allow(deprecated)]
+fn symbol_information(
+    name: String,
+    kind: SymbolKind,
+    location: Location,
+    container_name: Option<String>,
+) -> SymbolInformation {
+    SymbolInformation {
+        name,
+        kind,
+        tags: None,
+        deprecated: None,
+        location,
+        container_name,
+    }
+}
+
+fn value_text(x: i64) -> String {
+    format!("{x} 0x{x:04x}")
+}
+
+fn completion_item<S: Into<String>>(
+    label: S,
+    kind: CompletionItemKind,
+    detail: Option<String>,
+) -> CompletionItem {
+    CompletionItem {
+        label: label.into(),
+        kind: Some(kind),
+        detail,
+        ..CompletionItem::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::testing::{assemble_files, assemble_ok};
+
+    /// Where the nth occurrence of `find` starts
+    fn position_of(asm: &Assembler, find: &str, n: usize) -> (Position, Url) {
+        position_in(asm, &asm.opts.project_file, find, n)
+    }
+
+    /// Where the nth occurrence of `find` in this file starts
+    fn position_in(asm: &Assembler, file: &Path, find: &str, n: usize) -> (Position, Url) {
+        let (_, sf) = asm.sources().get_source(file).unwrap();
+        let source = &sf.get_text().source;
+        let start = source.match_indices(find).nth(n).unwrap().0;
+        let uri = Url::from_file_path(file).unwrap();
+        (text_range(source, start..start).start, uri)
+    }
+
+    #[test]
+    fn test_error_diagnostic() {
+        let text = "    fcb 1\n    fcb 2 ; é x\n";
+        let asm = assemble_ok(text, Opts::default());
+
+        let (_, sf) = asm.sources().get_source(&asm.opts.project_file).unwrap();
+        let start = text.find('x').unwrap();
+        let p = sf.get_position(start..start + 1);
+        let pos = GazmPosition::new(p.line(), p.col(), start..start + 1, sf.file_id);
+
+        let si = asm.sources().get_source_info(&pos).unwrap();
+        let err = UserError::from_text("Bad value", &si, true);
+
+        let (file, diag) = error_diagnostic(&asm, &err);
+        assert_eq!(file, si.file);
+        assert_eq!(diag.message, "Bad value");
+        assert_eq!(diag.severity, Some(DiagnosticSeverity::ERROR));
+
+        // Columns count UTF-16 code units, not bytes
+        let range = Range::new(Position::new(1, 14), Position::new(1, 15));
+        assert_eq!(diag.range, range);
+    }
+
+    #[test]
+    fn test_symbol_hover() {
+        let text = "screen equ $400\n    lda screen\n";
+        let asm = assemble_ok(text, Opts::default());
+
+        let (pos, uri) = position_of(&asm, "screen", 1);
+        let hover = asm.symbol_hover(&pos, &uri).unwrap();
+        assert_eq!(hover[0], "**Symbol** `screen`");
+        assert!(hover.iter().any(|l| l.contains("1024 0x0400")), "{hover:?}");
+    }
+
+    #[test]
+    fn test_opcode_hover() {
+        let text = "    org $1000\n    lda #1\n    target z80\n    ld a,b\n";
+        let asm = assemble_ok(text, Opts::default());
+
+        let (pos, uri) = position_of(&asm, "lda", 0);
+        let hover = asm.opcode_hover(&pos, &uri).unwrap();
+        assert_eq!(hover[0], "**Opcode** `lda` (6809)");
+        assert!(hover.iter().any(|l| l.contains("2 cycles")), "{hover:?}");
+
+        let (pos, uri) = position_of(&asm, "ld a", 0);
+        let hover = asm.opcode_hover(&pos, &uri).unwrap();
+        assert_eq!(hover[0], "**Opcode** `ld` (Z80)");
+        assert!(hover.iter().any(|l| l.contains("r,r'") && l.contains("4 cycles")));
+
+        let text = "    org $1000\n    target 65c02\n    stz $10\n    lda #1\n";
+        let asm = assemble_ok(text, Opts::default());
+
+        for op in ["stz", "lda"] {
+            let (pos, uri) = position_of(&asm, op, 0);
+            let hover = asm.opcode_hover(&pos, &uri).unwrap();
+            assert_eq!(hover[0], format!("**Opcode** `{op}` (65C02)"));
+        }
+    }
+
+    #[test]
+    fn test_completions() {
+        let text = "screen equ $400
+struct obj { xpos : byte, ypos : word }
+macro clear(addr) {
+    clr addr
+}
+    org $1000
+    lda screen
+    ldd #obj.ypos
+    clear(screen)
+";
+        let asm = assemble_ok(text, Opts::default());
+
+        let labels = |find: &str, n: usize, skip: u32| {
+            let (mut pos, uri) = position_of(&asm, find, n);
+            pos.character += skip;
+            let items = asm.completions(&pos, &uri).unwrap();
+            items.into_iter().map(|i| i.label).collect::<Vec<_>>()
+        };
+
+        // Symbols in an operand
+        let items = labels("screen", 1, 3);
+        assert!(items.contains(&"screen".to_string()));
+        assert!(!items.contains(&"lda".to_string()));
+
+        // Opcodes, directives and macros in the opcode column
+        let items = labels("lda", 0, 2);
+        for label in ["lda", "fcb", "clear"] {
+            assert!(items.contains(&label.to_string()), "{label} not in {items:?}");
+        }
+
+        // Struct members after a dot
+        let items = labels("ypos", 1, 1);
+        assert_eq!(items, ["xpos", "ypos", "size"]);
+    }
+
+    #[test]
+    fn test_completions_non_ascii() {
+        let text = "screen equ $400\n    org $1000\n    fdb 'é','→',screen\n";
+        let asm = assemble_ok(text, Opts::default());
+
+        // Columns are UTF-16 code units
+        let (mut pos, uri) = position_of(&asm, "screen", 1);
+        assert_eq!(pos.character, 16);
+        pos.character += 3;
+
+        let items = asm.completions(&pos, &uri).unwrap();
+        assert!(items.iter().any(|i| i.label == "screen"));
+
+        assert_eq!(split_word("    fcc \"é→\",scr"), ("    fcc \"é→\",", "scr"));
+        assert_eq!(split_word("→sc"), ("→", "sc"));
+        assert_eq!(split_word("    lda é"), ("    lda ", "é"));
+        assert_eq!(text_offset("é→x\nab", &Position::new(0, 2)), Some(5));
+        assert_eq!(text_offset("é→x\nab", &Position::new(1, 1)), Some(7));
+    }
+
+    #[test]
+    fn test_rename() {
+        let text = "screen equ $400
+other equ 1
+    org $1000
+    fdb 'é',screen ; screen
+    lda screen+1
+";
+        let asm = assemble_ok(text, Opts::default());
+        let (pos, uri) = position_of(&asm, "screen", 1);
+
+        let edit = asm.rename(&pos, &uri, "display").unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+        let edits = &changes[&uri];
+
+        let mut ranges: Vec<_> = edits.iter().map(|e| e.range).collect();
+        ranges.sort_by_key(|r| (r.start.line, r.start.character));
+
+        // Only the name is covered, columns are UTF-16 code units
+        let range = |line, col| Range::new(Position::new(line, col), Position::new(line, col + 6));
+        assert_eq!(ranges, [range(0, 0), range(3, 12), range(4, 8)]);
+        assert!(edits.iter().all(|e| e.new_text == "display"));
+
+        let err = asm.rename(&pos, &uri, "other").unwrap_err();
+        assert_eq!(err, "other is already defined in this scope");
+
+        assert!(asm.rename(&pos, &uri, "2nd").is_err());
+    }
+
+    #[test]
+    fn test_rename_included() {
+        let files = [
+            ("main.gazm", "    include \"inc.gazm\"\n    org $1000\n    lda screen\n"),
+            ("inc.gazm", "screen equ $400\n    fdb screen\n"),
+        ];
+        let (asm, res) = assemble_files(&files, Opts::default());
+        res.unwrap();
+
+        let inc = asm.opts.project_file.with_file_name("inc.gazm");
+        let range = |line, col| Range::new(Position::new(line, col), Position::new(line, col + 6));
+
+        // Prepared from either file, the range is in the file asked about
+        let (screen, main_uri) = position_of(&asm, "screen", 0);
+        let prepared = asm.prepare_rename(&screen, &main_uri).unwrap();
+        let desired = PrepareRenameResponse::RangeWithPlaceholder {
+            range: range(2, 8),
+            placeholder: "screen".to_owned(),
+        };
+        assert_eq!(prepared, desired);
+
+        let (pos, inc_uri) = position_in(&asm, &inc, "screen", 0);
+        let prepared = asm.prepare_rename(&pos, &inc_uri).unwrap();
+        let desired = PrepareRenameResponse::RangeWithPlaceholder {
+            range: range(0, 0),
+            placeholder: "screen".to_owned(),
+        };
+        assert_eq!(prepared, desired);
+
+        // Nothing to rename on an opcode
+        let (pos, uri) = position_of(&asm, "org", 0);
+        assert!(asm.prepare_rename(&pos, &uri).is_none());
+
+        let edit = asm.rename(&screen, &main_uri, "display").unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+
+        let ranges = |uri: &Url| {
+            let mut ranges: Vec<_> = changes[uri].iter().map(|e| e.range).collect();
+            ranges.sort_by_key(|r| (r.start.line, r.start.character));
+            ranges
+        };
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(ranges(&main_uri), [range(2, 8)]);
+        assert_eq!(ranges(&inc_uri), [range(0, 0), range(1, 8)]);
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let text = "screen equ $400
+struct obj { xpos : byte, ypos : word }
+macro clear(addr) {
+    clr addr
+}
+    org $1000
+start
+    lda screen
+    scope gfx
+draw
+    rts
+";
+        let asm = assemble_ok(text, Opts::default());
+        let uri = Url::from_file_path(&asm.opts.project_file).unwrap();
+        let syms = asm.document_symbols(&uri).unwrap();
+
+        let range = |line, col, len| {
+            Range::new(Position::new(line, col), Position::new(line, col + len))
+        };
+
+        // Names are selected, the range covers them
+        let summary = |syms: &[DocumentSymbol]| -> Vec<(String, SymbolKind, Range)> {
+            for s in syms {
+                let (r, sel) = (s.range, s.selection_range);
+                assert!(r.start <= sel.start && sel.end <= r.end, "{}", s.name);
+            }
+            syms.iter().map(|s| (s.name.clone(), s.kind, s.selection_range)).collect()
+        };
+
+        let desired = [
+            ("screen", SymbolKind::CONSTANT, range(0, 0, 6)),
+            ("obj", SymbolKind::STRUCT, range(1, 7, 3)),
+            ("clear", SymbolKind::FUNCTION, range(2, 6, 5)),
+            ("start", SymbolKind::FUNCTION, range(6, 0, 5)),
+        ];
+        let desired = desired.map(|(n, k, r)| (n.to_string(), k, r));
+
+        assert_eq!(syms.len(), 5);
+        assert_eq!(summary(&syms[..4]), desired);
+
+        let fields = summary(syms[1].children.as_ref().unwrap());
+        let desired = [
+            ("xpos".to_string(), SymbolKind::FIELD, range(1, 13, 4)),
+            ("ypos".to_string(), SymbolKind::FIELD, range(1, 26, 4)),
+        ];
+        assert_eq!(fields, desired);
+
+        // Symbols after a scope are nested in it
+        let gfx = &syms[4];
+        assert!(gfx.name.ends_with("gfx"), "{}", gfx.name);
+        assert_eq!(gfx.kind, SymbolKind::NAMESPACE);
+        assert_eq!(gfx.range.start.line, 8);
+
+        let draw = summary(gfx.children.as_ref().unwrap());
+        assert_eq!(draw, [("draw".to_string(), SymbolKind::FUNCTION, range(9, 0, 4))]);
+        assert!(gfx.range.end >= draw[0].2.end);
+    }
+
+    #[test]
+    fn test_is_label_name() {
+        for name in ["screen", "_tmp", "é2", "a_b_1"] {
+            assert!(is_label_name(name), "{name}");
+        }
+
+        for name in ["", "2nd", "a-b", "a.b", "org", "fcb"] {
+            assert!(!is_label_name(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "screen"), Some(0));
+        assert_eq!(fuzzy_score("scr", "screen"), Some(0));
+        assert_eq!(fuzzy_score("SCR", "Screen"), Some(0));
+        assert_eq!(fuzzy_score("sn", "screen"), Some(4));
+        assert_eq!(fuzzy_score("s r", "screen"), Some(1));
+        assert_eq!(fuzzy_score("ée", "éclair_e"), Some(6));
+        assert_eq!(fuzzy_score("rcs", "screen"), None);
+        assert_eq!(fuzzy_score("screens", "screen"), None);
+    }
+
+    #[test]
+    fn test_workspace_symbols() {
+        let text = "screen equ $400
+struct obj { xpos : byte, ypos : word }
+macro clear(addr) {
+    clr addr
+}
+    org $1000
+start
+    lda screen
+    clear(screen)
+";
+        let asm = assemble_ok(text, Opts::default());
+        let uri = Url::from_file_path(&asm.opts.project_file).unwrap();
+
+        let found = |query: &str| {
+            let syms = asm.workspace_symbols(query).unwrap();
+            syms.into_iter().map(|si| (si.name, si.kind)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(found("scr"), [("screen".to_string(), SymbolKind::CONSTANT)]);
+        assert_eq!(found("strt"), [("start".to_string(), SymbolKind::FUNCTION)]);
+        assert_eq!(found("clr"), [("clear".to_string(), SymbolKind::FUNCTION)]);
+
+        // Best matches first
+        let objs = found("obj");
+        assert_eq!(objs.len(), 3);
+        assert_eq!(objs[0], ("obj".to_string(), SymbolKind::STRUCT));
+
+        let fields = found("pos");
+        let desired = [("obj.xpos", SymbolKind::FIELD), ("obj.ypos", SymbolKind::FIELD)];
+        assert_eq!(fields, desired.map(|(n, k)| (n.to_string(), k)));
+
+        assert!(found("nothing").is_empty());
+
+        // Locations cover the name
+        let screen = &asm.workspace_symbols("screen").unwrap()[0];
+        let range = Range::new(Position::new(0, 0), Position::new(0, 6));
+        assert_eq!(screen.location, Location::new(uri, range));
+    }
+}
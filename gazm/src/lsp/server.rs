@@ -1,5 +1,9 @@
 use super::backend::Backend;
-use crate::opts::Opts;
+use crate::{
+    error::{GResult, GazmErrorKind},
+    messages::{messages, Verbosity},
+    opts::Opts,
+};
 use log::info;
 use log::LevelFilter;
 use serde::Deserialize;
@@ -14,28 +18,32 @@ pub struct LspConfig {
     pub log_file: Option<PathBuf>,
 }
 
-pub fn do_lsp(_opts: &Opts) -> Result<(), Box<dyn std::error::Error>> {
-    // TODO: LSP have the logger log to the file in the lsp_config opt
-    todo!()
-    // if let Some(log_path) = &opts.lsp_config.log_file {
-    //     simple_logging::log_to_file(log_path, LevelFilter::Info).unwrap();
-    // }
+/// Run the language server over stdio until the client shuts it down
+pub fn do_lsp(opts: &Opts) -> GResult<()> {
+    // Stdout is the lsp channel so nothing else can write to it
+    messages().set_verbosity(&Verbosity::Silent);
 
-    // info!("**Starting up gazm lsp");
+    if let Some(log_path) = &opts.lsp_config.log_file {
+        simple_logging::log_to_file(log_path, LevelFilter::Info).map_err(|e| {
+            GazmErrorKind::Misc(format!("Can't log to {}: {e}", log_path.to_string_lossy()))
+        })?;
+    }
 
-    // let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
-    // let (service, socket) = LspService::new(|client| Backend::new(client, opts.clone()));
+    info!("**Starting up gazm lsp");
 
-    // tokio::runtime::Builder::new_multi_thread()
-    //     .enable_all()
-    //     .build()
-    //     .unwrap()
-    //     .block_on(async {
-    //         info!("**About to create server");
-    //         let server = Server::new(stdin, stdout, socket);
-    //         server.serve(service).await;
-    //         info!("**About to exit");
-    //     });
+    let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    let (service, socket) = LspService::new(|client| Backend::new(client, opts.clone()));
 
-    // Ok(())
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| GazmErrorKind::Misc(format!("Can't start lsp runtime: {e}")))?
+        .block_on(async {
+            info!("**About to create server");
+            let server = Server::new(stdin, stdout, socket);
+            server.serve(service).await;
+            info!("**About to exit");
+        });
+
+    Ok(())
 }
@@ -1,16 +1,19 @@
 use gazm::{
     assembler::{Assembler, },
     cli::{parse_command_line, styling::get_banner},
-    error::{ErrorCollectorTrait, GazmErrorKind},
-    frontend, info_mess, messages,
-    opts::{BuildType, Opts},
+    error::{ErrorCollectorTrait, GazmErrorKind, UserWarning},
+    diagnostics, fmt, frontend, info_mess, lsp, messages,
+    opts::{BuildType, MessageFormat, Opts},
     status_mess,
 };
 
-fn do_build(opts: &Opts) -> Result<(), GazmErrorKind>
+/// Build as the options say, any warnings to report are added to `warnings`
+fn do_build(opts: &Opts, warnings: &mut Vec<UserWarning>) -> Result<(), GazmErrorKind>
 {
     let mess = messages::messages();
     mess.set_verbosity(&opts.verbose);
+    // Keep stdout for the errors and warnings
+    mess.set_stderr(opts.message_format != MessageFormat::Human);
 
     if let Some(assemble_dir) = &opts.assemble_dir {
         std::env::set_current_dir(assemble_dir).expect("Can't change dir")
@@ -26,14 +29,17 @@ fn do_build(opts: &Opts) -> Result<(), GazmErrorKind>
         }
 
         BuildType::Format => {
-            status_mess!("Format file");
-            todo!()
+            status_mess!("Format file {}", opts.project_file.to_string_lossy());
+            let unchanged = fmt::fmt_file(opts)?;
+
+            if opts.fmt_check && !unchanged {
+                let file = opts.project_file.to_string_lossy().to_string();
+                return Err(GazmErrorKind::NeedsFormatting(file));
+            }
         }
 
         BuildType::Lsp => {
-            status_mess!("LSP");
-            todo!()
-            // lsp::do_lsp(opts)?;
+            lsp::do_lsp(opts)?;
         }
 
         // Build of check to see if build is okay
@@ -47,7 +53,14 @@ fn do_build(opts: &Opts) -> Result<(), GazmErrorKind>
                 status_mess!("Async: NO ASYNC");
             }
 
-            asm.assemble()?;
+            let res = asm.assemble();
+
+            // Reported as errors instead if warnings are errors
+            if !opts.warnings_as_errors {
+                warnings.extend(asm.asm_out.errors.warnings.iter().cloned());
+            }
+
+            res?;
 
             // Only write outputs if this is of buildtype Build
             if opts.build_type == BuildType::Build {
@@ -62,26 +75,17 @@ fn do_build(opts: &Opts) -> Result<(), GazmErrorKind>
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    use std::env::{current_dir, set_current_dir};
-    let matches = parse_command_line();
-
-    let opts = Opts::from_arg_matches(matches)?;
-
-    // Todo move directory handling into assemble_from_opts
-    // probably as a function of Opts
-    let cur_dir = current_dir().unwrap();
-
-    use gazm::cpukind::CpuKind::*;
+/// Print a build's warnings and errors in the format asked for
+fn report(opts: &Opts, ret: &Result<(), GazmErrorKind>, warnings: &[UserWarning]) {
+    if opts.message_format != MessageFormat::Human {
+        let diags = diagnostics::collect(ret, warnings);
+        print!("{}", diagnostics::render(opts.message_format, &diags));
+        return;
+    }
 
-    let ret = match opts.cpu {
-        Cpu6809 => do_build(&opts),
-        Cpu6800 => do_build(&opts),
-        Cpu6502 | Cpu65c02 | CpuZ80 => Err(GazmErrorKind::NotImplemented(format!(
-            "Assembly for{:?}",
-            opts.cpu
-        ))),
-    };
+    for w in warnings {
+        w.print_pretty()
+    }
 
     match ret {
         Err(GazmErrorKind::UserErrors(user_errors)) => {
@@ -96,9 +100,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         Ok(..) => {}
     };
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    use std::env::{current_dir, set_current_dir};
+    let matches = parse_command_line();
+
+    let opts = Opts::from_arg_matches(matches)?;
+
+    // Todo move directory handling into assemble_from_opts
+    // probably as a function of Opts
+    let cur_dir = current_dir().unwrap();
+
+    use gazm::cpukind::CpuKind::*;
+
+    let mut warnings = vec![];
+
+    let ret = match opts.cpu {
+        Cpu6809 => do_build(&opts, &mut warnings),
+        Cpu6800 => do_build(&opts, &mut warnings),
+        Cpu6502 => do_build(&opts, &mut warnings),
+        Cpu65c02 => do_build(&opts, &mut warnings),
+        CpuZ80 => do_build(&opts, &mut warnings),
+        Cpu6309 => do_build(&opts, &mut warnings),
+    };
+
+    report(&opts, &ret, &warnings);
 
     set_current_dir(cur_dir)?;
 
+    if ret.is_err() {
+        std::process::exit(1);
+    }
+
     Ok(())
 }
 
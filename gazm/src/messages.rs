@@ -36,6 +36,8 @@ impl<'a> Messageize for &'a str {
 pub struct Messages {
     indent: usize,
     verbosity: Verbosity,
+    /// Print to stderr, keeps stdout for machine readable output
+    stderr: bool,
 }
 
 impl Default for Messages {
@@ -43,6 +45,7 @@ impl Default for Messages {
         Self {
             indent: 0,
             verbosity: Verbosity::Normal,
+            stderr: false,
         }
     }
 }
@@ -61,6 +64,18 @@ impl Messages {
         self.verbosity
     }
 
+    pub fn set_stderr(&mut self, stderr: bool) {
+        self.stderr = stderr;
+    }
+
+    fn print(&self, m: ColoredString) {
+        if self.stderr {
+            eprintln!("{}{m}", self.get_indent_str());
+        } else {
+            println!("{}{m}", self.get_indent_str());
+        }
+    }
+
     pub fn indent(&mut self) {
         self.indent += 1;
     }
@@ -84,7 +99,7 @@ impl Messages {
         S: Into<String>,
     {
         if self.verbosity >= Verbosity::Info {
-            println!("{}{}", self.get_indent_str(), m.into().blue());
+            self.print(m.into().blue());
         }
     }
 
@@ -93,7 +108,7 @@ impl Messages {
         S: Into<String>,
     {
         if self.verbosity >= Verbosity::Normal {
-            println!("{}{}", self.get_indent_str(), m.into().bright_magenta());
+            self.print(m.into().bright_magenta());
         }
     }
 
@@ -101,21 +116,21 @@ impl Messages {
     where
         S: Into<String>,
     {
-        println!("{}{}", self.get_indent_str(), m.into().red().bold());
+        self.print(m.into().red().bold());
     }
 
     pub fn warning<S>(&self, m: S)
     where
         S: Into<String>,
     {
-        println!("{}{}", self.get_indent_str(), m.into().yellow().bold());
+        self.print(m.into().yellow().bold());
     }
 
     pub fn success<S>(&self, m: S)
     where
         S: Into<String>,
     {
-        println!("{}{}", self.get_indent_str(), m.into().bold().green());
+        self.print(m.into().bold().green());
     }
 
     pub fn intertesting<S>(&self, m: S)
@@ -123,11 +138,7 @@ impl Messages {
         S: Into<String>,
     {
         if self.verbosity >= Verbosity::Interesting {
-            println!(
-                "{}{}",
-                self.get_indent_str(),
-                m.into().italic().bold().purple()
-            );
+            self.print(m.into().italic().bold().purple());
         }
     }
     pub fn debug<S>(&self, m: S)
@@ -135,7 +146,7 @@ impl Messages {
         S: Into<String>,
     {
         if self.verbosity >= Verbosity::Debug {
-            println!("{}{}", self.get_indent_str(), m.into().italic().yellow());
+            self.print(m.into().italic().yellow());
         }
     }
 }
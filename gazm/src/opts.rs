@@ -1,7 +1,14 @@
 #![forbid(unused_imports)]
 use std::{collections::HashMap, path::{ PathBuf,Path }};
 
-use crate::{lsp::LspConfig, messages::Verbosity, vars::{ Vars, VarsErrorKind }, error::GResult};
+use crate::{
+    assembler::SRecKind,
+    error::{GResult, WarningKind},
+    lsp::LspConfig,
+    messages::Verbosity,
+    sections::SerializedSection,
+    vars::{Vars, VarsErrorKind},
+};
 use serde::Deserialize;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -13,12 +20,61 @@ pub enum BuildType {
     Test,
 }
 
+/// How errors and warnings are reported
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MessageFormat {
+    /// Coloured text for people
+    #[default]
+    Human,
+    /// A JSON object per line
+    Json,
+    /// A single SARIF 2.1.0 log
+    Sarif,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct BinReference {
     pub file: PathBuf,
     pub addr: usize,
 }
 
+/// Which bytes of a 16 bit bus an image holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interleave {
+    Even,
+    Odd,
+}
+
+/// A ROM image built from the assembled binary, an `[[output]]` table in `gazm.toml`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutputImage {
+    pub file: PathBuf,
+    /// Physical address of the first byte
+    pub addr: usize,
+    pub size: usize,
+    /// Used for memory that wasn't written to and padding
+    #[serde(default = "OutputImage::default_fill")]
+    pub fill: u8,
+    /// Pad the image with the fill byte up to this size
+    pub pad_to: Option<usize>,
+    /// Swap the bytes of each 16 bit word
+    #[serde(default)]
+    pub byte_swap: bool,
+    /// Only keep the even or odd bytes, for boards with a ROM per byte lane
+    pub interleave: Option<Interleave>,
+}
+
+impl OutputImage {
+    /// Blank EPROM
+    fn default_fill() -> u8 {
+        0xff
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct CheckSum {
     pub addr: usize,
@@ -41,8 +97,21 @@ pub struct Opts {
     pub as6809_sym: Option<PathBuf>,
     pub deps_file: Option<PathBuf>,
     pub ast_file: Option<PathBuf>,
+    pub lst_file: Option<PathBuf>,
+    /// Intel HEX image of everything written
+    pub hex_file: Option<PathBuf>,
+    /// Motorola S-record image of everything written
+    pub srec_file: Option<PathBuf>,
+    /// Narrowest kind that fits if not set
+    pub srec_kind: Option<SRecKind>,
+    /// Yaml list of memory sections
+    pub sections_file: Option<PathBuf>,
+    /// A binary per bank, `{}` is replaced with the bank number
+    pub bank_file: Option<PathBuf>,
 
     pub ignore_relative_offset_errors: bool,
+    /// Promote short branches that can't reach their target to long branches
+    pub relax_branches: bool,
     pub mem_size: usize,
     pub max_errors: usize,
     pub no_async: bool,
@@ -53,24 +122,51 @@ pub struct Opts {
 
     pub error_mismatches: bool,
 
+    /// Warnings turned on or off by name, anything not listed uses its default
+    pub warnings: HashMap<WarningKind, bool>,
+    pub warnings_as_errors: bool,
+    pub message_format: MessageFormat,
+
     #[serde(skip)]
     pub do_includes: bool,
 
     #[serde(skip)]
     pub checksums: HashMap<String, CheckSum>,
 
+    #[serde(skip)]
+    pub outputs: Vec<OutputImage>,
+
+    /// `[[section]]` tables in `gazm.toml`
+    #[serde(skip)]
+    pub sections: Vec<SerializedSection>,
+
     #[serde(skip)]
     pub vars: Vars,
 
+    /// `--set` definitions, the numeric ones are symbols as well as vars
+    #[serde(skip)]
+    pub defines: Vars,
+
     #[serde(skip)]
     pub build_type: BuildType,
 
     #[serde(skip)]
     pub lsp_config: LspConfig,
+
+    /// `gazm fmt --check`, report unformatted files without changing them
+    #[serde(skip)]
+    pub fmt_check: bool,
 }
 
 
 impl Opts {
+    pub fn is_warning_enabled(&self, kind: WarningKind) -> bool {
+        self.warnings
+            .get(&kind)
+            .copied()
+            .unwrap_or_else(|| kind.default_enabled())
+    }
+
     pub fn update_vars(&mut self) {
         self.vars.set_var("PROJECT_FILE", &self.project_file.to_string_lossy());
         self.vars.set_var("MEM_SIZE", &format!("{}", self.mem_size));
@@ -95,18 +191,32 @@ impl Default for Opts {
             assemble_dir: Default::default(),
             source_mapping: Default::default(),
             ignore_relative_offset_errors: false,
+            relax_branches: false,
             as6809_sym: Default::default(),
             deps_file: Default::default(),
             project_file: Default::default(),
             ast_file: Default::default(),
+            lst_file: Default::default(),
+            hex_file: Default::default(),
+            srec_file: Default::default(),
+            srec_kind: Default::default(),
+            sections_file: Default::default(),
+            bank_file: Default::default(),
             vars: Default::default(),
+            defines: Default::default(),
             checksums: Default::default(),
+            outputs: Default::default(),
+            sections: Default::default(),
             bin_references: Default::default(),
             lsp_config: Default::default(),
+            fmt_check: false,
             no_async: false,
             syms_file: Default::default(),
             verbose_errors: false,
             error_mismatches: false,
+            warnings: Default::default(),
+            warnings_as_errors: false,
+            message_format: Default::default(),
 
             do_includes: true,
             build_type: BuildType::Build,
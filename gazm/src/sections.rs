@@ -1,7 +1,14 @@
 #![forbid(unused_imports)]
-use crate::assembler::*;
+//! Named memory sections code and data are placed in with `section NAME`
+//!
+//! Sections come from a yaml file named by `sections-file` and `[[section]]` tables
+//! in `gazm.toml`. Each has a logical range the cpu sees and a physical range in
+//! the binary, which differ for banked memory. Sections can share logical memory
+//! but not physical memory, so banks can be mapped into the same window. A section
+//! given a `cpu` switches to that cpu for the code placed in it.
+use crate::{assembler::*, cpukind::CpuKind, opts::Opts};
 
-use std::{collections::HashMap, ops::Deref, ops::DerefMut};
+use std::{collections::HashMap, path::Path};
 
 use serde::{Deserialize, Serialize};
 
@@ -9,29 +16,30 @@ fn default_access_type() -> AccessType {
     AccessType::ReadWrite
 }
 
+/// A section as written in config
+/// `start` is the physical address, `org` is the logical address if it's different
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
-struct SerializedSection {
-    name: String,
-    start: usize,
-    size: usize,
-    org: Option<usize>,
+#[serde(deny_unknown_fields)]
+pub struct SerializedSection {
+    pub name: String,
+    pub start: usize,
+    pub size: usize,
+    pub org: Option<usize>,
     #[serde(default = "default_access_type")]
-    access: AccessType,
-}
-
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
-struct SectionToml {
-    addr: usize,
-    len : usize,
+    pub access: AccessType,
+    /// Bank number for banked memory
+    pub bank: Option<usize>,
+    /// Cpu code in this section is assembled for
+    pub cpu: Option<CpuKind>,
 }
 
 impl From<SerializedSection> for SectionDescriptor {
     fn from(x: SerializedSection) -> Self {
-        let logical_range = x.start..x.start + x.size;
-        let physical_range = if let Some(org) = x.org {
+        let physical_range = x.start..x.start + x.size;
+        let logical_range = if let Some(org) = x.org {
             org..org + x.size
         } else {
-            logical_range.clone()
+            physical_range.clone()
         };
 
         SectionDescriptor {
@@ -39,61 +47,20 @@ impl From<SerializedSection> for SectionDescriptor {
             logical_range,
             physical_range,
             access_type: x.access,
+            bank: x.bank,
+            cpu: x.cpu,
         }
     }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct SectionDescriptor {
-    name: String,
-    logical_range: std::ops::Range<usize>,
-    physical_range: std::ops::Range<usize>,
-    access_type: AccessType,
-}
-
-struct Section {
-    descriptor: SectionDescriptor,
-    binary: Binary,
-}
-
-impl From<SectionDescriptor> for Section {
-    fn from(descriptor: SectionDescriptor) -> Self {
-        Self {
-            binary: make_binary(&descriptor),
-            descriptor,
-        }
-    }
-}
-
-pub fn make_binary(desc: &SectionDescriptor) -> Binary {
-    let p_start = desc.physical_range.start as isize;
-    let l_start = desc.logical_range.start as isize;
-    let offset = p_start - l_start;
-    let mut ret = Binary::new(desc.logical_range.len(), desc.access_type.clone());
-    ret.set_write_offset(offset);
-    ret
-}
-
-#[allow(dead_code)]
-impl Section {
-    fn new(
-        name: &str,
-        logical_range: std::ops::Range<usize>,
-        physical_base: usize,
-        access_type: AccessType,
-    ) -> Self {
-        let desc = SectionDescriptor::new(name, logical_range, physical_base, access_type);
-        desc.into()
-    }
-
-    /// Does this section overlap with other
-    pub fn overlaps(&self, other: &Self) -> bool {
-        let ostart = other.descriptor.logical_range.start;
-        let oend = other.descriptor.logical_range.end;
-        let olast = (ostart + oend) - 1;
-        self.descriptor.logical_range.contains(&ostart)
-            || self.descriptor.logical_range.contains(&olast)
-    }
+    pub name: String,
+    pub logical_range: std::ops::Range<usize>,
+    pub physical_range: std::ops::Range<usize>,
+    pub access_type: AccessType,
+    pub bank: Option<usize>,
+    pub cpu: Option<CpuKind>,
 }
 
 impl SectionDescriptor {
@@ -111,143 +78,181 @@ impl SectionDescriptor {
             logical_range,
             physical_range,
             access_type,
+            bank: None,
+            cpu: None,
         }
     }
-}
 
-#[derive(Default)]
-struct Sections {
-    current_section: Option<usize>,
-    sections: Vec<Section>,
-    name_to_section: HashMap<String, usize>,
-}
+    /// Does this section share any physical memory with other
+    /// Only physical ranges are checked, any sections can share logical memory
+    pub fn overlaps(&self, other: &Self) -> bool {
+        let (a, b) = (&self.physical_range, &other.physical_range);
+        a.start < b.end && b.start < a.end
+    }
 
-impl Deref for Sections {
-    type Target = Binary;
-    fn deref(&self) -> &Self::Target {
-        self.get_current_section_binary().unwrap()
+    /// Offset to add to a logical address to get its physical address
+    pub fn write_offset(&self) -> isize {
+        self.physical_range.start as isize - self.logical_range.start as isize
     }
 }
 
-impl DerefMut for Sections {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.get_current_section_binary_mut().unwrap()
-    }
+#[derive(Default, Debug, Clone)]
+pub struct Sections {
+    sections: Vec<SectionDescriptor>,
+    name_to_section: HashMap<String, usize>,
 }
 
 use thiserror::Error;
 
-#[derive(Error, Debug)]
-#[allow(dead_code)]
+#[derive(Error, Debug, Clone)]
 pub enum SectionError {
     #[error("Sections cannot be zero in size")]
     ZeroSizedSection,
-    #[error("No section selected as current")]
-    NoCurrentSection,
-    #[error("Org {org:04X} does not fit in current section")]
+    #[error("Org ${org:04X} does not fit in section {name}")]
     OrgOutsideSectionBounds {
+        name: String,
         bounds: std::ops::Range<usize>,
         org: usize,
     },
-    #[error("Section name already in use")]
+    #[error("Section {} overflows by {1} bytes", .0.name)]
+    Overflow(SectionDescriptor, usize),
+    #[error("Section name {} already in use", .0.name)]
     SectionNameInUse(SectionDescriptor),
-    #[error("Overlaps with section {0:?}")]
-    OverlapsWithExistingSection(SectionDescriptor),
+    #[error("Section {} overlaps with section {}", .0.name, .1.name)]
+    OverlapsWithExistingSection(SectionDescriptor, SectionDescriptor),
+    #[error("Section {} is outside of memory", .0.name)]
+    OutsideOfMemory(SectionDescriptor),
     #[error("Can't find a section with name {0}")]
     UknownSectionName(String),
     #[error("Error loading section file {0}")]
     ErrorLoadingSectionFile(String),
 }
 
-
-#[allow(dead_code)]
 impl Sections {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn from_file(file: &str) -> Result<Sections, SectionError> {
+    pub fn from_file<P: AsRef<Path>>(file: P) -> Result<Sections, SectionError> {
         use std::fs::read_to_string;
 
-        let x = read_to_string(file)
-            .map_err(|_| SectionError::ErrorLoadingSectionFile(file.to_string()))?;
+        let file = file.as_ref();
+        let err = || SectionError::ErrorLoadingSectionFile(file.to_string_lossy().into());
 
-        let s: Vec<SerializedSection> = serde_yaml::from_str(&x)
-            .map_err(|_| SectionError::ErrorLoadingSectionFile(file.to_string()))?;
+        let x = read_to_string(file).map_err(|_| err())?;
+        let s: Vec<SerializedSection> = serde_yaml::from_str(&x).map_err(|_| err())?;
 
-        let s2: Vec<SectionDescriptor> = s.into_iter().map(Into::into).collect();
         let mut ret = Self::new();
-        for s in s2 {
-            ret.add_section_descriptor(s).unwrap();
+
+        for s in s {
+            ret.add_section_descriptor(s.into())?;
+        }
+
+        Ok(ret)
+    }
+
+    /// Sections from `sections-file` followed by any `[[section]]` tables
+    /// Every section has to fit in `mem-size`
+    pub fn from_opts(opts: &Opts) -> Result<Sections, SectionError> {
+        let mut ret = match &opts.sections_file {
+            Some(file) => Self::from_file(file)?,
+            None => Self::new(),
+        };
+
+        for s in &opts.sections {
+            ret.add_section_descriptor(s.clone().into())?;
         }
+
+        for s in &ret.sections {
+            if s.physical_range.end > opts.mem_size {
+                return Err(SectionError::OutsideOfMemory(s.clone()));
+            }
+        }
+
         Ok(ret)
     }
 
+    /// End of the highest physical range
+    pub fn physical_end(&self) -> usize {
+        self.sections.iter().map(|s| s.physical_range.end).max().unwrap_or(0)
+    }
+
+    /// Physical ranges of a bank's sections, lowest first
+    /// Sections from other banks in between aren't included
+    pub fn bank_ranges(&self, bank: usize) -> Vec<std::ops::Range<usize>> {
+        let mut ret: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|s| s.bank == Some(bank))
+            .map(|s| s.physical_range.clone())
+            .collect();
+        ret.sort_by_key(|r| r.start);
+        ret
+    }
+
+    /// Cpus sections are configured with, by section name
+    pub fn cpus(&self) -> HashMap<String, CpuKind> {
+        self.sections
+            .iter()
+            .filter_map(|s| Some((s.name.clone(), s.cpu?)))
+            .collect()
+    }
+
+    /// Bank numbers in use, in order
+    pub fn banks(&self) -> Vec<usize> {
+        let mut ret: Vec<_> = self.sections.iter().filter_map(|s| s.bank).collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
     fn add_section_descriptor(
         &mut self,
         section: SectionDescriptor,
     ) -> Result<usize, SectionError> {
-        let name = section.name.clone();
-        let section = section.into();
+        if section.logical_range.is_empty() {
+            return Err(SectionError::ZeroSizedSection);
+        }
+
+        if let Some(s) = self.get_section(&section.name) {
+            return Err(SectionError::SectionNameInUse(s.clone()));
+        }
 
         for s in &self.sections {
             if s.overlaps(&section) {
-                return Err(SectionError::OverlapsWithExistingSection(
-                    s.descriptor.clone(),
-                ));
+                return Err(SectionError::OverlapsWithExistingSection(section, s.clone()));
             }
         }
 
         let id = self.sections.len();
 
+        self.name_to_section.insert(section.name.clone(), id);
         self.sections.push(section);
-        self.name_to_section.insert(name, id);
 
         Ok(id)
     }
 
-    pub fn set_current_section(&mut self, name: &str) -> Result<(), SectionError> {
-        let x = self
-            .name_to_section
-            .get(name)
-            .ok_or_else(|| SectionError::UknownSectionName(name.to_string()))?;
-        self.current_section = Some(*x);
-        Ok(())
-    }
-    /// Get the current section
-    pub fn get_current_section(&self) -> Result<&Section, SectionError> {
-        self.current_section
-            .map(|idx| &self.sections[idx])
-            .ok_or(SectionError::NoCurrentSection)
-    }
-
-    /// Get a mutable version of the current section
-    pub fn get_current_section_mut(&mut self) -> Result<&mut Section, SectionError> {
-        self.current_section
-            .map(|idx| &mut self.sections[idx])
-            .ok_or(SectionError::NoCurrentSection)
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
     }
 
-    /// Get the current section's binary
-    pub fn get_current_section_binary(&self) -> Result<&Binary, SectionError> {
-        self.get_current_section().map(|s| &s.binary)
+    pub fn get_section_id(&self, name: &str) -> Result<usize, SectionError> {
+        self.name_to_section
+            .get(name)
+            .copied()
+            .ok_or_else(|| SectionError::UknownSectionName(name.to_string()))
     }
 
-    /// Get a mutable version of the current section's binary chunk
-    pub fn get_current_section_binary_mut(&mut self) -> Result<&mut Binary, SectionError> {
-        self.get_current_section_mut().map(|s| &mut s.binary)
+    pub fn get_section(&self, name: &str) -> Option<&SectionDescriptor> {
+        self.name_to_section.get(name).map(|id| &self.sections[*id])
     }
 
-    /// org to a logical address within the current section
-    pub fn logical_org(&mut self, addr: usize) -> Result<(), SectionError> {
-        let b = self.get_current_section_binary_mut()?;
-        let offset = b.get_write_offset();
-        b.set_write_address(addr, offset);
-        Ok(())
+    pub fn get(&self, id: usize) -> &SectionDescriptor {
+        &self.sections[id]
     }
 
-    fn get_section(&self, name: &str) -> Option<&Section> {
-        self.name_to_section.get(name).map(|id| &self.sections[*id])
+    pub fn iter(&self) -> impl Iterator<Item = &SectionDescriptor> {
+        self.sections.iter()
     }
 
     /// Add a section
@@ -258,33 +263,159 @@ impl Sections {
         physical_base: usize,
         access_type: AccessType,
     ) -> Result<usize, SectionError> {
-        // Check to make sure the size is > 0
         if logical_range.is_empty() {
             return Err(SectionError::ZeroSizedSection);
         }
 
-        // check for section already existing
-        if let Some(s) = self.get_section(name) {
-            return Err(SectionError::SectionNameInUse(s.descriptor.clone()));
-        }
-
-        // check for overlaps
         let section = SectionDescriptor::new(name, logical_range, physical_base, access_type);
-        let section = section.into();
+        self.add_section_descriptor(section)
+    }
+}
 
-        for s in &self.sections {
-            if s.overlaps(&section) {
-                return Err(SectionError::OverlapsWithExistingSection(
-                    s.descriptor.clone(),
-                ));
-            }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        use AccessType::*;
+
+        let mut sections = Sections::new();
+        sections.add_section("ram", 0xa000..0xb000, 0xa000, ReadWrite).unwrap();
+        sections.add_section("io", 0xc000..0xd000, 0xc000, ReadOnly).unwrap();
+
+        // A bank shares logical memory but not physical
+        let id = sections.add_section("bank", 0xc000..0xc800, 0x18000, ReadWrite).unwrap();
+        assert_eq!(sections.get(id).write_offset(), 0x18000 - 0xc000);
+
+        let res = sections.add_section("clash", 0xa800..0xa900, 0xa800, ReadWrite);
+        assert!(matches!(res, Err(SectionError::OverlapsWithExistingSection(..))));
+
+        let res = sections.add_section("ram", 0x100..0x200, 0x100, ReadWrite);
+        assert!(matches!(res, Err(SectionError::SectionNameInUse(..))));
+
+        assert_eq!(sections.physical_end(), 0x18800);
+        assert!(sections.banks().is_empty());
+    }
+
+    fn section(name: &str, start: usize, size: usize) -> SerializedSection {
+        SerializedSection {
+            name: name.into(),
+            start,
+            size,
+            org: None,
+            access: AccessType::ReadWrite,
+            bank: None,
+            cpu: None,
         }
+    }
 
-        let id = self.sections.len();
+    fn opts_with(sections: Vec<SerializedSection>) -> Opts {
+        Opts {
+            sections,
+            ..Opts::default()
+        }
+    }
 
-        self.sections.push(section);
-        self.name_to_section.insert(name.to_string(), id);
+    #[test]
+    fn test_section_directive() {
+        use crate::assembler::testing::{assemble_ok, bytes, symbol};
+
+        let opts = opts_with(vec![section("code", 0x1000, 0x100), section("data", 0x2000, 0x10)]);
+
+        // Each section carries on from where it left off
+        let text = "
+    section code
+    lda #1
+    section data
+table fcb 2
+    section code
+    rts
+    section data
+    fcb 3
+";
+        let asm = assemble_ok(text, opts);
+        assert_eq!(bytes(&asm, 0x1000, 3), [0x86, 0x01, 0x39]);
+        assert_eq!(bytes(&asm, 0x2000, 2), [0x02, 0x03]);
+        assert_eq!(symbol(&asm, "table"), Some(0x2000));
+    }
 
-        Ok(id)
+    #[test]
+    fn test_section_errors() {
+        use crate::assembler::testing::assemble_err;
+
+        let opts = || opts_with(vec![section("tiny", 0x1000, 2)]);
+
+        let errs = assemble_err("    section tiny\n    lda #1\n    rts\n", opts());
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("Section tiny overflows by 1 bytes"), "{errs:?}");
+
+        let errs = assemble_err("    section tiny\n    org $2000\n", opts());
+        assert!(errs[0].contains("Org $2000 does not fit in section tiny"), "{errs:?}");
+
+        let errs = assemble_err("    section missing\n", opts());
+        assert!(errs[0].contains("missing"), "{errs:?}");
+    }
+
+    #[test]
+    fn test_read_only() {
+        use crate::assembler::testing::assemble_err;
+
+        let rom = SerializedSection {
+            access: AccessType::ReadOnly,
+            ..section("rom", 0x1000, 0x10)
+        };
+        let opts = opts_with(vec![section("ram", 0x2000, 0x10), rom]);
+
+        // Reserving space in a read only section is fine, writing to it isn't
+        let text = "
+    section ram
+    fcb 1
+    section rom
+    rmb 2
+    fcb 2
+";
+        let errs = assemble_err(text, opts);
+        let msg = "Write to read only memory: physical: $01002 logical: $1002";
+        assert_eq!(errs, [msg]);
+    }
+
+    #[test]
+    fn test_outside_of_memory() {
+        let mut opts = opts_with(vec![section("bank", 0x18000, 0x4000)]);
+
+        let res = Sections::from_opts(&opts);
+        assert!(matches!(res, Err(SectionError::OutsideOfMemory(..))));
+
+        opts.mem_size = 0x20000;
+        assert!(Sections::from_opts(&opts).is_ok());
+    }
+
+    #[test]
+    fn test_banks() {
+        let banked = |name: &str, start: usize, bank: usize| SerializedSection {
+            org: Some(0x8000),
+            bank: Some(bank),
+            ..section(name, start, 0x100)
+        };
+
+        let mut sections = Sections::new();
+
+        for s in [
+            section("main", 0x1000, 0x100),
+            banked("a", 0x10000, 2),
+            banked("b", 0x10100, 1),
+            banked("c", 0x10300, 2),
+        ] {
+            sections.add_section_descriptor(s.into()).unwrap();
+        }
+
+        assert_eq!(sections.banks(), [1, 2]);
+        assert_eq!(sections.bank_ranges(1), [0x10100..0x10200]);
+
+        // Bank 1's section between them is left out
+        assert_eq!(sections.bank_ranges(2), [0x10000..0x10100, 0x10300..0x10400]);
+        assert!(sections.bank_ranges(3).is_empty());
     }
 }
+
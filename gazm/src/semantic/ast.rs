@@ -138,6 +138,19 @@ impl Ast
         self.as_mut().orphan(ItemWithPos { item, pos }).id()
     }
 
+    /// Copy a node and everything under it to a new orphan
+    pub fn clone_subtree(&mut self, id: AstNodeId) -> AstNodeId {
+        let value = self.as_ref().get(id).unwrap().value().clone();
+        let new_id = self.as_mut().orphan(value).id();
+
+        for kid_id in self.get_kids_ids(id) {
+            let kid_copy = self.clone_subtree(kid_id);
+            self.as_mut().get_mut(new_id).unwrap().append_id(kid_copy);
+        }
+
+        new_id
+    }
+
     pub fn alter_node<F>(&mut self, node_id: AstNodeId, f: F)
     where
         F: Fn(&mut ItemWithPos),
@@ -168,8 +181,13 @@ pub struct AstCtx<'a>
     pub macro_defs: ThinVec<AstNodeId>,
     pub ctx: &'a mut Assembler,
     pub docs: HashMap<AstNodeId, String>,
+    /// Repeats expanded so far, to give each one's scopes unique names
+    repeats: usize,
 }
 
+/// Most iterations a `rept` can ask for, or a `while` can make before it's taken to be stuck
+const MAX_ITERATIONS: usize = 0x10000;
+
 /// Iterate through the nodes recursively, depth first
 fn get_recursive<F>(node: AstNodeRef, f: &mut F)
 where
@@ -214,6 +232,20 @@ fn iter_items_recursive(node: AstNodeRef) -> impl Iterator<Item = (AstNodeId, &A
     })
 }
 
+/// The node a depth first walk under `root` visits after everything under `node`
+fn next_after_subtree(node: AstNodeRef, root: AstNodeId) -> Option<AstNodeId> {
+    let mut node = node;
+
+    while node.id() != root {
+        if let Some(next) = node.next_sibling() {
+            return Some(next.id());
+        }
+        node = node.parent()?;
+    }
+
+    None
+}
+
 #[allow(dead_code)]
 fn iter_values_recursive(node: AstNodeRef
 ) -> impl Iterator<Item = (AstNodeId, &ItemWithPos)> 
@@ -247,7 +279,10 @@ impl<'a> AstCtx<'a>
             self.create_scopes()?;
             self.postfix_expressions()?;
             self.rename_locals();
+            self.add_define_symbols()?;
+            self.expand_blocks()?;
             self.process_macros_definitions()?;
+            self.expand_macro_blocks()?;
             self.generate_struct_symbols()?;
             self.scope_assignments()?;
             self.process_imports()?;
@@ -360,6 +395,7 @@ impl<'a> AstCtx<'a>
             ctx,
             macro_defs: thin_vec![],
             docs: Default::default(),
+            repeats: 0,
         }
     }
 
@@ -805,35 +841,409 @@ impl<'a> AstCtx<'a>
             let mut scopes = self.get_root_scope_tracker();
 
             for node_id in iter_ids_recursive(self.get_tree().root()) {
-                let value = self.get_tree().get(node_id).unwrap().value();
-                let item = &value.item.clone();
+                if let ScopeId(scope_id) = self.get_tree().get(node_id).unwrap().value().item {
+                    scopes.set_scope(scope_id)
+                }
 
-                match item {
-                    ScopeId(scope_id) => scopes.set_scope(*scope_id),
+                self.scope_assignment(node_id, &scopes)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Reserve a label for this node if it's an assignment
+    fn scope_assignment(
+        &mut self,
+        node_id: AstNodeId,
+        scopes: &ScopeTracker,
+    ) -> Result<(), UserError> {
+        use AstNodeKind::*;
+
+        let item = self.get_tree().get(node_id).unwrap().value().item.clone();
+
+        match &item {
+            AssignmentFromPc(LabelDefinition::Text(name)) => {
+                debug_mess!("Assignment from PC: {name}");
+                let sym_id = self.create_symbol(name, node_id, scopes)?;
+                self.ast_tree.alter_node(node_id, |ipos| {
+                    ipos.item = AssignmentFromPc(sym_id.into());
+                });
+            }
+
+            Assignment(LabelDefinition::Text(name)) => {
+                debug_mess!("Assignment: {name}");
+                let sym_id = self.create_symbol(name, node_id, scopes)?;
+                self.ast_tree.alter_node(node_id, |ipos| {
+                    ipos.item = Assignment(sym_id.into());
+                });
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// `--set` vars with numeric values are symbols in the root scope
+    /// Numeric `--set` definitions are symbols in the root scope
+    fn add_define_symbols(&mut self) -> Result<(), UserError> {
+        let scopes = self.get_root_scope_tracker();
+        let root_id = self.get_tree().root().id();
+
+        let vars: Vec<_> = self
+            .ctx
+            .opts
+            .defines
+            .numeric_vars()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+
+        for (name, value) in vars {
+            debug_mess!("Var symbol: {name} = {value}");
+            self.create_and_set_symbol(value, &name, root_id, &scopes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Expand conditionals and repeats in source order
+    fn expand_blocks(&mut self) -> Result<(), UserError> {
+        info("Expanding conditionals and repeats", |_| {
+            let root_id = self.get_tree().root().id();
+            self.expand_blocks_under(root_id, self.get_root_scope_tracker(), false)
+        })
+    }
+
+    /// Expand the conditionals and repeats under a node in a single walk
+    /// The walk carries on from where an expanded block's body now is
+    /// so any blocks in it are expanded too
+    fn expand_blocks_under(
+        &mut self,
+        root_id: AstNodeId,
+        mut scopes: ScopeTracker,
+        in_macro: bool,
+    ) -> Result<(), UserError> {
+        let mut start = Some(root_id);
+
+        while let Some((id, block_scopes)) = self.next_block(start, root_id, scopes, in_macro)? {
+            let node = self.get_tree().get(id).unwrap();
+            let prev = node.prev_sibling().map(|n| n.id());
+            let parent = node.parent().unwrap().id();
+
+            self.expand_block(id, &block_scopes)?;
+
+            let tree = self.get_tree();
+            let (first, before) = match prev {
+                Some(prev) => (tree.get(prev).unwrap().next_sibling(), prev),
+                None => (tree.get(parent).unwrap().first_child(), parent),
+            };
+
+            start = match first {
+                Some(first) => Some(first.id()),
+                None => next_after_subtree(tree.get(before).unwrap(), root_id),
+            };
+
+            scopes = block_scopes;
+        }
+
+        Ok(())
+    }
+
+    /// The next conditional or repeat from `start` on in a walk under `root_id`,
+    /// and the scope it's in
+    /// Assignments before it are scoped and given values where they can be
+    /// so it can use them, macro bodies are left until they're called
+    fn next_block(
+        &mut self,
+        start: Option<AstNodeId>,
+        root_id: AstNodeId,
+        mut scopes: ScopeTracker,
+        in_macro: bool,
+    ) -> Result<Option<(AstNodeId, ScopeTracker)>, UserError> {
+        use super::gazmeval::eval;
+        use AstNodeKind::*;
+
+        let mut next = start;
+
+        while let Some(node_id) = next {
+            let node = self.get_tree().get(node_id).unwrap();
+            let item = node.value().item.clone();
+
+            next = match node.first_child() {
+                Some(child) => Some(child.id()),
+                None => next_after_subtree(node, root_id),
+            };
 
-                    AssignmentFromPc(LabelDefinition::Text(name)) => {
-                        debug_mess!("Assignment from PC: {name}");
-                        let sym_id = self.create_symbol(name, node_id, &scopes)?;
-                        self.ast_tree.alter_node(node_id, |ipos| {
-                            ipos.item = AssignmentFromPc(sym_id.into());
-                        });
+            match item {
+                MacroDef(..) if !in_macro => next = next_after_subtree(node, root_id),
+
+                ScopeId(scope_id) => scopes.set_scope(scope_id),
+
+                Conditional | Rept(..) | Irp(..) | While(..) => {
+                    return Ok(Some((node_id, scopes)))
+                }
+
+                _ if in_macro => (),
+
+                Assignment(LabelDefinition::Text(..)) => {
+                    self.scope_assignment(node_id, &scopes)?;
+
+                    let node = self.get_tree().get(node_id).unwrap();
+
+                    if let Assignment(LabelDefinition::Scoped(label_id)) = node.value().item {
+                        let reader = self.get_reader(&scopes);
+
+                        // Anything that can't be evaluated yet is left to evaluate_assignments
+                        if let Ok(value) = eval(&reader, node.first_child().unwrap()) {
+                            self.set_symbol(label_id, node_id, value)?;
+                        }
                     }
+                }
+
+                AssignmentFromPc(LabelDefinition::Text(..)) => {
+                    self.scope_assignment(node_id, &scopes)?
+                }
+
+                _ => (),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn expand_block(&mut self, id: AstNodeId, scopes: &ScopeTracker) -> Result<(), UserError> {
+        match self.get_tree().get(id).unwrap().value().item {
+            AstNodeKind::Conditional => self.resolve_conditional(id, scopes),
+            _ => self.expand_repeat(id, scopes),
+        }
+    }
+
+    /// Evaluate an expression now, everything it uses has to be known
+    fn eval_expr(&self, id: AstNodeId, scopes: &ScopeTracker) -> Result<i64, UserError> {
+        use super::gazmeval::eval;
+
+        let reader = self.get_reader(scopes);
+        let node = self.get_tree().get(id).unwrap();
+        eval(&reader, node).map_err(|e| self.convert_error(e.into()))
+    }
+
+    /// Replace a conditional with the body of the first branch taken, if any
+    fn resolve_conditional(
+        &mut self,
+        id: AstNodeId,
+        scopes: &ScopeTracker,
+    ) -> Result<(), UserError> {
+        use AstNodeKind::*;
+
+        let mut taken = None;
+
+        for branch_id in self.ast_tree.get_kids_ids(id) {
+            let branch = self.get_tree().get(branch_id).unwrap();
+
+            let (is_taken, body_start) = match &branch.value().item {
+                IfBranch => {
+                    let cond_id = branch.first_child().unwrap().id();
+                    (self.eval_expr(cond_id, scopes)? != 0, 1)
+                }
+
+                IfDefBranch(name, defined) => {
+                    let is_defined = self.get_reader(scopes).get_symbol_info(name).is_ok()
+                        || self.ctx.opts.defines.get_var(name).is_some();
+                    (is_defined == *defined, 0)
+                }
+
+                _ => (true, 0),
+            };
+
+            if is_taken {
+                taken = Some((branch_id, body_start));
+                break;
+            }
+        }
+
+        if let Some((branch_id, body_start)) = taken {
+            for body_id in self.ast_tree.get_kids_ids(branch_id).into_iter().skip(body_start) {
+                self.get_tree_mut().get_mut(body_id).unwrap().detach();
+                self.get_tree_mut().get_mut(id).unwrap().insert_id_before(body_id);
+            }
+        }
+
+        self.get_tree_mut().get_mut(id).unwrap().detach();
+
+        Ok(())
+    }
+
+    /// Replace a repeat with a copy of its body for each iteration
+    /// Each iteration has its own scope with the index symbol set to the
+    /// iteration number, or the value for `irp`
+    fn expand_repeat(&mut self, id: AstNodeId, scopes: &ScopeTracker) -> Result<(), UserError> {
+        use AstNodeKind::*;
+
+        let item = self.get_tree().get(id).unwrap().value().item.clone();
+        let pos = self.get_tree().get(id).unwrap().value().pos;
+        let kids = self.ast_tree.get_kids_ids(id);
+
+        let (index, values, body): (_, Option<Vec<i64>>, _) = match &item {
+            Rept(index) => {
+                let count = self.eval_expr(kids[0], scopes)?;
+
+                if count < 0 {
+                    return Err(self.node_error("Repeat count can't be negative", id, true));
+                }
+
+                if count > MAX_ITERATIONS as i64 {
+                    let msg = format!("Repeat count of {count} is more than {MAX_ITERATIONS}");
+                    return Err(self.node_error(msg, id, true));
+                }
+
+                (index.clone(), Some((0..count).collect()), &kids[1..])
+            }
+
+            Irp(index, num_of_values) => {
+                let values: Result<Vec<_>, _> = kids[..*num_of_values]
+                    .iter()
+                    .map(|v| self.eval_expr(*v, scopes))
+                    .collect();
+
+                (Some(index.clone()), Some(values?), &kids[*num_of_values..])
+            }
+
+            While(index) => (Some(index.clone()), None, &kids[1..]),
+
+            _ => panic!("Expected a repeat"),
+        };
+
+        self.repeats += 1;
+
+        // Make every iteration's scope first so a while loop knows when it stops before
+        // any body is copied
+        let mut iterations = vec![];
+
+        for n in 0.. {
+            let value = match &values {
+                Some(values) if n == values.len() => break,
+                Some(values) => values[n],
+                None if n == MAX_ITERATIONS => {
+                    let msg = format!("While loop still going after {n} iterations");
+                    return Err(self.node_error(msg, id, true));
+                }
+                None => n as i64,
+            };
 
-                    Assignment(LabelDefinition::Text(name)) => {
-                        debug_mess!("Assignment: {name}");
-                        let sym_id = self.create_symbol(name, node_id, &scopes)?;
-                        self.ast_tree.alter_node(node_id, |ipos| {
-                            ipos.item = Assignment(sym_id.into());
-                        });
+            let scope_name = format!("%REPT%_{}_{n}", self.repeats);
+            let syms = &mut self.ctx.asm_out.symbols;
+            let scope_id = syms.create_or_get_scope_for_parent(&scope_name, scopes.scope());
+            let iteration_scopes = ScopeTracker::new(scope_id);
+
+            if let Some(index) = &index {
+                self.create_and_set_symbol(value, index, id, &iteration_scopes)?;
+            }
+
+            if values.is_none() && self.eval_expr(kids[0], &iteration_scopes)? == 0 {
+                break;
+            }
+
+            iterations.push(scope_id);
+        }
+
+        for scope_id in iterations {
+            let scope_node = self.ast_tree.create_orphan(ScopeId(scope_id), pos);
+            self.get_tree_mut().get_mut(id).unwrap().insert_id_before(scope_node);
+
+            for body_id in body {
+                let copy_id = self.ast_tree.clone_subtree(*body_id);
+                self.get_tree_mut().get_mut(id).unwrap().insert_id_before(copy_id);
+            }
+        }
+
+        // Back to the scope the repeat was in
+        let scope_node = self.ast_tree.create_orphan(ScopeId(scopes.scope()), pos);
+        self.get_tree_mut().get_mut(id).unwrap().insert_id_before(scope_node);
+        self.get_tree_mut().get_mut(id).unwrap().detach();
+
+        Ok(())
+    }
+
+    /// Calls of macros with conditionals or repeats in get their own copy of the body
+    /// with them expanded for that call's arguments
+    fn expand_macro_blocks(&mut self) -> Result<(), UserError> {
+        info("Expanding conditionals and repeats in macros", |_| {
+            use AstNodeKind::*;
+
+            let mut scopes = self.get_root_scope_tracker();
+            let mut calls = vec![];
+
+            for node in iter_refs_recursive(self.get_tree().root()) {
+                match &node.value().item {
+                    ScopeId(scope_id) => scopes.set_scope(*scope_id),
+
+                    MacroCallProcessed { macro_id, .. } => {
+                        let body = self.get_tree().get(*macro_id).unwrap();
+
+                        let is_block = |n: AstNodeRef| {
+                            matches!(n.value().item, Conditional | Rept(..) | Irp(..) | While(..))
+                        };
+
+                        if body.descendants().any(is_block) {
+                            calls.push((node.id(), scopes.clone()));
+                        }
                     }
+
                     _ => (),
                 }
             }
 
+            for (call_id, scopes) in calls {
+                self.expand_macro_call(call_id, &scopes)?;
+            }
+
             Ok(())
         })
     }
 
+    fn expand_macro_call(
+        &mut self,
+        call_id: AstNodeId,
+        scopes: &ScopeTracker,
+    ) -> Result<(), UserError> {
+        use super::gazmeval::eval;
+        use AstNodeKind::*;
+
+        let call = self.get_tree().get(call_id).unwrap();
+
+        let (macro_id, scope_id, params) = match &call.value().item {
+            MacroCallProcessed {
+                macro_id,
+                scope_id,
+                params_vec_of_id,
+            } => (*macro_id, *scope_id, params_vec_of_id.clone()),
+            _ => panic!("Expected a macro call"),
+        };
+
+        // Args are evaluated in the caller's scope
+        // any that can't be are left for the sizer
+        for (param_id, arg_id) in params.into_iter().zip(self.ast_tree.get_kids_ids(call_id)) {
+            let reader = self.get_reader(scopes);
+
+            if let Ok(value) = eval(&reader, self.get_tree().get(arg_id).unwrap()) {
+                self.set_symbol(param_id, arg_id, value)?;
+            }
+        }
+
+        let body_id = self.ast_tree.clone_subtree(macro_id);
+        self.expand_blocks_under(body_id, ScopeTracker::new(scope_id), true)?;
+
+        self.ast_tree.alter_node(call_id, |ipos| {
+            if let MacroCallProcessed { macro_id, .. } = &mut ipos.item {
+                *macro_id = body_id
+            }
+        });
+
+        self.macro_defs.push(body_id);
+
+        Ok(())
+    }
+
     fn evaluate_assignments(&mut self) -> Result<(), UserError> {
         info("Evaluating assignments", |_| {
             use super::gazmeval::eval;
@@ -856,8 +1266,9 @@ impl<'a> AstCtx<'a>
                                 .set_symbol(label_id, node_id, value)
                                 .expect("Can't set symbol"),
 
+                            // Banks aren't known until sizing either
                             Err(EvalError {
-                                source: EvalErrorEnum::CotainsPcReference,
+                                source: EvalErrorEnum::CotainsPcReference | EvalErrorEnum::NoBank,
                                 ..
                             }) => {
                                 self.ast_tree.alter_node(node_id, |ipos| {
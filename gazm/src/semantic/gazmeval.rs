@@ -6,7 +6,7 @@ use thiserror::Error;
 use crate::{
     error::AstError,
     frontend::{AstNodeKind, LabelDefinition, ParsedFrom},
-    gazmsymbols::{SymbolError, SymbolTreeReader},
+    gazmsymbols::{SymbolBanks, SymbolError, SymbolTreeReader},
     semantic::{AstNodeId, AstNodeRef},
 };
 
@@ -29,6 +29,8 @@ pub enum EvalErrorEnum {
     UnableToEvaluate,
     #[error("Can't pop top!")]
     CantPopTop,
+    #[error("Label is not in a bank")]
+    NoBank,
 }
 
 #[derive(Error, Debug, Clone)]
@@ -91,6 +93,7 @@ impl GetPriority for AstNodeKind
 ///  - Must eval to a number
 fn eval_internal(
     symbols: &SymbolTreeReader,
+    banks: &SymbolBanks,
     n: AstNodeRef,
 ) -> Result<AstNodeKind, EvalError>
 {
@@ -107,7 +110,19 @@ fn eval_internal(
     };
 
     let rez = match i {
-        PostFixExpr => eval_postfix(symbols, n)?,
+        PostFixExpr => eval_postfix(symbols, banks, n)?,
+
+        Bank => {
+            let label = n.first_child().map(|c| c.value().item.clone());
+
+            match label {
+                Some(Label(LabelDefinition::Scoped(id))) => banks
+                    .get(&id)
+                    .map(|b| AstNodeKind::from_number(*b as i64, ParsedFrom::Expression))
+                    .ok_or_else(|| EvalError::new(EvalErrorEnum::NoBank, n))?,
+                _ => return Err(EvalError::new(EvalErrorEnum::UnableToEvaluate, n)),
+            }
+        }
 
         Label(LabelDefinition::Scoped(id)) => {
             symbols
@@ -135,7 +150,7 @@ fn eval_internal(
             let mut c = n.children();
             let ops = c.next().unwrap();
             let num = c.next().unwrap();
-            let r = eval_internal(symbols, num)?;
+            let r = eval_internal(symbols, banks, num)?;
 
             let num = r.unrwap_number().unwrap();
 
@@ -165,6 +180,7 @@ fn eval_internal(
 /// Evaluates a postfix expression
 fn eval_postfix(
     symbols: &SymbolTreeReader,
+    banks: &SymbolBanks,
     n: AstNodeRef,
 ) -> Result<AstNodeKind, EvalError>
 {
@@ -180,7 +196,7 @@ fn eval_postfix(
             let item = if i.is_op() {
                 i.clone()
             } else {
-                eval_internal(symbols, c)?.clone()
+                eval_internal(symbols, banks, c)?.clone()
             };
 
             items.push((c, item));
@@ -218,6 +234,16 @@ fn eval_postfix(
 
 pub fn eval(symbols: &SymbolTreeReader, n: AstNodeRef) -> Result<i64, EvalError>
 {
-    let ret = eval_internal(symbols, n)?;
+    eval_with_banks(symbols, &SymbolBanks::new(), n)
+}
+
+/// Evaluate with the banks labels are in known, for `bank(label)`
+pub fn eval_with_banks(
+    symbols: &SymbolTreeReader,
+    banks: &SymbolBanks,
+    n: AstNodeRef,
+) -> Result<i64, EvalError>
+{
+    let ret = eval_internal(symbols, banks, n)?;
     Ok(ret.unrwap_number().unwrap())
 }
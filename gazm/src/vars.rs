@@ -72,12 +72,28 @@ impl Vars {
         Ok(ret)
     }
 
+    /// Vars whose values are numbers, decimal or `$` / `0x` hex
+    pub fn numeric_vars(&self) -> impl Iterator<Item = (&str, i64)> {
+        self.vars
+            .iter()
+            .filter_map(|(k, v)| parse_number(v).map(|n| (k.as_str(), n)))
+    }
+
     pub fn expand_vars_in_path<P: AsRef<Path>>(&self, p: P) -> Result<PathBuf, VarsErrorKind> {
         let r = self.expand_vars(p.as_ref().to_string_lossy())?;
         Ok(PathBuf::from(r))
     }
 }
 
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+
+    match text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
 #[allow(unused_imports)]
 mod test {
     use super::*;
@@ -91,6 +107,14 @@ mod test {
         assert_eq!(y, "outdir/hello/bingbong/hello/outdir");
     }
 
+    #[test]
+    fn test_numeric_vars() {
+        let vars = Vars::from(vec![("REV", "2"), ("BASE", "$c000"), ("OUTDIR", "outdir")]);
+        let mut nums: Vec<_> = vars.numeric_vars().collect();
+        nums.sort();
+        assert_eq!(nums, vec![("BASE", 0xc000), ("REV", 2)]);
+    }
+
     #[test]
     fn text_expand_failure() {
         let vars = vec![("OUTDIR", "outdir"), ("BINGBONG", "bingbong")];